@@ -114,9 +114,17 @@ fn generate_manpages(out_dir: PathBuf) -> std::io::Result<()> {
 
     generate_command_manpage(crunchy_cli_core::Cli::command(), &out_dir, "")?;
     generate_command_manpage(crunchy_cli_core::Archive::command(), &out_dir, "archive")?;
+    generate_command_manpage(crunchy_cli_core::Config::command(), &out_dir, "config")?;
     generate_command_manpage(crunchy_cli_core::Download::command(), &out_dir, "download")?;
+    generate_command_manpage(
+        crunchy_cli_core::ExportMetadata::command(),
+        &out_dir,
+        "export-metadata",
+    )?;
     generate_command_manpage(crunchy_cli_core::Login::command(), &out_dir, "login")?;
     generate_command_manpage(crunchy_cli_core::Search::command(), &out_dir, "search")?;
+    generate_command_manpage(crunchy_cli_core::State::command(), &out_dir, "state")?;
+    generate_command_manpage(crunchy_cli_core::Watch::command(), &out_dir, "watch")?;
 
     Ok(())
 }
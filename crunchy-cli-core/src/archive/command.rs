@@ -1,29 +1,48 @@
 use crate::archive::filter::ArchiveFilter;
+use crate::utils::chapter_file::ChapterFileFormat;
+use crate::utils::clip::Clip;
 use crate::utils::context::Context;
 use crate::utils::download::{
-    DownloadBuilder, DownloadFormat, DownloadFormatMetadata, MergeBehavior,
+    CoverArt, DownloadBuilder, DownloadFormat, DownloadFormatMetadata, LocalMux, MergeBehavior,
+    Only, SidecarNaming, SubtitleFormat, AUDIO_ONLY_CONTAINERS,
+};
+use crate::utils::download_archive::DownloadArchive;
+use crate::utils::exec::run_exec;
+use crate::utils::ffmpeg::{ffmpeg_binary, FFmpegPreset, FFmpegRateControl};
+use crate::utils::filter::{Filter, MaxMaturity};
+use crate::utils::fmt::format_size;
+use crate::utils::format::{
+    uses_skip_event_variables, EpisodeOrder, Format, ListFormat, SingleFormat, TitleCleanup,
 };
-use crate::utils::ffmpeg::FFmpegPreset;
-use crate::utils::filter::Filter;
-use crate::utils::format::{Format, SingleFormat};
 use crate::utils::locale::{all_locale_in_locales, resolve_locales, LanguageTagging};
-use crate::utils::log::progress;
+use crate::utils::log::{progress, set_terminal_title_episode, OverallProgress};
+use crate::utils::nfo::write_nfo;
+use crate::utils::notify::notify_webhook;
 use crate::utils::os::{free_file, has_ffmpeg, is_special_file};
-use crate::utils::parse::parse_url;
-use crate::utils::video::stream_data_from_stream;
+use crate::utils::parse::{parse_url, ResolutionSelector};
+use crate::utils::schedule::Schedule;
+use crate::utils::segment_recording::SegmentRecording;
+use crate::utils::size_budget::SizeBudget;
+use crate::utils::skip_file::{skip_event_ranges, write_skip_file, SkipEventKind, SkipFileFormat};
+use crate::utils::sync::{SeasonFingerprintCache, SyncWindow};
+use crate::utils::timeout::{seconds_to_timeout, with_stage_timeout};
+use crate::utils::video::{is_same_stream, stream_data_from_stream, Codec};
 use crate::Execute;
 use anyhow::bail;
 use anyhow::Result;
 use chrono::Duration;
-use crunchyroll_rs::media::{Resolution, Subtitle};
+use crunchyroll_rs::media::{Stream, Subtitle};
 use crunchyroll_rs::Locale;
-use log::{debug, warn};
+use futures_util::StreamExt;
+use log::{debug, error, info, warn};
 use regex::Regex;
 use std::fmt::{Display, Formatter};
+use std::fs;
 use std::iter::zip;
 use std::ops::Sub;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::time::Instant;
 
 #[derive(Clone, Debug, clap::Parser)]
 #[clap(about = "Archive a video")]
@@ -60,12 +79,15 @@ pub struct Archive {
       {relative_episode_number}  → Number of the episode relative to its season\n  \
       {sequence_number}          → Like '{episode_number}' but without possible non-number characters\n  \
       {relative_sequence_number} → Like '{relative_episode_number}' but with support for episode 0's and .5's\n  \
+      {special_label}            → Label of specials (e.g. SP1 or OVA), empty for regular episodes\n  \
+      {rating}                   → Maturity rating of the video (e.g. TV-14)\n  \
       {release_year}             → Release year of the video\n  \
       {release_month}            → Release month of the video\n  \
       {release_day}              → Release day of the video\n  \
       {series_id}                → ID of the series\n  \
       {season_id}                → ID of the season\n  \
-      {episode_id}               → ID of the episode")]
+      {episode_id}               → ID of the episode\n\
+    Numbers are padded with zeros to 2 digits, use e.g. '{episode_number:3}' for another width")]
     #[arg(short, long, default_value = "{title}.mkv")]
     pub(crate) output: String,
     #[arg(help = "Name of the output file if the episode is a special")]
@@ -73,21 +95,193 @@ pub struct Archive {
     If not set, the '-o'/'--output' flag will be used as name template")]
     #[arg(long)]
     pub(crate) output_specials: Option<String>,
+    #[arg(help = "Directory in which the output files are stored")]
+    #[arg(long_help = "Directory in which the output files are stored. \
+    Relative '-o'/'--output' and '--output-specials' paths are resolved relative to it, so the same template can be used with different directories. \
+    Absolute output paths, '-' and special files are used as they are")]
+    #[arg(long)]
+    pub(crate) output_dir: Option<PathBuf>,
+    #[arg(help = "Name of the output file if only the audio is downloaded (`--only audio`)")]
+    #[arg(
+        long_help = "Name of the output file if only the audio is downloaded (`--only audio`). \
+    Accepts the same template options as '-o'/'--output', which is ignored in this case. \
+    Valid extensions are '.m4a' and '.mka' (the audio is copied as is) and '.mp3' and '.flac' (the audio is transcoded). \
+    If multiple audios are downloaded, '.m4a' and '.mka' files contain all of them, for '.mp3' and '.flac' every audio is written to its own file with its locale added (e.g. `audio.en-US.mp3`)"
+    )]
+    #[arg(long, default_value = "{title}.mka")]
+    pub(crate) output_audio: String,
+    #[arg(
+        help = "Put specials into season 0, numbered by their special label (e.g. SP3 → episode 3)"
+    )]
+    #[arg(
+        long_help = "Put specials into season 0, like media servers like Plex or Jellyfin expect them. \
+    The episode number is taken from the special label Crunchyroll uses (e.g. SP3 → episode 3), so the numbering may have gaps. \
+    This changes the '{season_number}' and '{episode_number}' template options of specials"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) specials_season_zero: bool,
+
+    #[arg(help = "Embed the maturity rating (e.g. TV-14) into the output file")]
+    #[arg(
+        long_help = "Embed the maturity rating (e.g. TV-14) as 'LAW_RATING' tag into the output file. \
+    Only supported by .mkv files. Independent of this flag, the rating is available as '{rating}' template option"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) embed_rating: bool,
+    #[arg(help = "Embed the episode thumbnail or series poster as cover art into the output file")]
+    #[arg(long_help = "Embed cover art into the output file. \
+    Valid options are 'episode' (thumbnail of the episode), 'series' (poster of the series) and 'none'. \
+    Only supported by .mkv (as attachment), .mp4 and .mov files (as attached picture). \
+    Movies use their own thumbnail or the poster of their movie listing")]
+    #[arg(long, default_value = "none")]
+    #[arg(value_parser = CoverArt::parse)]
+    pub(crate) cover_art: CoverArt,
+    #[arg(help = "Add track statistics tags (bitrate, duration, frame count) to .mkv files")]
+    #[arg(
+        long_help = "Add track statistics tags (BPS, DURATION, NUMBER_OF_FRAMES, NUMBER_OF_BYTES) to .mkv files, \
+    which media servers like Plex or Jellyfin use to show accurate bitrates. \
+    Requires mkvpropedit (MKVToolNix) to be installed"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) track_statistics: bool,
+    #[arg(help = "Write .nfo metadata files for media servers like Kodi or Jellyfin")]
+    #[arg(
+        long_help = "Write .nfo metadata files for media servers like Kodi or Jellyfin. \
+    Every episode gets a .nfo file next to it with its title, description, air date, season and episode number and id. \
+    Additionally a 'season.nfo' and 'tvshow.nfo' file is written to the season and series directory if they don't exist yet. \
+    The series directory is the parent directory if the episode directory is named like a season directory (e.g. 'Season 01')"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) write_nfo: bool,
+    #[arg(help = "Save the episode thumbnail next to the output file")]
+    #[arg(long_help = "Save the episode thumbnail next to the output file. \
+    The image has the same name as the output file, only the extension differs (e.g. 'episode.jpg' for 'episode.mkv')")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) write_thumbnail: bool,
+    #[arg(
+        help = "Write a file next to the output file which tells players to skip the intro, credits, recap and preview. Valid options are 'edl', 'kodi' and 'mpv'"
+    )]
+    #[arg(
+        long_help = "Write a file next to the output file which tells players to skip the intro, credits, recap and preview, for players which don't use the chapters. \
+    Valid options are 'edl' (MPlayer edl file, 'episode.edl' for 'episode.mkv'), 'kodi' (Kodi edl file which marks the parts as commercial breaks, 'episode.edl') \
+    and 'mpv' (mpv edl file which plays the video without the parts if opened instead of the video, 'episode.mpv.edl'). \
+    Nothing is written if Crunchyroll has no skip events for the episode"
+    )]
+    #[arg(long, value_parser = SkipFileFormat::parse)]
+    pub(crate) write_skip_file: Option<SkipFileFormat>,
+    #[arg(
+        help = "Write the chapters into a file next to the output file. Valid options are 'ffmetadata', 'mkv-xml', 'ogm' and 'edl'"
+    )]
+    #[arg(
+        long_help = "Write the chapters into a file next to the output file, additionally to embedding them. Requires `--include-chapters`. \
+    Valid options are 'ffmetadata' (ffmpeg metadata file, 'episode.ffmetadata' for 'episode.mkv'), 'mkv-xml' (matroska chapter xml, 'episode.chapters.xml'), \
+    'ogm' (OGM chapter file, 'episode.chapters.txt') and 'edl' (Kodi edl file which marks every chapter except the episode content as commercial break, 'episode.edl')"
+    )]
+    #[arg(long, value_parser = ChapterFileFormat::parse)]
+    pub(crate) write_chapters: Option<ChapterFileFormat>,
+    #[arg(
+        help = "Cut the given parts out of the output file. Valid options are 'intro', 'recap', 'preview' and 'credits'"
+    )]
+    #[arg(
+        long_help = "Cut the given parts out of the output file, using the skip events Crunchyroll provides for the episode. \
+    Valid options are 'intro', 'recap', 'preview' and 'credits', multiple can be given comma separated (e.g. 'intro,recap'). \
+    The streams aren't re-encoded, so every kept part starts at a keyframe and a few seconds of a cut part may remain before it. Requires ffprobe. \
+    Nothing is cut if Crunchyroll has no skip events for the episode"
+    )]
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = SkipEventKind::parse,
+        conflicts_with_all = ["include_chapters", "write_skip_file", "also_sidecar_subs"]
+    )]
+    pub(crate) cut: Vec<SkipEventKind>,
+    #[arg(help = "Only download the given time range of every episode, e.g. '00:05:30-00:08:00'")]
+    #[arg(
+        long_help = "Only download the given time range of every episode, e.g. '00:05:30-00:08:00'. \
+    Only the segments which overlap with the range are downloaded, the range is cut out precisely when generating the output file. \
+    With the default ffmpeg preset the streams aren't re-encoded, so the video starts at the keyframe before the range start. \
+    The times can be given as HH:MM:SS, MM:SS or seconds, with optional fractions of a second"
+    )]
+    #[arg(
+        long,
+        value_parser = Clip::parse,
+        conflicts_with_all = ["include_chapters", "cut", "write_skip_file", "resume"]
+    )]
+    pub(crate) clip: Option<Clip>,
+    #[arg(
+        help = "Send a POST request with details about every downloaded or failed episode to this url"
+    )]
+    #[arg(
+        long_help = "Send a POST request with details about every downloaded or failed episode to this url. \
+    The json body contains the series name, season and episode number, title, episode id, output path, duration, file size, download time and whether the download succeeded (with the error if not). \
+    Useful to integrate with chat or home automation services"
+    )]
+    #[arg(long)]
+    pub(crate) notify_webhook: Option<String>,
+    #[arg(help = "Run a command after every successfully downloaded episode")]
+    #[arg(
+        long_help = "Run a command after every successfully downloaded episode. \
+    Accepts the same template options as '-o'/'--output' and additionally '{path}' (the output file). \
+    The command is split into arguments like a shell would do it, but not run by a shell, so template options always stay one argument. \
+    If the command fails, the episode is treated as failed"
+    )]
+    #[arg(long)]
+    pub(crate) exec: Option<String>,
+    #[arg(help = "Run a command if the download of an episode failed")]
+    #[arg(long_help = "Run a command if the download of an episode failed. \
+    Accepts the same template options as '--exec' and additionally '{error}' (the error message)")]
+    #[arg(long)]
+    pub(crate) exec_on_error: Option<String>,
 
     #[arg(help = "Sanitize the output file for use with all operating systems. \
     This option only affects template options and not static characters.")]
     #[arg(long, default_value_t = false)]
     pub(crate) universal_output: bool,
 
+    #[arg(help = "Maximum length of the full output path")]
+    #[arg(
+        long_help = "Maximum length of the full output path, including the current directory if the path is relative. \
+    If the path is longer, the title is shortened first and then the file name (the extension is kept). \
+    Useful on Windows, which has a default maximum path length of 260 characters, or on network shares"
+    )]
+    #[arg(long)]
+    pub(crate) max_path_length: Option<usize>,
+
+    #[arg(
+        help = "Remove dub and split cour suffixes like '(English Dub)' or 'Part 2' from series and season names"
+    )]
+    #[arg(
+        long_help = "Remove dub and split cour suffixes like '(English Dub)' or 'Part 2' from series and season names. \
+    The cleaned names are used in the output template. \
+    Additional rules can be specified with `--clean-titles-pattern`"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) clean_titles: bool,
+    #[arg(
+        help = "Regex whose matches are removed from series and season names. Can be used multiple times"
+    )]
+    #[arg(long)]
+    pub(crate) clean_titles_pattern: Vec<Regex>,
+
     #[arg(help = "Video resolution")]
     #[arg(long_help = "The video resolution. \
     Can either be specified via the pixels (e.g. 1920x1080), the abbreviation for pixels (e.g. 1080p) or 'common-use' words (e.g. best). \
     Specifying the exact pixels is not recommended, use one of the other options instead. \
     Crunchyroll let you choose the quality with pixel abbreviation on their clients, so you might be already familiar with the available options. \
-    The available common-use words are 'best' (choose the best resolution available) and 'worst' (worst resolution available)")]
+    The available common-use words are 'best' (choose the best resolution available) and 'worst' (worst resolution available). \
+    Besides that, constraints like '<=1080p,>=720p' select the best resolution which fulfills all of them ('worst' can be added to the list to select the worst one instead). \
+    The long form 'best[height<=720]' / 'worst[width>=1280,height>=720]' constrains the width and height separately")]
     #[arg(short, long, default_value = "best")]
     #[arg(value_parser = crate::utils::clap::clap_parse_resolution)]
-    pub(crate) resolution: Resolution,
+    pub(crate) resolution: ResolutionSelector,
+    #[arg(help = "Preferred video codec. Valid options are 'h264', 'h265' and 'any'")]
+    #[arg(
+        long_help = "Preferred video codec. Valid options are 'h264', 'h265' and 'any'. \
+    Some videos are available in multiple codecs, h265 (hevc) videos are usually smaller than h264 ones with the same quality. \
+    If a video isn't available in the preferred codec, any other codec is used"
+    )]
+    #[arg(long, default_value = "any", value_parser = Codec::parse)]
+    pub(crate) codec: Codec,
 
     #[arg(
         help = "Sets the behavior of the stream merging. Valid behaviors are 'auto', 'sync', 'audio' and 'video'"
@@ -115,6 +309,36 @@ pub struct Archive {
     )]
     #[arg(long, default_value_t = 4)]
     pub(crate) merge_sync_precision: u32,
+    #[arg(
+        help = "If the merge behavior is 'sync', only use this part of the audios to find the offsets. Must be in format <start>-<end> (e.g. 0-5:00)"
+    )]
+    #[arg(
+        long_help = "If the merge behavior is 'sync', only use this part of the audios to find the offsets. \
+            Must be in format <start>-<end>, with times in format [[hh:]mm:]ss (e.g. '0-5:00' for the first five minutes or '2:00-' for everything after the second minute). \
+            This helps if parts like recaps or cold opens differ between the audios. The window must be at least 30 seconds long"
+    )]
+    #[arg(long, value_parser = SyncWindow::parse)]
+    pub(crate) sync_window: Option<SyncWindow>,
+    #[arg(
+        help = "If the merge behavior is 'sync', write a plot of the offsets which were found while syncing next to the output file"
+    )]
+    #[arg(
+        long_help = "If the merge behavior is 'sync', write a plot of the offsets which were found while syncing next to the output file. \
+            It shows the offset of every run against the length of the matching audio part the offset was calculated from, which helps to see why an offset was applied or rejected. \
+            The plot is saved as svg with the file name of the output file and the '.sync.svg' extension"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) sync_plot: bool,
+    #[arg(
+        help = "If the merge behavior is 'sync', sync all audios and subtitles to the audio of this local video or audio file and write them as separate files instead of generating a video"
+    )]
+    #[arg(
+        long_help = "If the merge behavior is 'sync', sync all audios and subtitles to the audio of this local video or audio file and write them as separate files instead of generating a video. \
+            No video is downloaded. Every audio is written next to the output file as '<output name>.<locale>.mka' and every subtitle as '<output name>.<locale>.ass', with their start shifted to match the given file. \
+            This can be used to add dubs or subtitles to videos from other sources, e.g. a bluray remux"
+    )]
+    #[arg(long)]
+    pub(crate) sync_reference: Option<PathBuf>,
 
     #[arg(
         help = "Specified which language tagging the audio and subtitle tracks and language specific format options should have. \
@@ -146,6 +370,35 @@ pub struct Archive {
     )]
     #[arg(long)]
     pub(crate) ffmpeg_threads: Option<usize>,
+    #[arg(
+        help = "Constant rate factor of the video encoder, replaces the quality of the ffmpeg preset"
+    )]
+    #[arg(
+        long_help = "Constant rate factor of the video encoder, between 0 and 63. Lower values mean better quality and bigger files. \
+    Replaces the quality of the `--ffmpeg-preset` and is translated to the quality option of hardware encoders which don't support crf. \
+    Only works with predefined presets, set the quality directly in custom ffmpeg output arguments instead"
+    )]
+    #[arg(long, value_parser = crate::utils::clap::clap_parse_crf)]
+    pub(crate) ffmpeg_crf: Option<u8>,
+    #[arg(
+        help = "Target bitrate of the video encoder (e.g. '2500k'), replaces the quality of the ffmpeg preset"
+    )]
+    #[arg(
+        long_help = "Target bitrate of the video encoder, e.g. '2500k' or '2.5M'. \
+    Replaces the quality of the `--ffmpeg-preset`. \
+    Only works with predefined presets, set the bitrate directly in custom ffmpeg output arguments instead"
+    )]
+    #[arg(long, conflicts_with = "ffmpeg_crf", value_parser = crate::utils::clap::clap_parse_bitrate)]
+    pub(crate) ffmpeg_bitrate: Option<String>,
+    #[arg(
+        help = "Encode the video in two passes to hit the bitrate of the ffmpeg preset more precisely"
+    )]
+    #[arg(long_help = "Encode the video in two passes. \
+    The first pass analyses the video, the second one uses the analysis to distribute the bitrate better and to hit the target bitrate more precisely. \
+    Requires an `--ffmpeg-preset` which encodes the video with a target bitrate (`--ffmpeg-bitrate` or `-b:v`) and a software encoder (libx264, libx265, libvpx, libvpx-vp9 or libaom-av1). \
+    Takes about twice as long as a normal encode")]
+    #[arg(long, default_value_t = false, conflicts_with = "low_disk")]
+    pub(crate) ffmpeg_two_pass: bool,
 
     #[arg(
         help = "Set which subtitle language should be set as default / auto shown when starting a video"
@@ -155,6 +408,23 @@ pub struct Archive {
     #[arg(help = "Include fonts in the downloaded file")]
     #[arg(long)]
     pub(crate) include_fonts: bool,
+    #[arg(
+        help = "Fallback for fonts which aren't available, in format <font>=<fallback>. Can be used multiple times"
+    )]
+    #[arg(
+        long_help = "Fallback for fonts which aren't available, in format <font>=<fallback> (e.g. 'Comic Neue=Arial'). Can be used multiple times. \
+    Subtitles may use fonts which Crunchyroll doesn't provide, instead of them the fallback font is included. \
+    Use '*' as font to set the fallback for all unknown fonts (e.g. '*=Arial')"
+    )]
+    #[arg(long, requires = "include_fonts", value_parser = crate::utils::clap::clap_parse_font_fallback)]
+    pub(crate) font_fallback: Vec<(String, String)>,
+    #[arg(help = "Replace fonts which have a fallback with it in the subtitles")]
+    #[arg(
+        long_help = "Replace fonts which have a fallback (see '--font-fallback') with it in the subtitle styles and font overrides. \
+    Without it, the player decides which font is used for unavailable fonts, even if the fallback is included"
+    )]
+    #[arg(long, requires = "font_fallback")]
+    pub(crate) rewrite_font_fallback: bool,
     #[arg(
         help = "Includes chapters (e.g. intro, credits, ...). Only works if `--merge` is set to 'audio'"
     )]
@@ -166,6 +436,24 @@ pub struct Archive {
     )]
     #[arg(long, default_value_t = false)]
     pub(crate) include_chapters: bool,
+    #[arg(
+        help = "Detect intro and credits chapters via audio fingerprinting if Crunchyroll provides no chapter information. Only works with `--include-chapters`"
+    )]
+    #[arg(
+        long_help = "Detect intro and credits chapters via audio fingerprinting if Crunchyroll provides no chapter information. Only works with `--include-chapters`. \
+    The audio of every episode is compared with the previous episode of the same season, parts which occur in both are used as intro and credits. \
+    The first episode of a season therefore never gets synthesized chapters"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) synthesize_chapters: bool,
+    #[arg(help = "Language of the chapter titles. Only works with `--include-chapters`")]
+    #[arg(
+        long_help = "Language of the chapter titles. Only works with `--include-chapters`. \
+    The chapter titles (e.g. 'Intro' or 'Credits') are translated if the language is supported, otherwise they stay english. \
+    Chapters of .mkv files are additionally tagged with the language, which requires mkvpropedit (part of MKVToolNix) to be installed"
+    )]
+    #[arg(long)]
+    pub(crate) metadata_locale: Option<Locale>,
 
     #[arg(help = "Omit closed caption subtitles in the downloaded file")]
     #[arg(long, default_value_t = false)]
@@ -184,9 +472,139 @@ pub struct Archive {
     #[arg(long, default_values_t = SkipExistingMethod::default())]
     #[arg(value_parser = SkipExistingMethod::parse)]
     pub(crate) skip_existing_method: Vec<SkipExistingMethod>,
+    #[arg(
+        help = "Record downloaded episodes in the given file and skip episodes which are already recorded"
+    )]
+    #[arg(
+        long_help = "Record the identifier of every successfully downloaded episode in the given file and skip episodes which are already recorded in it. \
+    Unlike `--skip-existing`, this also works if the downloaded files were moved or renamed"
+    )]
+    #[arg(long)]
+    pub(crate) download_archive: Option<PathBuf>,
     #[arg(help = "Skip special episodes")]
     #[arg(long, default_value_t = false)]
     pub(crate) skip_specials: bool,
+    #[arg(help = "Wait for announced episodes which aren't released yet and download them then")]
+    #[arg(
+        long_help = "Wait for announced episodes which aren't released yet (e.g. weekly simulcasts with a premiere countdown) until they are available and download them then. \
+    Without this flag, downloading such episodes fails"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) wait_for_release: bool,
+    #[arg(
+        help = "Order in which the episodes are downloaded. Valid options are 'sequence', 'air' and 'alphabetical'"
+    )]
+    #[arg(
+        long_help = "Order in which the episodes are downloaded. Valid options are 'sequence' (by season and episode number), \
+    'air' (by the date the episodes first aired, across all seasons, e.g. for specials which aired between regular episodes) and 'alphabetical' (by episode title)"
+    )]
+    #[arg(long, default_value = "sequence")]
+    #[arg(value_parser = EpisodeOrder::parse)]
+    pub(crate) episode_order: EpisodeOrder,
+    #[arg(help = "Only download as many episodes as fit into the given size, e.g. '200G'")]
+    #[arg(
+        long_help = "Only download as many episodes as fit into the given size, e.g. '500M' or '200G'. \
+    The size of every episode (with all its versions) is estimated before the download starts, episodes which don't fit are skipped (or can be chosen interactively). \
+    While downloading, episodes which would exceed the size because the estimates were too low are skipped too"
+    )]
+    #[arg(long, value_parser = crate::utils::clap::clap_parse_size)]
+    pub(crate) max_total_size: Option<u64>,
+
+    #[arg(
+        help = "Skip episodes and series which have a higher maturity rating than the given one. Valid ratings are TV-Y, TV-Y7, TV-G, TV-PG, TV-14 and TV-MA"
+    )]
+    #[arg(
+        long_help = "Skip episodes and series which have a higher maturity rating than the given one. \
+    Valid ratings are TV-Y, TV-Y7, TV-G, TV-PG, TV-14 and TV-MA. \
+    Media without a known rating is skipped if it's flagged as mature by Crunchyroll, unless the rating is TV-MA"
+    )]
+    #[arg(long, value_parser = MaxMaturity::parse)]
+    pub(crate) max_maturity: Option<MaxMaturity>,
+
+    #[arg(help = "Additionally write the subtitles next to the output file")]
+    #[arg(
+        long_help = "Additionally write the subtitles next to the output file, e.g. `video.en-US.ass` for `video.mkv`. \
+    The subtitles are still embedded into / burned into the video"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) also_sidecar_subs: bool,
+    #[arg(
+        help = "Naming convention of sidecar subtitles. Valid options are 'default', 'plex', 'jellyfin' and 'kodi'"
+    )]
+    #[arg(
+        long_help = "Naming convention of the subtitles written with `--also-sidecar-subs`. \
+    Valid options are 'default' (`video.en-US.ass`, closed captions as `video.en-US.cc.ass`), \
+    'plex' (`video.en.ass`, closed captions as `video.en.sdh.ass`), \
+    'jellyfin' (`video.en-US.ass`, closed captions as `video.en-US.sdh.ass`, the default subtitle as `video.en-US.default.ass`) \
+    and 'kodi' (`video.English.ass`, closed captions as `video.English.sdh.ass`)"
+    )]
+    #[arg(long, default_value = "default")]
+    #[arg(value_parser = SidecarNaming::parse)]
+    pub(crate) sidecar_naming: SidecarNaming,
+    #[arg(
+        help = "Format of the embedded and sidecar subtitles. Valid options are 'ass', 'srt' and 'vtt'"
+    )]
+    #[arg(
+        long_help = "Format of the embedded and sidecar subtitles. Valid options are 'ass', 'srt' and 'vtt'. \
+    Subtitles converted to 'srt' or 'vtt' lose their styling (fonts, colors, positioning), which is useful for players without ass support. \
+    Subtitles which are burned into the video are always kept as 'ass'"
+    )]
+    #[arg(long, default_value = "ass")]
+    #[arg(value_parser = SubtitleFormat::parse)]
+    pub(crate) subtitle_format: SubtitleFormat,
+
+    #[arg(help = "Only download the audios and write each of them as standalone file")]
+    #[arg(
+        long_help = "Only download the audios and write each of them as standalone file instead of generating a video. \
+    The audios are named after the output file with their locale added, e.g. `video.en-US.mka` for `video.mkv`. \
+    If the output file has the `.m4a` extension, the audios are written as `.m4a` too. Subtitles are not downloaded"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) extract_audio: bool,
+    #[arg(help = "Only download parts of the episode. Valid options are 'subtitles' and 'audio'")]
+    #[arg(
+        long_help = "Only download parts of the episode instead of the complete video. Valid options are 'subtitles' and 'audio'. \
+    With 'subtitles', no video or audio is downloaded and every subtitle (`-s`) is written next to the output file, named like sidecar subtitles (see `--sidecar-naming`). \
+    With 'audio', no video or subtitles are downloaded and the audios are written to the file given by `--output-audio`"
+    )]
+    #[arg(long)]
+    #[arg(value_parser = Only::parse)]
+    pub(crate) only: Option<Only>,
+
+    #[arg(
+        help = "Generate the output file in the temp directory and move it afterwards. Valid options are 'auto', 'always' and 'never'"
+    )]
+    #[arg(
+        long_help = "Generate the output file in the temp directory and move it to its destination afterwards. \
+    Valid options are 'auto', 'always' and 'never'. \
+    'auto' only does this if the destination is on a network share (SMB, NFS, ...), as ffmpeg writes very slowly to them"
+    )]
+    #[arg(long, default_value = "auto")]
+    #[arg(value_parser = LocalMux::parse)]
+    pub(crate) local_mux: LocalMux,
+
+    #[arg(
+        help = "Only re-generate output files whose ffmpeg run failed previously, without downloading anything again"
+    )]
+    #[arg(
+        long_help = "Only re-generate output files whose ffmpeg run failed previously, without downloading anything again. \
+    If ffmpeg fails (e.g. because it ran out of memory), all downloaded files of the episode are kept in the temp directory. \
+    With this flag, only ffmpeg is run again with these files. Episodes without kept files are skipped"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) remux_only: bool,
+
+    #[arg(
+        help = "Only list the episodes which would be downloaded. Valid options are 'text' and 'json'"
+    )]
+    #[arg(
+        long_help = "Only list the episodes which would be downloaded, without downloading them. \
+    Valid options are 'text' and 'json'. 'json' prints a tree of series, seasons and episodes \
+    (with ids, locales and durations) to stdout and hides all other output"
+    )]
+    #[arg(long, num_args = 0..=1, default_missing_value = "text")]
+    #[arg(value_parser = ListFormat::parse)]
+    pub(crate) list: Option<ListFormat>,
 
     #[arg(help = "Skip any interactive input")]
     #[arg(short, long, default_value_t = false)]
@@ -196,20 +614,156 @@ pub struct Archive {
     #[arg(short, long, default_value_t = num_cpus::get())]
     pub(crate) threads: usize,
 
-    #[arg(help = "Crunchyroll series url(s)")]
+    #[arg(
+        help = "Retry a segment download if no data was received for the given amount of seconds. 0 disables it"
+    )]
+    #[arg(
+        long_help = "Retry a segment download if no data was received for the given amount of seconds. \
+    Without this, a stalled segment download only fails after the request timeout of 60 seconds. \
+    0 disables the stall detection"
+    )]
+    #[arg(long, default_value_t = 15)]
+    pub(crate) stall_timeout: u64,
+    #[arg(
+        help = "Start downloads with fewer threads and increase them over the given amount of seconds. 0 disables it"
+    )]
+    #[arg(
+        long_help = "Start downloads with fewer threads and increase them over the given amount of seconds. \
+    Every stream download starts with one thread, the other threads are started evenly spread over the given time. \
+    Some ISPs or CDNs throttle connections which cause a lot of traffic right at the start, this may help to avoid it. \
+    0 disables the slow start"
+    )]
+    #[arg(long, default_value_t = 0)]
+    pub(crate) slow_start: u64,
+    #[arg(help = "Only download segments within the given local time window, e.g. '01:00-07:00'")]
+    #[arg(
+        long_help = "Only download segments within the given local time window, e.g. '01:00-07:00'. \
+    Outside of the window the download pauses and continues automatically when the window starts again. \
+    The window may span midnight (e.g. '22:00-06:00'). Useful for metered or shared connections"
+    )]
+    #[arg(long, value_parser = Schedule::parse)]
+    pub(crate) schedule: Option<Schedule>,
+    #[arg(
+        help = "Save the raw downloaded segments to the given directory, to replay them later with `--replay-segments`"
+    )]
+    #[arg(
+        long_help = "Save the raw downloaded segments with their checksums to the given directory. \
+    The segments can be replayed with `--replay-segments` on another run or machine, which reproduces muxing or syncing issues without downloading the streams again"
+    )]
+    #[arg(long, conflicts_with = "replay_segments")]
+    pub(crate) record_segments: Option<PathBuf>,
+    #[arg(
+        help = "Read the segments from a directory written by `--record-segments` instead of downloading them"
+    )]
+    #[arg(
+        long_help = "Read the segments from a directory written by `--record-segments` instead of downloading them. \
+    Every segment is checked against its recorded checksum. \
+    The metadata and subtitles are still fetched from Crunchyroll, so the same episodes with the same streams must be requested as when recording"
+    )]
+    #[arg(long)]
+    pub(crate) replay_segments: Option<PathBuf>,
+    #[arg(
+        help = "Abort if fetching the metadata of an url takes longer than the given amount of seconds. 0 disables it"
+    )]
+    #[arg(
+        long_help = "Abort if parsing an url or fetching the metadata of its episodes takes longer than the given amount of seconds. \
+    0 disables the timeout"
+    )]
+    #[arg(long, default_value_t = 0)]
+    pub(crate) metadata_timeout: u64,
+    #[arg(
+        help = "Abort an episode if its download takes longer than the given amount of seconds. 0 disables it"
+    )]
+    #[arg(
+        long_help = "Abort an episode if its download, including generating the output file, takes longer than the given amount of seconds. \
+    If multiple episodes are downloaded at the same time (`--concurrent-episodes`), only the episode is aborted, otherwise the whole command. \
+    0 disables the timeout"
+    )]
+    #[arg(long, default_value_t = 0)]
+    pub(crate) episode_timeout: u64,
+    #[arg(
+        help = "Abort if ffmpeg takes longer than the given amount of seconds to generate the output file. 0 disables it"
+    )]
+    #[arg(
+        long_help = "Abort if ffmpeg takes longer than the given amount of seconds to generate the output file. \
+    ffmpeg is killed in this case. 0 disables the timeout"
+    )]
+    #[arg(long, default_value_t = 0)]
+    pub(crate) mux_timeout: u64,
+
+    #[arg(
+        help = "Download the video in the next lower resolution if it can't be downloaded in the requested one"
+    )]
+    #[arg(
+        long_help = "Download the video in the next lower resolution if some of its segments persistently fail to download (e.g. because the server responds with 404 or 500) in the requested resolution. \
+    A warning is shown if this happens"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) resolution_fallback: bool,
+
+    #[arg(help = "Continue interrupted downloads instead of starting them again")]
+    #[arg(
+        long_help = "Continue interrupted downloads instead of starting them again. \
+    The progress of every video and audio download is stored next to the temporary files, if the same episode is downloaded again with this flag, already downloaded parts are skipped"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) resume: bool,
+
+    #[arg(
+        help = "Pass the video and audio directly to ffmpeg instead of storing them in temporary files first"
+    )]
+    #[arg(
+        long_help = "Pass the video and audio directly to ffmpeg instead of storing them in temporary files first. \
+    This roughly halves the required disk space, but a failed episode can't be retried with `--remux-only` and `--resolution-fallback` has no effect. \
+    Audios which are used to sync videos or to search the intro and ending are still stored temporarily. Not supported on Windows"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) low_disk: bool,
+
+    #[arg(help = "Number of episodes which are downloaded at the same time")]
+    #[arg(
+        long_help = "Number of episodes which are downloaded at the same time. \
+    Every episode uses the threads set by `--threads`. \
+    If more than one episode is downloaded at the same time, a failed episode doesn't abort the other downloads, the command fails after all episodes are processed instead"
+    )]
+    #[arg(long, default_value_t = 1)]
+    pub(crate) concurrent_episodes: usize,
+
+    #[arg(help = "Crunchyroll series url(s) or id(s)")]
     #[arg(required = true)]
     pub(crate) urls: Vec<String>,
 }
 
 impl Execute for Archive {
     fn pre_check(&mut self) -> Result<()> {
-        if !has_ffmpeg() {
+        if self.only == Some(Only::Audio) {
+            if !AUDIO_ONLY_CONTAINERS.contains(
+                &Path::new(&self.output_audio)
+                    .extension()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .as_ref(),
+            ) {
+                bail!(
+                    "File extension of `--output-audio` is not supported. Valid extensions are {}",
+                    AUDIO_ONLY_CONTAINERS
+                        .map(|e| format!("'.{}'", e))
+                        .join(", ")
+                )
+            }
+            // the audio file is generated via the regular output path
+            self.output.clone_from(&self.output_audio);
+            self.output_specials = None
+        }
+
+        if !has_ffmpeg() && self.only != Some(Only::Subtitles) {
             bail!("FFmpeg is needed to run this command")
-        } else if PathBuf::from(&self.output)
-            .extension()
-            .unwrap_or_default()
-            .to_string_lossy()
-            != "mkv"
+        } else if self.only != Some(Only::Audio)
+            && PathBuf::from(&self.output)
+                .extension()
+                .unwrap_or_default()
+                .to_string_lossy()
+                != "mkv"
             && !is_special_file(&self.output)
             && self.output != "-"
         {
@@ -233,6 +787,155 @@ impl Execute for Archive {
         {
             bail!("`--include-chapters` can only be used if `--merge` is set to 'audio' or 'sync'")
         }
+        if let Some(replay_segments) = &self.replay_segments {
+            if !replay_segments.is_dir() {
+                bail!(
+                    "`--replay-segments` directory {} does not exist",
+                    replay_segments.to_string_lossy()
+                )
+            }
+        }
+
+        if self.low_disk {
+            if cfg!(target_os = "windows") {
+                bail!("`--low-disk` is not supported on Windows")
+            }
+            if self.resume {
+                bail!("`--low-disk` cannot be used together with `--resume`")
+            }
+        }
+        if (self.ffmpeg_crf.is_some() || self.ffmpeg_bitrate.is_some())
+            && !matches!(self.ffmpeg_preset, Some(FFmpegPreset::Predefined(..)))
+        {
+            bail!("`--ffmpeg-crf` and `--ffmpeg-bitrate` can only be used with a predefined `--ffmpeg-preset`")
+        }
+        let rate_control = FFmpegRateControl {
+            crf: self.ffmpeg_crf,
+            bitrate: self.ffmpeg_bitrate.clone(),
+        };
+        self.ffmpeg_preset = self.ffmpeg_preset.take().map(move |preset| {
+            preset
+                .with_rate_control(rate_control)
+                .with_available_encoder()
+        });
+        if let Some(ffmpeg_preset) = &self.ffmpeg_preset {
+            ffmpeg_preset.check_encoders()?
+        }
+        if self.ffmpeg_two_pass
+            && !self
+                .ffmpeg_preset
+                .as_ref()
+                .is_some_and(FFmpegPreset::supports_two_pass)
+        {
+            bail!("`--ffmpeg-two-pass` requires an `--ffmpeg-preset` which encodes the video with a target bitrate (`--ffmpeg-bitrate` or `-b:v`) and a software encoder (libx264, libx265, libvpx, libvpx-vp9 or libaom-av1)")
+        }
+
+        if self.concurrent_episodes == 0 {
+            bail!("`--concurrent-episodes` must be at least 1")
+        } else if self.concurrent_episodes > 1
+            && (is_special_file(&self.output) || self.output == "-")
+        {
+            bail!("`--concurrent-episodes` cannot be used if the output is written to stdout or a special file")
+        }
+        if self.clip.is_some() && matches!(self.merge, MergeBehavior::Sync) {
+            bail!("`--clip` can't be used with `--merge sync`")
+        }
+        if self.sync_plot && !matches!(self.merge, MergeBehavior::Sync) {
+            bail!("`--sync-plot` can only be used if `--merge` is set to 'sync'")
+        }
+        if self.sync_window.is_some() && !matches!(self.merge, MergeBehavior::Sync) {
+            bail!("`--sync-window` can only be used if `--merge` is set to 'sync'")
+        }
+        if self.only.is_some() {
+            if self.extract_audio || self.sync_reference.is_some() {
+                bail!(
+                    "`--only` cannot be used together with `--extract-audio` or `--sync-reference`"
+                )
+            }
+            if is_special_file(&self.output) || self.output == "-" {
+                bail!(
+                    "`--only` cannot be used if the output is written to stdout or a special file"
+                )
+            }
+        }
+        if self.extract_audio {
+            if self.sync_reference.is_some() {
+                bail!("`--extract-audio` and `--sync-reference` cannot be used together")
+            }
+            if is_special_file(&self.output) || self.output == "-" {
+                bail!("`--extract-audio` cannot be used if the output is written to stdout or a special file")
+            }
+        }
+        if let Some(sync_reference) = &self.sync_reference {
+            if !matches!(self.merge, MergeBehavior::Sync) {
+                bail!("`--sync-reference` can only be used if `--merge` is set to 'sync'")
+            }
+            if !sync_reference.is_file() {
+                bail!(
+                    "Sync reference {} does not exist or is not a file",
+                    sync_reference.to_string_lossy()
+                )
+            }
+            if is_special_file(&self.output) || self.output == "-" {
+                bail!("`--sync-reference` cannot be used if the output is written to stdout or a special file")
+            }
+        }
+        if let Some(output_dir) = &self.output_dir {
+            if output_dir.is_file() {
+                bail!(
+                    "`--output-dir` {} is a file, not a directory",
+                    output_dir.to_string_lossy()
+                )
+            }
+        }
+        if self.write_nfo && (self.output == "-" || is_special_file(&self.output)) {
+            bail!("`--write-nfo` can only be used if the output is written to a file")
+        }
+        if self.write_skip_file.is_some() && (self.output == "-" || is_special_file(&self.output)) {
+            bail!("`--write-skip-file` can only be used if the output is written to a file")
+        }
+        if !self.cut.is_empty() && (self.output == "-" || is_special_file(&self.output)) {
+            bail!("`--cut` can only be used if the output is written to a file")
+        }
+        if self.max_total_size.is_some() && (self.output == "-" || is_special_file(&self.output)) {
+            bail!("`--max-total-size` can only be used if the output is written to a file")
+        }
+        for (flag, command) in [
+            ("--exec", &self.exec),
+            ("--exec-on-error", &self.exec_on_error),
+        ] {
+            if command
+                .as_ref()
+                .is_some_and(|c| matches!(shlex::split(c).as_deref(), None | Some([])))
+            {
+                bail!("`{}` is not a valid command", flag)
+            }
+        }
+        if self.write_thumbnail && (self.output == "-" || is_special_file(&self.output)) {
+            bail!("`--write-thumbnail` can only be used if the output is written to a file")
+        }
+        if self.metadata_locale.is_some() && !self.include_chapters {
+            bail!("`--metadata-locale` can only be used in combination with `--include-chapters`")
+        }
+        if self.write_chapters.is_some() && !self.include_chapters {
+            bail!("`--write-chapters` can only be used in combination with `--include-chapters`")
+        }
+        if self.write_chapters.is_some() && (self.output == "-" || is_special_file(&self.output)) {
+            bail!("`--write-chapters` can only be used if the output is written to a file")
+        }
+        if self.write_chapters == Some(ChapterFileFormat::Edl)
+            && matches!(
+                self.write_skip_file,
+                Some(SkipFileFormat::Edl | SkipFileFormat::Kodi)
+            )
+        {
+            bail!("`--write-chapters edl` and `--write-skip-file edl` / `--write-skip-file kodi` can't be used together as both write to the same file")
+        }
+        if self.synthesize_chapters && !self.include_chapters {
+            bail!(
+                "`--synthesize-chapters` can only be used in combination with `--include-chapters`"
+            )
+        }
 
         if !self.skip_existing_method.is_empty() && !self.skip_existing {
             warn!("`--skip-existing-method` has no effect if `--skip-existing` is not set")
@@ -273,7 +976,13 @@ impl Execute for Archive {
 
         for (i, url) in self.urls.clone().into_iter().enumerate() {
             let progress_handler = progress!("Parsing url {}", i + 1);
-            match parse_url(&ctx.crunchy, url.clone(), true).await {
+            match with_stage_timeout(
+                "Parsing the url",
+                seconds_to_timeout(self.metadata_timeout),
+                parse_url(&ctx.crunchy, url.clone(), true),
+            )
+            .await
+            {
                 Ok((media_collection, url_filter)) => {
                     progress_handler.stop(format!("Parsed url {}", i + 1));
                     parsed_urls.push((media_collection, url_filter))
@@ -282,16 +991,28 @@ impl Execute for Archive {
             };
         }
 
+        let mut download_archive = self
+            .download_archive
+            .as_deref()
+            .map(DownloadArchive::open)
+            .transpose()?;
+        let mut failed_episodes = 0;
+        let size_budget = self.max_total_size.map(SizeBudget::new);
+        let mut json_output = vec![];
         for (i, (media_collection, url_filter)) in parsed_urls.into_iter().enumerate() {
             let progress_handler = progress!("Fetching series details");
-            let single_format_collection = ArchiveFilter::new(
-                url_filter,
-                self.clone(),
-                !self.yes,
-                self.skip_specials,
-                ctx.crunchy.premium().await,
+            let single_format_collection = with_stage_timeout(
+                "Fetching series details",
+                seconds_to_timeout(self.metadata_timeout),
+                ArchiveFilter::new(
+                    url_filter,
+                    self.clone(),
+                    !self.yes,
+                    self.skip_specials,
+                    ctx.crunchy.premium().await,
+                )
+                .visit(media_collection),
             )
-            .visit(media_collection)
             .await?;
 
             if single_format_collection.is_empty() {
@@ -300,13 +1021,27 @@ impl Execute for Archive {
             }
             progress_handler.stop(format!("Loaded series information for url {}", i + 1));
 
-            single_format_collection.full_visual_output();
+            match &self.list {
+                Some(ListFormat::Json) => {
+                    json_output.extend(single_format_collection.json_output().await);
+                    continue;
+                }
+                Some(ListFormat::Text) => {
+                    single_format_collection.full_visual_output();
+                    continue;
+                }
+                None => single_format_collection.full_visual_output(),
+            }
 
             let download_builder =
                 DownloadBuilder::new(ctx.client.clone(), ctx.rate_limiter.clone())
+                    .proxy_pool(ctx.proxy_pool.clone())
                     .default_subtitle(self.default_subtitle.clone())
                     .download_fonts(self.include_fonts)
+                    .font_fallback(self.font_fallback.clone())
+                    .rewrite_font_fallback(self.rewrite_font_fallback)
                     .ffmpeg_preset(self.ffmpeg_preset.clone().unwrap_or_default())
+                    .ffmpeg_two_pass(self.ffmpeg_two_pass)
                     .ffmpeg_threads(self.ffmpeg_threads)
                     .output_format(Some("matroska".to_string()))
                     .audio_sort(Some(self.audio.clone()))
@@ -320,7 +1055,35 @@ impl Execute for Archive {
                         MergeBehavior::Sync => Some(self.merge_sync_precision),
                         _ => None,
                     })
+                    .sync_window(self.sync_window.clone())
+                    .sync_plot(self.sync_plot)
+                    .sync_reference(self.sync_reference.clone())
+                    .extract_audio(self.extract_audio)
+                    .fingerprint_cache(self.include_chapters.then(SeasonFingerprintCache::default))
+                    .synthesize_chapters(self.synthesize_chapters)
+                    .metadata_locale(self.metadata_locale.clone())
+                    .track_statistics(self.track_statistics)
+                    .write_chapters(self.write_chapters)
+                    .clip(self.clip)
+                    .remux_only(self.remux_only)
+                    .resolution_fallback(self.resolution_fallback)
+                    .resume(self.resume)
+                    .low_disk(self.low_disk)
+                    .sidecar_subtitles(self.also_sidecar_subs)
+                    .sidecar_naming(self.sidecar_naming.clone())
+                    .subtitle_format(self.subtitle_format)
+                    .local_mux(self.local_mux.clone())
                     .threads(self.threads)
+                    .stall_timeout(seconds_to_timeout(self.stall_timeout))
+                    .slow_start(seconds_to_timeout(self.slow_start))
+                    .schedule(self.schedule)
+                    .segment_recording(
+                        self.record_segments
+                            .clone()
+                            .map(SegmentRecording::Record)
+                            .or_else(|| self.replay_segments.clone().map(SegmentRecording::Replay)),
+                    )
+                    .mux_timeout(seconds_to_timeout(self.mux_timeout))
                     .audio_locale_output_map(
                         zip(self.audio.clone(), self.output_audio_locales.clone()).collect(),
                     )
@@ -328,108 +1091,137 @@ impl Execute for Archive {
                         zip(self.subtitle.clone(), self.output_subtitle_locales.clone()).collect(),
                     );
 
-            for single_formats in single_format_collection.into_iter() {
-                let (download_formats, mut format) = get_format(&self, &single_formats).await?;
+            let title_cleanup =
+                TitleCleanup::new(self.clean_titles, self.clean_titles_pattern.clone());
 
-                let mut downloader = download_builder.clone().build();
-                for download_format in download_formats {
-                    downloader.add_format(download_format)
-                }
+            let single_format_collection: Vec<Vec<SingleFormat>> = single_format_collection
+                .into_ordered_iter(self.episode_order)
+                .filter(|single_formats| {
+                    let recorded = download_archive.as_ref().is_some_and(|a| {
+                        single_formats
+                            .iter()
+                            .all(|f| a.contains(&f.identifier))
+                    });
+                    if recorded {
+                        debug!(
+                            "Skipping episode '{}' as it's already recorded in the download archive",
+                            single_formats[0].title
+                        );
+                    }
+                    !recorded
+                })
+                .collect();
 
-                let formatted_path = if format.is_special() {
-                    format.format_path(
-                        self.output_specials
-                            .as_ref()
-                            .map_or((&self.output).into(), |so| so.into()),
-                        self.universal_output,
-                        self.language_tagging.as_ref(),
-                    )
-                } else {
-                    format.format_path(
-                        (&self.output).into(),
-                        self.universal_output,
-                        self.language_tagging.as_ref(),
+            let single_format_collection = match &size_budget {
+                Some(size_budget) => {
+                    let estimated = self.estimate_sizes(single_format_collection).await;
+                    size_budget.trim(
+                        estimated,
+                        |single_formats| single_formats[0].title.clone(),
+                        !self.yes,
                     )
-                };
-                let (mut path, changed) = free_file(formatted_path.clone());
-
-                if changed && self.skip_existing {
-                    let mut skip = true;
+                }
+                None => single_format_collection
+                    .into_iter()
+                    .map(|s| (s, 0))
+                    .collect(),
+            };
 
-                    if !self.skip_existing_method.is_empty() {
-                        if let Some((audio_locales, subtitle_locales)) =
-                            get_video_streams(&formatted_path)?
+            let overall_progress = OverallProgress::new(single_format_collection.len());
+            let archive = &self;
+            let size_budget = &size_budget;
+            let mut episodes = futures_util::stream::iter(single_format_collection)
+                .map(|(single_formats, estimated_size)| {
+                    let download_builder = &download_builder;
+                    let title_cleanup = &title_cleanup;
+                    let client = &ctx.client;
+                    async move {
+                        if size_budget
+                            .as_ref()
+                            .is_some_and(|budget| !budget.reserve(estimated_size))
                         {
-                            let method_audio = self
-                                .skip_existing_method
-                                .contains(&SkipExistingMethod::Audio);
-                            let method_subtitle = self
-                                .skip_existing_method
-                                .contains(&SkipExistingMethod::Subtitle);
-
-                            let audio_differ = if method_audio {
-                                format
-                                    .locales
-                                    .iter()
-                                    .any(|(a, _)| !audio_locales.contains(a))
-                            } else {
-                                false
-                            };
-                            let subtitle_differ = if method_subtitle {
-                                format
-                                    .locales
-                                    .clone()
-                                    .into_iter()
-                                    .flat_map(|(a, mut s)| {
-                                        // remove the closed caption if the flag is given to omit
-                                        // closed captions
-                                        if self.no_closed_caption && a != Locale::ja_JP {
-                                            s.retain(|l| l != &a)
-                                        }
-                                        s
-                                    })
-                                    .any(|l| !subtitle_locales.contains(&l))
-                            } else {
-                                false
-                            };
-
-                            if (method_audio && audio_differ)
-                                || (method_subtitle && subtitle_differ)
-                            {
-                                skip = false;
-                                path.clone_from(&formatted_path)
+                            warn!(
+                                "Skipping episode '{}' as it would exceed the size budget",
+                                single_formats[0].title
+                            );
+                            return (single_formats, Ok(None));
+                        }
+                        if archive.wait_for_release {
+                            for single_format in &single_formats {
+                                single_format.wait_for_release().await
                             }
                         }
+                        set_terminal_title_episode(&single_formats[0].title);
+                        let start = Instant::now();
+                        let result = with_stage_timeout(
+                            &format!("Downloading episode '{}'", single_formats[0].title),
+                            seconds_to_timeout(archive.episode_timeout),
+                            archive.download_episode(
+                                &single_formats,
+                                download_builder,
+                                title_cleanup,
+                            ),
+                        )
+                        .await;
+                        if let Some(size_budget) = size_budget {
+                            let size = result
+                                .as_ref()
+                                .ok()
+                                .and_then(|path| path.as_ref())
+                                .and_then(|path| fs::metadata(path).ok())
+                                .map_or(0, |metadata| metadata.len());
+                            size_budget.settle(estimated_size, size)
+                        }
+                        if let Some(notify_webhook_url) = &archive.notify_webhook {
+                            notify_webhook(
+                                client,
+                                notify_webhook_url,
+                                &single_formats[0],
+                                &result,
+                                start.elapsed(),
+                            )
+                            .await
+                        }
+                        (single_formats, result)
                     }
-
-                    if skip {
-                        debug!(
-                            "Skipping already existing file '{}'",
-                            formatted_path.to_string_lossy()
+                })
+                .buffer_unordered(self.concurrent_episodes);
+            let mut done = 0;
+            while let Some((single_formats, result)) = episodes.next().await {
+                done += 1;
+                overall_progress.set_position(done);
+                match result {
+                    Ok(Some(_)) => {
+                        if let Some(download_archive) = &mut download_archive {
+                            for single_format in &single_formats {
+                                download_archive.record(&single_format.identifier)?
+                            }
+                        }
+                    }
+                    Ok(None) => (),
+                    // if episodes are downloaded concurrently, a failed episode must not abort
+                    // the other running downloads
+                    Err(e) if self.concurrent_episodes > 1 => {
+                        error!(
+                            "Failed to download episode '{}': {}",
+                            single_formats[0].title, e
                         );
-                        continue;
+                        failed_episodes += 1
                     }
+                    Err(e) => return Err(e),
                 }
+            }
+        }
 
-                format.locales.sort_by(|(a, _), (b, _)| {
-                    self.audio
-                        .iter()
-                        .position(|l| l == a)
-                        .cmp(&self.audio.iter().position(|l| l == b))
-                });
-                for (_, subtitles) in format.locales.iter_mut() {
-                    subtitles.sort_by(|a, b| {
-                        self.subtitle
-                            .iter()
-                            .position(|l| l == a)
-                            .cmp(&self.subtitle.iter().position(|l| l == b))
-                    })
-                }
-
-                format.visual_output(&path);
+        if let Some(size_budget) = &size_budget {
+            info!("Downloaded {} in total", format_size(size_budget.used()))
+        }
+        if failed_episodes > 0 {
+            bail!("{} episode(s) failed to download", failed_episodes)
+        }
 
-                downloader.download(&path).await?
-            }
+        if self.list == Some(ListFormat::Json) {
+            println!("{}", serde_json::to_string_pretty(&json_output)?)
         }
 
         Ok(())
@@ -466,17 +1258,277 @@ impl SkipExistingMethod {
     }
 }
 
+impl Archive {
+    /// Downloads a single episode in all its versions. Returns the output path, or `None` if the episode was skipped.
+    async fn download_episode(
+        &self,
+        single_formats: &[SingleFormat],
+        download_builder: &DownloadBuilder,
+        title_cleanup: &TitleCleanup,
+    ) -> Result<Option<PathBuf>> {
+        if self.only == Some(Only::Subtitles) {
+            return self
+                .download_subtitles(single_formats, download_builder, title_cleanup)
+                .await;
+        }
+
+        let (download_formats, mut format) = get_format(self, single_formats).await?;
+        format.clean_titles(title_cleanup);
+        if self.specials_season_zero {
+            format.move_special_to_season_zero()
+        }
+
+        let mut downloader = download_builder
+            .clone()
+            .content_rating(self.embed_rating.then(|| format.rating()).flatten())
+            .cut(format.skip_events.as_ref().map_or(vec![], |skip_events| {
+                skip_event_ranges(skip_events, &self.cut)
+            }))
+            .episode_id(Some(format.episode_id.clone()))
+            .cover_art(single_formats[0].cover_art_url(self.cover_art).await?)
+            .thumbnail(if self.write_thumbnail {
+                single_formats[0].cover_art_url(CoverArt::Episode).await?
+            } else {
+                None
+            })
+            .build();
+        for download_format in download_formats {
+            downloader.add_format(download_format)
+        }
+
+        let formatted_path = self.formatted_path(&format);
+        let (mut path, changed) = free_file(formatted_path.clone());
+
+        if changed && self.skip_existing {
+            let mut skip = true;
+
+            if !self.skip_existing_method.is_empty() {
+                if let Some((audio_locales, subtitle_locales)) = get_video_streams(&formatted_path)?
+                {
+                    let method_audio = self
+                        .skip_existing_method
+                        .contains(&SkipExistingMethod::Audio);
+                    let method_subtitle = self
+                        .skip_existing_method
+                        .contains(&SkipExistingMethod::Subtitle);
+
+                    let audio_differ = if method_audio {
+                        format
+                            .locales
+                            .iter()
+                            .any(|(a, _)| !audio_locales.contains(a))
+                    } else {
+                        false
+                    };
+                    let subtitle_differ = if method_subtitle {
+                        format
+                            .locales
+                            .clone()
+                            .into_iter()
+                            .flat_map(|(a, mut s)| {
+                                // remove the closed caption if the flag is given to omit
+                                // closed captions
+                                if self.no_closed_caption && a != Locale::ja_JP {
+                                    s.retain(|l| l != &a)
+                                }
+                                s
+                            })
+                            .any(|l| !subtitle_locales.contains(&l))
+                    } else {
+                        false
+                    };
+
+                    if (method_audio && audio_differ) || (method_subtitle && subtitle_differ) {
+                        skip = false;
+                        path.clone_from(&formatted_path)
+                    }
+                }
+            }
+
+            if skip {
+                debug!(
+                    "Skipping already existing file '{}'",
+                    formatted_path.to_string_lossy()
+                );
+                return Ok(None);
+            }
+        }
+
+        format.locales.sort_by(|(a, _), (b, _)| {
+            self.audio
+                .iter()
+                .position(|l| l == a)
+                .cmp(&self.audio.iter().position(|l| l == b))
+        });
+        for (_, subtitles) in format.locales.iter_mut() {
+            subtitles.sort_by(|a, b| {
+                self.subtitle
+                    .iter()
+                    .position(|l| l == a)
+                    .cmp(&self.subtitle.iter().position(|l| l == b))
+            })
+        }
+
+        format.visual_output(&path);
+
+        let result = if self.only == Some(Only::Audio) {
+            downloader.download_audio_only(&path).await
+        } else {
+            downloader.download(&path).await
+        };
+        if let (Err(e), Some(exec_on_error)) = (&result, &self.exec_on_error) {
+            if let Err(exec_error) = run_exec(exec_on_error, &format, &path, Some(e)).await {
+                warn!("Failed to run `--exec-on-error` command: {}", exec_error)
+            }
+        }
+        result?;
+        if self.write_nfo {
+            write_nfo(&path, &format, single_formats[0].is_episode())?
+        }
+        if let (Some(skip_file), Some(skip_events)) = (self.write_skip_file, &format.skip_events) {
+            write_skip_file(&path, skip_events, skip_file)?
+        }
+        if let Some(exec) = &self.exec {
+            if let Err(e) = run_exec(exec, &format, &path, None).await {
+                bail!("Failed to run `--exec` command: {}", e)
+            }
+        }
+        Ok(Some(path))
+    }
+
+    /// Downloads only the subtitles of all versions of an episode, without requesting any stream
+    /// data.
+    async fn download_subtitles(
+        &self,
+        single_formats: &[SingleFormat],
+        download_builder: &DownloadBuilder,
+        title_cleanup: &TitleCleanup,
+    ) -> Result<Option<PathBuf>> {
+        let mut format_pairs = vec![];
+        let mut subtitles: Vec<(Subtitle, bool)> = vec![];
+        for single_format in single_formats {
+            let stream = single_format.stream().await?;
+            let stream_subtitles = find_subtitles(self, single_format, &stream);
+            stream.invalidate().await?;
+
+            // every version of an episode usually has the same subtitles, so each one is only
+            // written once
+            for (subtitle, cc) in &stream_subtitles {
+                if !subtitles
+                    .iter()
+                    .any(|(s, c)| s.locale == subtitle.locale && c == cc)
+                {
+                    subtitles.push((subtitle.clone(), *cc))
+                }
+            }
+            format_pairs.push((single_format.clone(), stream_subtitles))
+        }
+
+        let mut format = Format::from_single_formats_without_stream(format_pairs);
+        if self.needs_skip_events() {
+            format.skip_events = single_formats[0].skip_events().await?
+        }
+        format.clean_titles(title_cleanup);
+        if self.specials_season_zero {
+            format.move_special_to_season_zero()
+        }
+        let path = self.formatted_path(&format);
+
+        format.visual_output(&path);
+
+        download_builder
+            .clone()
+            .build()
+            .download_subtitles(&path, &subtitles)
+            .await?;
+        Ok(Some(path))
+    }
+
+    /// The estimated size of every episode with all its versions. Episodes whose size can't be
+    /// estimated count as 0, the download fails for them anyway.
+    async fn estimate_sizes(
+        &self,
+        single_format_collection: Vec<Vec<SingleFormat>>,
+    ) -> Vec<(Vec<SingleFormat>, u64)> {
+        let _progress_handler = progress!("Estimating the size of the episodes");
+        let mut estimated = vec![];
+        for single_formats in single_format_collection {
+            let size = if self.only == Some(Only::Subtitles) {
+                0
+            } else {
+                match get_format(self, &single_formats).await {
+                    Ok((download_formats, _)) => download_formats
+                        .iter()
+                        .map(|f| f.estimated_size(self.only == Some(Only::Audio)))
+                        .sum(),
+                    Err(e) => {
+                        debug!(
+                            "Failed to estimate the size of episode '{}': {}",
+                            single_formats[0].title, e
+                        );
+                        0
+                    }
+                }
+            };
+            estimated.push((single_formats, size))
+        }
+        estimated
+    }
+
+    /// If the skip events are needed, either for `--write-skip-file` and `--cut` or because the
+    /// output or `--exec` templates contain variables which need them.
+    fn needs_skip_events(&self) -> bool {
+        self.write_skip_file.is_some()
+            || !self.cut.is_empty()
+            || [
+                Some(&self.output),
+                self.output_specials.as_ref(),
+                self.exec.as_ref(),
+                self.exec_on_error.as_ref(),
+            ]
+            .into_iter()
+            .flatten()
+            .any(|template| uses_skip_event_variables(template))
+    }
+
+    fn formatted_path(&self, format: &Format) -> PathBuf {
+        let path = if format.is_special() {
+            format.format_path(
+                self.output_specials
+                    .as_ref()
+                    .map_or((&self.output).into(), |so| so.into()),
+                self.universal_output,
+                self.language_tagging.as_ref(),
+                self.max_path_length,
+            )
+        } else {
+            format.format_path(
+                (&self.output).into(),
+                self.universal_output,
+                self.language_tagging.as_ref(),
+                self.max_path_length,
+            )
+        };
+        match &self.output_dir {
+            Some(output_dir) if !is_special_file(&path) && path.to_string_lossy() != "-" => {
+                output_dir.join(path)
+            }
+            _ => path,
+        }
+    }
+}
+
 async fn get_format(
     archive: &Archive,
-    single_formats: &Vec<SingleFormat>,
+    single_formats: &[SingleFormat],
 ) -> Result<(Vec<DownloadFormat>, Format)> {
     let mut format_pairs = vec![];
     let mut single_format_to_format_pairs = vec![];
 
     for single_format in single_formats {
         let stream = single_format.stream().await?;
-        let Some((video, audio, _)) =
-            stream_data_from_stream(&stream, &archive.resolution, None).await?
+        let Some((video, audio, _, video_fallbacks)) =
+            stream_data_from_stream(&stream, &archive.resolution, archive.codec, None).await?
         else {
             if single_format.is_episode() {
                 bail!(
@@ -497,44 +1549,42 @@ async fn get_format(
             }
         };
 
-        let subtitles: Vec<(Subtitle, bool)> = archive
-            .subtitle
-            .iter()
-            .flat_map(|s| {
-                let mut subtitles = vec![];
-                if let Some(caption) = stream.captions.get(s) {
-                    subtitles.push((caption.clone(), true))
-                }
-                if let Some(subtitle) = stream.subtitles.get(s) {
-                    // the subtitle is probably cc if the audio is not japanese or only one subtitle
-                    // exists for this stream
-                    let cc = single_format.audio != Locale::ja_JP && stream.subtitles.len() == 1;
-                    // only include the subtitles if no cc subtitle is already present or if it's
-                    // not cc
-                    if subtitles.is_empty() || !cc {
-                        subtitles.push((subtitle.clone(), cc))
-                    }
-                }
-                subtitles
-            })
-            .collect();
+        let subtitles = find_subtitles(archive, single_format, &stream);
 
-        format_pairs.push((single_format, video.clone(), audio, subtitles.clone()));
+        format_pairs.push((
+            single_format,
+            video.clone(),
+            audio,
+            subtitles.clone(),
+            video_fallbacks,
+        ));
         single_format_to_format_pairs.push((single_format.clone(), video, subtitles));
 
         stream.invalidate().await?
     }
 
+    let skip_events = match format_pairs.first() {
+        Some((single_format, ..)) if archive.needs_skip_events() => {
+            single_format.skip_events().await?
+        }
+        _ => None,
+    };
     let mut download_formats = vec![];
 
     match archive.merge {
         MergeBehavior::Video => {
-            for (single_format, video, audio, subtitles) in format_pairs {
+            for (single_format, video, audio, subtitles, video_fallbacks) in format_pairs {
                 download_formats.push(DownloadFormat {
                     video: (video, single_format.audio.clone()),
+                    video_fallbacks,
                     audios: vec![(audio, single_format.audio.clone())],
                     subtitles,
-                    metadata: DownloadFormatMetadata { skip_events: None },
+                    metadata: DownloadFormatMetadata {
+                        episode_id: single_format.episode_id.clone(),
+                        hardsub: None,
+                        skip_events: None,
+                        season_id: None,
+                    },
                 })
             }
         }
@@ -543,28 +1593,50 @@ async fn get_format(
                 format_pairs.first().unwrap().1.clone(),
                 format_pairs.first().unwrap().0.audio.clone(),
             ),
+            video_fallbacks: format_pairs.first().unwrap().4.clone(),
             audios: format_pairs
                 .iter()
-                .map(|(single_format, _, audio, _)| (audio.clone(), single_format.audio.clone()))
+                .map(|(single_format, _, audio, _, _)| (audio.clone(), single_format.audio.clone()))
                 .collect(),
             // mix all subtitles together and then reduce them via a map so that only one subtitle
             // per language exists
             subtitles: format_pairs
                 .iter()
-                .flat_map(|(_, _, _, subtitles)| subtitles.clone())
+                .flat_map(|(_, _, _, subtitles, _)| subtitles.clone())
                 .collect(),
             metadata: DownloadFormatMetadata {
+                episode_id: format_pairs.first().unwrap().0.episode_id.clone(),
+                hardsub: None,
                 skip_events: if archive.include_chapters {
                     format_pairs.first().unwrap().0.skip_events().await?
                 } else {
                     None
                 },
+                season_id: format_pairs
+                    .first()
+                    .unwrap()
+                    .0
+                    .is_episode()
+                    .then(|| format_pairs.first().unwrap().0.season_id.clone()),
             },
         }),
         MergeBehavior::Auto | MergeBehavior::Sync => {
             let mut d_formats: Vec<(Duration, DownloadFormat)> = vec![];
 
-            for (single_format, video, audio, subtitles) in format_pairs {
+            for (single_format, video, audio, subtitles, video_fallbacks) in format_pairs {
+                // if the video is identical to the video of another format, it doesn't matter how
+                // far apart the durations are, the video only has to be downloaded once
+                if let Some((_, identical_format)) = d_formats
+                    .iter_mut()
+                    .find(|(_, f)| is_same_stream(&f.video.0, &video))
+                {
+                    identical_format
+                        .audios
+                        .push((audio, single_format.audio.clone()));
+                    identical_format.subtitles.extend(subtitles);
+                    continue;
+                }
+
                 let closest_format = d_formats.iter_mut().min_by(|(x, _), (y, _)| {
                     x.sub(single_format.duration)
                         .abs()
@@ -592,14 +1664,20 @@ async fn get_format(
                             single_format.duration,
                             DownloadFormat {
                                 video: (video, single_format.audio.clone()),
+                                video_fallbacks,
                                 audios: vec![(audio, single_format.audio.clone())],
                                 subtitles,
                                 metadata: DownloadFormatMetadata {
+                                    episode_id: single_format.episode_id.clone(),
+                                    hardsub: None,
                                     skip_events: if archive.include_chapters {
                                         single_format.skip_events().await?
                                     } else {
                                         None
                                     },
+                                    season_id: single_format
+                                        .is_episode()
+                                        .then(|| single_format.season_id.clone()),
                                 },
                             },
                         ));
@@ -613,10 +1691,39 @@ async fn get_format(
         }
     }
 
-    Ok((
-        download_formats,
-        Format::from_single_formats(single_format_to_format_pairs),
-    ))
+    let mut format = Format::from_single_formats(single_format_to_format_pairs);
+    format.skip_events = skip_events;
+
+    Ok((download_formats, format))
+}
+
+/// The requested subtitles of the stream and whether they are closed captions.
+fn find_subtitles(
+    archive: &Archive,
+    single_format: &SingleFormat,
+    stream: &Stream,
+) -> Vec<(Subtitle, bool)> {
+    archive
+        .subtitle
+        .iter()
+        .flat_map(|s| {
+            let mut subtitles = vec![];
+            if let Some(caption) = stream.captions.get(s) {
+                subtitles.push((caption.clone(), true))
+            }
+            if let Some(subtitle) = stream.subtitles.get(s) {
+                // the subtitle is probably cc if the audio is not japanese or only one subtitle
+                // exists for this stream
+                let cc = single_format.audio != Locale::ja_JP && stream.subtitles.len() == 1;
+                // only include the subtitles if no cc subtitle is already present or if it's
+                // not cc
+                if subtitles.is_empty() || !cc {
+                    subtitles.push((subtitle.clone(), cc))
+                }
+            }
+            subtitles
+        })
+        .collect()
 }
 
 fn get_video_streams(path: &Path) -> Result<Option<(Vec<Locale>, Vec<Locale>)>> {
@@ -624,7 +1731,7 @@ fn get_video_streams(path: &Path) -> Result<Option<(Vec<Locale>, Vec<Locale>)>>
         Regex::new(r"(?m)Stream\s#\d+:\d+\((?P<language>.+)\):\s(?P<type>(Audio|Subtitle))")
             .unwrap();
 
-    let ffmpeg = Command::new("ffmpeg")
+    let ffmpeg = Command::new(ffmpeg_binary())
         .stdout(Stdio::null())
         .stderr(Stdio::piped())
         .arg("-hide_banner")
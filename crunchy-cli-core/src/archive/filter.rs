@@ -1,5 +1,5 @@
 use crate::archive::command::Archive;
-use crate::utils::filter::{real_dedup_vec, Filter};
+use crate::utils::filter::{is_too_mature, real_dedup_vec, Filter};
 use crate::utils::format::{Format, SingleFormat, SingleFormatCollection};
 use crate::utils::interactive_select::{check_for_duplicated_seasons, get_duplicated_seasons};
 use crate::utils::parse::{fract, UrlFilter};
@@ -54,6 +54,16 @@ impl Filter for ArchiveFilter {
     type Output = SingleFormatCollection;
 
     async fn visit_series(&mut self, series: Series) -> Result<Vec<Season>> {
+        if is_too_mature(
+            self.archive.max_maturity.as_ref(),
+            "series",
+            &series.title,
+            &series.maturity_ratings,
+            series.is_mature,
+        ) {
+            return Ok(vec![]);
+        }
+
         // `series.audio_locales` isn't always populated b/c of crunchyrolls api. so check if the
         // audio is matching only if the field is populated
         if !series.audio_locales.is_empty() {
@@ -253,6 +263,15 @@ impl Filter for ArchiveFilter {
         {
             return Ok(None);
         }
+        if is_too_mature(
+            self.archive.max_maturity.as_ref(),
+            "episode",
+            &episode.title,
+            &episode.maturity_ratings,
+            episode.is_mature,
+        ) {
+            return Ok(None);
+        }
 
         // skip the episode if it's a special
         if self.skip_special
@@ -314,7 +333,8 @@ impl Filter for ArchiveFilter {
             episodes.push((episode.clone(), episode.subtitle_locales.clone()))
         }
 
-        if self.seasons_with_premium.is_some() {
+        // premium episodes are only listed (and marked as unavailable) if nothing is downloaded
+        if self.seasons_with_premium.is_some() && self.archive.list.is_none() {
             let episode_len_before = episodes.len();
             episodes.retain(|(e, _)| !e.is_premium_only);
             if episode_len_before < episodes.len()
@@ -379,34 +399,98 @@ impl Filter for ArchiveFilter {
             }
         }
 
-        Ok(Some(
-            episodes
-                .into_iter()
-                .map(|(e, s)| {
-                    SingleFormat::new_from_episode(
-                        e,
-                        s,
-                        relative_episode_number.map(|n| n as u32),
-                        relative_sequence_number,
-                    )
-                })
-                .collect(),
-        ))
+        let mut single_formats: Vec<SingleFormat> = episodes
+            .into_iter()
+            .map(|(e, s)| {
+                let unavailable = self.seasons_with_premium.is_some() && e.is_premium_only;
+                let mut single_format = SingleFormat::new_from_episode(
+                    e,
+                    s,
+                    relative_episode_number.map(|n| n as u32),
+                    relative_sequence_number,
+                );
+                single_format.unavailable = unavailable;
+                single_format
+            })
+            .collect();
+        // take blank titles and descriptions from the other versions which are downloaded anyway
+        // before requesting them
+        let title = single_formats
+            .iter()
+            .map(|f| f.title.clone())
+            .find(|t| !t.trim().is_empty());
+        let description = single_formats
+            .iter()
+            .map(|f| f.description.clone())
+            .find(|d| !d.trim().is_empty());
+        for single_format in single_formats.iter_mut() {
+            if let Some(title) = title
+                .as_ref()
+                .filter(|_| single_format.title.trim().is_empty())
+            {
+                single_format.title = title.clone()
+            }
+            if let Some(description) = description
+                .as_ref()
+                .filter(|_| single_format.description.trim().is_empty())
+            {
+                single_format.description = description.clone()
+            }
+            single_format.fill_blank_metadata().await
+        }
+
+        Ok(Some(single_formats))
     }
 
     async fn visit_movie_listing(&mut self, movie_listing: MovieListing) -> Result<Vec<Movie>> {
+        if is_too_mature(
+            self.archive.max_maturity.as_ref(),
+            "movie listing",
+            &movie_listing.title,
+            &movie_listing.maturity_ratings,
+            movie_listing.is_mature,
+        ) {
+            return Ok(vec![]);
+        }
         Ok(movie_listing.movies().await?)
     }
 
     async fn visit_movie(&mut self, movie: Movie) -> Result<Option<Self::T>> {
+        if is_too_mature(
+            self.archive.max_maturity.as_ref(),
+            "movie",
+            &movie.title,
+            &movie.maturity_ratings,
+            movie.is_mature,
+        ) {
+            return Ok(None);
+        }
         Ok(Some(vec![SingleFormat::new_from_movie(movie, vec![])]))
     }
 
     async fn visit_music_video(&mut self, music_video: MusicVideo) -> Result<Option<Self::T>> {
+        if is_too_mature(
+            self.archive.max_maturity.as_ref(),
+            "music video",
+            &music_video.title,
+            &[],
+            music_video.is_mature,
+        ) {
+            return Ok(None);
+        }
         Ok(Some(vec![SingleFormat::new_from_music_video(music_video)]))
     }
 
     async fn visit_concert(&mut self, concert: Concert) -> Result<Option<Self::T>> {
+        if is_too_mature(
+            self.archive.max_maturity.as_ref(),
+            "concert",
+            &concert.title,
+            &[],
+            concert.is_mature,
+        ) {
+            return Ok(None);
+        }
         Ok(Some(vec![SingleFormat::new_from_concert(concert)]))
     }
 
@@ -0,0 +1,42 @@
+use crate::utils::config::{config_file_path, effective_config, load_config};
+use anyhow::Result;
+use clap::CommandFactory;
+
+#[derive(Debug, clap::Parser)]
+#[clap(about = "Print the configuration after merging the config file with the default values")]
+#[clap(
+    long_about = "Print the configuration after merging the config file with the default values. \
+        The config file is read from the crunchy-cli directory in your config directory (e.g. '~/.config/crunchy-cli/config.toml' on Linux)"
+)]
+pub struct Config {
+    #[arg(help = "Only print the path of the config file")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) path: bool,
+}
+
+impl Config {
+    pub fn run(&self) -> Result<()> {
+        let path = config_file_path();
+        if self.path {
+            match path {
+                Some(path) => println!("{}", path.to_string_lossy()),
+                None => anyhow::bail!("Cannot find config path"),
+            }
+            return Ok(());
+        }
+
+        if let Some(path) = &path {
+            println!(
+                "# {} ({})",
+                path.to_string_lossy(),
+                if path.exists() { "found" } else { "not found" }
+            )
+        }
+        let config = load_config()?.unwrap_or_default();
+        print!(
+            "{}",
+            toml::to_string(&effective_config(crate::Cli::command(), &config)?)?
+        );
+        Ok(())
+    }
+}
@@ -1,22 +1,44 @@
 use crate::download::filter::DownloadFilter;
+use crate::utils::chapter_file::ChapterFileFormat;
+use crate::utils::clip::Clip;
 use crate::utils::context::Context;
-use crate::utils::download::{DownloadBuilder, DownloadFormat, DownloadFormatMetadata};
-use crate::utils::ffmpeg::{FFmpegPreset, SOFTSUB_CONTAINERS};
-use crate::utils::filter::Filter;
-use crate::utils::format::{Format, SingleFormat};
+use crate::utils::download::{
+    CoverArt, DownloadBuilder, DownloadFormat, DownloadFormatMetadata, HardsubStyle, LocalMux,
+    Only, SidecarNaming, SubtitleFormat, AUDIO_ONLY_CONTAINERS,
+};
+use crate::utils::download_archive::DownloadArchive;
+use crate::utils::exec::run_exec;
+use crate::utils::ffmpeg::{FFmpegPreset, FFmpegRateControl, SOFTSUB_CONTAINERS};
+use crate::utils::filter::{Filter, MaxMaturity};
+use crate::utils::fmt::format_size;
+use crate::utils::format::{
+    uses_skip_event_variables, EpisodeOrder, Format, ListFormat, SingleFormat, TitleCleanup,
+};
 use crate::utils::locale::{resolve_locales, LanguageTagging};
-use crate::utils::log::progress;
+use crate::utils::log::{progress, set_terminal_title_episode, OverallProgress};
+use crate::utils::nfo::write_nfo;
+use crate::utils::notify::notify_webhook;
 use crate::utils::os::{free_file, has_ffmpeg, is_special_file};
-use crate::utils::parse::parse_url;
-use crate::utils::video::stream_data_from_stream;
+use crate::utils::parse::{parse_url, ResolutionSelector};
+use crate::utils::schedule::Schedule;
+use crate::utils::segment_recording::SegmentRecording;
+use crate::utils::size_budget::SizeBudget;
+use crate::utils::skip_file::{skip_event_ranges, write_skip_file, SkipEventKind, SkipFileFormat};
+use crate::utils::sync::SeasonFingerprintCache;
+use crate::utils::timeout::{seconds_to_timeout, with_stage_timeout};
+use crate::utils::video::{stream_data_from_stream, Codec};
 use crate::Execute;
 use anyhow::bail;
 use anyhow::Result;
-use crunchyroll_rs::media::Resolution;
+use crunchyroll_rs::media::{Stream, Subtitle};
 use crunchyroll_rs::Locale;
-use log::{debug, warn};
+use futures_util::StreamExt;
+use log::{debug, error, info, warn};
+use regex::Regex;
 use std::collections::HashMap;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 #[derive(Clone, Debug, clap::Parser)]
 #[clap(about = "Download a video")]
@@ -52,12 +74,15 @@ pub struct Download {
       {relative_episode_number}  → Number of the episode relative to its season\n  \
       {sequence_number}          → Like '{episode_number}' but without possible non-number characters\n  \
       {relative_sequence_number} → Like '{relative_episode_number}' but with support for episode 0's and .5's\n  \
+      {special_label}            → Label of specials (e.g. SP1 or OVA), empty for regular episodes\n  \
+      {rating}                   → Maturity rating of the video (e.g. TV-14)\n  \
       {release_year}             → Release year of the video\n  \
       {release_month}            → Release month of the video\n  \
       {release_day}              → Release day of the video\n  \
       {series_id}                → ID of the series\n  \
       {season_id}                → ID of the season\n  \
-      {episode_id}               → ID of the episode")]
+      {episode_id}               → ID of the episode\n\
+    Numbers are padded with zeros to 2 digits, use e.g. '{episode_number:3}' for another width")]
     #[arg(short, long, default_value = "{title}.mp4")]
     pub(crate) output: String,
     #[arg(help = "Name of the output file if the episode is a special")]
@@ -65,21 +90,192 @@ pub struct Download {
     If not set, the '-o'/'--output' flag will be used as name template")]
     #[arg(long)]
     pub(crate) output_specials: Option<String>,
+    #[arg(help = "Directory in which the output files are stored")]
+    #[arg(long_help = "Directory in which the output files are stored. \
+    Relative '-o'/'--output' and '--output-specials' paths are resolved relative to it, so the same template can be used with different directories. \
+    Absolute output paths, '-' and special files are used as they are")]
+    #[arg(long)]
+    pub(crate) output_dir: Option<PathBuf>,
+    #[arg(help = "Name of the output file if only the audio is downloaded (`--only audio`)")]
+    #[arg(
+        long_help = "Name of the output file if only the audio is downloaded (`--only audio`). \
+    Accepts the same template options as '-o'/'--output', which is ignored in this case. \
+    Valid extensions are '.m4a' and '.mka' (the audio is copied as is) and '.mp3' and '.flac' (the audio is transcoded)"
+    )]
+    #[arg(long, default_value = "{title}.m4a")]
+    pub(crate) output_audio: String,
+    #[arg(
+        help = "Put specials into season 0, numbered by their special label (e.g. SP3 → episode 3)"
+    )]
+    #[arg(
+        long_help = "Put specials into season 0, like media servers like Plex or Jellyfin expect them. \
+    The episode number is taken from the special label Crunchyroll uses (e.g. SP3 → episode 3), so the numbering may have gaps. \
+    This changes the '{season_number}' and '{episode_number}' template options of specials"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) specials_season_zero: bool,
+
+    #[arg(help = "Embed the maturity rating (e.g. TV-14) into the output file")]
+    #[arg(
+        long_help = "Embed the maturity rating (e.g. TV-14) as 'LAW_RATING' tag into the output file. \
+    Only supported by .mkv files. Independent of this flag, the rating is available as '{rating}' template option"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) embed_rating: bool,
+    #[arg(help = "Embed the episode thumbnail or series poster as cover art into the output file")]
+    #[arg(long_help = "Embed cover art into the output file. \
+    Valid options are 'episode' (thumbnail of the episode), 'series' (poster of the series) and 'none'. \
+    Only supported by .mkv (as attachment), .mp4 and .mov files (as attached picture). \
+    Movies use their own thumbnail or the poster of their movie listing")]
+    #[arg(long, default_value = "none")]
+    #[arg(value_parser = CoverArt::parse)]
+    pub(crate) cover_art: CoverArt,
+    #[arg(help = "Add track statistics tags (bitrate, duration, frame count) to .mkv files")]
+    #[arg(
+        long_help = "Add track statistics tags (BPS, DURATION, NUMBER_OF_FRAMES, NUMBER_OF_BYTES) to .mkv files, \
+    which media servers like Plex or Jellyfin use to show accurate bitrates. \
+    Requires mkvpropedit (MKVToolNix) to be installed"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) track_statistics: bool,
+    #[arg(help = "Write .nfo metadata files for media servers like Kodi or Jellyfin")]
+    #[arg(
+        long_help = "Write .nfo metadata files for media servers like Kodi or Jellyfin. \
+    Every episode gets a .nfo file next to it with its title, description, air date, season and episode number and id. \
+    Additionally a 'season.nfo' and 'tvshow.nfo' file is written to the season and series directory if they don't exist yet. \
+    The series directory is the parent directory if the episode directory is named like a season directory (e.g. 'Season 01')"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) write_nfo: bool,
+    #[arg(help = "Save the episode thumbnail next to the output file")]
+    #[arg(long_help = "Save the episode thumbnail next to the output file. \
+    The image has the same name as the output file, only the extension differs (e.g. 'episode.jpg' for 'episode.mkv')")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) write_thumbnail: bool,
+    #[arg(
+        help = "Write a file next to the output file which tells players to skip the intro, credits, recap and preview. Valid options are 'edl', 'kodi' and 'mpv'"
+    )]
+    #[arg(
+        long_help = "Write a file next to the output file which tells players to skip the intro, credits, recap and preview, for players which don't use the chapters. \
+    Valid options are 'edl' (MPlayer edl file, 'episode.edl' for 'episode.mkv'), 'kodi' (Kodi edl file which marks the parts as commercial breaks, 'episode.edl') \
+    and 'mpv' (mpv edl file which plays the video without the parts if opened instead of the video, 'episode.mpv.edl'). \
+    Nothing is written if Crunchyroll has no skip events for the episode"
+    )]
+    #[arg(long, value_parser = SkipFileFormat::parse)]
+    pub(crate) write_skip_file: Option<SkipFileFormat>,
+    #[arg(
+        help = "Write the chapters into a file next to the output file. Valid options are 'ffmetadata', 'mkv-xml', 'ogm' and 'edl'"
+    )]
+    #[arg(
+        long_help = "Write the chapters into a file next to the output file, additionally to embedding them. Requires `--include-chapters`. \
+    Valid options are 'ffmetadata' (ffmpeg metadata file, 'episode.ffmetadata' for 'episode.mkv'), 'mkv-xml' (matroska chapter xml, 'episode.chapters.xml'), \
+    'ogm' (OGM chapter file, 'episode.chapters.txt') and 'edl' (Kodi edl file which marks every chapter except the episode content as commercial break, 'episode.edl')"
+    )]
+    #[arg(long, value_parser = ChapterFileFormat::parse)]
+    pub(crate) write_chapters: Option<ChapterFileFormat>,
+    #[arg(
+        help = "Cut the given parts out of the output file. Valid options are 'intro', 'recap', 'preview' and 'credits'"
+    )]
+    #[arg(
+        long_help = "Cut the given parts out of the output file, using the skip events Crunchyroll provides for the episode. \
+    Valid options are 'intro', 'recap', 'preview' and 'credits', multiple can be given comma separated (e.g. 'intro,recap'). \
+    The streams aren't re-encoded, so every kept part starts at a keyframe and a few seconds of a cut part may remain before it. Requires ffprobe. \
+    Nothing is cut if Crunchyroll has no skip events for the episode"
+    )]
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = SkipEventKind::parse,
+        conflicts_with_all = ["include_chapters", "write_skip_file", "also_sidecar_subs"]
+    )]
+    pub(crate) cut: Vec<SkipEventKind>,
+    #[arg(help = "Only download the given time range of every episode, e.g. '00:05:30-00:08:00'")]
+    #[arg(
+        long_help = "Only download the given time range of every episode, e.g. '00:05:30-00:08:00'. \
+    Only the segments which overlap with the range are downloaded, the range is cut out precisely when generating the output file. \
+    With the default ffmpeg preset the streams aren't re-encoded, so the video starts at the keyframe before the range start. \
+    The times can be given as HH:MM:SS, MM:SS or seconds, with optional fractions of a second"
+    )]
+    #[arg(
+        long,
+        value_parser = Clip::parse,
+        conflicts_with_all = ["include_chapters", "cut", "write_skip_file", "resume"]
+    )]
+    pub(crate) clip: Option<Clip>,
+    #[arg(
+        help = "Send a POST request with details about every downloaded or failed episode to this url"
+    )]
+    #[arg(
+        long_help = "Send a POST request with details about every downloaded or failed episode to this url. \
+    The json body contains the series name, season and episode number, title, episode id, output path, duration, file size, download time and whether the download succeeded (with the error if not). \
+    Useful to integrate with chat or home automation services"
+    )]
+    #[arg(long)]
+    pub(crate) notify_webhook: Option<String>,
+    #[arg(help = "Run a command after every successfully downloaded episode")]
+    #[arg(
+        long_help = "Run a command after every successfully downloaded episode. \
+    Accepts the same template options as '-o'/'--output' and additionally '{path}' (the output file). \
+    The command is split into arguments like a shell would do it, but not run by a shell, so template options always stay one argument. \
+    If the command fails, the episode is treated as failed"
+    )]
+    #[arg(long)]
+    pub(crate) exec: Option<String>,
+    #[arg(help = "Run a command if the download of an episode failed")]
+    #[arg(long_help = "Run a command if the download of an episode failed. \
+    Accepts the same template options as '--exec' and additionally '{error}' (the error message)")]
+    #[arg(long)]
+    pub(crate) exec_on_error: Option<String>,
 
     #[arg(help = "Sanitize the output file for use with all operating systems. \
     This option only affects template options and not static characters.")]
     #[arg(long, default_value_t = false)]
     pub(crate) universal_output: bool,
 
+    #[arg(help = "Maximum length of the full output path")]
+    #[arg(
+        long_help = "Maximum length of the full output path, including the current directory if the path is relative. \
+    If the path is longer, the title is shortened first and then the file name (the extension is kept). \
+    Useful on Windows, which has a default maximum path length of 260 characters, or on network shares"
+    )]
+    #[arg(long)]
+    pub(crate) max_path_length: Option<usize>,
+
+    #[arg(
+        help = "Remove dub and split cour suffixes like '(English Dub)' or 'Part 2' from series and season names"
+    )]
+    #[arg(
+        long_help = "Remove dub and split cour suffixes like '(English Dub)' or 'Part 2' from series and season names. \
+    The cleaned names are used in the output template. \
+    Additional rules can be specified with `--clean-titles-pattern`"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) clean_titles: bool,
+    #[arg(
+        help = "Regex whose matches are removed from series and season names. Can be used multiple times"
+    )]
+    #[arg(long)]
+    pub(crate) clean_titles_pattern: Vec<Regex>,
+
     #[arg(help = "Video resolution")]
     #[arg(long_help = "The video resolution. \
     Can either be specified via the pixels (e.g. 1920x1080), the abbreviation for pixels (e.g. 1080p) or 'common-use' words (e.g. best). \
     Specifying the exact pixels is not recommended, use one of the other options instead. \
     Crunchyroll let you choose the quality with pixel abbreviation on their clients, so you might be already familiar with the available options. \
-    The available common-use words are 'best' (choose the best resolution available) and 'worst' (worst resolution available)")]
+    The available common-use words are 'best' (choose the best resolution available) and 'worst' (worst resolution available). \
+    Besides that, constraints like '<=1080p,>=720p' select the best resolution which fulfills all of them ('worst' can be added to the list to select the worst one instead). \
+    The long form 'best[height<=720]' / 'worst[width>=1280,height>=720]' constrains the width and height separately")]
     #[arg(short, long, default_value = "best")]
     #[arg(value_parser = crate::utils::clap::clap_parse_resolution)]
-    pub(crate) resolution: Resolution,
+    pub(crate) resolution: ResolutionSelector,
+    #[arg(help = "Preferred video codec. Valid options are 'h264', 'h265' and 'any'")]
+    #[arg(
+        long_help = "Preferred video codec. Valid options are 'h264', 'h265' and 'any'. \
+    Some videos are available in multiple codecs, h265 (hevc) videos are usually smaller than h264 ones with the same quality. \
+    If a video isn't available in the preferred codec, any other codec is used"
+    )]
+    #[arg(long, default_value = "any", value_parser = Codec::parse)]
+    pub(crate) codec: Codec,
 
     #[arg(
         long,
@@ -111,13 +307,87 @@ pub struct Download {
     )]
     #[arg(long)]
     pub(crate) ffmpeg_threads: Option<usize>,
+    #[arg(
+        help = "Constant rate factor of the video encoder, replaces the quality of the ffmpeg preset"
+    )]
+    #[arg(
+        long_help = "Constant rate factor of the video encoder, between 0 and 63. Lower values mean better quality and bigger files. \
+    Replaces the quality of the `--ffmpeg-preset` and is translated to the quality option of hardware encoders which don't support crf. \
+    Only works with predefined presets, set the quality directly in custom ffmpeg output arguments instead"
+    )]
+    #[arg(long, value_parser = crate::utils::clap::clap_parse_crf)]
+    pub(crate) ffmpeg_crf: Option<u8>,
+    #[arg(
+        help = "Target bitrate of the video encoder (e.g. '2500k'), replaces the quality of the ffmpeg preset"
+    )]
+    #[arg(
+        long_help = "Target bitrate of the video encoder, e.g. '2500k' or '2.5M'. \
+    Replaces the quality of the `--ffmpeg-preset`. \
+    Only works with predefined presets, set the bitrate directly in custom ffmpeg output arguments instead"
+    )]
+    #[arg(long, conflicts_with = "ffmpeg_crf", value_parser = crate::utils::clap::clap_parse_bitrate)]
+    pub(crate) ffmpeg_bitrate: Option<String>,
+    #[arg(
+        help = "Encode the video in two passes to hit the bitrate of the ffmpeg preset more precisely"
+    )]
+    #[arg(long_help = "Encode the video in two passes. \
+    The first pass analyses the video, the second one uses the analysis to distribute the bitrate better and to hit the target bitrate more precisely. \
+    Requires an `--ffmpeg-preset` which encodes the video with a target bitrate (`--ffmpeg-bitrate` or `-b:v`) and a software encoder (libx264, libx265, libvpx, libvpx-vp9 or libaom-av1). \
+    Takes about twice as long as a normal encode")]
+    #[arg(long, default_value_t = false, conflicts_with = "low_disk")]
+    pub(crate) ffmpeg_two_pass: bool,
 
     #[arg(help = "Skip files which are already existing by their name")]
     #[arg(long, default_value_t = false)]
     pub(crate) skip_existing: bool,
+    #[arg(
+        help = "Record downloaded episodes in the given file and skip episodes which are already recorded"
+    )]
+    #[arg(
+        long_help = "Record the identifier of every successfully downloaded episode in the given file and skip episodes which are already recorded in it. \
+    Unlike `--skip-existing`, this also works if the downloaded files were moved or renamed"
+    )]
+    #[arg(long)]
+    pub(crate) download_archive: Option<PathBuf>,
     #[arg(help = "Skip special episodes")]
     #[arg(long, default_value_t = false)]
     pub(crate) skip_specials: bool,
+    #[arg(help = "Wait for announced episodes which aren't released yet and download them then")]
+    #[arg(
+        long_help = "Wait for announced episodes which aren't released yet (e.g. weekly simulcasts with a premiere countdown) until they are available and download them then. \
+    Without this flag, downloading such episodes fails"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) wait_for_release: bool,
+    #[arg(
+        help = "Order in which the episodes are downloaded. Valid options are 'sequence', 'air' and 'alphabetical'"
+    )]
+    #[arg(
+        long_help = "Order in which the episodes are downloaded. Valid options are 'sequence' (by season and episode number), \
+    'air' (by the date the episodes first aired, across all seasons, e.g. for specials which aired between regular episodes) and 'alphabetical' (by episode title)"
+    )]
+    #[arg(long, default_value = "sequence")]
+    #[arg(value_parser = EpisodeOrder::parse)]
+    pub(crate) episode_order: EpisodeOrder,
+    #[arg(help = "Only download as many episodes as fit into the given size, e.g. '200G'")]
+    #[arg(
+        long_help = "Only download as many episodes as fit into the given size, e.g. '500M' or '200G'. \
+    The size of every episode is estimated before the download starts, episodes which don't fit are skipped (or can be chosen interactively). \
+    While downloading, episodes which would exceed the size because the estimates were too low are skipped too"
+    )]
+    #[arg(long, value_parser = crate::utils::clap::clap_parse_size)]
+    pub(crate) max_total_size: Option<u64>,
+
+    #[arg(
+        help = "Skip episodes and series which have a higher maturity rating than the given one. Valid ratings are TV-Y, TV-Y7, TV-G, TV-PG, TV-14 and TV-MA"
+    )]
+    #[arg(
+        long_help = "Skip episodes and series which have a higher maturity rating than the given one. \
+    Valid ratings are TV-Y, TV-Y7, TV-G, TV-PG, TV-14 and TV-MA. \
+    Media without a known rating is skipped if it's flagged as mature by Crunchyroll, unless the rating is TV-MA"
+    )]
+    #[arg(long, value_parser = MaxMaturity::parse)]
+    pub(crate) max_maturity: Option<MaxMaturity>,
 
     #[arg(help = "Includes chapters (e.g. intro, credits, ...)")]
     #[arg(long_help = "Includes chapters (e.g. intro, credits, ...). \
@@ -126,6 +396,112 @@ pub struct Download {
     Also chapters aren't always available, so in this case, just a big 'Episode' chapter from start to end will be created")]
     #[arg(long, default_value_t = false)]
     pub(crate) include_chapters: bool,
+    #[arg(
+        help = "Detect intro and credits chapters via audio fingerprinting if Crunchyroll provides no chapter information. Only works with `--include-chapters`"
+    )]
+    #[arg(
+        long_help = "Detect intro and credits chapters via audio fingerprinting if Crunchyroll provides no chapter information. Only works with `--include-chapters`. \
+    The audio of every episode is compared with the previous episode of the same season, parts which occur in both are used as intro and credits. \
+    The first episode of a season therefore never gets synthesized chapters"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) synthesize_chapters: bool,
+    #[arg(help = "Language of the chapter titles. Only works with `--include-chapters`")]
+    #[arg(
+        long_help = "Language of the chapter titles. Only works with `--include-chapters`. \
+    The chapter titles (e.g. 'Intro' or 'Credits') are translated if the language is supported, otherwise they stay english. \
+    Chapters of .mkv files are additionally tagged with the language, which requires mkvpropedit (part of MKVToolNix) to be installed"
+    )]
+    #[arg(long)]
+    pub(crate) metadata_locale: Option<Locale>,
+
+    #[arg(help = "Additionally write the subtitles next to the output file")]
+    #[arg(
+        long_help = "Additionally write the subtitles next to the output file, e.g. `video.en-US.ass` for `video.mkv`. \
+    The subtitles are still embedded into / burned into the video"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) also_sidecar_subs: bool,
+    #[arg(
+        help = "Naming convention of sidecar subtitles. Valid options are 'default', 'plex', 'jellyfin' and 'kodi'"
+    )]
+    #[arg(
+        long_help = "Naming convention of the subtitles written with `--also-sidecar-subs`. \
+    Valid options are 'default' (`video.en-US.ass`, closed captions as `video.en-US.cc.ass`), \
+    'plex' (`video.en.ass`, closed captions as `video.en.sdh.ass`), \
+    'jellyfin' (`video.en-US.ass`, closed captions as `video.en-US.sdh.ass`, the default subtitle as `video.en-US.default.ass`) \
+    and 'kodi' (`video.English.ass`, closed captions as `video.English.sdh.ass`)"
+    )]
+    #[arg(long, default_value = "default")]
+    #[arg(value_parser = SidecarNaming::parse)]
+    pub(crate) sidecar_naming: SidecarNaming,
+    #[arg(
+        help = "Save the fonts which the subtitles use into a directory next to the output file"
+    )]
+    #[arg(
+        long_help = "Save the fonts which the subtitles use into a directory next to the output file, so that players can render ass subtitles correctly if the container can't include fonts (e.g. .mp4 and .mov). \
+    The directory is relative to the directory of the output file, default is 'fonts'. \
+    Useful together with `--also-sidecar-subs`, players like mpv can be pointed to the directory with `--sub-fonts-dir`. \
+    Has no effect if the subtitles are burned into the video or aren't ass subtitles"
+    )]
+    #[arg(long, num_args = 0..=1, default_missing_value = "fonts")]
+    pub(crate) fonts_dir: Option<PathBuf>,
+    #[arg(
+        help = "Format of the embedded and sidecar subtitles. Valid options are 'ass', 'srt' and 'vtt'"
+    )]
+    #[arg(
+        long_help = "Format of the embedded and sidecar subtitles. Valid options are 'ass', 'srt' and 'vtt'. \
+    Subtitles converted to 'srt' or 'vtt' lose their styling (fonts, colors, positioning), which is useful for players without ass support. \
+    Subtitles which are burned into the video are always kept as 'ass'"
+    )]
+    #[arg(long, default_value = "ass")]
+    #[arg(value_parser = SubtitleFormat::parse)]
+    pub(crate) subtitle_format: SubtitleFormat,
+
+    #[arg(
+        help = "Generate the output file in the temp directory and move it afterwards. Valid options are 'auto', 'always' and 'never'"
+    )]
+    #[arg(
+        long_help = "Generate the output file in the temp directory and move it to its destination afterwards. \
+    Valid options are 'auto', 'always' and 'never'. \
+    'auto' only does this if the destination is on a network share (SMB, NFS, ...), as ffmpeg writes very slowly to them"
+    )]
+    #[arg(long, default_value = "auto")]
+    #[arg(value_parser = LocalMux::parse)]
+    pub(crate) local_mux: LocalMux,
+
+    #[arg(
+        help = "Only re-generate output files whose ffmpeg run failed previously, without downloading anything again"
+    )]
+    #[arg(
+        long_help = "Only re-generate output files whose ffmpeg run failed previously, without downloading anything again. \
+    If ffmpeg fails (e.g. because it ran out of memory), all downloaded files of the episode are kept in the temp directory. \
+    With this flag, only ffmpeg is run again with these files. Episodes without kept files are skipped"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) remux_only: bool,
+
+    #[arg(help = "Only download parts of the episode. Valid options are 'subtitles' and 'audio'")]
+    #[arg(
+        long_help = "Only download parts of the episode instead of the complete video. Valid options are 'subtitles' and 'audio'. \
+    With 'subtitles', no video or audio is downloaded and the subtitle (`-s`) is written next to the output file, named like sidecar subtitles (see `--sidecar-naming`). \
+    With 'audio', no video or subtitles are downloaded and the audio is written to the file given by `--output-audio`"
+    )]
+    #[arg(long)]
+    #[arg(value_parser = Only::parse)]
+    pub(crate) only: Option<Only>,
+
+    #[arg(
+        help = "Only list the episodes which would be downloaded. Valid options are 'text' and 'json'"
+    )]
+    #[arg(
+        long_help = "Only list the episodes which would be downloaded, without downloading them. \
+    Valid options are 'text' and 'json'. 'json' prints a tree of series, seasons and episodes \
+    (with ids, locales and durations) to stdout and hides all other output"
+    )]
+    #[arg(long, num_args = 0..=1, default_missing_value = "text")]
+    #[arg(value_parser = ListFormat::parse)]
+    pub(crate) list: Option<ListFormat>,
 
     #[arg(help = "Skip any interactive input")]
     #[arg(short, long, default_value_t = false)]
@@ -134,19 +510,175 @@ pub struct Download {
     #[arg(help = "Force subtitles to be always burnt-in")]
     #[arg(long, default_value_t = false)]
     pub(crate) force_hardsub: bool,
+    #[arg(
+        help = "Override the style of burned-in subtitles, e.g. 'Fontname=Arial,Fontsize=28,Outline=2'"
+    )]
+    #[arg(
+        long_help = "Override the style of burned-in subtitles, e.g. 'Fontname=Arial,Fontsize=28,Outline=2'. \
+    The given fields of every ass style of the subtitle are replaced before the subtitle is burned into the video. \
+    Valid fields are the ones of ass styles, e.g. 'Fontname', 'Fontsize', 'PrimaryColour' (in ass format, '&H00FFFFFF'), 'Bold', 'Outline', 'Shadow' and 'MarginV'. \
+    Only has an effect if the subtitles are burned into the video, i.e. with `--force-hardsub` or a container which doesn't support softsubs"
+    )]
+    #[arg(long, value_parser = HardsubStyle::parse)]
+    pub(crate) hardsub_style: Option<HardsubStyle>,
+    #[arg(
+        help = "Burn a second subtitle at the top of the video, additionally to the one given with `-s`"
+    )]
+    #[arg(
+        long_help = "Burn a second subtitle at the top of the video, additionally to the one given with `-s` which stays at the bottom, e.g. for language learners. \
+    Only supported if the subtitles are burned into the video, i.e. with `--force-hardsub` or a container which doesn't support softsubs. \
+    Subtitle lines which are explicitly positioned by the subtitle keep their position"
+    )]
+    #[arg(long, requires = "subtitle")]
+    pub(crate) secondary_subtitle: Option<Locale>,
 
     #[arg(help = "The number of threads used to download")]
     #[arg(short, long, default_value_t = num_cpus::get())]
     pub(crate) threads: usize,
 
-    #[arg(help = "Url(s) to Crunchyroll episodes or series")]
+    #[arg(
+        help = "Retry a segment download if no data was received for the given amount of seconds. 0 disables it"
+    )]
+    #[arg(
+        long_help = "Retry a segment download if no data was received for the given amount of seconds. \
+    Without this, a stalled segment download only fails after the request timeout of 60 seconds. \
+    0 disables the stall detection"
+    )]
+    #[arg(long, default_value_t = 15)]
+    pub(crate) stall_timeout: u64,
+    #[arg(
+        help = "Start downloads with fewer threads and increase them over the given amount of seconds. 0 disables it"
+    )]
+    #[arg(
+        long_help = "Start downloads with fewer threads and increase them over the given amount of seconds. \
+    Every stream download starts with one thread, the other threads are started evenly spread over the given time. \
+    Some ISPs or CDNs throttle connections which cause a lot of traffic right at the start, this may help to avoid it. \
+    0 disables the slow start"
+    )]
+    #[arg(long, default_value_t = 0)]
+    pub(crate) slow_start: u64,
+    #[arg(help = "Only download segments within the given local time window, e.g. '01:00-07:00'")]
+    #[arg(
+        long_help = "Only download segments within the given local time window, e.g. '01:00-07:00'. \
+    Outside of the window the download pauses and continues automatically when the window starts again. \
+    The window may span midnight (e.g. '22:00-06:00'). Useful for metered or shared connections"
+    )]
+    #[arg(long, value_parser = Schedule::parse)]
+    pub(crate) schedule: Option<Schedule>,
+    #[arg(
+        help = "Save the raw downloaded segments to the given directory, to replay them later with `--replay-segments`"
+    )]
+    #[arg(
+        long_help = "Save the raw downloaded segments with their checksums to the given directory. \
+    The segments can be replayed with `--replay-segments` on another run or machine, which reproduces muxing or syncing issues without downloading the streams again"
+    )]
+    #[arg(long, conflicts_with = "replay_segments")]
+    pub(crate) record_segments: Option<PathBuf>,
+    #[arg(
+        help = "Read the segments from a directory written by `--record-segments` instead of downloading them"
+    )]
+    #[arg(
+        long_help = "Read the segments from a directory written by `--record-segments` instead of downloading them. \
+    Every segment is checked against its recorded checksum. \
+    The metadata and subtitles are still fetched from Crunchyroll, so the same episodes with the same streams must be requested as when recording"
+    )]
+    #[arg(long)]
+    pub(crate) replay_segments: Option<PathBuf>,
+    #[arg(
+        help = "Abort if fetching the metadata of an url takes longer than the given amount of seconds. 0 disables it"
+    )]
+    #[arg(
+        long_help = "Abort if parsing an url or fetching the metadata of its episodes takes longer than the given amount of seconds. \
+    0 disables the timeout"
+    )]
+    #[arg(long, default_value_t = 0)]
+    pub(crate) metadata_timeout: u64,
+    #[arg(
+        help = "Abort an episode if its download takes longer than the given amount of seconds. 0 disables it"
+    )]
+    #[arg(
+        long_help = "Abort an episode if its download, including generating the output file, takes longer than the given amount of seconds. \
+    If multiple episodes are downloaded at the same time (`--concurrent-episodes`), only the episode is aborted, otherwise the whole command. \
+    0 disables the timeout"
+    )]
+    #[arg(long, default_value_t = 0)]
+    pub(crate) episode_timeout: u64,
+    #[arg(
+        help = "Abort if ffmpeg takes longer than the given amount of seconds to generate the output file. 0 disables it"
+    )]
+    #[arg(
+        long_help = "Abort if ffmpeg takes longer than the given amount of seconds to generate the output file. \
+    ffmpeg is killed in this case. 0 disables the timeout"
+    )]
+    #[arg(long, default_value_t = 0)]
+    pub(crate) mux_timeout: u64,
+
+    #[arg(
+        help = "Download the video in the next lower resolution if it can't be downloaded in the requested one"
+    )]
+    #[arg(
+        long_help = "Download the video in the next lower resolution if some of its segments persistently fail to download (e.g. because the server responds with 404 or 500) in the requested resolution. \
+    A warning is shown if this happens"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) resolution_fallback: bool,
+
+    #[arg(help = "Continue interrupted downloads instead of starting them again")]
+    #[arg(
+        long_help = "Continue interrupted downloads instead of starting them again. \
+    The progress of every video and audio download is stored next to the temporary files, if the same episode is downloaded again with this flag, already downloaded parts are skipped"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) resume: bool,
+
+    #[arg(
+        help = "Pass the video and audio directly to ffmpeg instead of storing them in temporary files first"
+    )]
+    #[arg(
+        long_help = "Pass the video and audio directly to ffmpeg instead of storing them in temporary files first. \
+    This roughly halves the required disk space, but a failed episode can't be retried with `--remux-only` and `--resolution-fallback` has no effect. \
+    If `--include-chapters` is used, the audio is still stored temporarily. Not supported on Windows"
+    )]
+    #[arg(long, default_value_t = false)]
+    pub(crate) low_disk: bool,
+
+    #[arg(help = "Number of episodes which are downloaded at the same time")]
+    #[arg(
+        long_help = "Number of episodes which are downloaded at the same time. \
+    Every episode uses the threads set by `--threads`. \
+    If more than one episode is downloaded at the same time, a failed episode doesn't abort the other downloads, the command fails after all episodes are processed instead"
+    )]
+    #[arg(long, default_value_t = 1)]
+    pub(crate) concurrent_episodes: usize,
+
+    #[arg(help = "Url(s) or id(s) of Crunchyroll episodes or series")]
     #[arg(required = true)]
     pub(crate) urls: Vec<String>,
 }
 
 impl Execute for Download {
     fn pre_check(&mut self) -> Result<()> {
-        if !has_ffmpeg() {
+        if self.only == Some(Only::Audio) {
+            if !AUDIO_ONLY_CONTAINERS.contains(
+                &Path::new(&self.output_audio)
+                    .extension()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .as_ref(),
+            ) {
+                bail!(
+                    "File extension of `--output-audio` is not supported. Valid extensions are {}",
+                    AUDIO_ONLY_CONTAINERS
+                        .map(|e| format!("'.{}'", e))
+                        .join(", ")
+                )
+            }
+            // the audio file is generated via the regular output path
+            self.output.clone_from(&self.output_audio);
+            self.output_specials = None
+        }
+
+        if !has_ffmpeg() && self.only != Some(Only::Subtitles) {
             bail!("FFmpeg is needed to run this command")
         } else if Path::new(&self.output)
             .extension()
@@ -158,6 +690,66 @@ impl Execute for Download {
             bail!("No file extension found. Please specify a file extension (via `-o`) for the output file")
         }
 
+        if let Some(output_dir) = &self.output_dir {
+            if output_dir.is_file() {
+                bail!(
+                    "`--output-dir` {} is a file, not a directory",
+                    output_dir.to_string_lossy()
+                )
+            }
+        }
+        if self.write_nfo && (self.output == "-" || is_special_file(&self.output)) {
+            bail!("`--write-nfo` can only be used if the output is written to a file")
+        }
+        if self.write_skip_file.is_some() && (self.output == "-" || is_special_file(&self.output)) {
+            bail!("`--write-skip-file` can only be used if the output is written to a file")
+        }
+        if !self.cut.is_empty() && (self.output == "-" || is_special_file(&self.output)) {
+            bail!("`--cut` can only be used if the output is written to a file")
+        }
+        if self.max_total_size.is_some() && (self.output == "-" || is_special_file(&self.output)) {
+            bail!("`--max-total-size` can only be used if the output is written to a file")
+        }
+        for (flag, command) in [
+            ("--exec", &self.exec),
+            ("--exec-on-error", &self.exec_on_error),
+        ] {
+            if command
+                .as_ref()
+                .is_some_and(|c| matches!(shlex::split(c).as_deref(), None | Some([])))
+            {
+                bail!("`{}` is not a valid command", flag)
+            }
+        }
+        if self.fonts_dir.is_some() && (self.output == "-" || is_special_file(&self.output)) {
+            bail!("`--fonts-dir` can only be used if the output is written to a file")
+        }
+        if self.write_thumbnail && (self.output == "-" || is_special_file(&self.output)) {
+            bail!("`--write-thumbnail` can only be used if the output is written to a file")
+        }
+        if self.metadata_locale.is_some() && !self.include_chapters {
+            bail!("`--metadata-locale` can only be used in combination with `--include-chapters`")
+        }
+        if self.write_chapters.is_some() && !self.include_chapters {
+            bail!("`--write-chapters` can only be used in combination with `--include-chapters`")
+        }
+        if self.write_chapters.is_some() && (self.output == "-" || is_special_file(&self.output)) {
+            bail!("`--write-chapters` can only be used if the output is written to a file")
+        }
+        if self.write_chapters == Some(ChapterFileFormat::Edl)
+            && matches!(
+                self.write_skip_file,
+                Some(SkipFileFormat::Edl | SkipFileFormat::Kodi)
+            )
+        {
+            bail!("`--write-chapters edl` and `--write-skip-file edl` / `--write-skip-file kodi` can't be used together as both write to the same file")
+        }
+        if self.synthesize_chapters && !self.include_chapters {
+            bail!(
+                "`--synthesize-chapters` can only be used in combination with `--include-chapters`"
+            )
+        }
+
         if self.subtitle.is_some() {
             if let Some(ext) = Path::new(&self.output).extension() {
                 if self.force_hardsub {
@@ -168,6 +760,30 @@ impl Execute for Download {
             }
         }
 
+        if let Some(secondary_subtitle) = &self.secondary_subtitle {
+            if self.subtitle.as_ref() == Some(secondary_subtitle) {
+                bail!("`--secondary-subtitle` must differ from the subtitle language (`-s`)")
+            }
+            let supports_softsubs = |output: &str| {
+                ["mkv", "mov", "mp4"].contains(
+                    &Path::new(output)
+                        .extension()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .as_ref(),
+                )
+            };
+            if !self.force_hardsub
+                && (supports_softsubs(&self.output)
+                    || self
+                        .output_specials
+                        .as_deref()
+                        .is_some_and(supports_softsubs))
+            {
+                bail!("`--secondary-subtitle` can only be used if the subtitles are burned into the video (`--force-hardsub` or a container which doesn't support softsubs)")
+            }
+        }
+
         if let Some(special_output) = &self.output_specials {
             if Path::new(special_output)
                 .extension()
@@ -187,6 +803,64 @@ impl Execute for Download {
             }
         }
 
+        if self.only == Some(Only::Subtitles) && self.subtitle.is_none() {
+            bail!("`--only subtitles` requires a subtitle language (`-s`)")
+        }
+        if self.only.is_some() && (is_special_file(&self.output) || self.output == "-") {
+            bail!("`--only` cannot be used if the output is written to stdout or a special file")
+        }
+
+        if let Some(replay_segments) = &self.replay_segments {
+            if !replay_segments.is_dir() {
+                bail!(
+                    "`--replay-segments` directory {} does not exist",
+                    replay_segments.to_string_lossy()
+                )
+            }
+        }
+
+        if self.low_disk {
+            if cfg!(target_os = "windows") {
+                bail!("`--low-disk` is not supported on Windows")
+            }
+            if self.resume {
+                bail!("`--low-disk` cannot be used together with `--resume`")
+            }
+        }
+        if (self.ffmpeg_crf.is_some() || self.ffmpeg_bitrate.is_some())
+            && !matches!(self.ffmpeg_preset, Some(FFmpegPreset::Predefined(..)))
+        {
+            bail!("`--ffmpeg-crf` and `--ffmpeg-bitrate` can only be used with a predefined `--ffmpeg-preset`")
+        }
+        let rate_control = FFmpegRateControl {
+            crf: self.ffmpeg_crf,
+            bitrate: self.ffmpeg_bitrate.clone(),
+        };
+        self.ffmpeg_preset = self.ffmpeg_preset.take().map(move |preset| {
+            preset
+                .with_rate_control(rate_control)
+                .with_available_encoder()
+        });
+        if let Some(ffmpeg_preset) = &self.ffmpeg_preset {
+            ffmpeg_preset.check_encoders()?
+        }
+        if self.ffmpeg_two_pass
+            && !self
+                .ffmpeg_preset
+                .as_ref()
+                .is_some_and(FFmpegPreset::supports_two_pass)
+        {
+            bail!("`--ffmpeg-two-pass` requires an `--ffmpeg-preset` which encodes the video with a target bitrate (`--ffmpeg-bitrate` or `-b:v`) and a software encoder (libx264, libx265, libvpx, libvpx-vp9 or libaom-av1)")
+        }
+
+        if self.concurrent_episodes == 0 {
+            bail!("`--concurrent-episodes` must be at least 1")
+        } else if self.concurrent_episodes > 1
+            && (is_special_file(&self.output) || self.output == "-")
+        {
+            bail!("`--concurrent-episodes` cannot be used if the output is written to stdout or a special file")
+        }
+
         if let Some(language_tagging) = &self.language_tagging {
             self.audio = resolve_locales(&[self.audio.clone()]).remove(0);
             self.subtitle = self
@@ -239,7 +913,13 @@ impl Execute for Download {
 
         for (i, url) in self.urls.clone().into_iter().enumerate() {
             let progress_handler = progress!("Parsing url {}", i + 1);
-            match parse_url(&ctx.crunchy, url.clone(), true).await {
+            match with_stage_timeout(
+                "Parsing the url",
+                seconds_to_timeout(self.metadata_timeout),
+                parse_url(&ctx.crunchy, url.clone(), true),
+            )
+            .await
+            {
                 Ok((media_collection, url_filter)) => {
                     progress_handler.stop(format!("Parsed url {}", i + 1));
                     parsed_urls.push((media_collection, url_filter))
@@ -248,16 +928,28 @@ impl Execute for Download {
             };
         }
 
+        let mut download_archive = self
+            .download_archive
+            .as_deref()
+            .map(DownloadArchive::open)
+            .transpose()?;
+        let mut failed_episodes = 0;
+        let mut json_output = vec![];
+        let size_budget = self.max_total_size.map(SizeBudget::new);
         for (i, (media_collection, url_filter)) in parsed_urls.into_iter().enumerate() {
             let progress_handler = progress!("Fetching series details");
-            let single_format_collection = DownloadFilter::new(
-                url_filter,
-                self.clone(),
-                !self.yes,
-                self.skip_specials,
-                ctx.crunchy.premium().await,
+            let single_format_collection = with_stage_timeout(
+                "Fetching series details",
+                seconds_to_timeout(self.metadata_timeout),
+                DownloadFilter::new(
+                    url_filter,
+                    self.clone(),
+                    !self.yes,
+                    self.skip_specials,
+                    ctx.crunchy.premium().await,
+                )
+                .visit(media_collection),
             )
-            .visit(media_collection)
             .await?;
 
             if single_format_collection.is_empty() {
@@ -266,20 +958,59 @@ impl Execute for Download {
             }
             progress_handler.stop(format!("Loaded series information for url {}", i + 1));
 
-            single_format_collection.full_visual_output();
+            match &self.list {
+                Some(ListFormat::Json) => {
+                    json_output.extend(single_format_collection.json_output().await);
+                    continue;
+                }
+                Some(ListFormat::Text) => {
+                    single_format_collection.full_visual_output();
+                    continue;
+                }
+                None => single_format_collection.full_visual_output(),
+            }
 
             let download_builder =
                 DownloadBuilder::new(ctx.client.clone(), ctx.rate_limiter.clone())
+                    .proxy_pool(ctx.proxy_pool.clone())
                     .default_subtitle(self.subtitle.clone())
                     .force_hardsub(self.force_hardsub)
+                    .hardsub_style(self.hardsub_style.clone())
+                    .secondary_subtitle(self.secondary_subtitle.clone())
                     .output_format(if is_special_file(&self.output) || self.output == "-" {
                         Some("mpegts".to_string())
                     } else {
                         None
                     })
                     .ffmpeg_preset(self.ffmpeg_preset.clone().unwrap_or_default())
+                    .ffmpeg_two_pass(self.ffmpeg_two_pass)
                     .ffmpeg_threads(self.ffmpeg_threads)
+                    .fingerprint_cache(self.include_chapters.then(SeasonFingerprintCache::default))
+                    .synthesize_chapters(self.synthesize_chapters)
+                    .metadata_locale(self.metadata_locale.clone())
+                    .track_statistics(self.track_statistics)
+                    .write_chapters(self.write_chapters)
+                    .clip(self.clip)
+                    .remux_only(self.remux_only)
+                    .resolution_fallback(self.resolution_fallback)
+                    .resume(self.resume)
+                    .low_disk(self.low_disk)
+                    .sidecar_subtitles(self.also_sidecar_subs)
+                    .sidecar_naming(self.sidecar_naming.clone())
+                    .fonts_dir(self.fonts_dir.clone())
+                    .subtitle_format(self.subtitle_format)
+                    .local_mux(self.local_mux.clone())
                     .threads(self.threads)
+                    .stall_timeout(seconds_to_timeout(self.stall_timeout))
+                    .slow_start(seconds_to_timeout(self.slow_start))
+                    .schedule(self.schedule)
+                    .segment_recording(
+                        self.record_segments
+                            .clone()
+                            .map(SegmentRecording::Record)
+                            .or_else(|| self.replay_segments.clone().map(SegmentRecording::Replay)),
+                    )
+                    .mux_timeout(seconds_to_timeout(self.mux_timeout))
                     .audio_locale_output_map(HashMap::from([(
                         self.audio.clone(),
                         self.output_audio_locale.clone(),
@@ -290,70 +1021,344 @@ impl Execute for Download {
                         }),
                     );
 
-            for mut single_formats in single_format_collection.into_iter() {
-                // the vec contains always only one item
-                let single_format = single_formats.remove(0);
-
-                let (download_format, format) = get_format(
-                    &self,
-                    &single_format,
-                    if self.force_hardsub {
-                        true
-                    } else if single_format.is_special() {
-                        !special_output_supports_softsubs
-                    } else {
-                        !output_supports_softsubs
-                    },
-                )
-                .await?;
+            let title_cleanup =
+                TitleCleanup::new(self.clean_titles, self.clean_titles_pattern.clone());
 
-                let mut downloader = download_builder.clone().build();
-                downloader.add_format(download_format);
+            // the vecs contain always only one item
+            let single_formats: Vec<SingleFormat> = single_format_collection
+                .into_ordered_iter(self.episode_order)
+                .map(|mut single_formats| single_formats.remove(0))
+                .filter(|single_format| {
+                    let recorded = download_archive
+                        .as_ref()
+                        .is_some_and(|a| a.contains(&single_format.identifier));
+                    if recorded {
+                        debug!(
+                            "Skipping episode '{}' as it's already recorded in the download archive",
+                            single_format.title
+                        );
+                    }
+                    !recorded
+                })
+                .collect();
 
-                let formatted_path = if format.is_special() {
-                    format.format_path(
-                        self.output_specials
-                            .as_ref()
-                            .map_or((&self.output).into(), |so| so.into()),
-                        self.universal_output,
-                        self.language_tagging.as_ref(),
+            let single_formats = match &size_budget {
+                Some(size_budget) => {
+                    let estimated = self.estimate_sizes(single_formats).await;
+                    size_budget.trim(
+                        estimated,
+                        |single_format| single_format.title.clone(),
+                        !self.yes,
                     )
-                } else {
-                    format.format_path(
-                        (&self.output).into(),
-                        self.universal_output,
-                        self.language_tagging.as_ref(),
-                    )
-                };
-                let (path, changed) = free_file(formatted_path.clone());
+                }
+                None => single_formats.into_iter().map(|s| (s, 0)).collect(),
+            };
 
-                if changed && self.skip_existing {
-                    debug!(
-                        "Skipping already existing file '{}'",
-                        formatted_path.to_string_lossy()
-                    );
-                    continue;
+            let overall_progress = OverallProgress::new(single_formats.len());
+            let download = &self;
+            let size_budget = &size_budget;
+            let mut episodes = futures_util::stream::iter(single_formats)
+                .map(|(single_format, estimated_size)| {
+                    let download_builder = &download_builder;
+                    let title_cleanup = &title_cleanup;
+                    let client = &ctx.client;
+                    async move {
+                        if size_budget
+                            .as_ref()
+                            .is_some_and(|budget| !budget.reserve(estimated_size))
+                        {
+                            warn!(
+                                "Skipping episode '{}' as it would exceed the size budget",
+                                single_format.title
+                            );
+                            return (single_format, Ok(None));
+                        }
+                        if download.wait_for_release {
+                            single_format.wait_for_release().await
+                        }
+                        set_terminal_title_episode(&single_format.title);
+                        let start = Instant::now();
+                        let result = with_stage_timeout(
+                            &format!("Downloading episode '{}'", single_format.title),
+                            seconds_to_timeout(download.episode_timeout),
+                            download.download_episode(
+                                &single_format,
+                                download_builder,
+                                title_cleanup,
+                                output_supports_softsubs,
+                                special_output_supports_softsubs,
+                            ),
+                        )
+                        .await;
+                        if let Some(size_budget) = size_budget {
+                            let size = result
+                                .as_ref()
+                                .ok()
+                                .and_then(|path| path.as_ref())
+                                .and_then(|path| fs::metadata(path).ok())
+                                .map_or(0, |metadata| metadata.len());
+                            size_budget.settle(estimated_size, size)
+                        }
+                        if let Some(notify_webhook_url) = &download.notify_webhook {
+                            notify_webhook(
+                                client,
+                                notify_webhook_url,
+                                &single_format,
+                                &result,
+                                start.elapsed(),
+                            )
+                            .await
+                        }
+                        (single_format, result)
+                    }
+                })
+                .buffer_unordered(self.concurrent_episodes);
+            let mut done = 0;
+            while let Some((single_format, result)) = episodes.next().await {
+                done += 1;
+                overall_progress.set_position(done);
+                match result {
+                    Ok(Some(_)) => {
+                        if let Some(download_archive) = &mut download_archive {
+                            download_archive.record(&single_format.identifier)?
+                        }
+                    }
+                    Ok(None) => (),
+                    // if episodes are downloaded concurrently, a failed episode must not abort
+                    // the other running downloads
+                    Err(e) if self.concurrent_episodes > 1 => {
+                        error!(
+                            "Failed to download episode '{}': {}",
+                            single_format.title, e
+                        );
+                        failed_episodes += 1
+                    }
+                    Err(e) => return Err(e),
                 }
+            }
+        }
 
-                format.visual_output(&path);
+        if let Some(size_budget) = &size_budget {
+            info!("Downloaded {} in total", format_size(size_budget.used()))
+        }
+        if failed_episodes > 0 {
+            bail!("{} episode(s) failed to download", failed_episodes)
+        }
 
-                downloader.download(&path).await?
-            }
+        if self.list == Some(ListFormat::Json) {
+            println!("{}", serde_json::to_string_pretty(&json_output)?)
         }
 
         Ok(())
     }
 }
 
+impl Download {
+    /// Downloads a single episode. Returns the output path, or `None` if the episode was skipped.
+    async fn download_episode(
+        &self,
+        single_format: &SingleFormat,
+        download_builder: &DownloadBuilder,
+        title_cleanup: &TitleCleanup,
+        output_supports_softsubs: bool,
+        special_output_supports_softsubs: bool,
+    ) -> Result<Option<PathBuf>> {
+        if self.only == Some(Only::Subtitles) {
+            return self
+                .download_subtitles(single_format, download_builder, title_cleanup)
+                .await;
+        }
+
+        let (download_format, mut format) = get_format(
+            self,
+            single_format,
+            if self.force_hardsub {
+                true
+            } else if single_format.is_special() {
+                !special_output_supports_softsubs
+            } else {
+                !output_supports_softsubs
+            },
+        )
+        .await?;
+        format.clean_titles(title_cleanup);
+        if self.specials_season_zero {
+            format.move_special_to_season_zero()
+        }
+
+        let mut downloader = download_builder
+            .clone()
+            .content_rating(self.embed_rating.then(|| format.rating()).flatten())
+            .cut(format.skip_events.as_ref().map_or(vec![], |skip_events| {
+                skip_event_ranges(skip_events, &self.cut)
+            }))
+            .episode_id(Some(format.episode_id.clone()))
+            .cover_art(single_format.cover_art_url(self.cover_art).await?)
+            .thumbnail(if self.write_thumbnail {
+                single_format.cover_art_url(CoverArt::Episode).await?
+            } else {
+                None
+            })
+            .build();
+        downloader.add_format(download_format);
+
+        let formatted_path = self.formatted_path(&format);
+        let (path, changed) = free_file(formatted_path.clone());
+
+        if changed && self.skip_existing {
+            debug!(
+                "Skipping already existing file '{}'",
+                formatted_path.to_string_lossy()
+            );
+            return Ok(None);
+        }
+
+        format.visual_output(&path);
+
+        let result = if self.only == Some(Only::Audio) {
+            downloader.download_audio_only(&path).await
+        } else {
+            downloader.download(&path).await
+        };
+        if let (Err(e), Some(exec_on_error)) = (&result, &self.exec_on_error) {
+            if let Err(exec_error) = run_exec(exec_on_error, &format, &path, Some(e)).await {
+                warn!("Failed to run `--exec-on-error` command: {}", exec_error)
+            }
+        }
+        result?;
+        if self.write_nfo {
+            write_nfo(&path, &format, single_format.is_episode())?
+        }
+        if let (Some(skip_file), Some(skip_events)) = (self.write_skip_file, &format.skip_events) {
+            write_skip_file(&path, skip_events, skip_file)?
+        }
+        if let Some(exec) = &self.exec {
+            if let Err(e) = run_exec(exec, &format, &path, None).await {
+                bail!("Failed to run `--exec` command: {}", e)
+            }
+        }
+        Ok(Some(path))
+    }
+
+    /// Downloads only the subtitle of a single episode, without requesting any stream data.
+    async fn download_subtitles(
+        &self,
+        single_format: &SingleFormat,
+        download_builder: &DownloadBuilder,
+        title_cleanup: &TitleCleanup,
+    ) -> Result<Option<PathBuf>> {
+        let stream = single_format.stream().await?;
+        let subtitles: Vec<(Subtitle, bool)> = self
+            .subtitle
+            .as_ref()
+            .and_then(|locale| find_subtitle(self, single_format, &stream, locale))
+            .into_iter()
+            .collect();
+        stream.invalidate().await?;
+
+        let mut format = Format::from_single_formats_without_stream(vec![(
+            single_format.clone(),
+            subtitles.clone(),
+        )]);
+        if self.needs_skip_events() {
+            format.skip_events = single_format.skip_events().await?
+        }
+        format.clean_titles(title_cleanup);
+        if self.specials_season_zero {
+            format.move_special_to_season_zero()
+        }
+        let path = self.formatted_path(&format);
+
+        format.visual_output(&path);
+
+        download_builder
+            .clone()
+            .build()
+            .download_subtitles(&path, &subtitles)
+            .await?;
+        Ok(Some(path))
+    }
+
+    /// The estimated size of every episode. Episodes whose size can't be estimated count as 0, the
+    /// download fails for them anyway.
+    async fn estimate_sizes(&self, single_formats: Vec<SingleFormat>) -> Vec<(SingleFormat, u64)> {
+        let _progress_handler = progress!("Estimating the size of the episodes");
+        let mut estimated = vec![];
+        for single_format in single_formats {
+            let size = if self.only == Some(Only::Subtitles) {
+                0
+            } else {
+                match get_format(self, &single_format, false).await {
+                    Ok((download_format, _)) => {
+                        download_format.estimated_size(self.only == Some(Only::Audio))
+                    }
+                    Err(e) => {
+                        debug!(
+                            "Failed to estimate the size of episode '{}': {}",
+                            single_format.title, e
+                        );
+                        0
+                    }
+                }
+            };
+            estimated.push((single_format, size))
+        }
+        estimated
+    }
+
+    /// If the skip events are needed, either for `--write-skip-file` and `--cut` or because the
+    /// output or `--exec` templates contain variables which need them.
+    fn needs_skip_events(&self) -> bool {
+        self.write_skip_file.is_some()
+            || !self.cut.is_empty()
+            || [
+                Some(&self.output),
+                self.output_specials.as_ref(),
+                self.exec.as_ref(),
+                self.exec_on_error.as_ref(),
+            ]
+            .into_iter()
+            .flatten()
+            .any(|template| uses_skip_event_variables(template))
+    }
+
+    fn formatted_path(&self, format: &Format) -> PathBuf {
+        let path = if format.is_special() {
+            format.format_path(
+                self.output_specials
+                    .as_ref()
+                    .map_or((&self.output).into(), |so| so.into()),
+                self.universal_output,
+                self.language_tagging.as_ref(),
+                self.max_path_length,
+            )
+        } else {
+            format.format_path(
+                (&self.output).into(),
+                self.universal_output,
+                self.language_tagging.as_ref(),
+                self.max_path_length,
+            )
+        };
+        match &self.output_dir {
+            Some(output_dir) if !is_special_file(&path) && path.to_string_lossy() != "-" => {
+                output_dir.join(path)
+            }
+            _ => path,
+        }
+    }
+}
+
 async fn get_format(
     download: &Download,
     single_format: &SingleFormat,
     try_peer_hardsubs: bool,
 ) -> Result<(DownloadFormat, Format)> {
     let stream = single_format.stream().await?;
-    let Some((video, audio, contains_hardsub)) = stream_data_from_stream(
+    let Some((video, audio, contains_hardsub, video_fallbacks)) = stream_data_from_stream(
         &stream,
         &download.resolution,
+        download.codec,
         if try_peer_hardsubs {
             download.subtitle.clone()
         } else {
@@ -383,52 +1388,45 @@ async fn get_format(
 
     let subtitle = if contains_hardsub {
         None
-    } else if let Some(subtitle_locale) = &download.subtitle {
-        if download.audio == Locale::ja_JP {
-            stream
-                .subtitles
-                .get(subtitle_locale)
-                // use closed captions as fallback if no actual subtitles are found
-                .or_else(|| stream.captions.get(subtitle_locale))
-                .cloned()
-        } else {
-            stream
-                .captions
-                .get(subtitle_locale)
-                .or_else(|| stream.subtitles.get(subtitle_locale))
-                .cloned()
-        }
     } else {
-        None
+        download
+            .subtitle
+            .as_ref()
+            .and_then(|locale| find_subtitle(download, single_format, &stream, locale))
     };
+    let secondary_subtitle = download
+        .secondary_subtitle
+        .as_ref()
+        .and_then(|locale| find_subtitle(download, single_format, &stream, locale));
+    let subtitles: Vec<(Subtitle, bool)> = subtitle.into_iter().chain(secondary_subtitle).collect();
 
+    let skip_events = if download.include_chapters || download.needs_skip_events() {
+        single_format.skip_events().await?
+    } else {
+        None
+    };
     let download_format = DownloadFormat {
         video: (video.clone(), single_format.audio.clone()),
+        video_fallbacks,
         audios: vec![(audio, single_format.audio.clone())],
-        subtitles: subtitle.clone().map_or(vec![], |s| {
-            vec![(
-                s,
-                single_format.audio != Locale::ja_JP && stream.subtitles.len() == 1,
-            )]
-        }),
+        subtitles: subtitles.clone(),
         metadata: DownloadFormatMetadata {
+            episode_id: single_format.episode_id.clone(),
+            hardsub: contains_hardsub
+                .then(|| download.subtitle.clone())
+                .flatten(),
             skip_events: if download.include_chapters {
-                single_format.skip_events().await?
+                skip_events.clone()
             } else {
                 None
             },
+            season_id: single_format
+                .is_episode()
+                .then(|| single_format.season_id.clone()),
         },
     };
-    let mut format = Format::from_single_formats(vec![(
-        single_format.clone(),
-        video,
-        subtitle.map_or(vec![], |s| {
-            vec![(
-                s,
-                single_format.audio != Locale::ja_JP && stream.subtitles.len() == 1,
-            )]
-        }),
-    )]);
+    let mut format = Format::from_single_formats(vec![(single_format.clone(), video, subtitles)]);
+    format.skip_events = skip_events;
     if contains_hardsub {
         let (_, subs) = format.locales.get_mut(0).unwrap();
         subs.push(download.subtitle.clone().unwrap())
@@ -438,3 +1436,30 @@ async fn get_format(
 
     Ok((download_format, format))
 }
+
+/// The subtitle of the stream in `subtitle_locale` and whether it's a closed caption.
+fn find_subtitle(
+    download: &Download,
+    single_format: &SingleFormat,
+    stream: &Stream,
+    subtitle_locale: &Locale,
+) -> Option<(Subtitle, bool)> {
+    let subtitle = if download.audio == Locale::ja_JP {
+        stream
+            .subtitles
+            .get(subtitle_locale)
+            // use closed captions as fallback if no actual subtitles are found
+            .or_else(|| stream.captions.get(subtitle_locale))
+            .cloned()
+    } else {
+        stream
+            .captions
+            .get(subtitle_locale)
+            .or_else(|| stream.subtitles.get(subtitle_locale))
+            .cloned()
+    }?;
+    Some((
+        subtitle,
+        single_format.audio != Locale::ja_JP && stream.subtitles.len() == 1,
+    ))
+}
@@ -1,5 +1,5 @@
 use crate::download::Download;
-use crate::utils::filter::Filter;
+use crate::utils::filter::{is_too_mature, Filter};
 use crate::utils::format::{Format, SingleFormat, SingleFormatCollection};
 use crate::utils::interactive_select::{check_for_duplicated_seasons, get_duplicated_seasons};
 use crate::utils::parse::{fract, UrlFilter};
@@ -46,6 +46,16 @@ impl Filter for DownloadFilter {
     type Output = SingleFormatCollection;
 
     async fn visit_series(&mut self, series: Series) -> Result<Vec<Season>> {
+        if is_too_mature(
+            self.download.max_maturity.as_ref(),
+            "series",
+            &series.title,
+            &series.maturity_ratings,
+            series.is_mature,
+        ) {
+            return Ok(vec![]);
+        }
+
         // `series.audio_locales` isn't always populated b/c of crunchyrolls api. so check if the
         // audio is matching only if the field is populated
         if !series.audio_locales.is_empty() && !series.audio_locales.contains(&self.download.audio)
@@ -140,6 +150,15 @@ impl Filter for DownloadFilter {
         {
             return Ok(None);
         }
+        if is_too_mature(
+            self.download.max_maturity.as_ref(),
+            "episode",
+            &episode.title,
+            &episode.maturity_ratings,
+            episode.is_mature,
+        ) {
+            return Ok(None);
+        }
 
         // skip the episode if it's a special
         if self.skip_special
@@ -205,7 +224,11 @@ impl Filter for DownloadFilter {
             }
         }
 
-        if self.seasons_with_premium.is_some() && episode.is_premium_only {
+        // premium episodes are only listed (and marked as unavailable) if nothing is downloaded
+        if self.seasons_with_premium.is_some()
+            && episode.is_premium_only
+            && self.download.list.is_none()
+        {
             if !self
                 .seasons_with_premium
                 .as_ref()
@@ -265,7 +288,7 @@ impl Filter for DownloadFilter {
             }
         }
 
-        Ok(Some(SingleFormat::new_from_episode(
+        let mut single_format = SingleFormat::new_from_episode(
             episode.clone(),
             self.download.subtitle.clone().map_or(vec![], |s| {
                 if episode.subtitle_locales.contains(&s) {
@@ -276,22 +299,62 @@ impl Filter for DownloadFilter {
             }),
             relative_episode_number.map(|n| n as u32),
             relative_sequence_number,
-        )))
+        );
+        single_format.unavailable = self.seasons_with_premium.is_some() && episode.is_premium_only;
+        single_format.fill_blank_metadata().await;
+
+        Ok(Some(single_format))
     }
 
     async fn visit_movie_listing(&mut self, movie_listing: MovieListing) -> Result<Vec<Movie>> {
+        if is_too_mature(
+            self.download.max_maturity.as_ref(),
+            "movie listing",
+            &movie_listing.title,
+            &movie_listing.maturity_ratings,
+            movie_listing.is_mature,
+        ) {
+            return Ok(vec![]);
+        }
         Ok(movie_listing.movies().await?)
     }
 
     async fn visit_movie(&mut self, movie: Movie) -> Result<Option<Self::T>> {
+        if is_too_mature(
+            self.download.max_maturity.as_ref(),
+            "movie",
+            &movie.title,
+            &movie.maturity_ratings,
+            movie.is_mature,
+        ) {
+            return Ok(None);
+        }
         Ok(Some(SingleFormat::new_from_movie(movie, vec![])))
     }
 
     async fn visit_music_video(&mut self, music_video: MusicVideo) -> Result<Option<Self::T>> {
+        if is_too_mature(
+            self.download.max_maturity.as_ref(),
+            "music video",
+            &music_video.title,
+            &[],
+            music_video.is_mature,
+        ) {
+            return Ok(None);
+        }
         Ok(Some(SingleFormat::new_from_music_video(music_video)))
     }
 
     async fn visit_concert(&mut self, concert: Concert) -> Result<Option<Self::T>> {
+        if is_too_mature(
+            self.download.max_maturity.as_ref(),
+            "concert",
+            &concert.title,
+            &[],
+            concert.is_mature,
+        ) {
+            return Ok(None);
+        }
         Ok(Some(SingleFormat::new_from_concert(concert)))
     }
 
@@ -0,0 +1,109 @@
+use crate::export::filter::ExportFilter;
+use crate::utils::context::Context;
+use crate::utils::filter::Filter;
+use crate::utils::format::CSV_COLUMNS;
+use crate::utils::log::progress;
+use crate::utils::parse::parse_url;
+use crate::Execute;
+use anyhow::{bail, Result};
+use crunchyroll_rs::Locale;
+use log::info;
+use std::fs;
+
+#[derive(Clone, Debug, clap::Parser)]
+#[clap(about = "Export the metadata of series without downloading anything")]
+#[command(arg_required_else_help(true))]
+pub struct ExportMetadata {
+    #[arg(help = format!("Only export versions with these audio languages. Can be used multiple times. \
+    Available languages are: {}", Locale::all().into_iter().map(|l| l.to_string()).collect::<Vec<String>>().join(", ")))]
+    #[arg(long_help = format!("Only export versions with these audio languages. Can be used multiple times. \
+    All versions are exported if not given. \
+    Available languages are:\n  {}\nIETF tagged language codes for the shown available locales can be used too", Locale::all().into_iter().map(|l| format!("{:<6} → {}", l.to_string(), l.to_human_readable())).collect::<Vec<String>>().join("\n  ")))]
+    #[arg(short, long)]
+    pub(crate) audio: Vec<Locale>,
+
+    #[arg(help = "Format of the export. Valid options are 'json' and 'csv'")]
+    #[arg(
+        long_help = "Format of the export. Valid options are 'json' (tree of series, seasons and episodes with all their versions) \
+    and 'csv' (one row for every version of every episode)"
+    )]
+    #[arg(long, default_value = "json", value_parser = ExportFormat::parse)]
+    pub(crate) format: ExportFormat,
+
+    #[arg(help = "File to write the export to. Use '-' to write it to stdout")]
+    #[arg(short, long, default_value = "-")]
+    pub(crate) output: String,
+
+    #[arg(help = "Skip special episodes")]
+    #[arg(long, default_value_t = false)]
+    pub(crate) skip_specials: bool,
+
+    #[arg(help = "Url(s) or id(s) of Crunchyroll episodes or series")]
+    #[arg(required = true)]
+    pub(crate) urls: Vec<String>,
+}
+
+/// Format in which the metadata is exported.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "json" => Self::Json,
+            "csv" => Self::Csv,
+            _ => return Err(format!("'{}' is not a valid export format", s)),
+        })
+    }
+}
+
+impl Execute for ExportMetadata {
+    async fn execute(self, ctx: Context) -> Result<()> {
+        let mut json_output = vec![];
+        let mut csv_output = vec![CSV_COLUMNS.join(",")];
+
+        for (i, url) in self.urls.iter().enumerate() {
+            let progress_handler = progress!("Parsing url {}", i + 1);
+            let (media_collection, url_filter) =
+                match parse_url(&ctx.crunchy, url.clone(), true).await {
+                    Ok(parsed) => parsed,
+                    Err(e) => bail!("url {} could not be parsed: {}", url, e),
+                };
+            progress_handler.stop(format!("Parsed url {}", i + 1));
+
+            let progress_handler = progress!("Fetching series details");
+            let single_format_collection =
+                ExportFilter::new(url_filter, self.audio.clone(), self.skip_specials)
+                    .visit(media_collection)
+                    .await?;
+            if single_format_collection.is_empty() {
+                progress_handler.stop(format!("Skipping url {} (no matching videos found)", i + 1));
+                continue;
+            }
+            progress_handler.stop(format!("Loaded series information for url {}", i + 1));
+
+            match self.format {
+                ExportFormat::Json => {
+                    json_output.extend(single_format_collection.json_output().await)
+                }
+                ExportFormat::Csv => csv_output.extend(single_format_collection.csv_output()),
+            }
+        }
+
+        let export = match self.format {
+            ExportFormat::Json => serde_json::to_string_pretty(&json_output)?,
+            ExportFormat::Csv => csv_output.join("\n"),
+        };
+        if self.output == "-" {
+            println!("{}", export)
+        } else {
+            fs::write(&self.output, format!("{}\n", export))?;
+            info!("Exported metadata to {}", self.output)
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+use crate::utils::filter::{real_dedup_vec, Filter};
+use crate::utils::format::{SingleFormat, SingleFormatCollection};
+use crate::utils::parse::UrlFilter;
+use anyhow::Result;
+use crunchyroll_rs::{Concert, Episode, Locale, Movie, MovieListing, MusicVideo, Season, Series};
+use std::collections::BTreeMap;
+
+/// Collects every episode with all its audio versions, or only the versions with the given audio
+/// locales if any are given. Nothing is filtered because of premium or maturity restrictions as
+/// nothing is downloaded.
+pub(crate) struct ExportFilter {
+    url_filter: UrlFilter,
+    audio: Vec<Locale>,
+    skip_special: bool,
+    season_sorting: Vec<String>,
+}
+
+impl ExportFilter {
+    pub(crate) fn new(url_filter: UrlFilter, audio: Vec<Locale>, skip_special: bool) -> Self {
+        Self {
+            url_filter,
+            audio,
+            skip_special,
+            season_sorting: vec![],
+        }
+    }
+
+    fn is_audio_requested(&self, locale: &Locale) -> bool {
+        self.audio.is_empty() || self.audio.contains(locale)
+    }
+}
+
+impl Filter for ExportFilter {
+    type T = Vec<SingleFormat>;
+    type Output = SingleFormatCollection;
+
+    async fn visit_series(&mut self, series: Series) -> Result<Vec<Season>> {
+        let mut seasons = series.seasons().await?;
+        seasons.retain(|s| self.url_filter.is_season_valid(s.season_number));
+        Ok(seasons)
+    }
+
+    async fn visit_season(&mut self, season: Season) -> Result<Vec<Episode>> {
+        if !self.url_filter.is_season_valid(season.season_number) {
+            return Ok(vec![]);
+        }
+        self.season_sorting.push(season.id.clone());
+        Ok(season.episodes().await?)
+    }
+
+    async fn visit_episode(&mut self, episode: Episode) -> Result<Option<Self::T>> {
+        if !self
+            .url_filter
+            .is_episode_valid(episode.sequence_number, episode.season_number)
+        {
+            return Ok(None);
+        }
+        if self.skip_special
+            && (episode.sequence_number == 0.0 || episode.sequence_number.fract() != 0.0)
+        {
+            return Ok(None);
+        }
+
+        let mut available_locales = episode.available_versions().await?;
+        available_locales.retain(|l| l != &episode.audio_locale && self.is_audio_requested(l));
+        real_dedup_vec(&mut available_locales);
+
+        let mut episodes = vec![];
+        if !available_locales.is_empty() {
+            episodes.extend(episode.version(available_locales).await?)
+        }
+        if self.is_audio_requested(&episode.audio_locale) {
+            episodes.insert(0, episode)
+        }
+
+        let mut single_formats = vec![];
+        for episode in episodes {
+            let subtitles = episode.subtitle_locales.clone();
+            let mut single_format = SingleFormat::new_from_episode(episode, subtitles, None, None);
+            single_format.fill_blank_metadata().await;
+            single_formats.push(single_format)
+        }
+        if single_formats.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(single_formats))
+    }
+
+    async fn visit_movie_listing(&mut self, movie_listing: MovieListing) -> Result<Vec<Movie>> {
+        Ok(movie_listing.movies().await?)
+    }
+
+    async fn visit_movie(&mut self, movie: Movie) -> Result<Option<Self::T>> {
+        Ok(Some(vec![SingleFormat::new_from_movie(movie, vec![])]))
+    }
+
+    async fn visit_music_video(&mut self, music_video: MusicVideo) -> Result<Option<Self::T>> {
+        Ok(Some(vec![SingleFormat::new_from_music_video(music_video)]))
+    }
+
+    async fn visit_concert(&mut self, concert: Concert) -> Result<Option<Self::T>> {
+        Ok(Some(vec![SingleFormat::new_from_concert(concert)]))
+    }
+
+    async fn finish(self, input: Vec<Self::T>) -> Result<Self::Output> {
+        // crunchyroll lists some dubs as own seasons, their episodes have the same identifier as
+        // the original ones and are merged into them
+        let mut pre_sorted: BTreeMap<String, Self::T> = BTreeMap::new();
+        for data in input.into_iter().flatten() {
+            let versions = pre_sorted.entry(data.identifier.clone()).or_default();
+            if !versions.iter().any(|v| v.episode_id == data.episode_id) {
+                versions.push(data)
+            }
+        }
+
+        let mut sorted: Vec<Self::T> = pre_sorted.into_values().collect();
+        sorted.sort_by_key(|data| {
+            self.season_sorting
+                .iter()
+                .position(|p| p == &data.first().unwrap().season_id)
+                .unwrap_or(usize::MAX)
+        });
+
+        let mut single_format_collection = SingleFormatCollection::new();
+        for data in sorted {
+            single_format_collection.add_single_formats(data)
+        }
+        Ok(single_format_collection)
+    }
+}
@@ -0,0 +1,4 @@
+mod command;
+mod filter;
+
+pub use command::ExportMetadata;
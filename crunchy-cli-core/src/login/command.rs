@@ -5,8 +5,9 @@ use anyhow::Result;
 use clap::Parser;
 use crunchyroll_rs::crunchyroll::SessionToken;
 use log::info;
-use std::fs;
+use std::io::stdin;
 use std::path::PathBuf;
+use std::{env, fs};
 
 #[derive(Debug, clap::Parser)]
 #[clap(about = "Save your login credentials persistent on disk")]
@@ -38,16 +39,119 @@ impl Execute for Login {
     }
 }
 
-#[derive(Clone, Debug, Parser)]
+#[derive(Clone, Parser)]
 pub struct LoginMethod {
     #[arg(
         help = "Login with credentials (email and password). Must be provided as email:password"
     )]
+    #[arg(
+        long_help = "Login with credentials (email and password). Must be provided as email:password. \
+            If only the email is given, the password is read from '--password-stdin', '--password-fd' or the 'CRUNCHY_CLI_PASSWORD' environment variable"
+    )]
     #[arg(global = true, long)]
     pub credentials: Option<String>,
+    #[arg(help = "Read the password of '--credentials' from stdin")]
+    #[arg(global = true, long, default_value_t = false)]
+    pub password_stdin: bool,
+    #[arg(help = "Read the password of '--credentials' from the given file descriptor")]
+    #[arg(global = true, long)]
+    pub password_fd: Option<i32>,
     #[arg(help = "Login anonymously / without an account")]
     #[arg(global = true, long, default_value_t = false)]
     pub anonymous: bool,
+
+    /// Credentials which were already resolved. Logging in again uses them instead of reading
+    /// the password from stdin or the file descriptor a second time.
+    #[arg(skip)]
+    pub resolved_credentials: Option<(String, String)>,
+}
+
+// the credentials must not end up in the debug output of the cli input
+impl std::fmt::Debug for LoginMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoginMethod")
+            .field("credentials", &self.credentials.as_ref().map(|_| "***"))
+            .field("password_stdin", &self.password_stdin)
+            .field("password_fd", &self.password_fd)
+            .field("anonymous", &self.anonymous)
+            .field(
+                "resolved_credentials",
+                &self.resolved_credentials.as_ref().map(|_| "***"),
+            )
+            .finish()
+    }
+}
+
+impl LoginMethod {
+    /// Email and password, either given via '--credentials' or, if no other login method is
+    /// specified, via the 'CRUNCHY_CLI_CREDENTIALS' environment variable.
+    pub fn resolve_credentials(&self) -> Result<Option<(String, String)>> {
+        if let Some(credentials) = &self.resolved_credentials {
+            return Ok(Some(credentials.clone()));
+        }
+
+        let credentials = match &self.credentials {
+            Some(credentials) => credentials.clone(),
+            None => {
+                if self.password_stdin || self.password_fd.is_some() {
+                    bail!("'--password-stdin' and '--password-fd' can only be used together with '--credentials'")
+                }
+                match env::var("CRUNCHY_CLI_CREDENTIALS") {
+                    Ok(credentials) if !self.anonymous => credentials,
+                    _ => return Ok(None),
+                }
+            }
+        };
+
+        if let Some((email, password)) = credentials.split_once(':') {
+            if self.password_stdin || self.password_fd.is_some() {
+                bail!("The password is already part of the credentials, remove '--password-stdin' / '--password-fd' or provide only the email")
+            }
+            return Ok(Some((email.to_string(), password.to_string())));
+        }
+
+        let password = if self.password_stdin && self.password_fd.is_some() {
+            bail!("'--password-stdin' and '--password-fd' cannot be used at the same time")
+        } else if self.password_stdin {
+            let mut password = String::new();
+            stdin().read_line(&mut password)?;
+            password
+        } else if let Some(fd) = self.password_fd {
+            read_fd(fd)?
+        } else if let Ok(password) = env::var("CRUNCHY_CLI_PASSWORD") {
+            password
+        } else {
+            bail!("Invalid credentials format. Please provide your credentials as email:password or provide the password via '--password-stdin', '--password-fd' or the 'CRUNCHY_CLI_PASSWORD' environment variable")
+        };
+        let password = password.trim_end_matches(['\r', '\n']).to_string();
+        if password.is_empty() {
+            bail!("The provided password is empty")
+        }
+
+        Ok(Some((credentials, password)))
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn read_fd(fd: i32) -> Result<String> {
+    use std::io::Read;
+    use std::os::fd::FromRawFd;
+
+    if fd <= 2 {
+        bail!("File descriptor {} cannot be used to read the password", fd)
+    }
+    // SAFETY: the file descriptor is explicitly handed over by the user and used only here
+    let mut file = unsafe { fs::File::from_raw_fd(fd) };
+    let mut content = String::new();
+    file.read_to_string(&mut content).map_err(|e| {
+        anyhow::anyhow!("Failed to read password from file descriptor {}: {}", fd, e)
+    })?;
+    Ok(content)
+}
+
+#[cfg(target_os = "windows")]
+fn read_fd(_fd: i32) -> Result<String> {
+    bail!("'--password-fd' is not supported on Windows")
 }
 
 pub fn session_file_path() -> Option<PathBuf> {
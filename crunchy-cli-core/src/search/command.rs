@@ -1,7 +1,7 @@
 use crate::search::filter::FilterOptions;
 use crate::search::format::Format;
 use crate::utils::context::Context;
-use crate::utils::parse::{parse_url, UrlFilter};
+use crate::utils::parse::{normalize_url, parse_url, UrlFilter};
 use crate::Execute;
 use anyhow::{bail, Result};
 use crunchyroll_rs::common::StreamExt;
@@ -108,10 +108,10 @@ pub struct Search {
 impl Execute for Search {
     async fn execute(self, ctx: Context) -> Result<()> {
         if !ctx.crunchy.premium().await {
-            warn!("Using `search` anonymously or with a non-premium account may return incomplete results")
+            warn!("Using `search` anonymously or with a non-premium account may return incomplete results. Stream information of premium only media is unavailable")
         }
 
-        let input = if crunchyroll_rs::parse::parse_url(&self.input).is_some() {
+        let input = if crunchyroll_rs::parse::parse_url(normalize_url(&self.input)).is_some() {
             match parse_url(&ctx.crunchy, self.input.clone(), true).await {
                 Ok(ok) => vec![ok],
                 Err(e) => bail!("url {} could not be parsed: {}", self.input, e),
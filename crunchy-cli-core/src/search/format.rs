@@ -462,8 +462,14 @@ impl Format {
             }
         }
         if !stream_empty {
+            let premium = self.crunchyroll.premium().await;
             for (_, episodes) in tree.iter_mut() {
                 for (episode, streams) in episodes {
+                    // streams of premium only episodes are unavailable without premium
+                    if episode.is_premium_only && !premium {
+                        streams.push(Stream::default());
+                        continue;
+                    }
                     let stream = episode.stream_maybe_without_drm().await?;
                     stream.clone().invalidate().await?;
                     streams.push(stream)
@@ -540,8 +546,14 @@ impl Format {
             tree.extend(movies.into_iter().map(|m| (m, vec![])))
         }
         if !stream_empty {
+            let premium = self.crunchyroll.premium().await;
             for (movie, streams) in tree.iter_mut() {
-                streams.push(movie.stream_maybe_without_drm().await?)
+                // streams of premium only movies are unavailable without premium
+                if movie.is_premium_only && !premium {
+                    streams.push(Stream::default())
+                } else {
+                    streams.push(movie.stream_maybe_without_drm().await?)
+                }
             }
         } else {
             for (_, streams) in tree.iter_mut() {
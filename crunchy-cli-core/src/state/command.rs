@@ -0,0 +1,254 @@
+use crate::utils::download::{ResumeState, RESUME_STATE_VERSION};
+use crate::utils::download_archive::DownloadArchive;
+use crate::utils::os::{temp_directory, RESUME_FILE_PREFIX};
+use anyhow::{bail, Result};
+use clap::Subcommand;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+/// Version of the export file format. If the format changes, increase it and add a migration to
+/// [`import`].
+const EXPORT_VERSION: u32 = 1;
+
+#[derive(Debug, clap::Parser)]
+#[clap(about = "Export or import the progress of downloads")]
+#[command(arg_required_else_help(true))]
+pub struct State {
+    #[command(subcommand)]
+    command: StateCommand,
+}
+
+#[derive(Debug, Subcommand)]
+enum StateCommand {
+    #[clap(about = "Export interrupted downloads and the download archive into a zip file")]
+    #[clap(
+        long_about = "Export interrupted downloads and the download archive into a zip file. \
+            It can be imported on another machine or after an upgrade with 'crunchy-cli state import' to continue the downloads with '--resume'"
+    )]
+    Export {
+        #[arg(help = "Path of the zip file to write")]
+        file: PathBuf,
+        #[arg(help = "Include the download archive at this path")]
+        #[arg(long)]
+        download_archive: Option<PathBuf>,
+    },
+    #[clap(about = "Import a zip file which was written by 'crunchy-cli state export'")]
+    #[clap(
+        long_about = "Import a zip file which was written by 'crunchy-cli state export'. \
+            Files written by older versions of crunchy-cli are migrated to the current format. \
+            Interrupted downloads which also exist locally are skipped"
+    )]
+    Import {
+        #[arg(help = "Path of the zip file to read")]
+        file: PathBuf,
+        #[arg(help = "Merge the exported download archive into the download archive at this path")]
+        #[arg(long)]
+        download_archive: Option<PathBuf>,
+    },
+}
+
+#[derive(Deserialize, Serialize)]
+struct Manifest {
+    version: u32,
+    crunchy_cli_version: String,
+    resume_state_version: u32,
+}
+
+const MANIFEST_FILE: &str = "manifest.json";
+const DOWNLOAD_ARCHIVE_FILE: &str = "download_archive.txt";
+const RESUME_DIRECTORY: &str = "resume/";
+
+impl State {
+    pub fn run(&self) -> Result<()> {
+        match &self.command {
+            StateCommand::Export {
+                file,
+                download_archive,
+            } => export(file, download_archive.as_deref()),
+            StateCommand::Import {
+                file,
+                download_archive,
+            } => import(file, download_archive.as_deref()),
+        }
+    }
+}
+
+fn export(path: &Path, download_archive: Option<&Path>) -> Result<()> {
+    let mut zip = ZipWriter::new(File::create(path)?);
+
+    zip.start_file(MANIFEST_FILE, SimpleFileOptions::default())?;
+    zip.write_all(&serde_json::to_vec_pretty(&Manifest {
+        version: EXPORT_VERSION,
+        crunchy_cli_version: env!("CARGO_PKG_VERSION").to_string(),
+        resume_state_version: RESUME_STATE_VERSION,
+    })?)?;
+
+    let mut resume_states = 0;
+    for entry in fs::read_dir(temp_directory())?.flatten() {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if !file_name.starts_with(RESUME_FILE_PREFIX) || !file_name.ends_with(".json") {
+            continue;
+        }
+        let state = match ResumeState::from_slice(&fs::read(entry.path())?) {
+            Ok(state) => state,
+            Err(e) => {
+                warn!("Skipping resume state {}: {}", file_name, e);
+                continue;
+            }
+        };
+        let Some(data_file_name) = state.file.file_name() else {
+            continue;
+        };
+        if !state.file.exists() {
+            continue;
+        }
+
+        zip.start_file(
+            format!("{}{}", RESUME_DIRECTORY, file_name),
+            SimpleFileOptions::default(),
+        )?;
+        zip.write_all(&fs::read(entry.path())?)?;
+        zip.start_file(
+            format!("{}{}", RESUME_DIRECTORY, data_file_name.to_string_lossy()),
+            SimpleFileOptions::default(),
+        )?;
+        io::copy(&mut File::open(&state.file)?, &mut zip)?;
+        resume_states += 1
+    }
+
+    if let Some(download_archive) = download_archive {
+        let archive = DownloadArchive::open(download_archive)?;
+        zip.start_file(DOWNLOAD_ARCHIVE_FILE, SimpleFileOptions::default())?;
+        for identifier in archive.identifiers() {
+            writeln!(zip, "{}", identifier)?
+        }
+    }
+
+    zip.finish()?;
+    info!(
+        "Exported {} interrupted download(s){} to {}",
+        resume_states,
+        if download_archive.is_some() {
+            " and the download archive"
+        } else {
+            ""
+        },
+        path.to_string_lossy()
+    );
+
+    Ok(())
+}
+
+fn import(path: &Path, download_archive: Option<&Path>) -> Result<()> {
+    let mut zip = ZipArchive::new(File::open(path)?)?;
+
+    let manifest: Manifest = {
+        let mut content = vec![];
+        zip.by_name(MANIFEST_FILE)?.read_to_end(&mut content)?;
+        serde_json::from_slice(&content)?
+    };
+    if manifest.version > EXPORT_VERSION {
+        bail!(
+            "{} was exported by crunchy-cli {} and has format version {} which isn't supported by this version",
+            path.to_string_lossy(),
+            manifest.crunchy_cli_version,
+            manifest.version
+        )
+    }
+
+    let state_names: Vec<String> = zip
+        .file_names()
+        .filter(|name| name.starts_with(RESUME_DIRECTORY) && name.ends_with(".json"))
+        .map(|name| name.to_string())
+        .collect();
+    let mut resume_states = 0;
+    for state_name in state_names {
+        // the names come from the archive, so only plain resume files are written into the temp
+        // directory and nothing outside of it
+        let Some(file_name) = plain_file_name(
+            zip.by_name(&state_name)?.enclosed_name(),
+            RESUME_FILE_PREFIX,
+        ) else {
+            warn!("Skipping {} as it isn't a resume state", state_name);
+            continue;
+        };
+        let state_path = temp_directory().join(&file_name);
+        if state_path.exists() {
+            info!("Skipping {} as it also exists locally", file_name);
+            continue;
+        }
+
+        let mut content = vec![];
+        zip.by_name(&state_name)?.read_to_end(&mut content)?;
+        let mut state = match ResumeState::from_slice(&content) {
+            Ok(state) => state,
+            Err(e) => {
+                warn!("Skipping resume state {}: {}", file_name, e);
+                continue;
+            }
+        };
+        let Some(data_file_name) = plain_file_name(
+            state
+                .file
+                .file_name()
+                .map(|name| Path::new(RESUME_DIRECTORY).join(name)),
+            ".crunchy-cli_",
+        )
+        .filter(|name| !name.starts_with(RESUME_FILE_PREFIX)) else {
+            warn!(
+                "Skipping resume state {} as its data file is invalid",
+                file_name
+            );
+            continue;
+        };
+
+        // the temp directory may be different on this machine
+        state.file = temp_directory().join(&data_file_name);
+        state.path = state_path;
+        io::copy(
+            &mut zip.by_name(&format!("{}{}", RESUME_DIRECTORY, data_file_name))?,
+            &mut File::create(&state.file)?,
+        )?;
+        state.write()?;
+        resume_states += 1
+    }
+
+    if let Some(download_archive) = download_archive {
+        match zip.by_name(DOWNLOAD_ARCHIVE_FILE) {
+            Ok(mut file) => {
+                let mut content = String::new();
+                file.read_to_string(&mut content)?;
+                let mut archive = DownloadArchive::open(download_archive)?;
+                for identifier in content.lines().filter(|l| !l.trim().is_empty()) {
+                    archive.record(identifier.trim())?
+                }
+            }
+            Err(_) => warn!("{} contains no download archive", path.to_string_lossy()),
+        }
+    }
+
+    info!(
+        "Imported {} interrupted download(s) from {}",
+        resume_states,
+        path.to_string_lossy()
+    );
+
+    Ok(())
+}
+
+/// The name of a file in the resume directory of an exported zip file, if it's a plain file name
+/// which starts with `prefix`.
+fn plain_file_name(path: Option<PathBuf>, prefix: &str) -> Option<String> {
+    let path = path?;
+    let mut components = path.strip_prefix(RESUME_DIRECTORY).ok()?.components();
+    let (Some(Component::Normal(name)), None) = (components.next(), components.next()) else {
+        return None;
+    };
+    let name = name.to_str()?;
+    name.starts_with(prefix).then(|| name.to_string())
+}
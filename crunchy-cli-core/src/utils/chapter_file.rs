@@ -0,0 +1,155 @@
+use crate::utils::locale::iso_639_2_code;
+use crate::utils::nfo::escape_xml;
+use anyhow::Result;
+use crunchyroll_rs::Locale;
+use log::debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Format of a chapter file which is written next to the output file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChapterFileFormat {
+    /// ffmpeg metadata file, the same which is used to embed the chapters.
+    FFMetadata,
+    /// Matroska chapter xml, as used by mkvmerge and mkvpropedit.
+    MkvXml,
+    /// OGM chapter file (`CHAPTER01=...`), which most muxers and players understand.
+    Ogm,
+    /// Kodi edl file, every chapter except the episode content is marked as commercial break.
+    Edl,
+}
+
+impl ChapterFileFormat {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "ffmetadata" => Self::FFMetadata,
+            "mkv-xml" => Self::MkvXml,
+            "ogm" => Self::Ogm,
+            "edl" => Self::Edl,
+            _ => return Err(format!("'{}' is not a valid chapter file format", s)),
+        })
+    }
+
+    fn path(&self, video: &Path) -> PathBuf {
+        match self {
+            ChapterFileFormat::FFMetadata => video.with_extension("ffmetadata"),
+            ChapterFileFormat::MkvXml => video.with_extension("chapters.xml"),
+            ChapterFileFormat::Ogm => video.with_extension("chapters.txt"),
+            ChapterFileFormat::Edl => video.with_extension("edl"),
+        }
+    }
+}
+
+/// Writes the chapters of a video into a chapter file next to it. The chapter times are in
+/// milliseconds. Chapters titled `content_title` are the actual episode and are left out of edl
+/// files.
+pub fn write_chapter_file(
+    video: &Path,
+    chapters: &[(String, u32, u32)],
+    format: ChapterFileFormat,
+    locale: Option<&Locale>,
+    content_title: &str,
+) -> Result<()> {
+    let content = match format {
+        ChapterFileFormat::FFMetadata => ffmetadata(chapters),
+        ChapterFileFormat::MkvXml => mkv_xml(chapters, locale),
+        ChapterFileFormat::Ogm => ogm(chapters),
+        ChapterFileFormat::Edl => edl(chapters, content_title),
+    };
+    let path = format.path(video);
+    fs::write(&path, content)?;
+    debug!("Wrote chapter file {}", path.to_string_lossy());
+    Ok(())
+}
+
+pub fn ffmetadata(chapters: &[(String, u32, u32)]) -> String {
+    let mut content = ";FFMETADATA1\n".to_string();
+    for (title, start, end) in chapters {
+        content.push_str(&format!(
+            "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            start, end, title
+        ))
+    }
+    content
+}
+
+/// If `locale` is given, the chapter titles are tagged with its language.
+pub fn mkv_xml(chapters: &[(String, u32, u32)], locale: Option<&Locale>) -> String {
+    let mut content = r#"<?xml version="1.0" encoding="UTF-8"?>"#.to_string() + "\n";
+    content.push_str("<Chapters>\n");
+    content.push_str("  <EditionEntry>\n");
+    for (title, start, end) in chapters {
+        content.push_str("    <ChapterAtom>\n");
+        content.push_str(&format!(
+            "      <ChapterTimeStart>{}</ChapterTimeStart>\n",
+            format_chapter_time(*start)
+        ));
+        content.push_str(&format!(
+            "      <ChapterTimeEnd>{}</ChapterTimeEnd>\n",
+            format_chapter_time(*end)
+        ));
+        content.push_str("      <ChapterDisplay>\n");
+        content.push_str(&format!(
+            "        <ChapterString>{}</ChapterString>\n",
+            escape_xml(title)
+        ));
+        if let Some(locale) = locale {
+            if let Some(code) = iso_639_2_code(locale) {
+                content.push_str(&format!(
+                    "        <ChapterLanguage>{}</ChapterLanguage>\n",
+                    code
+                ))
+            }
+            content.push_str(&format!(
+                "        <ChapLanguageIETF>{}</ChapLanguageIETF>\n",
+                locale
+            ))
+        }
+        content.push_str("      </ChapterDisplay>\n");
+        content.push_str("    </ChapterAtom>\n");
+    }
+    content.push_str("  </EditionEntry>\n");
+    content.push_str("</Chapters>\n");
+    content
+}
+
+/// Only contains the chapter starts, the format has no chapter ends.
+fn ogm(chapters: &[(String, u32, u32)]) -> String {
+    chapters
+        .iter()
+        .enumerate()
+        .map(|(i, (title, start, _))| {
+            format!(
+                "CHAPTER{0:02}={1}\nCHAPTER{0:02}NAME={2}\n",
+                i + 1,
+                format_chapter_time(*start),
+                title
+            )
+        })
+        .collect()
+}
+
+fn edl(chapters: &[(String, u32, u32)], content_title: &str) -> String {
+    chapters
+        .iter()
+        .filter(|(title, _, _)| title != content_title)
+        .map(|(_, start, end)| {
+            format!(
+                "{:.3}\t{:.3}\t3\n",
+                *start as f32 / 1000.0,
+                *end as f32 / 1000.0
+            )
+        })
+        .collect()
+}
+
+/// Formats milliseconds as `HH:MM:SS.mmm`.
+fn format_chapter_time(millis: u32) -> String {
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
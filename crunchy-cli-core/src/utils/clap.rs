@@ -1,12 +1,32 @@
-use crate::utils::parse::parse_resolution;
-use crunchyroll_rs::media::Resolution;
+use crate::utils::download::is_known_font;
+use crate::utils::parse::{parse_resolution, ResolutionSelector};
 use regex::Regex;
 use reqwest::Proxy;
+use std::time::Duration;
 
-pub fn clap_parse_resolution(s: &str) -> Result<Resolution, String> {
+pub fn clap_parse_resolution(s: &str) -> Result<ResolutionSelector, String> {
     parse_resolution(s.to_string()).map_err(|e| e.to_string())
 }
 
+/// Parses a `<font>=<fallback>` pair. `<font>` may be `*` to use the fallback for every unknown
+/// font.
+pub fn clap_parse_font_fallback(s: &str) -> Result<(String, String), String> {
+    let Some((font, fallback)) = s.split_once('=') else {
+        return Err(format!(
+            "'{}' is not a valid font fallback, it must be in format <font>=<fallback>",
+            s
+        ));
+    };
+    let (font, fallback) = (font.trim(), fallback.trim());
+    if !is_known_font(fallback) {
+        return Err(format!(
+            "'{}' is not a font which Crunchyroll provides and can't be used as fallback",
+            fallback
+        ));
+    }
+    Ok((font.to_string(), fallback.to_string()))
+}
+
 pub fn clap_parse_proxies(s: &str) -> Result<(Option<Proxy>, Option<Proxy>), String> {
     let double_proxy_regex =
         Regex::new(r"^(?P<first>(https?|socks5h?)://.+):(?P<second>(https?|socks5h?)://.+)$")
@@ -19,30 +39,63 @@ pub fn clap_parse_proxies(s: &str) -> Result<(Option<Proxy>, Option<Proxy>), Str
         let first = capture.name("first").unwrap().as_str();
         let second = capture.name("second").unwrap().as_str();
         Ok((
-            Some(Proxy::all(first).map_err(|e| format!("first proxy: {e}"))?),
-            Some(Proxy::all(second).map_err(|e| format!("second proxy: {e}"))?),
+            Some(parse_proxy(first).map_err(|e| format!("first proxy: {e}"))?),
+            Some(parse_proxy(second).map_err(|e| format!("second proxy: {e}"))?),
         ))
     } else if s.starts_with(':') {
         // checks if the input is formatted like ':https://example.com' and returns a proxy on the
         // second tuple position
-        Ok((
-            None,
-            Some(Proxy::all(s.trim_start_matches(':')).map_err(|e| e.to_string())?),
-        ))
+        Ok((None, Some(parse_proxy(s.trim_start_matches(':'))?)))
     } else if s.ends_with(':') {
         // checks if the input is formatted like 'https://example.com:' and returns a proxy on the
         // first tuple position
-        Ok((
-            Some(Proxy::all(s.trim_end_matches(':')).map_err(|e| e.to_string())?),
-            None,
-        ))
+        Ok((Some(parse_proxy(s.trim_end_matches(':'))?), None))
     } else {
         // returns the same proxy for both tuple positions
-        let proxy = Proxy::all(s).map_err(|e| e.to_string())?;
+        let proxy = parse_proxy(s)?;
         Ok((Some(proxy.clone()), Some(proxy)))
     }
 }
 
+/// Parses a http(s) or socks5 proxy url. Credentials (`<scheme>://<user>:<password>@<host>`) may
+/// contain characters which are reserved in urls, they're percent encoded before the url is parsed.
+pub fn parse_proxy(s: &str) -> Result<Proxy, String> {
+    let Some((scheme, rest)) = s.split_once("://") else {
+        return Err(format!("'{}' is not a valid proxy url", s));
+    };
+    if !["http", "https", "socks5", "socks5h"].contains(&scheme.to_lowercase().as_str()) {
+        return Err(format!(
+            "unsupported proxy scheme '{}', valid schemes are 'http', 'https', 'socks5' and 'socks5h'",
+            scheme
+        ));
+    }
+
+    let url = if let Some((credentials, host)) = rest.rsplit_once('@') {
+        let (user, password) = credentials.split_once(':').unwrap_or((credentials, ""));
+        format!(
+            "{}://{}:{}@{}",
+            scheme,
+            encode_credential(user),
+            encode_credential(password),
+            host
+        )
+    } else {
+        s.to_string()
+    };
+    Proxy::all(url).map_err(|e| e.to_string())
+}
+
+/// Percent encodes the characters which aren't allowed in the user or password of an url. `%` is
+/// kept, so that already encoded credentials stay valid.
+fn encode_credential(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            ':' | '@' | '/' | '?' | '#' | '[' | ']' | ' ' => format!("%{:02X}", c as u8),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
 pub fn clap_parse_speed_limit(s: &str) -> Result<u32, String> {
     let quota = s.to_lowercase();
 
@@ -59,3 +112,68 @@ pub fn clap_parse_speed_limit(s: &str) -> Result<u32, String> {
     };
     Ok(bytes)
 }
+
+pub fn clap_parse_interval(s: &str) -> Result<Duration, String> {
+    let interval = s.to_lowercase();
+
+    let (number, multiplier) = if let Some(d) = interval.strip_suffix('d') {
+        (d, 24 * 60 * 60)
+    } else if let Some(h) = interval.strip_suffix('h') {
+        (h, 60 * 60)
+    } else if let Some(m) = interval.strip_suffix('m') {
+        (m, 60)
+    } else {
+        (interval.trim_end_matches('s'), 1)
+    };
+    match number.trim().parse::<u64>() {
+        Ok(0) => Err("Interval must be greater than 0".to_string()),
+        Ok(n) => Ok(Duration::from_secs(n * multiplier)),
+        Err(_) => Err("Invalid interval".to_string()),
+    }
+}
+
+pub fn clap_parse_crf(s: &str) -> Result<u8, String> {
+    match s.trim().parse::<u8>() {
+        Ok(crf) if crf <= 63 => Ok(crf),
+        _ => Err(format!(
+            "'{}' is not a valid crf, it must be between 0 and 63",
+            s
+        )),
+    }
+}
+
+/// Parses a bitrate as ffmpeg understands it, e.g. `2500k` or `2.5M`.
+pub fn clap_parse_bitrate(s: &str) -> Result<String, String> {
+    let bitrate = s.trim();
+    let number = bitrate.trim_end_matches(['k', 'K', 'm', 'M']);
+    if bitrate.len() - number.len() > 1 {
+        return Err(format!("'{}' is not a valid bitrate", s));
+    }
+    match number.parse::<f64>() {
+        Ok(n) if n > 0.0 && n.is_finite() => Ok(bitrate.to_string()),
+        _ => Err(format!(
+            "'{}' is not a valid bitrate, it must be a number with an optional 'k' or 'M' suffix, e.g. '2500k'",
+            s
+        )),
+    }
+}
+
+/// Parses a size in bytes or with a unit, e.g. `500M` or `1.5G`. Units are binary, so `1K` is 1024
+/// bytes.
+pub fn clap_parse_size(s: &str) -> Result<u64, String> {
+    let size = s.trim().to_lowercase();
+    let size = size.trim_end_matches("ib").trim_end_matches('b').trim_end();
+
+    let (number, multiplier) = match size.char_indices().last() {
+        Some((i, 'k')) => (&size[..i], 1u64 << 10),
+        Some((i, 'm')) => (&size[..i], 1 << 20),
+        Some((i, 'g')) => (&size[..i], 1 << 30),
+        Some((i, 't')) => (&size[..i], 1 << 40),
+        _ => (size, 1),
+    };
+    match number.trim().parse::<f64>() {
+        Ok(n) if n > 0.0 && n.is_finite() => Ok((n * multiplier as f64) as u64),
+        Ok(_) => Err("Size must be greater than 0".to_string()),
+        Err(_) => Err(format!("'{}' is not a valid size", s)),
+    }
+}
@@ -0,0 +1,82 @@
+use crunchyroll_rs::media::StreamSegment;
+use std::time::Duration;
+
+/// Time range of an episode which is downloaded instead of the whole episode.
+#[derive(Clone, Copy, Debug)]
+pub struct Clip {
+    pub start: Duration,
+    pub end: Duration,
+}
+
+impl Clip {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let Some((start, end)) = s.split_once('-') else {
+            return Err(format!(
+                "'{}' is not a valid clip, it must be in format <start>-<end>, e.g. '00:05:30-00:08:00'",
+                s
+            ));
+        };
+        let clip = Self {
+            start: parse_timestamp(start)?,
+            end: parse_timestamp(end)?,
+        };
+        if clip.start >= clip.end {
+            return Err("the start of the clip must be before its end".to_string());
+        }
+        Ok(clip)
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+
+    /// The segments which overlap with the clip, and the time at which the first of them starts.
+    /// Segments without a length are initialization segments which every file needs, so they're
+    /// always kept.
+    pub fn select_segments(&self, segments: Vec<StreamSegment>) -> (Vec<StreamSegment>, Duration) {
+        let mut selected = vec![];
+        let mut offset = None;
+        let mut position = Duration::ZERO;
+        for segment in segments {
+            let start = position;
+            position += segment.length;
+            if segment.length.is_zero() {
+                selected.push(segment)
+            } else if position > self.start && start < self.end {
+                offset.get_or_insert(start);
+                selected.push(segment)
+            }
+        }
+        (selected, offset.unwrap_or_default())
+    }
+
+    /// Where the clip starts in a file which consists of the segments returned by
+    /// [`Clip::select_segments`].
+    pub fn start_in(&self, segments: Vec<StreamSegment>) -> Duration {
+        let (_, offset) = self.select_segments(segments);
+        self.start.saturating_sub(offset)
+    }
+}
+
+/// Parses `[[HH:]MM:]SS[.mmm]`.
+fn parse_timestamp(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("'{}' is not a valid time, it must be in format HH:MM:SS", s);
+
+    let mut seconds = 0.0;
+    let parts: Vec<&str> = s.trim().split(':').collect();
+    if parts.len() > 3 {
+        return Err(invalid());
+    }
+    for (i, part) in parts.iter().enumerate() {
+        let value = if i == parts.len() - 1 {
+            part.parse::<f64>().map_err(|_| invalid())?
+        } else {
+            part.parse::<u64>().map_err(|_| invalid())? as f64
+        };
+        if !value.is_finite() || value < 0.0 {
+            return Err(invalid());
+        }
+        seconds = seconds * 60.0 + value
+    }
+    Ok(Duration::from_secs_f64(seconds))
+}
@@ -0,0 +1,256 @@
+use anyhow::{bail, Result};
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, Command};
+use std::fs;
+use std::path::PathBuf;
+use toml::{Table, Value};
+
+/// Path of the config file which stores default values for command line flags.
+pub fn config_file_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|config_dir| config_dir.join("crunchy-cli").join("config.toml"))
+}
+
+/// Reads the config file. Returns `None` if it doesn't exist.
+pub fn load_config() -> Result<Option<Table>> {
+    let Some(path) = config_file_path() else {
+        return Ok(None);
+    };
+    if !path.exists() {
+        return Ok(None);
+    }
+    match fs::read_to_string(&path)?.parse::<Table>() {
+        Ok(config) => Ok(Some(config)),
+        Err(e) => bail!("Failed to parse {}: {}", path.to_string_lossy(), e),
+    }
+}
+
+/// Adds the flags of the config file to `args`. Top level keys apply to every command which has a
+/// flag with this name, keys in a table named after a command (e.g. `[download]`) only to this
+/// command and take precedence over top level keys. Flags which are given on the command line
+/// always override the config, config values which conflict with them are ignored.
+pub fn apply_config(mut command: Command, config: &Table, args: &[String]) -> Result<Vec<String>> {
+    command.build();
+    validate_config(&command, config)?;
+
+    // if the arguments are invalid (or help is requested), clap should report it without any
+    // config values
+    let Ok(matches) = command.clone().try_get_matches_from(args) else {
+        return Ok(args.to_vec());
+    };
+    let Some((name, sub_matches)) = matches.subcommand() else {
+        return Ok(args.to_vec());
+    };
+    let subcommand = command.find_subcommand(name).unwrap();
+
+    let config_args = command_config_args(subcommand, config, |id| {
+        sub_matches.value_source(id) == Some(ValueSource::CommandLine)
+    })?;
+
+    // the config flags are inserted directly after the subcommand, so that they don't get mixed
+    // up with positional arguments
+    let position = args
+        .iter()
+        .skip(1)
+        .position(|arg| arg == name)
+        .map_or(args.len(), |pos| pos + 2);
+    let mut merged = args[..position].to_vec();
+    merged.extend(config_args);
+    merged.extend_from_slice(&args[position..]);
+    Ok(merged)
+}
+
+/// The values of all flags of every command after the config file is applied.
+pub fn effective_config(mut command: Command, config: &Table) -> Result<Table> {
+    command.build();
+    validate_config(&command, config)?;
+
+    let mut effective = Table::new();
+    let subcommands: Vec<Command> = command.get_subcommands().cloned().collect();
+
+    // global flags are shown once at the top level, with only the top level config values applied
+    let top_level: Table = config
+        .iter()
+        .filter(|(_, v)| !v.is_table())
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    if let Some(subcommand) = subcommands.first() {
+        let matches = parse_config_args(&command, subcommand, &top_level)?;
+        for arg in subcommand.get_arguments().filter(|a| a.is_global_set()) {
+            if let Some(value) = effective_value(arg, &matches) {
+                effective.insert(arg.get_long().unwrap().to_string(), value);
+            }
+        }
+    }
+
+    for subcommand in &subcommands {
+        let command_table = config.get(subcommand.get_name()).and_then(|v| v.as_table());
+        let matches = parse_config_args(&command, subcommand, config)?;
+
+        let mut table = Table::new();
+        for arg in subcommand.get_arguments() {
+            // global flags are only shown if they're overwritten for this command
+            if arg.is_global_set()
+                && !command_table.is_some_and(|t| arg.get_long().is_some_and(|l| t.contains_key(l)))
+            {
+                continue;
+            }
+            if let Some(value) = effective_value(arg, &matches) {
+                table.insert(arg.get_long().unwrap().to_string(), value);
+            }
+        }
+        if !table.is_empty() {
+            effective.insert(subcommand.get_name().to_string(), Value::Table(table));
+        }
+    }
+
+    Ok(effective)
+}
+
+/// Parses the config values of `subcommand` without any other arguments.
+fn parse_config_args(
+    command: &Command,
+    subcommand: &Command,
+    config: &Table,
+) -> Result<clap::ArgMatches> {
+    let name = subcommand.get_name();
+    let mut args = vec![command.get_name().to_string(), name.to_string()];
+    args.extend(command_config_args(subcommand, config, |_| false)?);
+
+    // positional arguments like urls aren't part of the config, so they mustn't be required
+    let mut matches = command
+        .clone()
+        .mut_subcommand(name, |s| {
+            s.arg_required_else_help(false).mut_args(|a| {
+                if a.is_positional() {
+                    a.required(false)
+                } else {
+                    a
+                }
+            })
+        })
+        .try_get_matches_from(args)?;
+    Ok(matches.remove_subcommand().unwrap().1)
+}
+
+fn effective_value(arg: &Arg, matches: &clap::ArgMatches) -> Option<Value> {
+    if arg.get_long().is_none() || arg.is_positional() || arg.is_hide_set() {
+        return None;
+    }
+    let values: Vec<String> = matches
+        .get_raw(arg.get_id().as_str())?
+        .map(|v| v.to_string_lossy().to_string())
+        .collect();
+    // credentials are printed to the terminal, so they're hidden
+    if arg.get_id() == "credentials" {
+        return Some(Value::String("***".to_string()));
+    }
+
+    match arg.get_action() {
+        ArgAction::SetTrue | ArgAction::SetFalse => {
+            Some(Value::Boolean(values.first().is_some_and(|v| v == "true")))
+        }
+        ArgAction::Append => Some(Value::Array(
+            values.into_iter().map(Value::String).collect(),
+        )),
+        _ if values.len() > 1 => Some(Value::Array(
+            values.into_iter().map(Value::String).collect(),
+        )),
+        _ => values.into_iter().next().map(Value::String),
+    }
+}
+
+/// Converts the config values which apply to `command` to command line flags. Flags for which
+/// `is_explicit` returns true, and flags which conflict with one of them, are skipped.
+fn command_config_args(
+    command: &Command,
+    config: &Table,
+    is_explicit: impl Fn(&str) -> bool,
+) -> Result<Vec<String>> {
+    let explicit: Vec<&Arg> = command
+        .get_arguments()
+        .filter(|a| is_explicit(a.get_id().as_str()))
+        .collect();
+    // clap only stores a conflict at the argument which declares it, so both sides are checked
+    let conflicts_with_explicit = |arg: &Arg| {
+        let conflicts = command.get_arg_conflicts_with(arg);
+        explicit.iter().any(|explicit| {
+            conflicts.iter().any(|c| c.get_id() == explicit.get_id())
+                || command
+                    .get_arg_conflicts_with(explicit)
+                    .iter()
+                    .any(|c| c.get_id() == arg.get_id())
+        })
+    };
+
+    let mut values: Vec<(&Arg, &Value)> = vec![];
+    let command_table = config.get(command.get_name()).and_then(|v| v.as_table());
+    for arg in command.get_arguments() {
+        let Some(long) = arg.get_long() else {
+            continue;
+        };
+        let value = command_table
+            .and_then(|t| t.get(long))
+            .or_else(|| config.get(long).filter(|v| !v.is_table()));
+        if let Some(value) = value {
+            if !is_explicit(arg.get_id().as_str()) && !conflicts_with_explicit(arg) {
+                values.push((arg, value))
+            }
+        }
+    }
+
+    let mut args = vec![];
+    for (arg, value) in values {
+        let long = arg.get_long().unwrap();
+        if !arg.get_action().takes_values() {
+            match value {
+                Value::Boolean(true) => args.push(format!("--{}", long)),
+                Value::Boolean(false) => (),
+                _ => bail!("Config value of `{}` must be `true` or `false`", long),
+            }
+            continue;
+        }
+
+        let values = match value {
+            Value::Array(array) => array.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                Value::Integer(_) | Value::Float(_) | Value::Boolean(_) => value.to_string(),
+                _ => bail!("Config value of `{}` has an unsupported type", long),
+            };
+            args.push(format!("--{}={}", long, value))
+        }
+    }
+    Ok(args)
+}
+
+/// Checks that every key in the config file is a flag of the command (or table) it's set for.
+fn validate_config(command: &Command, config: &Table) -> Result<()> {
+    let has_flag = |command: &Command, name: &str| {
+        command
+            .get_arguments()
+            .any(|a| !a.is_positional() && a.get_long() == Some(name))
+    };
+
+    for (key, value) in config {
+        if let Some(table) = value.as_table() {
+            let Some(subcommand) = command.find_subcommand(key) else {
+                bail!("Unknown command `{}` in config file", key)
+            };
+            for key in table.keys() {
+                if !has_flag(subcommand, key) {
+                    bail!(
+                        "Unknown option `{}` for `{}` in config file",
+                        key,
+                        subcommand.get_name()
+                    )
+                }
+            }
+        } else if !has_flag(command, key) && !command.get_subcommands().any(|s| has_flag(s, key)) {
+            bail!("Unknown option `{}` in config file", key)
+        }
+    }
+    Ok(())
+}
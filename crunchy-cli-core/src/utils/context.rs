@@ -1,9 +1,12 @@
+use crate::utils::proxy_pool::ProxyPoolService;
 use crate::utils::rate_limit::RateLimiterService;
 use crunchyroll_rs::Crunchyroll;
 use reqwest::Client;
 
+#[derive(Clone)]
 pub struct Context {
     pub crunchy: Crunchyroll,
     pub client: Client,
     pub rate_limiter: Option<RateLimiterService>,
+    pub proxy_pool: Option<ProxyPoolService>,
 }
@@ -0,0 +1,149 @@
+use crate::utils::error::DownloadError;
+use crate::utils::ffmpeg::ffmpeg_binary;
+use crate::utils::log::{recent_lines, scrub};
+use anyhow::Result;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Mutex;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+lazy_static! {
+    static ref FFMPEG_ARGS: Mutex<Option<Vec<String>>> = Mutex::new(None);
+    static ref MANIFEST: Mutex<Option<serde_json::Value>> = Mutex::new(None);
+    static ref THROUGHPUT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+    static ref URL_USERINFO_REGEX: Regex = Regex::new(r"://[^/@\s]+@").unwrap();
+    static ref URL_QUERY_REGEX: Regex = Regex::new(r"(https?://[^\s?]+)\?\S*").unwrap();
+}
+
+/// Remembers the arguments of the last ffmpeg invocation.
+pub(crate) fn record_ffmpeg_args(args: &[String]) {
+    *FFMPEG_ARGS.lock().unwrap() = Some(args.to_vec())
+}
+
+/// Remembers the stream metadata of the last download.
+pub(crate) fn record_manifest(manifest: serde_json::Value) {
+    *MANIFEST.lock().unwrap() = Some(manifest)
+}
+
+/// Maximum number of throughput lines which are kept for the debug bundle.
+const MAX_THROUGHPUT_LINES: usize = 200;
+
+/// Remembers the download speeds of the last downloaded streams.
+pub(crate) fn record_throughput(lines: &[String]) {
+    let mut throughput = THROUGHPUT.lock().unwrap();
+    throughput.extend(lines.iter().cloned());
+    while throughput.len() > MAX_THROUGHPUT_LINES {
+        throughput.pop_front();
+    }
+}
+
+/// Writes everything which is useful for bug reports into a zip file in the current directory.
+/// Credentials, proxy logins, url query parameters (which contain stream tokens) and other secrets
+/// which are hidden in the log output are removed.
+pub(crate) fn write_debug_bundle(args: &[String], err: &anyhow::Error) -> Result<PathBuf> {
+    let secrets = secrets(args);
+    let sanitize = |text: &str| {
+        let mut text = URL_USERINFO_REGEX.replace_all(text, "://***@").to_string();
+        text = URL_QUERY_REGEX.replace_all(&text, "$1?***").to_string();
+        for secret in &secrets {
+            text = text.replace(secret, "***")
+        }
+        scrub(&text)
+    };
+
+    let mut files = vec![
+        (
+            "command.txt",
+            shlex::try_join(args.iter().map(|a| a.as_str())).unwrap_or_else(|_| args.join(" ")),
+        ),
+        ("versions.txt", versions()),
+        (
+            "error.txt",
+            format!(
+                "{:#}\nclass: {}\n",
+                err,
+                DownloadError::classify(err).map_or("unknown", |e| e.class())
+            ),
+        ),
+        ("log.txt", recent_lines().join("\n")),
+    ];
+    if let Some(ffmpeg_args) = FFMPEG_ARGS.lock().unwrap().as_ref() {
+        files.push(("ffmpeg_args.txt", ffmpeg_args.join("\n")))
+    }
+    if let Some(manifest) = MANIFEST.lock().unwrap().as_ref() {
+        files.push(("manifest.json", serde_json::to_string_pretty(manifest)?))
+    }
+    let throughput = THROUGHPUT.lock().unwrap();
+    if !throughput.is_empty() {
+        files.push((
+            "throughput.txt",
+            throughput
+                .iter()
+                .cloned()
+                .collect::<Vec<String>>()
+                .join("\n"),
+        ))
+    }
+
+    let path = PathBuf::from(format!(
+        "crunchy-cli-debug-{}.zip",
+        chrono::Local::now().format("%Y%m%d-%H%M%S")
+    ));
+    let mut zip = ZipWriter::new(File::create(&path)?);
+    for (name, content) in files {
+        zip.start_file(name, SimpleFileOptions::default())?;
+        zip.write_all(sanitize(&content).as_bytes())?
+    }
+    zip.finish()?;
+
+    Ok(path)
+}
+
+/// Values which must not end up in a debug bundle.
+fn secrets(args: &[String]) -> Vec<String> {
+    let mut secrets = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--credentials" {
+            secrets.extend(args.next().cloned())
+        } else if let Some(credentials) = arg.strip_prefix("--credentials=") {
+            secrets.push(credentials.to_string())
+        }
+    }
+    // the email and password are also logged on their own, e.g. in the debug output of the cli
+    // input
+    for credentials in secrets.clone() {
+        if let Some((email, password)) = credentials.split_once(':') {
+            secrets.extend([email.to_string(), password.to_string()])
+        }
+    }
+    secrets.retain(|s| !s.is_empty());
+    secrets
+}
+
+fn versions() -> String {
+    let ffmpeg = Command::new(ffmpeg_binary())
+        .arg("-version")
+        .output()
+        .ok()
+        .and_then(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .next()
+                .map(|l| l.to_string())
+        })
+        .unwrap_or_else(|| "not found".to_string());
+    format!(
+        "crunchy-cli: {}\nos: {} ({})\nffmpeg: {}\n",
+        crate::version(),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        ffmpeg
+    )
+}
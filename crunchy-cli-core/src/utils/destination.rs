@@ -0,0 +1,91 @@
+use crate::utils::os::{is_special_file, temp_directory};
+use anyhow::{bail, Result};
+use std::path::{Component, Path, PathBuf};
+
+/// Special files which are allowed as destination besides pipes.
+#[cfg(not(target_os = "windows"))]
+const ALLOWED_DEVICES: [&str; 4] = ["/dev/null", "/dev/stdout", "/dev/stderr", "/dev/tty"];
+
+/// Checks if it's safe to write the output file to `dst`. Rejected are destinations inside
+/// directories or files which are created by crunchy-cli as temporary storage, destinations which
+/// are also used as input and device files which aren't meant to store data (e.g. disks).
+pub fn validate_destination(dst: &Path, inputs: &[PathBuf]) -> Result<()> {
+    if dst.to_string_lossy() == "-" {
+        return Ok(());
+    }
+
+    if is_special_file(dst) {
+        return validate_special_file(dst);
+    }
+
+    let absolute_dst = absolute(dst);
+    if let Ok(relative) = absolute_dst.strip_prefix(absolute(&temp_directory())) {
+        if relative.components().any(|c| {
+            matches!(c, Component::Normal(name) if name.to_string_lossy().starts_with(".crunchy-cli_"))
+        }) {
+            bail!(
+                "Output file {} would overwrite temporary files of crunchy-cli, choose another destination",
+                dst.to_string_lossy()
+            )
+        }
+    }
+
+    if let Some(input) = inputs.iter().find(|i| absolute(i) == absolute_dst) {
+        bail!(
+            "Output file {} is also used as input ({}), choose another destination",
+            dst.to_string_lossy(),
+            input.to_string_lossy()
+        )
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "windows"))]
+fn validate_special_file(dst: &Path) -> Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let file_type = dst.metadata()?.file_type();
+    // paths like `/dev/stdout` are symlinks, so the given and the resolved path are checked
+    let is_allowed_device = [dst.to_path_buf(), absolute(dst)].iter().any(|path| {
+        ALLOWED_DEVICES.iter().any(|d| path == Path::new(d))
+            || ["/dev/fd", "/dev/pts", "/proc/self/fd"]
+                .iter()
+                .any(|p| path.starts_with(p))
+    });
+    if file_type.is_fifo() || (file_type.is_char_device() && is_allowed_device) {
+        Ok(())
+    } else {
+        bail!(
+            "Output file {} is a device file which is not supported as destination",
+            dst.to_string_lossy()
+        )
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn validate_special_file(_dst: &Path) -> Result<()> {
+    // windows has no device files which can be written to accidentally
+    Ok(())
+}
+
+/// Makes the path absolute and resolves symlinks as far as the path exists.
+fn absolute(path: &Path) -> PathBuf {
+    let mut existing = path.to_path_buf();
+    let mut rest = vec![];
+    while !existing.as_os_str().is_empty() && !existing.exists() {
+        let Some(name) = existing.file_name() else {
+            break;
+        };
+        rest.push(name.to_os_string());
+        existing.pop();
+    }
+
+    let mut absolute = if existing.as_os_str().is_empty() {
+        std::env::current_dir().unwrap_or_default()
+    } else {
+        existing.canonicalize().unwrap_or(existing)
+    };
+    absolute.extend(rest.iter().rev());
+    absolute
+}
@@ -1,34 +1,59 @@
-use crate::utils::ffmpeg::FFmpegPreset;
+use crate::utils::chapter_file::{ffmetadata, mkv_xml, write_chapter_file, ChapterFileFormat};
+use crate::utils::clip::Clip;
+use crate::utils::debug_bundle;
+use crate::utils::destination::validate_destination;
+use crate::utils::error::DownloadError;
+use crate::utils::ffmpeg::{args, ffmpeg_binary, ffprobe_binary, FFmpegPass, FFmpegPreset};
 use crate::utils::filter::real_dedup_vec;
 use crate::utils::fmt::format_time_delta;
-use crate::utils::log::progress;
-use crate::utils::os::{cache_dir, is_special_file, temp_directory, temp_named_pipe, tempfile};
+use crate::utils::locale::LanguageTagging;
+use crate::utils::log::{
+    add_progress_bar, download_bar_style, ffmpeg_bar_style, labeled_spinner_style, progress,
+    progress_enabled, progress_update, segment_download_bar_style, set_terminal_title_percent,
+};
+use crate::utils::os::{
+    cache_dir, has_mkvpropedit, is_network_path, is_special_file, named_tempfile, temp_directory,
+    temp_named_pipe, temp_pipe_writer, tempdir, tempfile, TempPipeWriter, REMUX_FILE_PREFIX,
+    RESUME_FILE_PREFIX,
+};
+use crate::utils::proxy_pool::ProxyPoolService;
 use crate::utils::rate_limit::RateLimiterService;
-use crate::utils::sync::{sync_audios, SyncAudio};
+use crate::utils::schedule::Schedule;
+use crate::utils::segment_recording::SegmentRecording;
+use crate::utils::sync::{
+    fingerprint_audio, sync_audios, sync_plot, RecurringParts, SeasonFingerprintCache, SyncAudio,
+    SyncSample, SyncWindow,
+};
+use crate::utils::timeout::with_stage_timeout;
 use anyhow::{bail, Result};
 use chrono::{NaiveTime, TimeDelta};
-use crunchyroll_rs::media::{SkipEvents, SkipEventsEvent, StreamData, StreamSegment, Subtitle};
+use crunchyroll_rs::media::{SkipEvents, StreamData, StreamSegment, Subtitle};
 use crunchyroll_rs::Locale;
-use indicatif::{ProgressBar, ProgressDrawTarget, ProgressFinish, ProgressStyle};
-use log::{debug, warn, LevelFilter};
+use futures_util::{StreamExt, TryStreamExt};
+use indicatif::{ProgressBar, ProgressFinish};
+use log::{debug, info, warn};
 use regex::Regex;
-use reqwest::Client;
+use reqwest::{Client, Response};
 use rsubs_lib::{SSA, VTT};
+use serde::{Deserialize, Serialize};
 use std::borrow::Borrow;
 use std::cmp::Ordering;
-use std::collections::{BTreeMap, HashMap};
-use std::io::Write;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
 use std::ops::Add;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering as AtomicOrdering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::{env, fs};
-use tempfile::TempPath;
+use tempfile::{TempDir, TempPath};
 use time::Time;
 use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
 use tokio::select;
-use tokio::sync::mpsc::unbounded_channel;
+use tokio::sync::mpsc::channel;
 use tokio::sync::Mutex;
 use tokio::task::JoinSet;
 use tokio_util::sync::CancellationToken;
@@ -58,17 +83,52 @@ impl MergeBehavior {
 pub struct DownloadBuilder {
     client: Client,
     rate_limiter: Option<RateLimiterService>,
+    proxy_pool: Option<ProxyPoolService>,
     ffmpeg_preset: FFmpegPreset,
+    ffmpeg_two_pass: bool,
     default_subtitle: Option<Locale>,
     output_format: Option<String>,
+    content_rating: Option<String>,
+    cover_art: Option<String>,
+    thumbnail: Option<String>,
+    episode_id: Option<String>,
     audio_sort: Option<Vec<Locale>>,
     subtitle_sort: Option<Vec<Locale>>,
     force_hardsub: bool,
+    hardsub_style: Option<HardsubStyle>,
+    secondary_subtitle: Option<Locale>,
     download_fonts: bool,
+    font_fallback: Vec<(String, String)>,
+    rewrite_font_fallback: bool,
+    fonts_dir: Option<PathBuf>,
     no_closed_caption: bool,
+    sidecar_subtitles: bool,
+    sidecar_naming: SidecarNaming,
+    subtitle_format: SubtitleFormat,
+    local_mux: LocalMux,
     merge_sync_tolerance: Option<u32>,
     merge_sync_precision: Option<u32>,
+    sync_window: Option<SyncWindow>,
+    sync_plot: bool,
+    sync_reference: Option<PathBuf>,
+    extract_audio: bool,
+    fingerprint_cache: Option<SeasonFingerprintCache>,
+    synthesize_chapters: bool,
+    metadata_locale: Option<Locale>,
+    track_statistics: bool,
+    write_chapters: Option<ChapterFileFormat>,
+    cut: Vec<(f32, f32)>,
+    clip: Option<Clip>,
+    remux_only: bool,
+    resolution_fallback: bool,
+    resume: bool,
+    low_disk: bool,
     threads: usize,
+    stall_timeout: Option<Duration>,
+    slow_start: Option<Duration>,
+    schedule: Option<Schedule>,
+    segment_recording: Option<SegmentRecording>,
+    mux_timeout: Option<Duration>,
     ffmpeg_threads: Option<usize>,
     audio_locale_output_map: HashMap<Locale, String>,
     subtitle_locale_output_map: HashMap<Locale, String>,
@@ -79,17 +139,52 @@ impl DownloadBuilder {
         Self {
             client,
             rate_limiter,
+            proxy_pool: None,
             ffmpeg_preset: FFmpegPreset::default(),
+            ffmpeg_two_pass: false,
             default_subtitle: None,
             output_format: None,
+            content_rating: None,
+            cover_art: None,
+            thumbnail: None,
+            episode_id: None,
             audio_sort: None,
             subtitle_sort: None,
             force_hardsub: false,
+            hardsub_style: None,
+            secondary_subtitle: None,
             download_fonts: false,
+            font_fallback: vec![],
+            rewrite_font_fallback: false,
+            fonts_dir: None,
             no_closed_caption: false,
+            sidecar_subtitles: false,
+            sidecar_naming: SidecarNaming::default(),
+            subtitle_format: SubtitleFormat::default(),
+            local_mux: LocalMux::default(),
             merge_sync_tolerance: None,
             merge_sync_precision: None,
+            sync_window: None,
+            sync_plot: false,
+            sync_reference: None,
+            extract_audio: false,
+            fingerprint_cache: None,
+            synthesize_chapters: false,
+            metadata_locale: None,
+            track_statistics: false,
+            write_chapters: None,
+            cut: vec![],
+            clip: None,
+            remux_only: false,
+            resolution_fallback: false,
+            resume: false,
+            low_disk: false,
             threads: num_cpus::get(),
+            stall_timeout: None,
+            slow_start: None,
+            schedule: None,
+            segment_recording: None,
+            mux_timeout: None,
             ffmpeg_threads: None,
             audio_locale_output_map: HashMap::new(),
             subtitle_locale_output_map: HashMap::new(),
@@ -97,23 +192,66 @@ impl DownloadBuilder {
     }
 
     pub fn build(self) -> Downloader {
+        // a downloader is built for every episode, so this is the point where the proxy pool
+        // switches to the next proxy if it rotates per episode
+        if let Some(proxy_pool) = &self.proxy_pool {
+            proxy_pool.next_episode()
+        }
+
         Downloader {
             client: self.client,
             rate_limiter: self.rate_limiter,
+            proxy_pool: self.proxy_pool,
             ffmpeg_preset: self.ffmpeg_preset,
+            ffmpeg_two_pass: self.ffmpeg_two_pass,
             default_subtitle: self.default_subtitle,
             output_format: self.output_format,
+            content_rating: self.content_rating,
+            cover_art: self.cover_art,
+            thumbnail: self.thumbnail,
+            episode_id: self.episode_id,
             audio_sort: self.audio_sort,
             subtitle_sort: self.subtitle_sort,
 
             force_hardsub: self.force_hardsub,
+            hardsub_style: self.hardsub_style,
+            secondary_subtitle: self.secondary_subtitle,
             download_fonts: self.download_fonts,
+            font_fallback: self.font_fallback,
+            rewrite_font_fallback: self.rewrite_font_fallback,
+            fonts_dir: self.fonts_dir,
             no_closed_caption: self.no_closed_caption,
+            sidecar_subtitles: self.sidecar_subtitles,
+            sidecar_naming: self.sidecar_naming,
+            subtitle_format: self.subtitle_format,
+            local_mux: self.local_mux,
 
             merge_sync_tolerance: self.merge_sync_tolerance,
             merge_sync_precision: self.merge_sync_precision,
+            sync_window: self.sync_window,
+            sync_plot: self.sync_plot,
+            sync_reference: self.sync_reference,
+            extract_audio: self.extract_audio,
+
+            fingerprint_cache: self.fingerprint_cache,
+            synthesize_chapters: self.synthesize_chapters,
+            metadata_locale: self.metadata_locale,
+            track_statistics: self.track_statistics,
+            write_chapters: self.write_chapters,
+            cut: self.cut,
+            clip: self.clip,
+
+            remux_only: self.remux_only,
+            resolution_fallback: self.resolution_fallback,
+            resume: self.resume,
+            low_disk: self.low_disk,
 
             download_threads: self.threads,
+            stall_timeout: self.stall_timeout,
+            slow_start: self.slow_start,
+            schedule: self.schedule,
+            segment_recording: self.segment_recording,
+            mux_timeout: self.mux_timeout,
             ffmpeg_threads: self.ffmpeg_threads,
 
             formats: vec![],
@@ -145,35 +283,382 @@ struct FFmpegSubtitleMeta {
     video_idx: usize,
 }
 
+/// If the output file is generated in the temp directory and moved to its destination afterwards.
+#[derive(Clone, Debug, Default)]
+pub enum LocalMux {
+    /// Only if the destination is on a network share.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl LocalMux {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "auto" => Self::Auto,
+            "always" => Self::Always,
+            "never" => Self::Never,
+            _ => return Err(format!("'{}' is not a valid local mux mode", s)),
+        })
+    }
+
+    fn enabled(&self, dst: &Path) -> bool {
+        if is_special_file(dst) || dst.to_string_lossy() == "-" {
+            return false;
+        }
+        match self {
+            LocalMux::Auto => is_network_path(dst),
+            LocalMux::Always => true,
+            LocalMux::Never => false,
+        }
+    }
+}
+
+/// File naming convention of sidecar subtitles.
+#[derive(Clone, Debug, Default)]
+pub enum SidecarNaming {
+    /// `video.en-US.ass`, closed captions as `video.en-US.cc.ass`.
+    #[default]
+    Default,
+    /// `video.en.ass`, closed captions as `video.en.sdh.ass`.
+    Plex,
+    /// `video.en-US.ass`, closed captions as `video.en-US.sdh.ass` and the default subtitle as
+    /// `video.en-US.default.ass`.
+    Jellyfin,
+    /// `video.English.ass`, closed captions as `video.English.sdh.ass`.
+    Kodi,
+}
+
+impl SidecarNaming {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "default" => Self::Default,
+            "plex" => Self::Plex,
+            "jellyfin" => Self::Jellyfin,
+            "kodi" => Self::Kodi,
+            _ => return Err(format!("'{}' is not a valid sidecar naming", s)),
+        })
+    }
+
+    fn language(
+        &self,
+        locale: &Locale,
+        subtitles: &[FFmpegSubtitleMeta],
+        subtitle_locale_output_map: &HashMap<Locale, String>,
+    ) -> String {
+        let short = |l: &Locale| match self {
+            Self::Default => subtitle_locale_output_map
+                .get(l)
+                .cloned()
+                .unwrap_or(l.to_string()),
+            Self::Plex => LanguageTagging::IETF.for_locale(l),
+            Self::Jellyfin => l.to_string(),
+            Self::Kodi => l
+                .to_human_readable()
+                .split(" (")
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+        };
+
+        let language = short(locale);
+        // multiple locales may have the same short name, e.g. 'es-419' and 'es-ES' are both
+        // 'es'. in this case the full locale is used, so that no subtitle overwrites another one
+        if subtitles
+            .iter()
+            .any(|s| &s.locale != locale && short(&s.locale) == language)
+        {
+            match self {
+                Self::Kodi => locale.to_human_readable(),
+                _ => locale.to_string(),
+            }
+        } else {
+            language
+        }
+    }
+}
+
+/// Parts of an episode which are downloaded exclusively, instead of a complete video.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Only {
+    /// Only the subtitles, written next to the output file.
+    Subtitles,
+    /// Only the audios, muxed or transcoded into an audio file.
+    Audio,
+}
+
+impl Only {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "subtitles" => Self::Subtitles,
+            "audio" => Self::Audio,
+            _ => return Err(format!("'{}' is not a valid download mode", s)),
+        })
+    }
+}
+
+/// Extensions of the audio files which can be written with [`Only::Audio`].
+pub const AUDIO_ONLY_CONTAINERS: [&str; 4] = ["m4a", "mka", "mp3", "flac"];
+
+/// Format in which subtitles are embedded into the output file and written as sidecar files.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SubtitleFormat {
+    #[default]
+    Ass,
+    Srt,
+    Vtt,
+}
+
+impl SubtitleFormat {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "ass" => Self::Ass,
+            "srt" => Self::Srt,
+            "vtt" => Self::Vtt,
+            _ => return Err(format!("'{}' is not a valid subtitle format", s)),
+        })
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Ass => "ass",
+            Self::Srt => "srt",
+            Self::Vtt => "vtt",
+        }
+    }
+}
+
+/// How often a failed subtitle download is retried before giving up.
+const SUBTITLE_DOWNLOAD_RETRIES: u32 = 5;
+
+/// Fields of the ass styles which can be overridden with [`HardsubStyle`].
+const ASS_STYLE_FIELDS: [&str; 22] = [
+    "Fontname",
+    "Fontsize",
+    "PrimaryColour",
+    "SecondaryColour",
+    "OutlineColour",
+    "BackColour",
+    "Bold",
+    "Italic",
+    "Underline",
+    "StrikeOut",
+    "ScaleX",
+    "ScaleY",
+    "Spacing",
+    "Angle",
+    "BorderStyle",
+    "Outline",
+    "Shadow",
+    "Alignment",
+    "MarginL",
+    "MarginR",
+    "MarginV",
+    "Encoding",
+];
+
+/// Overrides for the ass styles of subtitles which are burned into the video, e.g.
+/// `Fontname=Arial,Fontsize=28,Outline=2`. Every style of the subtitle gets the overrides.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HardsubStyle(Vec<(&'static str, String)>);
+
+impl HardsubStyle {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut overrides = vec![];
+        for part in s.split(',') {
+            let Some((field, value)) = part.split_once('=') else {
+                return Err(format!(
+                    "'{}' is not a valid style override, it must be in format <field>=<value>",
+                    part
+                ));
+            };
+            // the field names are matched case-insensitive as players don't agree on the
+            // spelling either, e.g. 'FontName' and 'Fontname'
+            let Some(field) = ASS_STYLE_FIELDS
+                .into_iter()
+                .find(|f| f.eq_ignore_ascii_case(field.trim()))
+            else {
+                return Err(format!(
+                    "'{}' is not a valid style field. Valid fields are: {}",
+                    field,
+                    ASS_STYLE_FIELDS.join(", ")
+                ));
+            };
+            overrides.push((field, value.trim().to_string()))
+        }
+        Ok(Self(overrides))
+    }
+
+    /// Rewrites the `Style:` lines of the ass subtitle `ass`. The position of the fields is read
+    /// from the `Format:` line of the styles section.
+    fn apply(&self, ass: &str) -> String {
+        let mut in_styles = false;
+        let mut fields: Vec<String> = vec![];
+        let mut lines = vec![];
+        for line in ass.lines() {
+            let trimmed = line.trim();
+            if trimmed.starts_with('[') {
+                in_styles = trimmed.eq_ignore_ascii_case("[v4+ styles]")
+                    || trimmed.eq_ignore_ascii_case("[v4 styles]");
+            } else if in_styles {
+                if let Some(format) = trimmed.strip_prefix("Format:") {
+                    fields = format.split(',').map(|f| f.trim().to_string()).collect();
+                } else if let Some(style) = trimmed.strip_prefix("Style:") {
+                    let mut values: Vec<String> = style
+                        .splitn(fields.len().max(1), ',')
+                        .map(|v| v.trim().to_string())
+                        .collect();
+                    for (field, value) in &self.0 {
+                        if let Some(position) =
+                            fields.iter().position(|f| f.eq_ignore_ascii_case(field))
+                        {
+                            if let Some(v) = values.get_mut(position) {
+                                v.clone_from(value)
+                            }
+                        }
+                    }
+                    lines.push(format!("Style: {}", values.join(",")));
+                    continue;
+                }
+            }
+            lines.push(line.to_string())
+        }
+        lines.join("\n") + "\n"
+    }
+}
+
+/// Image which is embedded as cover art into the output file.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CoverArt {
+    Episode,
+    Series,
+    #[default]
+    None,
+}
+
+impl CoverArt {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "episode" => Self::Episode,
+            "series" => Self::Series,
+            "none" => Self::None,
+            _ => return Err(format!("'{}' is not a valid cover art source", s)),
+        })
+    }
+}
+
 pub struct DownloadFormat {
     pub video: (StreamData, Locale),
+    /// Lower resolution variants of `video`, used if `video` can't be downloaded.
+    pub video_fallbacks: Vec<StreamData>,
     pub audios: Vec<(StreamData, Locale)>,
     pub subtitles: Vec<(Subtitle, bool)>,
     pub metadata: DownloadFormatMetadata,
 }
 
+impl DownloadFormat {
+    /// Estimated size of the streams in bytes. The video isn't counted if `audio_only`.
+    pub fn estimated_size(&self, audio_only: bool) -> u64 {
+        let mut stream_data: Vec<&StreamData> = self.audios.iter().map(|(a, _)| a).collect();
+        if !audio_only {
+            stream_data.push(&self.video.0)
+        }
+        stream_data
+            .into_iter()
+            .map(|s| estimate_stream_data_file_size(s, &s.segments()))
+            .sum()
+    }
+}
+
 pub struct DownloadFormatMetadata {
+    /// Id of the episode (or movie, ...) the video belongs to. Identifies interrupted downloads
+    /// with `--resume`, as the segment urls change every time the stream is requested.
+    pub episode_id: String,
+    /// Locale of the subtitle which is burned into the video by Crunchyroll. Identifies
+    /// interrupted downloads with `--resume` too, as videos with and without hardsub can't be told
+    /// apart by their codec, bandwidth and resolution.
+    pub hardsub: Option<Locale>,
     pub skip_events: Option<SkipEvents>,
+    pub season_id: Option<String>,
+}
+
+/// The download threads and the speed limit a download gets. Downloads which run at the same time
+/// split them between each other.
+#[derive(Clone)]
+struct DownloadShare {
+    threads: usize,
+    rate_limiter: Option<RateLimiterService>,
+}
+
+impl DownloadShare {
+    /// Splits the share evenly between `count` downloads. Each gets at least one thread.
+    fn divide(&self, count: usize) -> Self {
+        Self {
+            threads: (self.threads / count.max(1)).max(1),
+            rate_limiter: self
+                .rate_limiter
+                .as_ref()
+                .map(|r| r.share(1.0 / count.max(1) as f64)),
+        }
+    }
 }
 
 pub struct Downloader {
     client: Client,
     rate_limiter: Option<RateLimiterService>,
+    proxy_pool: Option<ProxyPoolService>,
 
     ffmpeg_preset: FFmpegPreset,
+    ffmpeg_two_pass: bool,
     default_subtitle: Option<Locale>,
     output_format: Option<String>,
+    content_rating: Option<String>,
+    cover_art: Option<String>,
+    thumbnail: Option<String>,
+    episode_id: Option<String>,
     audio_sort: Option<Vec<Locale>>,
     subtitle_sort: Option<Vec<Locale>>,
 
     force_hardsub: bool,
+    hardsub_style: Option<HardsubStyle>,
+    secondary_subtitle: Option<Locale>,
     download_fonts: bool,
+    font_fallback: Vec<(String, String)>,
+    rewrite_font_fallback: bool,
+    fonts_dir: Option<PathBuf>,
     no_closed_caption: bool,
+    sidecar_subtitles: bool,
+    sidecar_naming: SidecarNaming,
+    subtitle_format: SubtitleFormat,
+    local_mux: LocalMux,
 
     merge_sync_tolerance: Option<u32>,
     merge_sync_precision: Option<u32>,
+    sync_window: Option<SyncWindow>,
+    sync_plot: bool,
+    sync_reference: Option<PathBuf>,
+    extract_audio: bool,
+
+    fingerprint_cache: Option<SeasonFingerprintCache>,
+    synthesize_chapters: bool,
+    metadata_locale: Option<Locale>,
+    track_statistics: bool,
+    write_chapters: Option<ChapterFileFormat>,
+    cut: Vec<(f32, f32)>,
+    clip: Option<Clip>,
+
+    remux_only: bool,
+    resolution_fallback: bool,
+    resume: bool,
+    low_disk: bool,
 
     download_threads: usize,
+    stall_timeout: Option<Duration>,
+    slow_start: Option<Duration>,
+    schedule: Option<Schedule>,
+    segment_recording: Option<SegmentRecording>,
+    mux_timeout: Option<Duration>,
     ffmpeg_threads: Option<usize>,
 
     formats: Vec<DownloadFormat>,
@@ -187,7 +672,100 @@ impl Downloader {
         self.formats.push(format);
     }
 
+    /// Downloads only `subtitles` and writes them next to `dst` (named like sidecar subtitles).
+    /// No stream data is requested and ffmpeg isn't invoked.
+    pub async fn download_subtitles(
+        self,
+        dst: &Path,
+        subtitles: &[(Subtitle, bool)],
+    ) -> Result<()> {
+        validate_destination(dst, &[])?;
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?
+        }
+
+        let progress_handler = progress!("Downloading subtitles");
+        let mut metas = vec![];
+        for (subtitle, cc) in subtitles {
+            if *cc && self.no_closed_caption {
+                continue;
+            }
+            let ass = self.download_subtitle(subtitle).await?;
+            metas.push(FFmpegSubtitleMeta {
+                path: write_subtitle(
+                    ass,
+                    None,
+                    self.subtitle_format,
+                    None,
+                    self.temp_file_context(&subtitle.locale, "subtitle"),
+                )?,
+                locale: subtitle.locale.clone(),
+                cc: *cc,
+                start_time: None,
+                video_idx: 0,
+            })
+        }
+        write_sidecar_subtitles(
+            dst,
+            &metas,
+            false,
+            &self.sidecar_naming,
+            self.default_subtitle.as_ref(),
+            &self.subtitle_locale_output_map,
+        )?;
+        progress_handler.stop(format!("Downloaded {} subtitle(s)", metas.len()));
+
+        Ok(())
+    }
+
+    /// Downloads only the audios and writes them into `dst`. `.m4a` and `.mka` files contain all
+    /// audios, `.mp3` and `.flac` files can only store one audio each. If multiple audios are
+    /// downloaded into such a file, every audio is written next to `dst` with its locale added
+    /// instead, e.g. `audio.en-US.mp3` for `audio.mp3`.
+    pub async fn download_audio_only(self, dst: &Path) -> Result<()> {
+        validate_destination(dst, &[])?;
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?
+        }
+        debug_bundle::record_manifest(debug_manifest(&self.formats));
+
+        let fmt_space = self
+            .formats
+            .iter()
+            .flat_map(|f| {
+                f.audios
+                    .iter()
+                    .map(|(_, locale)| format!("Downloading {} audio", locale).len())
+            })
+            .max()
+            .unwrap();
+        let raw_audios = self.download_audios(fmt_space, &self.full_share()).await?;
+
+        let _progress_handler = progress!("Generating output file");
+        let extension = dst.extension().unwrap_or_default().to_string_lossy();
+        if raw_audios.len() > 1 && ["mp3", "flac"].contains(&extension.as_ref()) {
+            let stem = dst.file_stem().unwrap_or_default().to_string_lossy();
+            for raw_audio in &raw_audios {
+                let path = dst.with_file_name(format!(
+                    "{}.{}.{}",
+                    stem,
+                    self.audio_language(&raw_audio.locale),
+                    extension
+                ));
+                self.write_audio(&[raw_audio], &path)?
+            }
+            Ok(())
+        } else {
+            self.write_audio(&raw_audios.iter().collect::<Vec<&SyncAudio>>(), dst)
+        }
+    }
+
     pub async fn download(mut self, dst: &Path) -> Result<()> {
+        validate_destination(dst, &[])?;
+        if self.remux_only {
+            return remux(dst, self.mux_timeout).await;
+        }
+
         // `.unwrap_or_default()` here unless https://doc.rust-lang.org/stable/std/path/fn.absolute.html
         // gets stabilized as the function might throw error on weird file paths
         let required = self.check_free_space(dst).await.unwrap_or_default();
@@ -247,11 +825,11 @@ impl Downloader {
                     })
             }
         }
+        debug_bundle::record_manifest(debug_manifest(&self.formats));
 
         let mut video_offset = None;
         let mut audio_offsets = HashMap::new();
         let mut subtitle_offsets = HashMap::new();
-        let mut raw_audios = vec![];
         let mut videos = vec![];
         let mut audios = vec![];
         let mut subtitles = vec![];
@@ -270,33 +848,252 @@ impl Downloader {
             .max()
             .unwrap();
 
-        // downloads all audios
+        if self.extract_audio {
+            let raw_audios = self.download_audios(fmt_space, &self.full_share()).await?;
+            let _progress_handler = progress!("Writing audios");
+            return self.write_audio_files(dst, &raw_audios, |_| TimeDelta::zero());
+        }
+
+        // subtitles and fonts are fetched before the audios and videos. they are cheap to download
+        // and failures (e.g. invalid subtitles) are noticed before gigabytes of video are downloaded
+        let mut fetched_subtitles = HashMap::new();
         for (i, format) in self.formats.iter().enumerate() {
-            for (stream_data, locale) in &format.audios {
-                let path = self
-                    .download_audio(
-                        stream_data,
-                        format!("{:<1$}", format!("Downloading {} audio", locale), fmt_space),
-                    )
+            if format.subtitles.is_empty() {
+                continue;
+            }
+
+            let progress_spinner = if progress_enabled() {
+                let progress_spinner = add_progress_bar(
+                    ProgressBar::new_spinner()
+                        .with_style(labeled_spinner_style("Downloading subtitles", fmt_space))
+                        .with_finish(ProgressFinish::Abandon),
+                );
+                progress_spinner.enable_steady_tick(Duration::from_millis(100));
+                Some(progress_spinner)
+            } else {
+                None
+            };
+
+            let progress_spinner = &progress_spinner;
+            let this = &self;
+            // subtitles are small, so multiple of them are downloaded at the same time. the number
+            // of simultaneous downloads is bound to the download threads
+            let downloaded_subtitles: Vec<(&Subtitle, SSA)> = futures_util::stream::iter(
+                format
+                    .subtitles
+                    .iter()
+                    .filter(|(_, cc)| !(*cc && this.no_closed_caption)),
+            )
+            .map(|(subtitle, cc)| async move {
+                let ass = this.download_subtitle(subtitle).await?;
+                debug!(
+                    "Downloaded {} subtitles{}",
+                    subtitle.locale,
+                    cc.then_some(" (cc)").unwrap_or_default(),
+                );
+
+                if let Some(pb) = progress_spinner {
+                    let mut progress_message = pb.message();
+                    if !progress_message.is_empty() {
+                        progress_message += ", "
+                    }
+                    progress_message += &subtitle.locale.to_string();
+                    if *cc {
+                        progress_message += " (CC)";
+                    }
+                    if i != 0 {
+                        progress_message += &format!(" [Video: #{}]", i + 1);
+                    }
+                    pb.set_message(progress_message)
+                }
+
+                Ok::<_, anyhow::Error>((subtitle, ass))
+            })
+            .buffered(self.download_threads.max(1))
+            .try_collect()
+            .await?;
+
+            for (subtitle, ass) in downloaded_subtitles {
+                fetched_subtitles.insert(subtitle.url.clone(), ass);
+            }
+        }
+
+        if let Some(reference) = self.sync_reference.clone() {
+            return self
+                .download_synced_to_reference(dst, &reference, fmt_space, fetched_subtitles)
+                .await;
+        }
+
+        let attach_fonts = self.download_fonts
+            && !self.force_hardsub
+            && self.subtitle_format == SubtitleFormat::Ass
+            && dst.extension().unwrap_or_default().to_str().unwrap() == "mkv";
+        let extract_fonts = self.fonts_dir.is_some()
+            && self.subtitle_format == SubtitleFormat::Ass
+            && !self.burns_subtitles(dst)
+            && !is_special_file(dst)
+            && dst.to_str().unwrap() != "-";
+        if attach_fonts || extract_fonts {
+            let mut font_names = vec![];
+            for (subtitle, _) in self.formats.iter().flat_map(|f| &f.subtitles) {
+                if let Some(ass) = fetched_subtitles.get(&subtitle.url) {
+                    font_names.extend(get_subtitle_stats(ass))
+                }
+            }
+            real_dedup_vec(&mut font_names);
+            load_font_manifest(&self.client).await;
+            let substitutions = self.apply_font_fallback(&mut font_names);
+            if self.rewrite_font_fallback && !substitutions.is_empty() {
+                for ass in fetched_subtitles.values_mut() {
+                    rewrite_subtitle_fonts(ass, &substitutions)
+                }
+            }
+
+            let progress_spinner = if progress_enabled() {
+                let progress_spinner = add_progress_bar(
+                    ProgressBar::new_spinner()
+                        .with_style(labeled_spinner_style("Downloading fonts", fmt_space))
+                        .with_finish(ProgressFinish::Abandon),
+                );
+                progress_spinner.enable_steady_tick(Duration::from_millis(100));
+                Some(progress_spinner)
+            } else {
+                None
+            };
+            if let Some(pb) = &progress_spinner {
+                pb.set_message(font_names.join(", "))
+            }
+            // the fonts are small, so they're downloaded in parallel with the same amount of
+            // threads which are used for segments
+            let downloaded: Vec<Option<(PathBuf, bool)>> =
+                futures_util::stream::iter(font_names.iter().map(|name| self.download_font(name)))
+                    .buffered(self.download_threads.max(1))
+                    .try_collect()
                     .await?;
-                raw_audios.push(SyncAudio {
-                    format_id: i,
-                    path,
-                    locale: locale.clone(),
-                    sample_rate: stream_data.sampling_rate().unwrap(),
-                    video_idx: i,
-                })
+            let mut progress_message = vec![];
+            for (font_name, font) in font_names.iter().zip(downloaded) {
+                let Some((font, cached)) = font else {
+                    progress_message.push(font_name.clone());
+                    continue;
+                };
+                if cached {
+                    debug!("Downloaded font {} (cached)", font_name);
+                    progress_message.push(format!("{} (cached)", font_name))
+                } else {
+                    debug!("Downloaded font {}", font_name);
+                    progress_message.push(font_name.clone())
+                }
+                fonts.push(font)
+            }
+            if let Some(pb) = &progress_spinner {
+                pb.set_message(progress_message.join(", "))
+            }
+        }
+
+        let extension = dst.extension().unwrap_or_default().to_string_lossy();
+        // the cover art of mp4 and mov files is a video stream which would get the hardsubs burned
+        // in too
+        let cover_art = if extension == "mkv"
+            || (["mov", "mp4"].contains(&extension.as_ref()) && !self.force_hardsub)
+        {
+            match &self.cover_art {
+                Some(url) => match self.download_image(url).await {
+                    Ok(cover_art) => Some(cover_art),
+                    Err(e) => {
+                        warn!("Failed to download cover art, skipping it: {}", e);
+                        None
+                    }
+                },
+                None => None,
+            }
+        } else {
+            None
+        };
+
+        let segment_cache = if self.uses_segment_cache() {
+            Some(SegmentCache::new()?)
+        } else {
+            None
+        };
+
+        // streams which are written into named pipes while ffmpeg generates the output file, instead
+        // of being downloaded first
+        let mut pipes = vec![];
+
+        // audios and videos are separate streams. if the videos which get downloaded don't depend
+        // on the audio sync, both are downloaded at the same time
+        let (mut raw_audios, mut video_paths) = if self.low_disk {
+            if self.pipes_audios() {
+                (vec![], None)
+            } else {
+                (
+                    self.download_audios(fmt_space, &self.full_share()).await?,
+                    None,
+                )
+            }
+        } else if (self.formats.len() > 1 && self.merge_sync_tolerance.is_some())
+            || self.download_threads < 2
+        {
+            // with a single thread, the videos are downloaded after the audios
+            (
+                self.download_audios(fmt_space, &self.full_share()).await?,
+                None,
+            )
+        } else {
+            let (video_share, audio_share) = self.split_download_share();
+            let (raw_audios, video_paths) = futures_util::try_join!(
+                self.download_audios(fmt_space, &audio_share),
+                self.download_videos(fmt_space, segment_cache.as_ref(), &video_share)
+            )?;
+            (raw_audios, Some(video_paths))
+        };
+
+        // chromaprints of the whole audio by format id. they're shared between the intro / ending
+        // detection and the audio sync so that every audio is only fingerprinted once
+        let mut chromaprints = HashMap::new();
+        let mut recurring_parts = HashMap::new();
+        if let Some(fingerprint_cache) = &self.fingerprint_cache {
+            for (i, format) in self.formats.iter().enumerate() {
+                let Some(season_id) = &format.metadata.season_id else {
+                    continue;
+                };
+                let Some(raw_audio) = raw_audios.iter().find(|a| a.format_id == i) else {
+                    continue;
+                };
+                if !self.searches_recurring_parts(format) {
+                    continue;
+                }
+
+                let _progress_handler = progress!("Searching intro and ending");
+                let chromaprint = fingerprint_audio(raw_audio)?;
+                // the sync tolerance is only set when syncing, 6 is the default of
+                // `--merge-sync-tolerance`
+                recurring_parts.insert(
+                    i,
+                    fingerprint_cache.find_recurring_parts(
+                        season_id,
+                        &chromaprint,
+                        self.merge_sync_tolerance.unwrap_or(6),
+                    ),
+                );
+                chromaprints.insert(i, chromaprint);
             }
         }
 
         if self.formats.len() > 1 && self.merge_sync_tolerance.is_some() {
             let _progress_handler =
                 progress!("Syncing video start times (this might take some time)");
+            let mut sync_samples = vec![];
             let mut offsets = sync_audios(
                 &raw_audios,
+                &mut chromaprints,
                 self.merge_sync_tolerance.unwrap(),
                 self.merge_sync_precision.unwrap(),
+                self.sync_window.as_ref(),
+                &mut sync_samples,
+                |stage| progress_update!("{}", stage),
             )?;
+            let calculated_offsets = offsets.clone();
             drop(_progress_handler);
 
             let mut offset_pre_checked = false;
@@ -320,6 +1117,16 @@ impl Downloader {
                 }
             }
 
+            if self.sync_plot {
+                write_sync_plot(
+                    dst,
+                    &sync_samples,
+                    calculated_offsets.unwrap_or_default(),
+                    offsets.is_some(),
+                    &raw_audios,
+                )
+            }
+
             if let Some(offsets) = offsets {
                 let mut root_format_idx = 0;
                 let mut root_format_length = 0;
@@ -371,6 +1178,9 @@ impl Downloader {
                 root_format.subtitles.extend(subtitle_append);
 
                 self.formats = vec![root_format];
+                recurring_parts = recurring_parts
+                    .remove(&root_format_idx)
+                    .map_or(HashMap::new(), |parts| HashMap::from([(0, parts)]));
                 video_offset = offsets.get(&root_format_idx).copied();
                 for raw_audio in raw_audios.iter_mut() {
                     raw_audio.video_idx = root_format_idx;
@@ -387,96 +1197,104 @@ impl Downloader {
 
         // add audio metadata
         for raw_audio in raw_audios {
+            let start_time = add_offsets(
+                self.audio_clip_start(&raw_audio),
+                audio_offsets.get(&raw_audio.format_id).copied(),
+            );
             audios.push(FFmpegAudioMeta {
                 path: raw_audio.path,
                 locale: raw_audio.locale,
-                start_time: audio_offsets.get(&raw_audio.format_id).copied(),
+                start_time,
                 video_idx: raw_audio.video_idx,
             })
         }
-
-        // downloads all videos
-        for (i, format) in self.formats.iter().enumerate() {
-            let path = self
-                .download_video(
-                    &format.video.0,
-                    format!("{:<1$}", format!("Downloading video #{}", i + 1), fmt_space),
-                    None,
-                )
-                .await?;
-
-            let (len, fps) = get_video_stats(&path)?;
-            if max_len < len {
-                max_len = len
-            }
-            let frames = ((len.num_milliseconds() as f64
-                - video_offset.unwrap_or_default().num_milliseconds() as f64)
-                / 1000.0
-                * fps) as u64;
-            if max_frames < frames {
-                max_frames = frames
+        if self.low_disk && self.pipes_audios() {
+            for (i, format) in self.formats.iter().enumerate() {
+                for (j, (stream_data, locale)) in format.audios.iter().enumerate() {
+                    let (path, writer) = temp_pipe_writer()?;
+                    audios.push(FFmpegAudioMeta {
+                        path,
+                        locale: locale.clone(),
+                        start_time: self.clip_start(stream_data),
+                        video_idx: i,
+                    });
+                    pipes.push((writer, i, Some(j)))
+                }
+            }
+        }
+
+        // downloads all videos if they weren't already downloaded together with the audios
+        let mut video_stats = vec![];
+        if self.low_disk {
+            // the video can't be inspected before ffmpeg reads it, so its stats are taken from the
+            // stream metadata
+            for (i, format) in self.formats.iter().enumerate() {
+                let (path, writer) = temp_pipe_writer()?;
+                video_stats.push((
+                    path,
+                    (
+                        len_from_segments(&format.video.0.segments()),
+                        // crunchyroll videos have almost always this frame rate
+                        format.video.0.fps().unwrap_or(23.976),
+                    ),
+                ));
+                pipes.push((writer, i, None))
+            }
+        } else {
+            if video_paths.is_none() {
+                video_paths = Some(
+                    self.download_videos(fmt_space, segment_cache.as_ref(), &self.full_share())
+                        .await?,
+                )
+            }
+            for path in video_paths.unwrap() {
+                let stats = get_video_stats(&path)?;
+                video_stats.push((path, stats))
+            }
+        }
+        for (i, (path, (len, fps))) in video_stats.into_iter().enumerate() {
+            if max_len < len {
+                max_len = len
+            }
+            let clip_start = self
+                .formats
+                .get(i)
+                .and_then(|format| self.clip_start(&format.video.0));
+            let output_len = match &self.clip {
+                Some(clip) => TimeDelta::from_std(clip.duration()).unwrap_or_default(),
+                None => len - video_offset.unwrap_or_default(),
+            };
+            let frames = (output_len.num_milliseconds() as f64 / 1000.0 * fps) as u64;
+            if max_frames < frames {
+                max_frames = frames
             }
 
             videos.push(FFmpegVideoMeta {
                 path,
                 length: len,
-                start_time: video_offset,
+                start_time: add_offsets(clip_start, video_offset),
             })
         }
 
+        // writes all subtitles. they were already fetched before the audios and videos got
+        // downloaded, but their length can only be fixed with the actual video length
         for (i, format) in self.formats.iter().enumerate() {
-            if format.subtitles.is_empty() {
-                continue;
-            }
-
-            let progress_spinner = if log::max_level() == LevelFilter::Info {
-                let progress_spinner = ProgressBar::new_spinner()
-                    .with_style(
-                        ProgressStyle::with_template(
-                            format!(
-                                ":: {:<1$}  {{msg}} {{spinner}}",
-                                "Downloading subtitles", fmt_space
-                            )
-                            .as_str(),
-                        )
-                        .unwrap()
-                        .tick_strings(&["—", "\\", "|", "/", ""]),
-                    )
-                    .with_finish(ProgressFinish::Abandon);
-                progress_spinner.enable_steady_tick(Duration::from_millis(100));
-                Some(progress_spinner)
-            } else {
-                None
-            };
-
             for (j, (subtitle, cc)) in format.subtitles.iter().enumerate() {
                 if *cc && self.no_closed_caption {
                     continue;
                 }
 
-                if let Some(pb) = &progress_spinner {
-                    let mut progress_message = pb.message();
-                    if !progress_message.is_empty() {
-                        progress_message += ", "
-                    }
-                    progress_message += &subtitle.locale.to_string();
-                    if *cc {
-                        progress_message += " (CC)";
-                    }
-                    if i.min(videos.len() - 1) != 0 {
-                        progress_message += &format!(" [Video: #{}]", i + 1);
-                    }
-                    pb.set_message(progress_message)
+                let mut ass = fetched_subtitles.remove(&subtitle.url).unwrap();
+                if let Some(clip) = &self.clip {
+                    clip_subtitle(&mut ass, clip)
                 }
-
-                let path = self
-                    .download_subtitle(subtitle.clone(), videos[i.min(videos.len() - 1)].length)
-                    .await?;
-                debug!(
-                    "Downloaded {} subtitles{}",
-                    subtitle.locale,
-                    cc.then_some(" (cc)").unwrap_or_default(),
-                );
+                let path = write_subtitle(
+                    ass,
+                    Some(videos[i.min(videos.len() - 1)].length),
+                    self.output_subtitle_format(dst),
+                    self.burned_subtitle_style(&subtitle.locale, dst).as_ref(),
+                    self.temp_file_context(&subtitle.locale, "subtitle"),
+                )?;
                 subtitles.push(FFmpegSubtitleMeta {
                     path,
                     locale: subtitle.locale.clone(),
@@ -487,76 +1305,50 @@ impl Downloader {
             }
         }
 
-        for format in self.formats.iter() {
+        for (i, format) in self.formats.iter().enumerate() {
             if let Some(skip_events) = &format.metadata.skip_events {
+                let parts = recurring_parts.get(&i).copied().unwrap_or_default();
+                let events = [
+                    skip_events
+                        .recap
+                        .as_ref()
+                        .map(|e| ("Recap", e.start, e.end)),
+                    skip_events
+                        .intro
+                        .as_ref()
+                        .map(|e| ("Intro", e.start, e.end)),
+                    skip_events
+                        .credits
+                        .as_ref()
+                        .map(|e| ("Credits", e.start, e.end)),
+                    skip_events
+                        .preview
+                        .as_ref()
+                        .map(|e| ("Preview", e.start, e.end)),
+                ]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<(&str, f32, f32)>>();
+
                 let (file, path) = tempfile(".chapter")?.into_parts();
                 chapters = Some((
                     (file, path),
-                    [
-                        skip_events.recap.as_ref().map(|e| ("Recap", e)),
-                        skip_events.intro.as_ref().map(|e| ("Intro", e)),
-                        skip_events.credits.as_ref().map(|e| ("Credits", e)),
-                        skip_events.preview.as_ref().map(|e| ("Preview", e)),
-                    ]
-                    .into_iter()
-                    .flatten()
-                    .collect::<Vec<(&str, &SkipEventsEvent)>>(),
-                ));
-            }
-        }
-
-        if self.download_fonts
-            && !self.force_hardsub
-            && dst.extension().unwrap_or_default().to_str().unwrap() == "mkv"
-        {
-            let mut font_names = vec![];
-            for subtitle in subtitles.iter() {
-                font_names.extend(get_subtitle_stats(&subtitle.path)?)
-            }
-            real_dedup_vec(&mut font_names);
-
-            let progress_spinner = if log::max_level() == LevelFilter::Info {
-                let progress_spinner = ProgressBar::new_spinner()
-                    .with_style(
-                        ProgressStyle::with_template(
-                            format!(
-                                ":: {:<1$}  {{msg}} {{spinner}}",
-                                "Downloading fonts", fmt_space
-                            )
-                            .as_str(),
-                        )
-                        .unwrap()
-                        .tick_strings(&["—", "\\", "|", "/", ""]),
-                    )
-                    .with_finish(ProgressFinish::Abandon);
-                progress_spinner.enable_steady_tick(Duration::from_millis(100));
-                Some(progress_spinner)
-            } else {
-                None
-            };
-            for font_name in font_names {
-                if let Some(pb) = &progress_spinner {
-                    let mut progress_message = pb.message();
-                    if !progress_message.is_empty() {
-                        progress_message += ", "
-                    }
-                    progress_message += &font_name;
-                    pb.set_message(progress_message)
-                }
-                if let Some((font, cached)) = self.download_font(&font_name).await? {
-                    if cached {
-                        if let Some(pb) = &progress_spinner {
-                            let mut progress_message = pb.message();
-                            progress_message += " (cached)";
-                            pb.set_message(progress_message)
+                    if events.is_empty() {
+                        // crunchyroll has no skip events for this episode at all
+                        if self.synthesize_chapters {
+                            synthesize_chapters(parts)
+                        } else {
+                            events
                         }
-                        debug!("Downloaded font {} (cached)", font_name);
+                    } else if skip_events.intro.is_none() {
+                        events
+                            .into_iter()
+                            .chain(parts.intro.map(|(start, end)| ("Intro", start, end)))
+                            .collect()
                     } else {
-                        debug!("Downloaded font {}", font_name);
-                    }
-
-                    fonts.push(font)
-                }
+                        events
+                    },
+                ));
             }
         }
 
@@ -565,6 +1357,13 @@ impl Downloader {
         let mut attachments = vec![];
         let mut metadata = vec![];
 
+        if let Some(content_rating) = &self.content_rating {
+            // matroska's tag for content ratings
+            metadata.extend([
+                "-metadata".to_string(),
+                format!("LAW_RATING={}", content_rating),
+            ])
+        }
         for (i, meta) in videos.iter().enumerate() {
             if let Some(start_time) = meta.start_time {
                 input.extend(["-ss".to_string(), format_time_delta(&start_time)])
@@ -618,13 +1417,50 @@ impl Downloader {
             ]);
         }
 
-        for (i, font) in fonts.iter().enumerate() {
+        let attached_fonts: &[PathBuf] = if attach_fonts { &fonts } else { &[] };
+        for (i, font) in attached_fonts.iter().enumerate() {
             attachments.extend(["-attach".to_string(), font.to_string_lossy().to_string()]);
             metadata.extend([
                 format!("-metadata:s:t:{}", i),
                 "mimetype=font/woff2".to_string(),
             ])
         }
+        // matroska stores the cover art as attachment and mp4 / mov as video stream with only one
+        // frame. the latter is added after all other inputs so that their indices don't change
+        let mut cover_art_args = vec![];
+        if let Some(cover_art) = &cover_art {
+            let cover_art_extension = cover_art
+                .extension()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            if dst.extension().unwrap_or_default().to_str().unwrap() == "mkv" {
+                attachments.extend([
+                    "-attach".to_string(),
+                    cover_art.to_string_lossy().to_string(),
+                ]);
+                metadata.extend([
+                    format!("-metadata:s:t:{}", attached_fonts.len()),
+                    format!(
+                        "mimetype=image/{}",
+                        if cover_art_extension == "png" {
+                            "png"
+                        } else {
+                            "jpeg"
+                        }
+                    ),
+                    format!("-metadata:s:t:{}", attached_fonts.len()),
+                    format!("filename=cover.{}", cover_art_extension),
+                ])
+            } else {
+                cover_art_args.extend([
+                    format!("-c:v:{}", videos.len()),
+                    "copy".to_string(),
+                    format!("-disposition:v:{}", videos.len()),
+                    "attached_pic".to_string(),
+                ])
+            }
+        }
 
         // this formats are supporting embedding subtitles into the video container instead of
         // burning it into the video stream directly
@@ -633,6 +1469,12 @@ impl Downloader {
                 .contains(&dst.extension().unwrap_or_default().to_str().unwrap());
 
         if container_supports_softsubs {
+            // matroska would convert the subtitles back to ass otherwise
+            if self.subtitle_format != SubtitleFormat::Ass
+                && dst.extension().unwrap_or_default().to_str().unwrap() == "mkv"
+            {
+                metadata.extend(["-c:s".to_string(), "copy".to_string()])
+            }
             for (i, meta) in subtitles.iter().enumerate() {
                 if let Some(start_time) = meta.start_time {
                     input.extend(["-ss".to_string(), format_time_delta(&start_time)])
@@ -667,8 +1509,20 @@ impl Downloader {
             }
         }
 
-        if let Some(((file, path), chapters)) = chapters.as_mut() {
-            write_ffmpeg_chapters(file, max_len, chapters)?;
+        let chapter_list = chapters.as_mut().map(|(_, events)| {
+            chapter_list(max_len, events)
+                .into_iter()
+                .map(|(name, start, end)| {
+                    (
+                        chapter_title(name, self.metadata_locale.as_ref()),
+                        start,
+                        end,
+                    )
+                })
+                .collect::<Vec<(String, u32, u32)>>()
+        });
+        if let (Some(((file, path), _)), Some(chapter_list)) = (chapters.as_mut(), &chapter_list) {
+            write!(file, "{}", ffmetadata(chapter_list))?;
             input.extend(["-i".to_string(), path.to_string_lossy().to_string()]);
             maps.extend([
                 "-map_metadata".to_string(),
@@ -680,31 +1534,56 @@ impl Downloader {
                 .to_string(),
             ])
         }
+        if !cover_art_args.is_empty() {
+            let input_idx = input.iter().filter(|arg| *arg == "-i").count();
+            input.extend([
+                "-i".to_string(),
+                cover_art.as_ref().unwrap().to_string_lossy().to_string(),
+            ]);
+            maps.extend(["-map".to_string(), input_idx.to_string()])
+        }
 
+        // both passes of a two-pass encode read the input files, so they can't be piped
+        let two_pass_log = (self.ffmpeg_two_pass && pipes.is_empty()).then(|| two_pass_log(dst));
+        let pass = |number| {
+            two_pass_log
+                .as_deref()
+                .map(|log_file| FFmpegPass { number, log_file })
+        };
         let preset_custom = matches!(self.ffmpeg_preset, FFmpegPreset::Custom(_));
-        let (input_presets, mut output_presets) = self.ffmpeg_preset.into_input_output_args();
-        let fifo = temp_named_pipe()?;
-
-        let mut command_args = vec![
-            "-y".to_string(),
-            "-hide_banner".to_string(),
-            "-vstats_file".to_string(),
-            fifo.path().to_string_lossy().to_string(),
-        ];
-        command_args.extend(input_presets);
+        let (input_presets, mut output_presets) =
+            self.ffmpeg_preset.clone().into_input_output_args(pass(2));
+        let threads_args = match (preset_custom, self.ffmpeg_threads) {
+            (false, Some(ffmpeg_threads)) => {
+                vec!["-threads".to_string(), ffmpeg_threads.to_string()]
+            }
+            _ => vec![],
+        };
+        // the first pass only encodes the videos, its output is discarded
+        let mut first_pass_args = pass(1).map(|pass| {
+            let (input_presets, output_presets) = self
+                .ffmpeg_preset
+                .clone()
+                .into_input_output_args(Some(pass));
+            let mut args = input_presets;
+            args.extend(input.clone());
+            for i in 0..videos.len() {
+                args.extend(["-map".to_string(), i.to_string()])
+            }
+            args.extend(threads_args.clone());
+            args.extend(output_presets);
+            args
+        });
+        let mut command_args = input_presets;
         command_args.extend(input);
         command_args.extend(maps);
         command_args.extend(attachments);
         command_args.extend(metadata);
-        if !preset_custom {
-            if let Some(ffmpeg_threads) = self.ffmpeg_threads {
-                command_args.extend(vec!["-threads".to_string(), ffmpeg_threads.to_string()])
-            }
-        }
+        command_args.extend(threads_args);
 
         // set default subtitle
-        if let Some(default_subtitle) = self.default_subtitle {
-            if let Some(position) = subtitles.iter().position(|m| m.locale == default_subtitle) {
+        if let Some(default_subtitle) = &self.default_subtitle {
+            if let Some(position) = subtitles.iter().position(|m| &m.locale == default_subtitle) {
                 if container_supports_softsubs {
                     match dst.extension().unwrap_or_default().to_str().unwrap() {
                         "mov" | "mp4" => output_presets.extend([
@@ -731,39 +1610,25 @@ impl Downloader {
                         last.clone_from(s);
                     }
 
-                    output_presets.extend([
-                        "-vf".to_string(),
-                        format!(
-                            "ass='{}'",
-                            // ffmpeg doesn't removes all ':' and '\' from the filename when using
-                            // the ass filter. well, on windows these characters are used in
-                            // absolute paths, so they have to be correctly escaped here
-                            if cfg!(windows) {
-                                subtitles
-                                    .get(position)
-                                    .unwrap()
-                                    .path
-                                    .to_str()
-                                    .unwrap()
-                                    .replace('\\', "\\\\")
-                                    .replace(':', "\\:")
-                            } else {
-                                subtitles
-                                    .get(position)
-                                    .unwrap()
-                                    .path
-                                    .to_string_lossy()
-                                    .to_string()
-                            }
-                        ),
-                    ])
+                    let mut filters =
+                        vec![args::ass_filter(&subtitles.get(position).unwrap().path)];
+                    if let Some(secondary) = subtitles
+                        .iter()
+                        .find(|m| Some(&m.locale) == self.secondary_subtitle.as_ref())
+                    {
+                        filters.push(args::ass_filter(&secondary.path))
+                    }
+                    output_presets.extend(["-vf".to_string(), filters.join(",")]);
+                    if let Some(first_pass_args) = &mut first_pass_args {
+                        first_pass_args.extend(["-vf".to_string(), filters.join(",")])
+                    }
                 }
             }
 
             if container_supports_softsubs {
                 if let Some(position) = subtitles
                     .iter()
-                    .position(|meta| meta.locale == default_subtitle)
+                    .position(|meta| &meta.locale == default_subtitle)
                 {
                     command_args.extend([
                         format!("-disposition:s:s:{}", position),
@@ -783,23 +1648,29 @@ impl Downloader {
         }
 
         command_args.extend(output_presets);
-        if let Some(output_format) = self.output_format {
-            command_args.extend(["-f".to_string(), output_format]);
+        // must come after the output presets, otherwise their video codec would be used to
+        // re-encode the cover art
+        command_args.extend(cover_art_args);
+        if let Some(clip) = &self.clip {
+            command_args.extend([
+                "-t".to_string(),
+                format!("{:.3}", clip.duration().as_secs_f64()),
+            ]);
+            if let Some(first_pass_args) = &mut first_pass_args {
+                first_pass_args.extend([
+                    "-t".to_string(),
+                    format!("{:.3}", clip.duration().as_secs_f64()),
+                ])
+            }
         }
-
-        // prepend './' to the path on linux since ffmpeg may interpret the path incorrectly if it's just the filename.
-        // see https://github.com/crunchy-labs/crunchy-cli/issues/303 for example
-        if !cfg!(windows)
-            && dst
-                .parent()
-                .map_or(true, |p| p.to_string_lossy().is_empty())
-        {
-            command_args.push(Path::new("./").join(dst).to_string_lossy().to_string());
-        } else {
-            command_args.push(dst.to_string_lossy().to_string())
+        if let Some(first_pass_args) = &mut first_pass_args {
+            first_pass_args.extend(["-f", "null", "-"].map(|arg| arg.to_string()))
+        }
+        if let Some(output_format) = &self.output_format {
+            command_args.extend(["-f".to_string(), output_format.clone()]);
         }
 
-        debug!("ffmpeg {}", command_args.join(" "));
+        command_args.push(args::output_path(dst));
 
         // create parent directory if it does not exist
         if let Some(parent) = dst.parent() {
@@ -808,35 +1679,391 @@ impl Downloader {
             }
         }
 
-        let ffmpeg = Command::new("ffmpeg")
-            // pass ffmpeg stdout to real stdout only if output file is stdout
-            .stdout(if dst.to_str().unwrap() == "-" {
-                Stdio::inherit()
-            } else {
-                Stdio::null()
-            })
-            .stderr(Stdio::piped())
-            .args(command_args)
-            .spawn()?;
-        let ffmpeg_progress_cancel = CancellationToken::new();
-        let ffmpeg_progress_cancellation_token = ffmpeg_progress_cancel.clone();
-        let ffmpeg_progress = tokio::spawn(async move {
-            ffmpeg_progress(
+        // ffmpeg writes slowly to network shares, so the output file is generated locally and
+        // copied to the share afterwards
+        let local_dst = if self.local_mux.enabled(dst) {
+            let extension = dst.extension().unwrap_or_default().to_string_lossy();
+            Some(tempfile(format!(".{}", extension))?.into_temp_path())
+        } else {
+            None
+        };
+        let mut run_args = command_args.clone();
+        if let Some(local_dst) = &local_dst {
+            debug!(
+                "Generating output file locally at {}",
+                local_dst.to_string_lossy()
+            );
+            *run_args.last_mut().unwrap() = args::output_path(local_dst)
+        }
+        let ffmpeg = run_ffmpeg(
+            local_dst.as_deref().unwrap_or(dst),
+            run_args,
+            max_frames,
+            format!("{:<1$}", "Generating output file", fmt_space + 1),
+            self.mux_timeout,
+        );
+        let piped = !pipes.is_empty();
+        let mut result = if piped {
+            futures_util::try_join!(
+                ffmpeg,
+                self.write_pipes(pipes, fmt_space, segment_cache.as_ref())
+            )
+            .map(|_| ())
+        } else if let Some(first_pass_args) = &first_pass_args {
+            let first_pass = run_ffmpeg(
+                dst,
+                first_pass_args.clone(),
+                max_frames,
+                format!("{:<1$}", "Analysing video (pass 1/2)", fmt_space + 1),
+                self.mux_timeout,
+            )
+            .await;
+            match first_pass {
+                Ok(()) => ffmpeg.await,
+                Err(e) => Err(e),
+            }
+        } else {
+            ffmpeg.await
+        };
+        if let Some(two_pass_log) = &two_pass_log {
+            remove_two_pass_logs(two_pass_log)
+        }
+        if result.is_ok()
+            && !self.cut.is_empty()
+            && !is_special_file(dst)
+            && dst.to_str().unwrap() != "-"
+        {
+            result = cut_file(local_dst.as_deref().unwrap_or(dst), &self.cut)
+        }
+        // ffmpeg always tags matroska chapters as undetermined language, so they are replaced
+        if let (Ok(()), Some(chapter_list), Some(metadata_locale)) =
+            (&result, &chapter_list, &self.metadata_locale)
+        {
+            if dst.extension().unwrap_or_default().to_str().unwrap() == "mkv"
+                && !is_special_file(dst)
+            {
+                result = tag_chapter_language(
+                    local_dst.as_deref().unwrap_or(dst),
+                    chapter_list,
+                    metadata_locale,
+                )
+            }
+        }
+        // ffmpeg doesn't write the statistics tags which mkvmerge writes, but media servers use
+        // them to show the bitrate of the tracks
+        if result.is_ok()
+            && self.track_statistics
+            && dst.extension().unwrap_or_default().to_str().unwrap() == "mkv"
+            && !is_special_file(dst)
+        {
+            result = add_track_statistics(local_dst.as_deref().unwrap_or(dst))
+        }
+        if let (Ok(()), Some(local_dst)) = (&result, &local_dst) {
+            result = move_file(
+                local_dst,
+                dst,
+                format!("{:<1$}", "Moving output file", fmt_space + 1),
+            );
+        }
+        if let (Ok(()), Some(write_chapters), Some(chapter_list)) =
+            (&result, self.write_chapters, &chapter_list)
+        {
+            if !is_special_file(dst) && dst.to_str().unwrap() != "-" {
+                write_chapter_file(
+                    dst,
+                    chapter_list,
+                    write_chapters,
+                    self.metadata_locale.as_ref(),
+                    &chapter_title("Episode", self.metadata_locale.as_ref()),
+                )?
+            }
+        }
+        if result.is_ok()
+            && self.sidecar_subtitles
+            && !is_special_file(dst)
+            && dst.to_str().unwrap() != "-"
+        {
+            write_sidecar_subtitles(
+                dst,
+                &subtitles,
+                videos.len() > 1,
+                &self.sidecar_naming,
+                self.default_subtitle.as_ref(),
+                &self.subtitle_locale_output_map,
+            )?
+        }
+        if let (Ok(()), Some(fonts_dir), true) = (&result, &self.fonts_dir, extract_fonts) {
+            write_fonts(dst, fonts_dir, &fonts)?
+        }
+        if let (Ok(()), Some(thumbnail)) = (&result, &self.thumbnail) {
+            if !is_special_file(dst) && dst.to_str().unwrap() != "-" {
+                self.write_thumbnail(thumbnail, dst).await
+            }
+        }
+        if result.is_err() && dst.to_str().unwrap() != "-" && !piped {
+            // all downloaded files are kept, so that only ffmpeg must be re-run with
+            // `--remux-only` instead of downloading everything again
+            let mut files = vec![];
+            for path in videos
+                .into_iter()
+                .map(|v| v.path)
+                .chain(audios.into_iter().map(|a| a.path))
+                .chain(subtitles.into_iter().map(|s| s.path))
+                .chain(chapters.map(|((_, path), _)| path))
+            {
+                files.push(path.keep()?)
+            }
+            RemuxManifest {
+                first_pass_args,
+                args: command_args,
+                files,
                 max_frames,
-                fifo,
-                format!("{:<1$}", "Generating output file", fmt_space + 1),
-                ffmpeg_progress_cancellation_token,
+            }
+            .write(dst)?;
+            warn!(
+                "Kept the downloaded files of {}, use `--remux-only` to only retry generating the output file",
+                dst.to_string_lossy()
             )
-            .await
+        }
+        result
+    }
+
+    /// Downloads only the audios and subtitles and shifts them so that they're in sync with the
+    /// audio of the local `reference` file. Instead of generating `dst`, the audios are written as
+    /// `<dst name>.<locale>.mka` and the subtitles as sidecar files next to it.
+    async fn download_synced_to_reference(
+        self,
+        dst: &Path,
+        reference: &Path,
+        fmt_space: usize,
+        mut fetched_subtitles: HashMap<String, SSA>,
+    ) -> Result<()> {
+        let mut raw_audios = self.download_audios(fmt_space, &self.full_share()).await?;
+
+        let _progress_handler = progress!(
+            "Syncing audios to {} (this might take some time)",
+            reference.to_string_lossy()
+        );
+        // the audio is extracted once instead of letting every chromaprint generation decode the
+        // whole reference, which may be a large video file
+        let reference_format_id = self.formats.len();
+        raw_audios.push(SyncAudio {
+            format_id: reference_format_id,
+            path: extract_reference_audio(reference)?,
+            locale: Locale::Custom("reference".to_string()),
+            sample_rate: 48000,
+            video_idx: 0,
         });
+        let mut sync_samples = vec![];
+        let Some(offsets) = sync_audios(
+            &raw_audios,
+            &mut HashMap::new(),
+            self.merge_sync_tolerance.unwrap(),
+            self.merge_sync_precision.unwrap(),
+            self.sync_window.as_ref(),
+            &mut sync_samples,
+            |stage| progress_update!("{}", stage),
+        )?
+        else {
+            bail!("Couldn't find reliable sync positions")
+        };
+        drop(_progress_handler);
 
-        let result = ffmpeg.wait_with_output()?;
-        if !result.status.success() {
-            ffmpeg_progress.abort();
-            bail!("{}", String::from_utf8_lossy(result.stderr.as_slice()))
+        if self.sync_plot {
+            write_sync_plot(dst, &sync_samples, offsets.clone(), true, &raw_audios)
+        }
+        let reference_audio = raw_audios.pop().unwrap();
+        let reference_length = get_media_length(&reference_audio.path)?;
+        // the offsets are relative to the base audio of the sync, which may be any audio
+        let reference_offset = offsets.get(&reference_format_id).copied().unwrap();
+        let offset = |format_id: usize| {
+            offsets.get(&format_id).copied().unwrap_or_default() - reference_offset
+        };
+
+        let _progress_handler = progress!("Writing synced audios and subtitles");
+        self.write_audio_files(dst, &raw_audios, offset)?;
+
+        let mut subtitles = vec![];
+        for (i, format) in self.formats.iter().enumerate() {
+            for (subtitle, cc) in &format.subtitles {
+                if *cc && self.no_closed_caption {
+                    continue;
+                }
+
+                let unshifted = write_subtitle(
+                    fetched_subtitles.remove(&subtitle.url).unwrap(),
+                    Some(reference_length),
+                    self.subtitle_format,
+                    None,
+                    self.temp_file_context(&subtitle.locale, "subtitle"),
+                )?;
+                let path =
+                    tempfile(format!(".{}", self.subtitle_format.extension()))?.into_temp_path();
+                shift_media(&unshifted, &path, offset(i), &[])?;
+                subtitles.push(FFmpegSubtitleMeta {
+                    path,
+                    locale: subtitle.locale.clone(),
+                    cc: *cc,
+                    start_time: None,
+                    video_idx: i,
+                })
+            }
+        }
+        // the video index is only added to the file names if multiple subtitles would get the
+        // same name otherwise
+        let mut subtitle_names = subtitles
+            .iter()
+            .map(|s| (&s.locale, s.cc))
+            .collect::<Vec<_>>();
+        let subtitle_count = subtitle_names.len();
+        real_dedup_vec(&mut subtitle_names);
+        write_sidecar_subtitles(
+            dst,
+            &subtitles,
+            subtitle_names.len() != subtitle_count,
+            &self.sidecar_naming,
+            self.default_subtitle.as_ref(),
+            &self.subtitle_locale_output_map,
+        )
+    }
+
+    /// The format in which the subtitles are written. Subtitles which are burned into the video
+    /// are always kept as ass to preserve their styling.
+    fn output_subtitle_format(&self, dst: &Path) -> SubtitleFormat {
+        if self.burns_subtitles(dst) {
+            SubtitleFormat::Ass
+        } else {
+            self.subtitle_format
+        }
+    }
+
+    /// The style overrides of the subtitle in `locale` if it's burned into the video. The
+    /// secondary subtitle is moved to the top, so that it doesn't overlap with the default one.
+    fn burned_subtitle_style(&self, locale: &Locale, dst: &Path) -> Option<HardsubStyle> {
+        if !self.burns_subtitles(dst) {
+            return None;
+        }
+        if self.secondary_subtitle.as_ref() == Some(locale) {
+            let mut style = self.hardsub_style.clone().unwrap_or_default();
+            // top center
+            style.0.push(("Alignment", "8".to_string()));
+            Some(style)
+        } else {
+            self.hardsub_style.clone()
+        }
+    }
+
+    /// If the subtitles are burned into the video instead of embedded into the container.
+    fn burns_subtitles(&self, dst: &Path) -> bool {
+        self.force_hardsub
+            || !["mkv", "mov", "mp4"]
+                .contains(&dst.extension().unwrap_or_default().to_str().unwrap())
+    }
+
+    /// Muxes `raw_audios` into `dst`. Depending on the extension of `dst`, the audios are
+    /// transcoded to mp3 or flac.
+    fn write_audio(&self, raw_audios: &[&SyncAudio], dst: &Path) -> Result<()> {
+        let mut command = Command::new(ffmpeg_binary());
+        command
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .arg("-y")
+            .arg("-hide_banner");
+        for raw_audio in raw_audios {
+            if let Some(clip_start) = self.audio_clip_start(raw_audio) {
+                command.args(["-ss", &format_time_delta(&clip_start)]);
+            }
+            command.args(["-i", raw_audio.path.to_str().unwrap()]);
+        }
+        for (i, raw_audio) in raw_audios.iter().enumerate() {
+            command.args(["-map", &i.to_string()]).args([
+                format!("-metadata:s:a:{}", i),
+                format!("language={}", self.audio_language(&raw_audio.locale)),
+                format!("-metadata:s:a:{}", i),
+                format!("title={}", raw_audio.locale.to_human_readable()),
+            ]);
+        }
+        if let Some(clip) = &self.clip {
+            command.args(["-t", &format!("{:.3}", clip.duration().as_secs_f64())]);
+        }
+        match dst.extension().unwrap_or_default().to_str().unwrap() {
+            "mp3" => command.args(["-c:a", "libmp3lame", "-q:a", "2"]),
+            "flac" => command.args(["-c:a", "flac"]),
+            _ => command.args(["-c:a", "copy"]),
+        };
+        let ffmpeg = command.arg(dst.to_str().unwrap()).output()?;
+        if !ffmpeg.status.success() {
+            bail!(DownloadError::FFmpeg(
+                String::from_utf8_lossy(&ffmpeg.stderr).to_string()
+            ))
+        }
+        debug!("Wrote audio {}", dst.to_string_lossy());
+        Ok(())
+    }
+
+    /// Where the clip starts in the downloaded file of the stream.
+    fn clip_start(&self, stream_data: &StreamData) -> Option<TimeDelta> {
+        let clip = self.clip.as_ref()?;
+        TimeDelta::from_std(clip.start_in(stream_data.segments())).ok()
+    }
+
+    /// Where the clip starts in the downloaded file of the audio. The audio is looked up by its
+    /// locale, as `--merge sync` merges all formats into one and the format id of the audio may
+    /// not exist anymore.
+    fn audio_clip_start(&self, raw_audio: &SyncAudio) -> Option<TimeDelta> {
+        self.formats
+            .get(raw_audio.format_id)
+            .into_iter()
+            .chain(self.formats.iter())
+            .flat_map(|format| format.audios.iter())
+            .find(|(_, locale)| locale == &raw_audio.locale)
+            .and_then(|(stream_data, _)| self.clip_start(stream_data))
+    }
+
+    fn audio_language(&self, locale: &Locale) -> String {
+        self.audio_locale_output_map
+            .get(locale)
+            .cloned()
+            .unwrap_or(locale.to_string())
+    }
+
+    /// Writes every audio as standalone file next to `dst`, e.g. `video.en-US.mka` for
+    /// `video.mkv`. If `dst` is a `.m4a` file, the audios are also written as `.m4a`.
+    fn write_audio_files(
+        &self,
+        dst: &Path,
+        raw_audios: &[SyncAudio],
+        offset: impl Fn(usize) -> TimeDelta,
+    ) -> Result<()> {
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?
+        }
+        let stem = dst.file_stem().unwrap_or_default().to_string_lossy();
+        let extension = match dst.extension().unwrap_or_default().to_str().unwrap() {
+            "m4a" => "m4a",
+            _ => "mka",
+        };
+        for raw_audio in raw_audios {
+            let language = self.audio_language(&raw_audio.locale);
+            let path = dst.with_file_name(format!("{}.{}.{}", stem, language, extension));
+            shift_media(
+                &raw_audio.path,
+                &path,
+                offset(raw_audio.format_id),
+                &[
+                    "-metadata:s:a:0".to_string(),
+                    format!("language={}", language),
+                    "-metadata:s:a:0".to_string(),
+                    format!("title={}", raw_audio.locale.to_human_readable()),
+                ],
+            )?;
+            debug!(
+                "Wrote audio {} with offset of {}ms",
+                path.to_string_lossy(),
+                offset(raw_audio.format_id).num_milliseconds()
+            )
         }
-        ffmpeg_progress_cancel.cancel();
-        ffmpeg_progress.await?
+        Ok(())
     }
 
     async fn check_free_space(
@@ -845,15 +2072,36 @@ impl Downloader {
     ) -> Result<(Option<(PathBuf, u64)>, Option<(PathBuf, u64)>)> {
         let mut all_stream_data = vec![];
         for format in &self.formats {
-            all_stream_data.push(&format.video.0);
-            all_stream_data.extend(format.audios.iter().map(|(a, _)| a))
+            all_stream_data.push((&format.video.0, false));
+            all_stream_data.extend(format.audios.iter().map(|(a, _)| (a, true)))
         }
         let mut estimated_required_space: u64 = 0;
-        for stream_data in all_stream_data {
+        let mut estimated_required_tmp_space: u64 = 0;
+        // segments which are shared between videos are only cached once
+        let mut cached_segments = HashSet::new();
+        for (stream_data, audio) in all_stream_data {
             let segments = stream_data.segments();
 
             // sum the length of all streams up
-            estimated_required_space += estimate_stream_data_file_size(stream_data, &segments);
+            let size = estimate_stream_data_file_size(stream_data, &segments);
+            estimated_required_space += size;
+            // streams which are piped into ffmpeg are never stored in the temp directory
+            if !self.low_disk || (audio && !self.pipes_audios()) {
+                estimated_required_tmp_space += size
+            }
+            // the segment cache keeps a copy of every video segment in the temp directory, also
+            // if the video itself is piped into ffmpeg
+            if !audio && self.uses_segment_cache() {
+                let uncached: Vec<StreamSegment> = segments
+                    .into_iter()
+                    .filter(|s| {
+                        cached_segments
+                            .insert(s.url.split('?').next().unwrap_or(&s.url).to_string())
+                    })
+                    .collect();
+                estimated_required_tmp_space +=
+                    estimate_stream_data_file_size(stream_data, &uncached)
+            }
         }
 
         let tmp_stat = fs2::statvfs(temp_directory()).unwrap();
@@ -888,8 +2136,8 @@ impl Downloader {
         let mut tmp_required = None;
         let mut dst_required = None;
 
-        if tmp_space < estimated_required_space {
-            tmp_required = Some((temp_directory(), estimated_required_space))
+        if tmp_space < estimated_required_tmp_space {
+            tmp_required = Some((temp_directory(), estimated_required_tmp_space))
         }
         if (!is_special_file(dst) && dst.to_string_lossy() != "-")
             && dst_space < estimated_required_space
@@ -899,92 +2147,458 @@ impl Downloader {
         Ok((tmp_required, dst_required))
     }
 
-    async fn download_video(
-        &self,
-        stream_data: &StreamData,
-        message: String,
-        max_segments: Option<usize>,
-    ) -> Result<TempPath> {
-        let tempfile = tempfile(".mp4")?;
-        let (mut file, path) = tempfile.into_parts();
-
-        self.download_segments(&mut file, message, stream_data, max_segments)
-            .await?;
-
-        Ok(path)
+    /// If the video segments are cached, see [`SegmentCache`].
+    fn uses_segment_cache(&self) -> bool {
+        // if multiple videos are downloaded, they may share segments, e.g. when the same video is
+        // available for multiple audio locales
+        self.formats.len() > 1
     }
 
-    async fn download_audio(&self, stream_data: &StreamData, message: String) -> Result<TempPath> {
-        let tempfile = tempfile(".m4a")?;
-        let (mut file, path) = tempfile.into_parts();
-
-        self.download_segments(&mut file, message, stream_data, None)
-            .await?;
+    /// If the audios are piped into ffmpeg in low disk mode. They must be downloaded first if they
+    /// are used to sync the videos or to search the intro and ending.
+    fn pipes_audios(&self) -> bool {
+        let syncs = self.formats.len() > 1 && self.merge_sync_tolerance.is_some();
+        !syncs
+            && !self
+                .formats
+                .iter()
+                .any(|f| self.searches_recurring_parts(f))
+    }
 
-        Ok(path)
+    /// If the intro and ending of the format are searched in its audio. Fingerprinting the whole
+    /// audio takes some time, so it's only done if Crunchyroll doesn't provide the intro.
+    fn searches_recurring_parts(&self, format: &DownloadFormat) -> bool {
+        self.fingerprint_cache.is_some()
+            && format.metadata.season_id.is_some()
+            && format
+                .metadata
+                .skip_events
+                .as_ref()
+                .is_some_and(|skip_events| skip_events.intro.is_none())
     }
 
-    async fn download_subtitle(
+    /// Downloads the streams into the named pipes ffmpeg reads from. Every pipe is a tuple of the
+    /// pipe writer, the format index and, if the stream is an audio, the audio index.
+    async fn write_pipes(
         &self,
-        subtitle: Subtitle,
-        max_length: TimeDelta,
-    ) -> Result<TempPath> {
-        let buf = subtitle.data().await?;
-        let mut ass = match subtitle.format.as_str() {
-            "ass" => SSA::parse(String::from_utf8_lossy(&buf))?,
-            "vtt" => VTT::parse(String::from_utf8_lossy(&buf))?.to_ssa(),
-            _ => bail!("unknown subtitle format: {}", subtitle.format),
-        };
-        // subtitles aren't always correct sorted and video players may have issues with that. to
-        // prevent issues, the subtitles are sorted
-        // (https://github.com/crunchy-labs/crunchy-cli/issues/208)
-        ass.events.sort_by(|a, b| a.start.cmp(&b.start));
-        // it might be the case that the start and/or end time are greater than the actual video
-        // length. this might also result in issues with video players, thus the times are stripped
-        // to be at most as long as `max_length`
-        // (https://github.com/crunchy-labs/crunchy-cli/issues/32)
-        for i in (0..ass.events.len()).rev() {
-            let max_len = Time::from_hms(0, 0, 0)
-                .unwrap()
-                .add(Duration::from_millis(max_length.num_milliseconds() as u64));
-
-            if ass.events[i].start > max_len {
-                if ass.events[i].end > max_len {
-                    ass.events[i].start = max_len
+        pipes: Vec<(TempPipeWriter, usize, Option<usize>)>,
+        fmt_space: usize,
+        segment_cache: Option<&SegmentCache>,
+    ) -> Result<()> {
+        // all streams are downloaded at the same time, as ffmpeg reads them alternately
+        let (video_share, audio_share) = self.split_download_share();
+        let video_count = pipes.iter().filter(|(_, _, a)| a.is_none()).count().max(1);
+        let audio_count = pipes.iter().filter(|(_, _, a)| a.is_some()).count().max(1);
+        let (video_share, audio_share) = (&video_share, &audio_share);
+        futures_util::future::try_join_all(pipes.into_iter().map(
+            |(mut writer, format_idx, audio_idx)| async move {
+                let format = &self.formats[format_idx];
+                let (stream_data, message, share, segment_cache) = match audio_idx {
+                    Some(audio_idx) => {
+                        let (stream_data, locale) = &format.audios[audio_idx];
+                        (
+                            stream_data,
+                            format!("Downloading {} audio", locale),
+                            audio_share.divide(audio_count),
+                            None,
+                        )
+                    }
+                    None => (
+                        &format.video.0,
+                        format!("Downloading video #{}", format_idx + 1),
+                        video_share.divide(video_count),
+                        segment_cache,
+                    ),
+                };
+                self.download_segments(
+                    &mut writer,
+                    format!("{:<1$}", message, fmt_space),
+                    stream_data,
+                    None,
+                    segment_cache,
+                    &share,
+                    None,
+                )
+                .await?;
+                writer.finish().await?;
+                Ok::<_, anyhow::Error>(())
+            },
+        ))
+        .await?;
+        Ok(())
+    }
+
+    /// All download threads and the whole speed limit, for a download which runs on its own.
+    fn full_share(&self) -> DownloadShare {
+        DownloadShare {
+            threads: self.download_threads,
+            rate_limiter: self.rate_limiter.clone(),
+        }
+    }
+
+    /// Splits the download threads and the speed limit between video and audio downloads,
+    /// proportional to their bandwidth. Each gets at least one thread, so a single download thread
+    /// is only split if both have to run at the same time.
+    fn split_download_share(&self) -> (DownloadShare, DownloadShare) {
+        let video_bandwidth: u64 = self.formats.iter().map(|f| f.video.0.bandwidth).sum();
+        let audio_bandwidth: u64 = self
+            .formats
+            .iter()
+            .flat_map(|f| f.audios.iter().map(|(a, _)| a.bandwidth))
+            .sum();
+        let video_fraction =
+            video_bandwidth as f64 / (video_bandwidth + audio_bandwidth).max(1) as f64;
+        let video_threads = (self.download_threads as f64 * video_fraction).round() as usize;
+        let video_threads = video_threads.clamp(1, self.download_threads.max(2) - 1);
+        (
+            DownloadShare {
+                threads: video_threads,
+                rate_limiter: self.rate_limiter.as_ref().map(|r| r.share(video_fraction)),
+            },
+            DownloadShare {
+                threads: self.download_threads.saturating_sub(video_threads).max(1),
+                rate_limiter: self
+                    .rate_limiter
+                    .as_ref()
+                    .map(|r| r.share(1.0 - video_fraction)),
+            },
+        )
+    }
+
+    async fn download_audios(
+        &self,
+        fmt_space: usize,
+        share: &DownloadShare,
+    ) -> Result<Vec<SyncAudio>> {
+        let mut raw_audios = vec![];
+        for (i, format) in self.formats.iter().enumerate() {
+            for (stream_data, locale) in &format.audios {
+                let path = self
+                    .download_audio(
+                        stream_data,
+                        &format.metadata.episode_id,
+                        locale,
+                        format!("{:<1$}", format!("Downloading {} audio", locale), fmt_space),
+                        share,
+                    )
+                    .await?;
+                raw_audios.push(SyncAudio {
+                    format_id: i,
+                    path,
+                    locale: locale.clone(),
+                    sample_rate: stream_data.sampling_rate().unwrap(),
+                    video_idx: i,
+                })
+            }
+        }
+        Ok(raw_audios)
+    }
+
+    async fn download_videos(
+        &self,
+        fmt_space: usize,
+        segment_cache: Option<&SegmentCache>,
+        share: &DownloadShare,
+    ) -> Result<Vec<TempPath>> {
+        let mut paths = vec![];
+        for (i, format) in self.formats.iter().enumerate() {
+            let message = format!("{:<1$}", format!("Downloading video #{}", i + 1), fmt_space);
+            let mut result = self
+                .download_video(
+                    &format.video.0,
+                    &format.metadata,
+                    &format.video.1,
+                    message.clone(),
+                    None,
+                    segment_cache,
+                    share,
+                )
+                .await;
+            if self.resolution_fallback {
+                for fallback in &format.video_fallbacks {
+                    let Err(err) = &result else {
+                        break;
+                    };
+                    // only segments which are missing or can't be delivered by the server are
+                    // worth trying in another resolution
+                    if !matches!(
+                        DownloadError::classify(err),
+                        Some(DownloadError::Network(_) | DownloadError::ApiSchema(_))
+                    ) {
+                        break;
+                    }
+                    warn!(
+                        "Failed to download video #{} ({}), falling back to resolution {}",
+                        i + 1,
+                        err,
+                        fallback
+                            .resolution()
+                            .map_or("unknown".to_string(), |r| r.to_string())
+                    );
+                    result = self
+                        .download_video(
+                            fallback,
+                            &format.metadata,
+                            &format.video.1,
+                            message.clone(),
+                            None,
+                            segment_cache,
+                            share,
+                        )
+                        .await
                 }
-                ass.events[i].end = max_len
-            } else {
-                break;
             }
+            paths.push(result?)
+        }
+        Ok(paths)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn download_video(
+        &self,
+        stream_data: &StreamData,
+        metadata: &DownloadFormatMetadata,
+        locale: &Locale,
+        message: String,
+        max_segments: Option<usize>,
+        segment_cache: Option<&SegmentCache>,
+        share: &DownloadShare,
+    ) -> Result<TempPath> {
+        if self.resume && max_segments.is_none() {
+            return self
+                .download_resumable(
+                    stream_data,
+                    &metadata.episode_id,
+                    metadata.hardsub.as_ref(),
+                    locale,
+                    ".mp4",
+                    message,
+                    segment_cache,
+                    share,
+                )
+                .await;
         }
 
-        // without this additional info, subtitle look very messy in some video player
-        // (https://github.com/crunchy-labs/crunchy-cli/issues/66)
-        ass.info
-            .additional_fields
-            .insert("ScaledBorderAndShadow".to_string(), "yes".to_string());
+        let tempfile = named_tempfile(self.temp_file_context(locale, "video"), ".mp4")?;
+        let (mut file, path) = tempfile.into_parts();
+
+        self.download_segments(
+            &mut file,
+            message,
+            stream_data,
+            max_segments,
+            segment_cache,
+            share,
+            None,
+        )
+        .await?;
+
+        Ok(path)
+    }
+
+    async fn download_audio(
+        &self,
+        stream_data: &StreamData,
+        episode_id: &str,
+        locale: &Locale,
+        message: String,
+        share: &DownloadShare,
+    ) -> Result<TempPath> {
+        if self.resume {
+            return self
+                .download_resumable(
+                    stream_data,
+                    episode_id,
+                    None,
+                    locale,
+                    ".m4a",
+                    message,
+                    None,
+                    share,
+                )
+                .await;
+        }
 
-        let tempfile = tempfile(".ass")?;
-        let path = tempfile.into_temp_path();
+        let tempfile = named_tempfile(self.temp_file_context(locale, "audio"), ".m4a")?;
+        let (mut file, path) = tempfile.into_parts();
 
-        fs::write(&path, ass.to_string())?;
+        self.download_segments(&mut file, message, stream_data, None, None, share, None)
+            .await?;
 
         Ok(path)
     }
 
+    /// Downloads the stream into a file which is kept if the download gets interrupted. If a file
+    /// of a previous, interrupted download of the same stream exists, the download continues
+    /// after its last completely written segment.
+    #[allow(clippy::too_many_arguments)]
+    async fn download_resumable(
+        &self,
+        stream_data: &StreamData,
+        episode_id: &str,
+        hardsub: Option<&Locale>,
+        locale: &Locale,
+        suffix: &str,
+        message: String,
+        segment_cache: Option<&SegmentCache>,
+        share: &DownloadShare,
+    ) -> Result<TempPath> {
+        let mut state =
+            ResumeState::read_or_create(stream_data, episode_id, hardsub, locale, suffix)?;
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&state.file)?;
+        // everything after the last completely written segment may be incomplete
+        file.set_len(state.bytes)?;
+        file.seek(SeekFrom::End(0))?;
+        if state.segments > 0 {
+            info!("Resuming download from segment {}", state.segments + 1)
+        }
+
+        self.download_segments(
+            &mut file,
+            message,
+            stream_data,
+            None,
+            segment_cache,
+            share,
+            Some(&mut state),
+        )
+        .await?;
+
+        Ok(state.finish())
+    }
+
+    async fn fetch_subtitle(subtitle: &Subtitle) -> Result<SSA> {
+        let buf = subtitle.data().await?;
+        if buf.is_empty() {
+            bail!("subtitle file is empty")
+        }
+        let ass = match subtitle.format.as_str() {
+            "ass" => SSA::parse(String::from_utf8_lossy(&buf))?,
+            "vtt" => VTT::parse(String::from_utf8_lossy(&buf))?.to_ssa(),
+            _ => unreachable!(),
+        };
+        if ass.events.is_empty() {
+            bail!("subtitle file contains no events")
+        }
+        Ok(ass)
+    }
+
+    async fn download_subtitle(&self, subtitle: &Subtitle) -> Result<SSA> {
+        if !["ass", "vtt"].contains(&subtitle.format.as_str()) {
+            bail!("unknown subtitle format: {}", subtitle.format)
+        }
+
+        // a failed subtitle download would otherwise abort the whole episode, even if video and
+        // audio were already downloaded. because of this, responses which are erroneous or do not
+        // contain a usable subtitle are requested again a few times
+        let mut retry_count = 0;
+        let ass = loop {
+            let err = match Self::fetch_subtitle(subtitle).await {
+                Ok(ass) => break ass,
+                Err(e) => e,
+            };
+
+            if retry_count == SUBTITLE_DOWNLOAD_RETRIES {
+                bail!(
+                    "Max retry count reached ({}), multiple errors occurred while receiving {} subtitles: {}",
+                    retry_count,
+                    subtitle.locale,
+                    err
+                )
+            }
+            debug!(
+                "Failed to download {} subtitles ({}). Retrying, {} out of {} retries left",
+                subtitle.locale,
+                err,
+                SUBTITLE_DOWNLOAD_RETRIES - retry_count,
+                SUBTITLE_DOWNLOAD_RETRIES
+            );
+
+            // give the server some time to recover, retrying right away would likely fail again
+            tokio::time::sleep(Duration::from_millis(500 << retry_count)).await;
+            retry_count += 1;
+        };
+
+        Ok(ass)
+    }
+
+    /// Writes the image behind `url` next to `dst`, with the extension of the image. A failed
+    /// download only prints a warning as the actual output file was already written.
+    async fn write_thumbnail(&self, url: &str, dst: &Path) {
+        let result = async {
+            let image = self.download_image(url).await?;
+            let path = dst.with_extension(image.extension().unwrap_or_default());
+            fs::copy(&image, &path)?;
+            debug!("Wrote thumbnail to {}", path.to_string_lossy());
+            Ok::<_, anyhow::Error>(())
+        }
+        .await;
+        if let Err(e) = result {
+            warn!("Failed to write thumbnail: {}", e)
+        }
+    }
+
+    /// Context of temp files which contain a stream of the given locale, see [`named_tempfile`].
+    fn temp_file_context(&self, locale: &Locale, kind: &str) -> String {
+        format!(
+            "{}.{}.{}",
+            self.episode_id.as_deref().unwrap_or("unknown"),
+            locale,
+            kind
+        )
+    }
+
+    /// Replaces fonts which aren't available with their fallback from `--font-fallback`. Returns
+    /// the replaced fonts with their fallback.
+    fn apply_font_fallback(&self, font_names: &mut Vec<String>) -> HashMap<String, String> {
+        let mut substitutions = HashMap::new();
+        for font_name in font_names.iter_mut() {
+            if is_known_font(font_name) {
+                continue;
+            }
+            // '*' is the fallback of every font which hasn't its own one
+            let Some((_, fallback)) = self
+                .font_fallback
+                .iter()
+                .find(|(f, _)| f.eq_ignore_ascii_case(font_name))
+                .or_else(|| self.font_fallback.iter().find(|(f, _)| f == "*"))
+            else {
+                continue;
+            };
+            info!(
+                "Font {} is not available, using {} instead",
+                font_name, fallback
+            );
+            substitutions.insert(font_name.clone(), fallback.clone());
+            *font_name = fallback.clone()
+        }
+        real_dedup_vec(font_names);
+        substitutions
+    }
+
     async fn download_font(&self, name: &str) -> Result<Option<(PathBuf, bool)>> {
-        let Some((_, font_file)) = FONTS.iter().find(|(f, _)| f == &name) else {
+        let Some(font_file) = font_file(name) else {
             return Ok(None);
         };
 
         let cache_dir = cache_dir("fonts")?;
-        let file = cache_dir.join(font_file);
+        let file = cache_dir.join(&font_file);
         if file.exists() {
-            return Ok(Some((file, true)));
+            // an interrupted download may have left a truncated font behind, which would be
+            // attached to every following file
+            if is_valid_woff2(&fs::read(&file)?) {
+                return Ok(Some((file, true)));
+            }
+            warn!("Cached font {} is corrupt, downloading it again", name);
+            fs::remove_file(&file)?
         }
 
         // the speed limiter does not apply to this
-        let font = self
+        let response = self
             .client
             .get(format!(
                 "https://static.crunchyroll.com/vilos-v2/web/vilos/assets/libass-fonts/{}",
@@ -992,49 +2606,129 @@ impl Downloader {
             ))
             .send()
             .await?
+            .error_for_status()?;
+        let content_length = response.content_length();
+        let font = response.bytes().await?;
+        if content_length.is_some_and(|l| l != font.len() as u64) || !is_valid_woff2(&font) {
+            bail!("Downloaded font {} is corrupt", name)
+        }
+        // the font is written to a temporary file first and then renamed, so that the cache never
+        // contains a partially written font
+        let part_file = file.with_extension("woff2.part");
+        fs::write(&part_file, font)?;
+        fs::rename(&part_file, &file)?;
+
+        Ok(Some((file, false)))
+    }
+
+    async fn download_image(&self, url: &str) -> Result<TempPath> {
+        let extension = Path::new(url.split('?').next().unwrap())
+            .extension()
+            .map_or("jpg".to_string(), |e| e.to_string_lossy().to_lowercase());
+        // the speed limiter does not apply to this
+        let image = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
             .bytes()
             .await?;
-        fs::write(&file, font)?;
+        let (mut file, path) = tempfile(format!(".{}", extension))?.into_parts();
+        file.write_all(&image)?;
+        debug!("Downloaded image {}", url);
 
-        Ok(Some((file, false)))
+        Ok(path)
+    }
+
+    /// Opens as many connections to every segment host as download threads are used. The
+    /// connections are kept in the connection pool of the client and are reused by the actual
+    /// segment downloads, so that they don't have to wait for dns resolution and tls handshakes.
+    async fn warm_up_connections(&self, segments: &[StreamSegment], threads: usize) {
+        let mut urls: Vec<&str> = vec![];
+        for segment in segments {
+            let host = segment.url.split('/').nth(2);
+            if !urls.iter().any(|u| u.split('/').nth(2) == host) {
+                urls.push(&segment.url)
+            }
+        }
+
+        let connections = threads.min(segments.len());
+        let requests = urls.into_iter().flat_map(|url| {
+            (0..connections).map(move |_| async move {
+                if let Err(e) = self
+                    .client
+                    .head(url)
+                    .timeout(Duration::from_secs(10))
+                    .send()
+                    .await
+                {
+                    debug!("Failed to warm up connection to {}: {}", url, e)
+                }
+            })
+        });
+        futures_util::future::join_all(requests).await;
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn download_segments(
         &self,
         writer: &mut impl Write,
         message: String,
         stream_data: &StreamData,
         max_segments: Option<usize>,
+        segment_cache: Option<&SegmentCache>,
+        share: &DownloadShare,
+        mut resume: Option<&mut ResumeState>,
     ) -> Result<()> {
         let mut segments = stream_data.segments();
         if let Some(max_segments) = max_segments {
             segments = segments
                 .drain(0..max_segments.min(segments.len() - 1))
                 .collect();
+        } else if let Some(clip) = &self.clip {
+            // only the segments which overlap with the clip are downloaded, the exact clip is cut
+            // out when generating the output file
+            segments = clip.select_segments(segments).0
         }
+        // segments which were already written in a previous run are skipped
+        let skipped_segments = resume
+            .as_ref()
+            .map_or(0, |r| r.segments)
+            .min(segments.len());
+        segments.drain(0..skipped_segments);
         let total_segments = segments.len();
 
+        // replayed segments are read from disk, no connection is needed
+        if !self
+            .segment_recording
+            .as_ref()
+            .is_some_and(SegmentRecording::is_replay)
+        {
+            self.warm_up_connections(&segments, share.threads).await;
+        }
+
         let count = Arc::new(Mutex::new(0));
+        let stats = Arc::new(std::sync::Mutex::new(SegmentStats::default()));
+        let speed = Arc::new(AtomicU64::new(0));
+        let mut speed_ema = Ema::default();
+        let mut last_received = Instant::now();
 
-        let progress = if log::max_level() == LevelFilter::Info {
+        let progress = if progress_enabled() {
             let estimated_file_size = estimate_stream_data_file_size(stream_data, &segments);
 
-            let progress = ProgressBar::new(estimated_file_size)
-                .with_style(
-                    ProgressStyle::with_template(
-                        ":: {msg} {bytes:>10} {bytes_per_sec:>12} [{wide_bar}] {percent:>3}%",
-                    )
-                    .unwrap()
-                    .progress_chars("##-"),
-                )
-                .with_message(message)
-                .with_finish(ProgressFinish::Abandon);
+            let progress = add_progress_bar(
+                ProgressBar::new(estimated_file_size)
+                    .with_style(segment_download_bar_style(speed.clone()))
+                    .with_message(message)
+                    .with_finish(ProgressFinish::Abandon),
+            );
             Some(progress)
         } else {
             None
         };
 
-        let cpus = self.download_threads.min(segments.len());
+        let cpus = share.threads.min(segments.len());
         let mut segs: Vec<Vec<StreamSegment>> = Vec::with_capacity(cpus);
         for _ in 0..cpus {
             segs.push(vec![])
@@ -1043,48 +2737,98 @@ impl Downloader {
             segs[i - ((i / cpus) * cpus)].push(segment);
         }
 
-        let (sender, mut receiver) = unbounded_channel();
+        // the channel is bounded, so that the download threads pause if the writer can't take any
+        // more data, e.g. a pipe which ffmpeg isn't reading from at the moment
+        let (sender, mut receiver) = channel(cpus.max(1) * 2);
 
         let mut join_set: JoinSet<Result<()>> = JoinSet::new();
         for num in 0..cpus {
             let thread_sender = sender.clone();
             let thread_segments = segs.remove(0);
             let thread_client = self.client.clone();
-            let mut thread_rate_limiter = self.rate_limiter.clone();
+            let mut thread_rate_limiter = share.rate_limiter.clone();
+            let mut thread_proxy_pool = self.proxy_pool.clone();
             let thread_count = count.clone();
+            let thread_segment_cache = segment_cache.cloned();
+            let thread_segment_recording = self.segment_recording.clone();
+            let thread_stall_timeout = self.stall_timeout;
+            let thread_schedule = self.schedule;
+            let thread_stats = stats.clone();
+            // with slow start, the threads start one after another spread over the slow start
+            // time instead of all at once
+            let thread_delay = self.slow_start.map_or(Duration::ZERO, |slow_start| {
+                slow_start * num as u32 / cpus as u32
+            });
             join_set.spawn(async move {
                 let after_download_sender = thread_sender.clone();
+                tokio::time::sleep(thread_delay).await;
 
                 // the download process is encapsulated in its own function. this is done to easily
                 // catch errors which get returned with `...?` and `bail!(...)` and that the thread
                 // itself can report that an error has occurred
                 let download = || async move {
                     for (i, segment) in thread_segments.into_iter().enumerate() {
-                        let mut retry_count = 0;
-                        let buf = loop {
-                            let request = thread_client
-                                .get(&segment.url)
-                                .timeout(Duration::from_secs(60));
-                            let response = if let Some(rate_limiter) = &mut thread_rate_limiter {
-                                rate_limiter.call(request.build()?).await.map_err(anyhow::Error::new)
-                            } else {
-                                request.send().await.map_err(anyhow::Error::new)
-                            };
-
-                            let err = match response {
-                                Ok(r) => match r.bytes().await {
-                                    Ok(b) => break b.to_vec(),
-                                    Err(e) => anyhow::Error::new(e)
+                        let replayed = match &thread_segment_recording {
+                            Some(recording) => recording.replay(&segment.url)?,
+                            None => None,
+                        };
+                        let buf = if let Some(buf) = replayed {
+                            buf
+                        } else if let Some(buf) = thread_segment_cache.as_ref().and_then(|c| c.get(&segment.url)) {
+                            debug!("Using cached segment {}", segment.url);
+                            buf
+                        } else {
+                            // outside of the schedule window the thread pauses before it
+                            // requests its next segment
+                            if let Some(schedule) = &thread_schedule {
+                                schedule.wait().await
+                            }
+                            let mut retry_count = 0;
+                            let started = Instant::now();
+                            let buf = loop {
+                                let request = thread_client
+                                    .get(&segment.url)
+                                    .timeout(Duration::from_secs(60));
+                                let response = if let Some(rate_limiter) = &mut thread_rate_limiter {
+                                    rate_limiter.call(request.build()?).await.map_err(anyhow::Error::new)
+                                } else if let Some(proxy_pool) = &mut thread_proxy_pool {
+                                    proxy_pool.call(request.build()?).await.map_err(anyhow::Error::new)
+                                } else {
+                                    request.send().await.map_err(anyhow::Error::new)
+                                };
+
+                                let err = match response {
+                                    Ok(r) => match read_body(r, thread_stall_timeout).await {
+                                        Ok(b) => break b,
+                                        Err(e) => e
+                                    }
+                                    Err(e) => e,
+                                };
+
+                                if !DownloadError::classify(&err).map_or(true, |e| e.is_retryable()) {
+                                    debug!("Failed to download segment {}, error is not retryable", num + (i * cpus));
+                                    return Err(err)
                                 }
-                                Err(e) => e,
-                            };
+                                if retry_count == 5 {
+                                    bail!(DownloadError::Network(format!("Max retry count reached ({}), multiple errors occurred while receiving segment {}: {}", retry_count, num + (i * cpus), err)))
+                                }
+                                debug!("Failed to download segment {} ({}). Retrying, {} out of 5 retries left", num + (i * cpus), err, 5 - retry_count);
 
-                            if retry_count == 5 {
-                                bail!("Max retry count reached ({}), multiple errors occurred while receiving segment {}: {}", retry_count, num + (i * cpus), err)
+                                retry_count += 1;
+                            };
+                            if let Some(segment_cache) = &thread_segment_cache {
+                                segment_cache.insert(&segment.url, &buf)?
                             }
-                            debug!("Failed to download segment {} ({}). Retrying, {} out of 5 retries left", num + (i * cpus), err, 5 - retry_count);
-
-                            retry_count += 1;
+                            if let Some(segment_recording) = &thread_segment_recording {
+                                segment_recording.record(&segment.url, &buf)?
+                            }
+                            thread_stats.lock().unwrap().record(
+                                num,
+                                segment.url.split('/').nth(2).unwrap_or_default(),
+                                buf.len() as u64,
+                                started.elapsed(),
+                            );
+                            buf
                         };
 
                         let mut c = thread_count.lock().await;
@@ -1096,7 +2840,11 @@ impl Downloader {
                             segment.url
                         );
 
-                        thread_sender.send((num as i32 + (i * cpus) as i32, buf))?;
+                        // the receiver is only dropped if another thread failed, its error is
+                        // the one which gets returned
+                        if thread_sender.send((num as i32 + (i * cpus) as i32, buf)).await.is_err() {
+                            return Ok(())
+                        }
 
                         *c += 1;
                     }
@@ -1106,7 +2854,7 @@ impl Downloader {
 
                 let result = download().await;
                 if result.is_err() {
-                    after_download_sender.send((-1, vec![]))?;
+                    let _ = after_download_sender.send((-1, vec![])).await;
                 }
 
                 result
@@ -1119,6 +2867,21 @@ impl Downloader {
         // this is the main loop which writes the data. it uses a BTreeMap as a buffer as the write
         // happens synchronized. the download consist of multiple segments. the map keys are representing
         // the segment number and the values the corresponding bytes
+        let mut writer = ThroughputWriter::new(writer);
+        // returns false if the writer can't take the segment at the moment and it must be written
+        // again later
+        let mut write_segment = |pos: i32, bytes: &[u8]| -> Result<bool> {
+            match writer.write_all(bytes) {
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(false),
+                result => result?,
+            }
+            if let Some(state) = resume.as_mut() {
+                state.segments = skipped_segments + pos as usize + 1;
+                state.bytes += bytes.len() as u64;
+                state.write()?
+            }
+            Ok(true)
+        };
         let mut data_pos = 0;
         let mut buf: BTreeMap<i32, Vec<u8>> = BTreeMap::new();
         while let Some((pos, bytes)) = receiver.recv().await {
@@ -1127,6 +2890,18 @@ impl Downloader {
                 break;
             }
 
+            // the speed is smoothed, as segments are received in bursts from multiple threads
+            let elapsed = last_received.elapsed().as_secs_f64();
+            last_received = Instant::now();
+            if elapsed > 0.0 {
+                let alpha = 1.0 - (-elapsed / SPEED_SMOOTHING_SECONDS).exp();
+                speed_ema.update(bytes.len() as f64 / elapsed, alpha);
+                speed.store(
+                    speed_ema.value.unwrap_or_default() as u64,
+                    AtomicOrdering::Relaxed,
+                );
+            }
+
             if let Some(p) = &progress {
                 let progress_len = p.length().unwrap();
                 let estimated_segment_len = (stream_data.bandwidth / 8)
@@ -1141,26 +2916,45 @@ impl Downloader {
             // to the target without first adding them to the buffer.
             // if not, add them to the buffer
             if data_pos == pos {
-                writer.write_all(bytes.borrow())?;
+                while !write_segment(data_pos, bytes.borrow())? {
+                    tokio::time::sleep(BLOCKED_WRITER_WAIT).await
+                }
                 data_pos += 1;
             } else {
                 buf.insert(pos, bytes);
             }
             // check if the buffer contains the next segment(s)
             while let Some(b) = buf.remove(&data_pos) {
-                writer.write_all(b.borrow())?;
+                while !write_segment(data_pos, b.borrow())? {
+                    tokio::time::sleep(BLOCKED_WRITER_WAIT).await
+                }
                 data_pos += 1;
             }
+
+            // segments which are only downloaded to probe the stream are not part of the progress
+            if max_segments.is_none() {
+                set_terminal_title_percent(
+                    ((skipped_segments + data_pos as usize) * 100
+                        / (skipped_segments + total_segments).max(1)) as u64,
+                )
+            }
         }
 
+        // threads which still wait to send a segment stop if the loop above was left because of an
+        // error
+        drop(receiver);
+
         // if any error has occurred while downloading it gets returned here
         while let Some(joined) = join_set.join_next().await {
             joined??
         }
+        stats.lock().unwrap().report();
 
         // write the remaining buffer, if existent
         while let Some(b) = buf.remove(&data_pos) {
-            writer.write_all(b.borrow())?;
+            while !write_segment(data_pos, b.borrow())? {
+                tokio::time::sleep(BLOCKED_WRITER_WAIT).await
+            }
             data_pos += 1;
         }
 
@@ -1178,95 +2972,753 @@ impl Downloader {
     }
 }
 
-fn estimate_stream_data_file_size(stream_data: &StreamData, segments: &[StreamSegment]) -> u64 {
-    (stream_data.bandwidth / 8) * segments.iter().map(|s| s.length.as_secs()).sum::<u64>()
+/// Reads the whole response body. If `stall_timeout` is set and no data was received for this time,
+/// an error is returned so that the request can be retried on another connection instead of
+/// waiting for the full request timeout.
+async fn read_body(response: Response, stall_timeout: Option<Duration>) -> Result<Vec<u8>> {
+    let Some(stall_timeout) = stall_timeout else {
+        return Ok(response.error_for_status()?.bytes().await?.to_vec());
+    };
+
+    let mut stream = response.error_for_status()?.bytes_stream();
+    let mut buf = vec![];
+    loop {
+        match tokio::time::timeout(stall_timeout, stream.next()).await {
+            Ok(Some(chunk)) => buf.extend_from_slice(&chunk?),
+            Ok(None) => return Ok(buf),
+            Err(_) => bail!(DownloadError::Network(format!(
+                "transfer stalled, no data received for {} seconds",
+                stall_timeout.as_secs()
+            ))),
+        }
+    }
 }
 
-/// Get the length and fps of a video.
-fn get_video_stats(path: &Path) -> Result<(TimeDelta, f64)> {
-    let video_length = Regex::new(r"Duration:\s(?P<time>\d+:\d+:\d+\.\d+),")?;
-    let video_fps = Regex::new(r"(?P<fps>[\d/.]+)\sfps")?;
+/// Time in seconds after which a speed measurement has lost most of its weight in the displayed
+/// download speed.
+const SPEED_SMOOTHING_SECONDS: f64 = 5.0;
+/// How long to wait before a segment is written again to a writer which can't take more data at
+/// the moment.
+const BLOCKED_WRITER_WAIT: Duration = Duration::from_millis(20);
+/// Threads and hosts which are slower than this share of the median speed are reported as slow.
+const SLOW_SPEED_SHARE: f64 = 0.5;
+/// Minimum number of segments a thread must have downloaded before it may be reported as slow.
+const SLOW_MIN_SEGMENTS: usize = 3;
+
+/// Exponential moving average.
+#[derive(Default)]
+struct Ema {
+    value: Option<f64>,
+}
 
-    let ffmpeg = Command::new("ffmpeg")
-        .stdout(Stdio::null())
-        .stderr(Stdio::piped())
-        .arg("-y")
-        .arg("-hide_banner")
-        .args(["-i", path.to_str().unwrap()])
-        .output()?;
-    let ffmpeg_output = String::from_utf8(ffmpeg.stderr)?;
-    let length_caps = video_length
-        .captures(ffmpeg_output.as_str())
-        .ok_or(anyhow::anyhow!(
-            "failed to get video length: {}",
-            ffmpeg_output
-        ))?;
-    let fps_caps = video_fps
-        .captures(ffmpeg_output.as_str())
-        .ok_or(anyhow::anyhow!(
-            "failed to get video fps: {}",
-            ffmpeg_output
-        ))?;
+impl Ema {
+    fn update(&mut self, sample: f64, alpha: f64) {
+        self.value = Some(match self.value {
+            Some(value) => alpha * sample + (1.0 - alpha) * value,
+            None => sample,
+        })
+    }
+}
 
-    Ok((
-        NaiveTime::parse_from_str(length_caps.name("time").unwrap().as_str(), "%H:%M:%S%.f")
-            .unwrap()
-            .signed_duration_since(NaiveTime::MIN),
-        fps_caps.name("fps").unwrap().as_str().parse().unwrap(),
-    ))
+/// Download speeds of the download threads and segment hosts of a stream, to find out if some of
+/// them are slower than the others.
+#[derive(Default)]
+struct SegmentStats {
+    /// Speed and number of segments by thread.
+    threads: BTreeMap<usize, (Ema, usize)>,
+    /// Downloaded bytes and the time it took by host.
+    hosts: BTreeMap<String, (u64, Duration)>,
 }
 
-// all subtitle fonts (extracted from javascript)
-const FONTS: [(&str, &str); 68] = [
-    ("Adobe Arabic", "AdobeArabic-Bold.woff2"),
-    ("Andale Mono", "andalemo.woff2"),
-    ("Arial", "arial.woff2"),
-    ("Arial Black", "ariblk.woff2"),
-    ("Arial Bold", "arialbd.woff2"),
-    ("Arial Bold Italic", "arialbi.woff2"),
-    ("Arial Italic", "ariali.woff2"),
-    ("Arial Unicode MS", "arialuni.woff2"),
-    ("Comic Sans MS", "comic.woff2"),
-    ("Comic Sans MS Bold", "comicbd.woff2"),
-    ("Courier New", "cour.woff2"),
-    ("Courier New Bold", "courbd.woff2"),
-    ("Courier New Bold Italic", "courbi.woff2"),
-    ("Courier New Italic", "couri.woff2"),
-    ("DejaVu LGC Sans Mono", "DejaVuLGCSansMono.woff2"),
-    ("DejaVu LGC Sans Mono Bold", "DejaVuLGCSansMono-Bold.woff2"),
-    (
-        "DejaVu LGC Sans Mono Bold Oblique",
-        "DejaVuLGCSansMono-BoldOblique.woff2",
-    ),
-    (
-        "DejaVu LGC Sans Mono Oblique",
-        "DejaVuLGCSansMono-Oblique.woff2",
-    ),
-    ("DejaVu Sans", "DejaVuSans.woff2"),
-    ("DejaVu Sans Bold", "DejaVuSans-Bold.woff2"),
-    ("DejaVu Sans Bold Oblique", "DejaVuSans-BoldOblique.woff2"),
-    ("DejaVu Sans Condensed", "DejaVuSansCondensed.woff2"),
-    (
-        "DejaVu Sans Condensed Bold",
-        "DejaVuSansCondensed-Bold.woff2",
-    ),
-    (
-        "DejaVu Sans Condensed Bold Oblique",
-        "DejaVuSansCondensed-BoldOblique.woff2",
-    ),
-    (
-        "DejaVu Sans Condensed Oblique",
-        "DejaVuSansCondensed-Oblique.woff2",
-    ),
-    ("DejaVu Sans ExtraLight", "DejaVuSans-ExtraLight.woff2"),
-    ("DejaVu Sans Mono", "DejaVuSansMono.woff2"),
-    ("DejaVu Sans Mono Bold", "DejaVuSansMono-Bold.woff2"),
-    (
-        "DejaVu Sans Mono Bold Oblique",
-        "DejaVuSansMono-BoldOblique.woff2",
-    ),
-    ("DejaVu Sans Mono Oblique", "DejaVuSansMono-Oblique.woff2"),
-    ("DejaVu Sans Oblique", "DejaVuSans-Oblique.woff2"),
+impl SegmentStats {
+    fn record(&mut self, thread: usize, host: &str, bytes: u64, duration: Duration) {
+        let (ema, segments) = self.threads.entry(thread).or_default();
+        ema.update(bytes as f64 / duration.as_secs_f64().max(0.001), 0.3);
+        *segments += 1;
+
+        let (host_bytes, host_duration) = self.hosts.entry(host.to_string()).or_default();
+        *host_bytes += bytes;
+        *host_duration += duration;
+    }
+
+    /// Logs the speed of every thread and host and highlights the ones which are considerably
+    /// slower than the others. The summary is also added to the debug bundle.
+    fn report(&self) {
+        let mut lines = vec![];
+
+        let thread_speeds: Vec<(usize, f64, usize)> = self
+            .threads
+            .iter()
+            .map(|(thread, (ema, segments))| (*thread, ema.value.unwrap_or_default(), *segments))
+            .collect();
+        let thread_median = median(thread_speeds.iter().map(|(_, speed, _)| *speed).collect());
+        for (thread, speed, segments) in &thread_speeds {
+            let slow = thread_speeds.len() > 1
+                && *segments >= SLOW_MIN_SEGMENTS
+                && *speed < thread_median * SLOW_SPEED_SHARE;
+            lines.push(format!(
+                "Thread {}: {:.2} MB/s over {} segments{}",
+                thread,
+                speed / 1024.0 / 1024.0,
+                segments,
+                if slow { " (consistently slow)" } else { "" }
+            ))
+        }
+
+        let host_speeds: Vec<(&String, f64)> = self
+            .hosts
+            .iter()
+            .map(|(host, (bytes, duration))| {
+                (host, *bytes as f64 / duration.as_secs_f64().max(0.001))
+            })
+            .collect();
+        let host_median = median(host_speeds.iter().map(|(_, speed)| *speed).collect());
+        for (host, speed) in &host_speeds {
+            let slow = host_speeds.len() > 1 && *speed < host_median * SLOW_SPEED_SHARE;
+            lines.push(format!(
+                "Host {}: {:.2} MB/s{}",
+                host,
+                speed / 1024.0 / 1024.0,
+                if slow { " (slow)" } else { "" }
+            ))
+        }
+
+        for line in &lines {
+            debug!("{}", line)
+        }
+        debug_bundle::record_throughput(&lines)
+    }
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    values[values.len() / 2]
+}
+
+/// Warns once if writing to the temp directory takes more than this share of the time in the
+/// first [`THROUGHPUT_MEASURE_DURATION`] of a download.
+const THROUGHPUT_WARN_BUSY_SHARE: f64 = 0.5;
+const THROUGHPUT_MEASURE_DURATION: Duration = Duration::from_secs(10);
+static THROUGHPUT_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Measures how long writes take, to detect if the disk is slower than the network.
+struct ThroughputWriter<W: Write> {
+    inner: W,
+    started: Option<Instant>,
+    busy: Duration,
+    bytes: u64,
+    measured: bool,
+}
+
+impl<W: Write> ThroughputWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            started: None,
+            busy: Duration::ZERO,
+            bytes: 0,
+            measured: false,
+        }
+    }
+
+    fn check(&mut self) {
+        let elapsed = self.started.get_or_insert_with(Instant::now).elapsed();
+        if self.measured || elapsed < THROUGHPUT_MEASURE_DURATION {
+            return;
+        }
+        self.measured = true;
+
+        let busy_share = self.busy.as_secs_f64() / elapsed.as_secs_f64();
+        debug!(
+            "Wrote {} bytes in {:.2}s ({:.0}% of the time spent writing)",
+            self.bytes,
+            self.busy.as_secs_f64(),
+            busy_share * 100.0
+        );
+        if busy_share > THROUGHPUT_WARN_BUSY_SHARE
+            && !THROUGHPUT_WARNED.swap(true, AtomicOrdering::Relaxed)
+        {
+            warn!(
+                "Writing to the temp directory {} is slow ({:.2} MB/s), the disk and not the network limits the download speed. Use another directory by setting the CRUNCHY_CLI_TEMP_DIR environment variable",
+                temp_directory().to_string_lossy(),
+                self.bytes as f64 / 1024.0 / 1024.0 / self.busy.as_secs_f64().max(f64::EPSILON)
+            )
+        }
+    }
+}
+
+impl<W: Write> Write for ThroughputWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.check();
+        let start = Instant::now();
+        let written = self.inner.write(buf)?;
+        self.busy += start.elapsed();
+        self.bytes += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Stores downloaded segments by the hash of their url, without query parameters as they may
+/// contain request specific tokens.
+#[derive(Clone)]
+struct SegmentCache {
+    dir: Arc<TempDir>,
+}
+
+impl SegmentCache {
+    fn new() -> Result<Self> {
+        Ok(Self {
+            dir: Arc::new(tempdir()?),
+        })
+    }
+
+    fn path(&self, url: &str) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        url.split('?').next().unwrap_or(url).hash(&mut hasher);
+        self.dir.path().join(format!("{:x}", hasher.finish()))
+    }
+
+    fn get(&self, url: &str) -> Option<Vec<u8>> {
+        fs::read(self.path(url)).ok()
+    }
+
+    fn insert(&self, url: &str, data: &[u8]) -> Result<()> {
+        Ok(fs::write(self.path(url), data)?)
+    }
+}
+
+/// Version of the [`ResumeState`] format. If the format changes, increase it and add a migration
+/// to [`ResumeState::from_slice`], so that downloads which were interrupted before an upgrade can
+/// still be resumed.
+pub(crate) const RESUME_STATE_VERSION: u32 = 2;
+
+/// Progress of a stream download which is kept in the temp directory, so that an interrupted
+/// download can be continued with `--resume`. Streams are identified by their episode, locale and
+/// variant, so that the download can be continued with fresh segment urls after the ones of the
+/// interrupted download have expired, e.g. after a reboot.
+#[derive(Deserialize, Serialize)]
+pub(crate) struct ResumeState {
+    version: u32,
+    episode_id: String,
+    locale: String,
+    /// Codecs, bandwidth and resolution of the stream.
+    variant: String,
+    /// Number of segments of the whole stream. If the stream has a different number of segments
+    /// when the download is resumed, it has changed and the download starts from the beginning.
+    total_segments: usize,
+    /// Number of segments which are completely written to `file`.
+    segments: usize,
+    /// Length of `file` after the last completely written segment.
+    bytes: u64,
+    pub(crate) file: PathBuf,
+
+    #[serde(skip)]
+    pub(crate) path: PathBuf,
+}
+
+impl ResumeState {
+    /// Reads a state which was written by this or an older version of crunchy-cli.
+    pub(crate) fn from_slice(content: &[u8]) -> Result<Self> {
+        let mut value: serde_json::Value = serde_json::from_slice(content)?;
+        // states without version were written before the format was versioned
+        let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+        if version > RESUME_STATE_VERSION as u64 {
+            bail!(
+                "resume state has format version {} which is only supported by a newer version of crunchy-cli",
+                version
+            )
+        }
+
+        if version < 1 {
+            // version 0 has the same fields as version 1
+            value["version"] = 1.into()
+        }
+        if version < 2 {
+            // version 1 states are identified by the urls of their segments and can't be
+            // revalidated, they are resumed as they are
+            value["version"] = 2.into();
+            value["episode_id"] = "".into();
+            value["locale"] = "".into();
+            value["variant"] = "".into();
+            value["total_segments"] = 0.into()
+        }
+
+        Ok(serde_json::from_value(value)?)
+    }
+
+    fn read_or_create(
+        stream_data: &StreamData,
+        episode_id: &str,
+        hardsub: Option<&Locale>,
+        locale: &Locale,
+        suffix: &str,
+    ) -> Result<Self> {
+        let mut variant = format!(
+            "{} {} {}",
+            stream_data.codecs,
+            stream_data.bandwidth,
+            stream_data
+                .resolution()
+                .map_or("audio".to_string(), |r| r.to_string())
+        );
+        // only added if there is a hardsub, so that the state of downloads without hardsub which
+        // were interrupted by an older version is still found
+        if let Some(hardsub) = hardsub {
+            variant += &format!(" hardsub {}", hardsub)
+        }
+        let total_segments = stream_data.segments().len();
+
+        let mut hasher = DefaultHasher::new();
+        (episode_id, locale.to_string(), &variant, suffix).hash(&mut hasher);
+        let path =
+            temp_directory().join(format!("{}{:x}.json", RESUME_FILE_PREFIX, hasher.finish()));
+
+        if let Some(state) = Self::read(&path) {
+            if state.total_segments == total_segments {
+                return Ok(Self { path, ..state });
+            }
+            warn!(
+                "The stream changed since the download was interrupted ({} instead of {} segments), starting it from the beginning",
+                total_segments, state.total_segments
+            );
+            let _ = fs::remove_file(&state.file);
+        } else if let Some(legacy) = Self::read_legacy(stream_data) {
+            return Ok(legacy);
+        }
+
+        Ok(Self {
+            version: RESUME_STATE_VERSION,
+            episode_id: episode_id.to_string(),
+            locale: locale.to_string(),
+            variant,
+            total_segments,
+            segments: 0,
+            bytes: 0,
+            file: path.with_extension(suffix.trim_start_matches('.')),
+            path,
+        })
+    }
+
+    fn read(path: &Path) -> Option<Self> {
+        let content = fs::read(path).ok()?;
+        match Self::from_slice(&content) {
+            Ok(state) if state.file.exists() => Some(Self {
+                path: path.to_path_buf(),
+                ..state
+            }),
+            Ok(_) => {
+                debug!("Resume file {} is missing", path.to_string_lossy());
+                None
+            }
+            Err(e) => {
+                debug!(
+                    "Failed to read resume state {}: {}",
+                    path.to_string_lossy(),
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// Reads a state which was written by crunchy-cli before states were identified by their
+    /// episode. These are identified by the urls of their segments, without query parameters as
+    /// they may contain request specific tokens.
+    fn read_legacy(stream_data: &StreamData) -> Option<Self> {
+        let mut hasher = DefaultHasher::new();
+        for segment in stream_data.segments() {
+            segment
+                .url
+                .split('?')
+                .next()
+                .unwrap_or(&segment.url)
+                .hash(&mut hasher)
+        }
+        Self::read(&temp_directory().join(format!(
+            "{}{:x}.json",
+            RESUME_FILE_PREFIX,
+            hasher.finish()
+        )))
+    }
+
+    pub(crate) fn write(&self) -> Result<()> {
+        Ok(fs::write(&self.path, serde_json::to_vec(self)?)?)
+    }
+
+    /// Removes the state. The downloaded file is returned as temporary file, so that it gets
+    /// deleted after it's used.
+    fn finish(self) -> TempPath {
+        let _ = fs::remove_file(&self.path);
+        TempPath::from_path(self.file)
+    }
+}
+
+/// Writes the plot of the audio sync next to `dst`, e.g. `video.sync.svg` for `video.mkv`.
+fn write_sync_plot(
+    dst: &Path,
+    samples: &[SyncSample],
+    offsets: HashMap<usize, TimeDelta>,
+    applied: bool,
+    raw_audios: &[SyncAudio],
+) {
+    if is_special_file(dst) || dst.to_string_lossy() == "-" {
+        warn!("The sync plot can only be written if the output is a file");
+        return;
+    }
+
+    let labels = raw_audios
+        .iter()
+        .map(|audio| (audio.format_id, audio.locale.to_string()))
+        .collect();
+    let path = dst.with_extension("sync.svg");
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    match fs::write(&path, sync_plot(samples, &offsets, &labels, applied)) {
+        Ok(()) => debug!("Wrote sync plot to {}", path.to_string_lossy()),
+        Err(e) => warn!(
+            "Failed to write sync plot to {}: {}",
+            path.to_string_lossy(),
+            e
+        ),
+    }
+}
+
+fn estimate_stream_data_file_size(stream_data: &StreamData, segments: &[StreamSegment]) -> u64 {
+    (stream_data.bandwidth / 8) * segments.iter().map(|s| s.length.as_secs()).sum::<u64>()
+}
+
+/// Get the length and fps of a video. ffprobe is preferred as its json output is more reliable than
+/// the stream information ffmpeg prints, e.g. for videos with a variable frame rate. If ffprobe is
+/// not installed, the output of ffmpeg is parsed instead.
+fn get_video_stats(path: &Path) -> Result<(TimeDelta, f64)> {
+    match ffprobe_video_stats(path) {
+        Ok(Some(stats)) => return Ok(stats),
+        Ok(None) => (),
+        Err(e) => debug!(
+            "Failed to get video stats with ffprobe, falling back to ffmpeg: {}",
+            e
+        ),
+    }
+
+    let video_fps = Regex::new(r"(?P<fps>[\d/.]+)\sfps")?;
+
+    let ffmpeg_output = ffmpeg_media_info(path)?;
+    let fps_caps = video_fps
+        .captures(ffmpeg_output.as_str())
+        .ok_or(anyhow::anyhow!(
+            "failed to get video fps: {}",
+            ffmpeg_output
+        ))?;
+
+    Ok((
+        parse_media_length(&ffmpeg_output)?,
+        fps_caps.name("fps").unwrap().as_str().parse().unwrap(),
+    ))
+}
+
+/// Get the length and fps of a video with ffprobe. Returns `None` if ffprobe is not installed.
+fn ffprobe_video_stats(path: &Path) -> Result<Option<(TimeDelta, f64)>> {
+    let ffprobe = match Command::new(ffprobe_binary())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .args(["-v", "error", "-print_format", "json"])
+        .args(["-show_format", "-show_streams", "-select_streams", "v:0"])
+        .arg(path)
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if !ffprobe.status.success() {
+        bail!("{}", String::from_utf8_lossy(&ffprobe.stderr))
+    }
+    let info: serde_json::Value = serde_json::from_slice(&ffprobe.stdout)?;
+    let stream = &info["streams"][0];
+
+    // the container duration is preferred, as the stream duration is missing in some containers
+    let Some(duration) = [&info["format"]["duration"], &stream["duration"]]
+        .into_iter()
+        .find_map(|d| d.as_str()?.parse::<f64>().ok())
+    else {
+        bail!("no duration found in ffprobe output")
+    };
+    // the average frame rate is the most accurate for variable frame rate videos, the real base
+    // frame rate is used if the average one is unknown (`0/0`)
+    let Some(fps) = [&stream["avg_frame_rate"], &stream["r_frame_rate"]]
+        .into_iter()
+        .find_map(|r| parse_frame_rate(r.as_str()?))
+    else {
+        bail!("no frame rate found in ffprobe output")
+    };
+
+    Ok(Some((
+        TimeDelta::milliseconds((duration * 1000.0).round() as i64),
+        fps,
+    )))
+}
+
+/// Parses a frame rate like `24000/1001` or `25`.
+fn parse_frame_rate(rate: &str) -> Option<f64> {
+    let fps = match rate.split_once('/') {
+        Some((num, den)) => num.parse::<f64>().ok()? / den.parse::<f64>().ok()?,
+        None => rate.parse().ok()?,
+    };
+    (fps.is_finite() && fps > 0.0).then_some(fps)
+}
+
+/// Get the length of a video or audio.
+fn get_media_length(path: &Path) -> Result<TimeDelta> {
+    parse_media_length(&ffmpeg_media_info(path)?)
+}
+
+/// The stream information ffmpeg prints about `path`.
+fn ffmpeg_media_info(path: &Path) -> Result<String> {
+    let ffmpeg = Command::new(ffmpeg_binary())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .arg("-y")
+        .arg("-hide_banner")
+        .args(["-i", path.to_str().unwrap()])
+        .output()?;
+    Ok(String::from_utf8(ffmpeg.stderr)?)
+}
+
+fn parse_media_length(ffmpeg_output: &str) -> Result<TimeDelta> {
+    let media_length = Regex::new(r"Duration:\s(?P<time>\d+:\d+:\d+\.\d+),")?;
+
+    let length_caps = media_length.captures(ffmpeg_output).ok_or(anyhow::anyhow!(
+        "failed to get video length: {}",
+        ffmpeg_output
+    ))?;
+    Ok(
+        NaiveTime::parse_from_str(length_caps.name("time").unwrap().as_str(), "%H:%M:%S%.f")
+            .unwrap()
+            .signed_duration_since(NaiveTime::MIN),
+    )
+}
+
+/// Extracts the first audio track of `path` into a temporary file which can be used for syncing.
+fn extract_reference_audio(path: &Path) -> Result<TempPath> {
+    let audio = tempfile(".flac")?.into_temp_path();
+    let ffmpeg = Command::new(ffmpeg_binary())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .arg("-y")
+        .arg("-hide_banner")
+        .args(["-i", path.to_str().unwrap()])
+        .args(["-vn", "-sn", "-map", "0:a:0", "-ac", "2", "-ar", "48000"])
+        .args(["-c:a", "flac", audio.to_str().unwrap()])
+        .output()?;
+    if !ffmpeg.status.success() {
+        bail!(
+            "Failed to extract audio of {}: {}",
+            path.to_string_lossy(),
+            String::from_utf8_lossy(&ffmpeg.stderr)
+        )
+    }
+    Ok(audio)
+}
+
+/// Copies `src` to `dst` and shifts it by `offset`. A positive offset cuts the start, a negative
+/// offset delays it.
+fn shift_media(src: &Path, dst: &Path, offset: TimeDelta, metadata: &[String]) -> Result<()> {
+    let mut command = Command::new(ffmpeg_binary());
+    command
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .arg("-y")
+        .arg("-hide_banner");
+    if offset < TimeDelta::zero() {
+        command.args(["-itsoffset", &format_time_delta(&-offset)]);
+    } else if offset > TimeDelta::zero() {
+        command.args(["-ss", &format_time_delta(&offset)]);
+    }
+    let ffmpeg = command
+        .args(["-i", src.to_str().unwrap()])
+        .args(["-map", "0", "-c", "copy"])
+        .args(metadata)
+        .arg(dst.to_str().unwrap())
+        .output()?;
+    if !ffmpeg.status.success() {
+        bail!(
+            "Failed to write {}: {}",
+            dst.to_string_lossy(),
+            String::from_utf8_lossy(&ffmpeg.stderr)
+        )
+    }
+    Ok(())
+}
+
+/// Player script which contains the names and files of all subtitle fonts.
+const FONT_MANIFEST_URL: &str = "https://static.crunchyroll.com/vilos-v2/web/vilos/js/bundle.js";
+/// Time after which the cached font manifest is fetched again.
+const FONT_MANIFEST_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+lazy_static::lazy_static! {
+    static ref FONT_MANIFEST_REGEX: Regex =
+        Regex::new(r#""(?P<name>[^"\\]+)"\s*:\s*"(?P<file>[\w.\-]+\.woff2)""#).unwrap();
+    /// Fonts of the font manifest, set by [`load_font_manifest`].
+    static ref FONT_MANIFEST: std::sync::Mutex<Option<Vec<(String, String)>>> =
+        std::sync::Mutex::new(None);
+}
+
+fn font_manifest_path() -> Result<PathBuf> {
+    Ok(cache_dir("fonts")?.join("manifest.json"))
+}
+
+/// Reads the cached font manifest. If `max_age` is set, older manifests are ignored.
+fn read_cached_font_manifest(max_age: Option<Duration>) -> Option<Vec<(String, String)>> {
+    let path = font_manifest_path().ok()?;
+    if let Some(max_age) = max_age {
+        let modified = fs::metadata(&path).ok()?.modified().ok()?;
+        if modified.elapsed().unwrap_or_default() > max_age {
+            return None;
+        }
+    }
+    serde_json::from_slice(&fs::read(path).ok()?).ok()
+}
+
+/// Loads the fonts which Crunchyroll currently provides from the player script, so that fonts
+/// which were added after [`FONTS`] was extracted are found too. The result is cached for
+/// [`FONT_MANIFEST_MAX_AGE`]. If it can't be fetched, an outdated cached manifest or only the
+/// built-in fonts are used.
+async fn load_font_manifest(client: &Client) {
+    if FONT_MANIFEST.lock().unwrap().is_some() {
+        return;
+    }
+
+    let manifest = if let Some(manifest) = read_cached_font_manifest(Some(FONT_MANIFEST_MAX_AGE)) {
+        manifest
+    } else {
+        match fetch_font_manifest(client).await {
+            Ok(manifest) => {
+                if let Ok(path) = font_manifest_path() {
+                    let _ = fs::write(path, serde_json::to_vec(&manifest).unwrap());
+                }
+                debug!("Fetched font manifest with {} fonts", manifest.len());
+                manifest
+            }
+            Err(e) => {
+                debug!(
+                    "Failed to fetch font manifest, using cached or built-in fonts: {}",
+                    e
+                );
+                read_cached_font_manifest(None).unwrap_or_default()
+            }
+        }
+    };
+    *FONT_MANIFEST.lock().unwrap() = Some(manifest)
+}
+
+async fn fetch_font_manifest(client: &Client) -> Result<Vec<(String, String)>> {
+    let script = client
+        .get(FONT_MANIFEST_URL)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let mut fonts: Vec<(String, String)> = vec![];
+    for capture in FONT_MANIFEST_REGEX.captures_iter(&script) {
+        let name = capture.name("name").unwrap().as_str();
+        if !fonts.iter().any(|(f, _)| f == name) {
+            fonts.push((
+                name.to_string(),
+                capture.name("file").unwrap().as_str().to_string(),
+            ))
+        }
+    }
+    if fonts.is_empty() {
+        bail!("No fonts found in {}", FONT_MANIFEST_URL)
+    }
+    Ok(fonts)
+}
+
+/// The file name of a font. Fonts of the font manifest take precedence over the built-in ones.
+fn font_file(name: &str) -> Option<String> {
+    if let Some((_, file)) = FONT_MANIFEST
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|m| m.iter().find(|(f, _)| f == name).cloned())
+    {
+        return Some(file);
+    }
+    FONTS
+        .iter()
+        .find(|(f, _)| *f == name)
+        .map(|(_, file)| file.to_string())
+}
+
+/// If the font is one of the fonts Crunchyroll provides.
+pub fn is_known_font(name: &str) -> bool {
+    // when the command line arguments are validated the font manifest isn't loaded yet, so the
+    // cached one is checked too
+    font_file(name).is_some()
+        || read_cached_font_manifest(None).is_some_and(|m| m.iter().any(|(f, _)| f == name))
+}
+
+// all subtitle fonts (extracted from javascript). fallback if the font manifest can't be fetched
+
+const FONTS: [(&str, &str); 68] = [
+    ("Adobe Arabic", "AdobeArabic-Bold.woff2"),
+    ("Andale Mono", "andalemo.woff2"),
+    ("Arial", "arial.woff2"),
+    ("Arial Black", "ariblk.woff2"),
+    ("Arial Bold", "arialbd.woff2"),
+    ("Arial Bold Italic", "arialbi.woff2"),
+    ("Arial Italic", "ariali.woff2"),
+    ("Arial Unicode MS", "arialuni.woff2"),
+    ("Comic Sans MS", "comic.woff2"),
+    ("Comic Sans MS Bold", "comicbd.woff2"),
+    ("Courier New", "cour.woff2"),
+    ("Courier New Bold", "courbd.woff2"),
+    ("Courier New Bold Italic", "courbi.woff2"),
+    ("Courier New Italic", "couri.woff2"),
+    ("DejaVu LGC Sans Mono", "DejaVuLGCSansMono.woff2"),
+    ("DejaVu LGC Sans Mono Bold", "DejaVuLGCSansMono-Bold.woff2"),
+    (
+        "DejaVu LGC Sans Mono Bold Oblique",
+        "DejaVuLGCSansMono-BoldOblique.woff2",
+    ),
+    (
+        "DejaVu LGC Sans Mono Oblique",
+        "DejaVuLGCSansMono-Oblique.woff2",
+    ),
+    ("DejaVu Sans", "DejaVuSans.woff2"),
+    ("DejaVu Sans Bold", "DejaVuSans-Bold.woff2"),
+    ("DejaVu Sans Bold Oblique", "DejaVuSans-BoldOblique.woff2"),
+    ("DejaVu Sans Condensed", "DejaVuSansCondensed.woff2"),
+    (
+        "DejaVu Sans Condensed Bold",
+        "DejaVuSansCondensed-Bold.woff2",
+    ),
+    (
+        "DejaVu Sans Condensed Bold Oblique",
+        "DejaVuSansCondensed-BoldOblique.woff2",
+    ),
+    (
+        "DejaVu Sans Condensed Oblique",
+        "DejaVuSansCondensed-Oblique.woff2",
+    ),
+    ("DejaVu Sans ExtraLight", "DejaVuSans-ExtraLight.woff2"),
+    ("DejaVu Sans Mono", "DejaVuSansMono.woff2"),
+    ("DejaVu Sans Mono Bold", "DejaVuSansMono-Bold.woff2"),
+    (
+        "DejaVu Sans Mono Bold Oblique",
+        "DejaVuSansMono-BoldOblique.woff2",
+    ),
+    ("DejaVu Sans Mono Oblique", "DejaVuSansMono-Oblique.woff2"),
+    ("DejaVu Sans Oblique", "DejaVuSans-Oblique.woff2"),
     ("Gautami", "gautami.woff2"),
     ("Georgia", "georgia.woff2"),
     ("Georgia Bold", "georgiab.woff2"),
@@ -1306,14 +3758,187 @@ const FONTS: [(&str, &str); 68] = [
     ("Webdings", "webdings.woff2"),
 ];
 lazy_static::lazy_static! {
+    static ref OVERRIDE_TAG_REGEX: Regex = Regex::new(r"\{[^}]*\}").unwrap();
+    static ref DRAWING_REGEX: Regex = Regex::new(r"\{[^}]*\\p[1-9][^}]*\}").unwrap();
     static ref FONT_REGEX: Regex = Regex::new(r"(?m)^(?:Style:\s.+?,(?P<font>.+?),|(?:Dialogue:\s(?:.+?,)+,\{(?:\\.*)?\\fn(?P<overrideFont>[\w\s]+)(?:\\.*)?)\})").unwrap();
 }
 
+/// Checks the signature of a woff2 font and that its size matches the size in its header.
+fn is_valid_woff2(font: &[u8]) -> bool {
+    // the woff2 header is 48 bytes long, the total size of the font is stored at byte 8
+    font.len() >= 48
+        && &font[0..4] == b"wOF2"
+        && u32::from_be_bytes([font[8], font[9], font[10], font[11]]) as usize == font.len()
+}
+
+/// Writes the subtitle into a temporary file in the given format. The events are sorted and cut
+/// to `max_length`, a hardsub style is only applied to ass subtitles.
+fn write_subtitle(
+    mut ass: SSA,
+    max_length: Option<TimeDelta>,
+    format: SubtitleFormat,
+    style: Option<&HardsubStyle>,
+    temp_file_context: String,
+) -> Result<TempPath> {
+    // subtitles aren't always correct sorted and video players may have issues with that. to
+    // prevent issues, the subtitles are sorted
+    // (https://github.com/crunchy-labs/crunchy-cli/issues/208)
+    ass.events.sort_by(|a, b| a.start.cmp(&b.start));
+    // it might be the case that the start and/or end time are greater than the actual video
+    // length. this might also result in issues with video players, thus the times are stripped
+    // to be at most as long as `max_length`
+    // (https://github.com/crunchy-labs/crunchy-cli/issues/32)
+    if let Some(max_length) = max_length {
+        for i in (0..ass.events.len()).rev() {
+            let max_len = Time::from_hms(0, 0, 0)
+                .unwrap()
+                .add(Duration::from_millis(max_length.num_milliseconds() as u64));
+
+            if ass.events[i].start > max_len {
+                if ass.events[i].end > max_len {
+                    ass.events[i].start = max_len
+                }
+                ass.events[i].end = max_len
+            } else {
+                break;
+            }
+        }
+    }
+
+    // without this additional info, subtitle look very messy in some video player
+    // (https://github.com/crunchy-labs/crunchy-cli/issues/66)
+    ass.info
+        .additional_fields
+        .insert("ScaledBorderAndShadow".to_string(), "yes".to_string());
+
+    let tempfile = named_tempfile(temp_file_context, format!(".{}", format.extension()))?;
+    let path = tempfile.into_temp_path();
+
+    let content = match format {
+        SubtitleFormat::Ass => match style {
+            Some(style) => style.apply(&ass.to_string()),
+            None => ass.to_string(),
+        },
+        SubtitleFormat::Srt => strip_override_tags(ass).to_srt().to_string(),
+        SubtitleFormat::Vtt => strip_override_tags(ass).to_vtt().to_string(),
+    };
+    fs::write(&path, content)?;
+
+    Ok(path)
+}
+
+/// Sum of two optional offsets, e.g. the clip start and the sync offset of a stream.
+fn add_offsets(a: Option<TimeDelta>, b: Option<TimeDelta>) -> Option<TimeDelta> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a + b),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Moves the subtitle events so that they start with the clip. Events outside of the clip are
+/// removed.
+fn clip_subtitle(ass: &mut SSA, clip: &Clip) {
+    let zero = Time::from_hms(0, 0, 0).unwrap();
+    let start = zero.add(clip.start);
+    let end = zero.add(clip.end);
+    ass.events
+        .retain(|event| event.end > start && event.start < end);
+    for event in ass.events.iter_mut() {
+        event.start = zero + (event.start.max(start) - start);
+        event.end = zero + (event.end.min(end) - start);
+    }
+}
+
+/// Removes ass override tags (e.g. `{\an8}` or `{\fnArial}`) which other subtitle formats do not
+/// understand. Events which only consist of drawings are dropped completely.
+fn strip_override_tags(mut ass: SSA) -> SSA {
+    ass.events
+        .retain(|event| !DRAWING_REGEX.is_match(&event.text));
+    for event in ass.events.iter_mut() {
+        event.text = OVERRIDE_TAG_REGEX.replace_all(&event.text, "").to_string()
+    }
+    ass.events.retain(|event| !event.text.trim().is_empty());
+    ass
+}
+
+/// Copies the fonts into `fonts_dir`, which is relative to the directory of `dst` unless it's
+/// absolute, so that players can render the sidecar subtitles of formats which can't contain
+/// fonts.
+fn write_fonts(dst: &Path, fonts_dir: &Path, fonts: &[PathBuf]) -> Result<()> {
+    if fonts.is_empty() {
+        return Ok(());
+    }
+    let dir = dst.parent().unwrap_or(Path::new("")).join(fonts_dir);
+    fs::create_dir_all(&dir)?;
+    for font in fonts {
+        let Some(file_name) = font.file_name() else {
+            continue;
+        };
+        let path = dir.join(file_name);
+        // the fonts are shared by all episodes, so they only need to be copied once
+        if !path.exists() {
+            fs::copy(font, &path)?;
+        }
+    }
+    debug!("Wrote {} font(s) to {}", fonts.len(), dir.to_string_lossy());
+    Ok(())
+}
+
+/// Copies the subtitles next to the output file, e.g. `video.mkv` → `video.en-US.ass`. The
+/// extension is taken from the written subtitle file. Subtitles with a start time are shifted the
+/// same way as when they're muxed into the output file.
+fn write_sidecar_subtitles(
+    dst: &Path,
+    subtitles: &[FFmpegSubtitleMeta],
+    multiple_videos: bool,
+    naming: &SidecarNaming,
+    default_subtitle: Option<&Locale>,
+    subtitle_locale_output_map: &HashMap<Locale, String>,
+) -> Result<()> {
+    let stem = dst.file_stem().unwrap_or_default().to_string_lossy();
+    for meta in subtitles {
+        let mut name = format!(
+            "{}.{}",
+            stem,
+            naming.language(&meta.locale, subtitles, subtitle_locale_output_map)
+        );
+        if matches!(naming, SidecarNaming::Jellyfin) && Some(&meta.locale) == default_subtitle {
+            name += ".default"
+        }
+        // forced subtitles are only shown on foreign language parts, closed captions are subtitles
+        // for the deaf and hard of hearing
+        if meta.cc {
+            name += match naming {
+                SidecarNaming::Default => ".cc",
+                _ => ".sdh",
+            }
+        }
+        if multiple_videos {
+            name += &format!(".{}", meta.video_idx + 1)
+        }
+        let path = dst.with_file_name(format!(
+            "{}.{}",
+            name,
+            meta.path.extension().unwrap_or_default().to_string_lossy()
+        ));
+        match meta.start_time {
+            Some(start_time) if !start_time.is_zero() => {
+                shift_media(&meta.path, &path, start_time, &[])?
+            }
+            _ => {
+                fs::copy(&meta.path, &path)?;
+            }
+        }
+        debug!("Wrote sidecar subtitle {}", path.to_string_lossy())
+    }
+    Ok(())
+}
+
 /// Get the fonts used in the subtitle.
-fn get_subtitle_stats(path: &Path) -> Result<Vec<String>> {
+fn get_subtitle_stats(subtitle: &SSA) -> Vec<String> {
     let mut fonts = vec![];
 
-    for capture in FONT_REGEX.captures_iter(&(fs::read_to_string(path)?)) {
+    for capture in FONT_REGEX.captures_iter(&subtitle.to_string()) {
         if let Some(font) = capture.name("font") {
             let font_string = font.as_str().to_string();
             if !fonts.contains(&font_string) {
@@ -1328,58 +3953,603 @@ fn get_subtitle_stats(path: &Path) -> Result<Vec<String>> {
         }
     }
 
-    Ok(fonts)
+    fonts
+}
+
+/// Replaces the fonts of the subtitle styles and the font overrides (`\fn<font>`) in the dialogues
+/// with their fallback, so that the subtitle is rendered with the attached fallback font.
+fn rewrite_subtitle_fonts(subtitle: &mut SSA, substitutions: &HashMap<String, String>) {
+    for style in subtitle.styles.iter_mut() {
+        if let Some(fallback) = substitutions.get(&style.fontname) {
+            style.fontname = fallback.clone()
+        }
+    }
+    for event in subtitle.events.iter_mut() {
+        for (font, fallback) in substitutions {
+            event.text = event
+                .text
+                .replace(&format!("\\fn{}", font), &format!("\\fn{}", fallback))
+        }
+    }
 }
 
-fn write_ffmpeg_chapters(
-    file: &mut fs::File,
+fn synthesize_chapters(parts: RecurringParts) -> Vec<(&'static str, f32, f32)> {
+    [
+        parts.intro.map(|(start, end)| ("Intro", start, end)),
+        parts.ending.map(|(start, end)| ("Credits", start, end)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// Sorts the chapters and fills the gaps between them with 'Episode' chapters. The times of the
+/// returned chapters are in milliseconds.
+fn chapter_list<'a>(
     video_len: TimeDelta,
-    events: &mut Vec<(&str, &SkipEventsEvent)>,
-) -> Result<()> {
+    events: &mut [(&'a str, f32, f32)],
+) -> Vec<(&'a str, u32, u32)> {
     let video_len = video_len.num_milliseconds() as f32 / 1000.0;
-    events.sort_by(|(_, event_a), (_, event_b)| event_a.start.total_cmp(&event_b.start));
-
-    writeln!(file, ";FFMETADATA1")?;
+    events.sort_by(|(_, start_a, _), (_, start_b, _)| start_a.total_cmp(start_b));
 
+    let mut chapters = vec![];
     let mut last_end_time = 0.0;
-    for (name, event) in events {
+    for (name, start, end) in events.iter() {
         /*
             - Convert from seconds to milliseconds for the correct timescale
             - Include an extra 'Episode' chapter if the start of the current chapter is more than 10
               seconds later than the end of the last chapter.
-              This is done before writing the actual chapter of this loop to keep the chapter
+              This is done before adding the actual chapter of this loop to keep the chapter
               chronologically in order
         */
-        if event.start - last_end_time > 10.0 {
-            writeln!(file, "[CHAPTER]")?;
-            writeln!(file, "TIMEBASE=1/1000")?;
-            writeln!(file, "START={}", (last_end_time * 1000.0) as u32)?;
-            writeln!(file, "END={}", (event.start * 1000.0) as u32)?;
-            writeln!(file, "title=Episode")?;
+        if *start - last_end_time > 10.0 {
+            chapters.push((
+                "Episode",
+                (last_end_time * 1000.0) as u32,
+                (*start * 1000.0) as u32,
+            ))
         }
 
-        writeln!(file, "[CHAPTER]")?;
-        writeln!(file, "TIMEBASE=1/1000")?;
-        writeln!(file, "START={}", (event.start * 1000.0) as u32)?;
-        writeln!(file, "END={}", (event.end * 1000.0) as u32)?;
-        writeln!(file, "title={}", name)?;
+        chapters.push((*name, (*start * 1000.0) as u32, (*end * 1000.0) as u32));
 
-        last_end_time = event.end;
+        last_end_time = *end;
     }
 
     // only add a trailing chapter if the gap between the end of the last chapter and the total video
     // length is greater than 10 seconds
     if video_len - last_end_time > 10.0 {
-        writeln!(file, "[CHAPTER]")?;
-        writeln!(file, "TIMEBASE=1/1000")?;
-        writeln!(file, "START={}", (last_end_time * 1000.0) as u32)?;
-        writeln!(file, "END={}", (video_len * 1000.0) as u32)?;
-        writeln!(file, "title=Episode")?;
+        chapters.push((
+            "Episode",
+            (last_end_time * 1000.0) as u32,
+            (video_len * 1000.0) as u32,
+        ))
+    }
+
+    chapters
+}
+
+/// Translates the title of a chapter into the language of `locale`. Titles for unsupported
+/// languages stay english.
+fn chapter_title(name: &str, locale: Option<&Locale>) -> String {
+    let translations: &[&str; 5] = match locale {
+        Some(Locale::de_DE) => &["Episode", "Intro", "Abspann", "Rückblick", "Vorschau"],
+        Some(Locale::es_419 | Locale::es_ES | Locale::es_LA) => {
+            &["Episodio", "Intro", "Créditos", "Resumen", "Avance"]
+        }
+        Some(Locale::fr_FR) => &[
+            "Épisode",
+            "Générique de début",
+            "Générique de fin",
+            "Récapitulatif",
+            "Aperçu",
+        ],
+        Some(Locale::it_IT) => &[
+            "Episodio",
+            "Sigla iniziale",
+            "Sigla finale",
+            "Riassunto",
+            "Anteprima",
+        ],
+        Some(Locale::pt_BR | Locale::pt_PT) => &[
+            "Episódio",
+            "Abertura",
+            "Créditos",
+            "Recapitulação",
+            "Prévia",
+        ],
+        Some(Locale::ru_RU) => &["Эпизод", "Опенинг", "Титры", "Краткое содержание", "Превью"],
+        Some(Locale::ja_JP) => &[
+            "本編",
+            "オープニング",
+            "エンディング",
+            "前回のあらすじ",
+            "次回予告",
+        ],
+        _ => &["Episode", "Intro", "Credits", "Recap", "Preview"],
+    };
+    match name {
+        "Episode" => translations[0],
+        "Intro" => translations[1],
+        "Credits" => translations[2],
+        "Recap" => translations[3],
+        "Preview" => translations[4],
+        _ => name,
+    }
+    .to_string()
+}
+
+/// Replaces the chapters of the matroska file `path` with chapters which are tagged with the
+/// language of `locale`. This requires mkvpropedit, if it's not installed the chapters stay
+/// untagged.
+fn tag_chapter_language(
+    path: &Path,
+    chapters: &[(String, u32, u32)],
+    locale: &Locale,
+) -> Result<()> {
+    if !has_mkvpropedit() {
+        warn!("mkvpropedit (MKVToolNix) is not installed, the chapters can't be tagged with their language");
+        return Ok(());
+    }
+
+    let (mut file, xml_path) = tempfile(".xml")?.into_parts();
+    write!(file, "{}", mkv_xml(chapters, Some(locale)))?;
+    drop(file);
+
+    let mkvpropedit = Command::new("mkvpropedit")
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .arg(path)
+        .arg("--chapters")
+        .arg(&xml_path)
+        .output()?;
+    if !mkvpropedit.status.success() {
+        bail!(
+            "Failed to tag the chapters with their language: {}",
+            String::from_utf8_lossy(&mkvpropedit.stderr)
+        )
+    }
+    Ok(())
+}
+
+/// Adds the `BPS`, `DURATION`, `NUMBER_OF_FRAMES` and `NUMBER_OF_BYTES` tags to every track of
+/// the matroska file.
+fn add_track_statistics(path: &Path) -> Result<()> {
+    if !has_mkvpropedit() {
+        warn!("mkvpropedit (MKVToolNix) is not installed, no track statistics tags are added");
+        return Ok(());
+    }
+
+    let _progress_handler = progress!("Adding track statistics");
+    let mkvpropedit = Command::new("mkvpropedit")
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .arg(path)
+        .arg("--add-track-statistics-tags")
+        .output()?;
+    if !mkvpropedit.status.success() {
+        bail!(
+            "Failed to add track statistics tags: {}",
+            String::from_utf8_lossy(&mkvpropedit.stderr)
+        )
+    }
+    Ok(())
+}
+
+/// Removes the given time ranges (in seconds) from the file. The streams are copied and not
+/// re-encoded, so every kept part has to start at a keyframe. The end of each cut is moved back
+/// to the last keyframe before it, which leaves the end of the skipped part in the file.
+fn cut_file(path: &Path, cuts: &[(f32, f32)]) -> Result<()> {
+    let mut cuts: Vec<(f32, f32)> = cuts.iter().filter(|(s, e)| e > s).copied().collect();
+    if cuts.is_empty() {
+        return Ok(());
+    }
+    cuts.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let Some(keyframes) = video_keyframes(path)? else {
+        warn!("ffprobe is not installed, the skipped parts are not cut out");
+        return Ok(());
+    };
+
+    // the parts which are kept, the last one has no end and lasts until the end of the file
+    let mut keep: Vec<(f32, Option<f32>)> = vec![];
+    let mut position = 0.0;
+    for (start, end) in cuts {
+        // a kept part which doesn't start at a keyframe has broken frames until the next
+        // keyframe, so the part after the cut starts at the last keyframe in the cut
+        let Some(keyframe) = keyframes.iter().rev().find(|k| **k <= end).copied() else {
+            continue;
+        };
+        if keyframe <= f32::max(start, position) {
+            continue;
+        }
+        if start > position {
+            keep.push((position, Some(start)))
+        }
+        position = keyframe
+    }
+    // no cut has a keyframe in it, so nothing can be cut out
+    if keep.is_empty() && position == 0.0 {
+        return Ok(());
+    }
+    keep.push((position, None));
+
+    // paths in the list are relative to the list itself, which lives in the temp directory
+    let escaped_path = env::current_dir()?
+        .join(path)
+        .to_string_lossy()
+        .replace('\'', r"'\''");
+    let (mut file, list_path) = tempfile(".ffconcat")?.into_parts();
+    writeln!(file, "ffconcat version 1.0")?;
+    for (start, end) in keep {
+        writeln!(file, "file '{}'", escaped_path)?;
+        writeln!(file, "inpoint {:.3}", start)?;
+        if let Some(end) = end {
+            writeln!(file, "outpoint {:.3}", end)?;
+        }
+    }
+    drop(file);
+
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    let cut_path = path.with_extension(format!("cut.{}", extension));
+
+    let _progress_handler = progress!("Cutting out skipped parts");
+    let ffmpeg = Command::new(ffmpeg_binary())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .args(["-y", "-hide_banner", "-f", "concat", "-safe", "0", "-i"])
+        .arg(&list_path)
+        .arg("-i")
+        .arg(path)
+        .args([
+            "-map",
+            "0:V",
+            "-map",
+            "0:a?",
+            "-map",
+            "0:s?",
+            "-map",
+            "1:t?",
+            "-map_metadata",
+            "1",
+            "-map_chapters",
+            "-1",
+            "-c",
+            "copy",
+        ])
+        .arg(&cut_path)
+        .output()?;
+    if !ffmpeg.status.success() {
+        let _ = fs::remove_file(&cut_path);
+        bail!(
+            "Failed to cut out the skipped parts: {}",
+            String::from_utf8_lossy(&ffmpeg.stderr)
+        )
+    }
+    fs::rename(cut_path, path)?;
+    Ok(())
+}
+
+/// Timestamps (in seconds) of the keyframes of the first video stream, sorted ascending. Returns
+/// `None` if ffprobe is not installed.
+fn video_keyframes(path: &Path) -> Result<Option<Vec<f32>>> {
+    let ffprobe = match Command::new(ffprobe_binary())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .args(["-v", "error", "-select_streams", "v:0"])
+        .args(["-show_entries", "packet=pts_time,flags", "-of", "csv=p=0"])
+        .arg(path)
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if !ffprobe.status.success() {
+        bail!(
+            "Failed to get the keyframes of the video: {}",
+            String::from_utf8_lossy(&ffprobe.stderr)
+        )
+    }
+
+    let mut keyframes: Vec<f32> = String::from_utf8_lossy(&ffprobe.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (pts_time, flags) = line.split_once(',')?;
+            flags.contains('K').then(|| pts_time.parse().ok()).flatten()
+        })
+        .collect();
+    keyframes.sort_by(f32::total_cmp);
+    Ok(Some(keyframes))
+}
+
+/// The input of a failed ffmpeg run. Its files are kept in the temp directory, so that the output
+/// file can be re-generated with `--remux-only`.
+#[derive(Deserialize, Serialize)]
+struct RemuxManifest {
+    /// Arguments of the first pass of a two-pass encode.
+    #[serde(default)]
+    first_pass_args: Option<Vec<String>>,
+    args: Vec<String>,
+    files: Vec<PathBuf>,
+    max_frames: u64,
+}
+
+impl RemuxManifest {
+    fn path(dst: &Path) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        env::current_dir()
+            .unwrap_or_default()
+            .join(dst)
+            .hash(&mut hasher);
+        temp_directory().join(format!("{}{:x}.json", REMUX_FILE_PREFIX, hasher.finish()))
+    }
+
+    fn read(dst: &Path) -> Result<Option<Self>> {
+        let path = Self::path(dst);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&fs::read(path)?)?))
+    }
+
+    fn write(&self, dst: &Path) -> Result<()> {
+        Ok(fs::write(Self::path(dst), serde_json::to_vec(self)?)?)
+    }
+
+    fn remove(self, dst: &Path) -> Result<()> {
+        for file in self.files {
+            let _ = fs::remove_file(file);
+        }
+        Ok(fs::remove_file(Self::path(dst))?)
+    }
+}
+
+/// All files which are kept in the temp directory for `--remux-only`, including the manifests
+/// which list them.
+pub fn remux_files() -> Vec<PathBuf> {
+    let mut files = vec![];
+    let Ok(dir) = fs::read_dir(temp_directory()) else {
+        return files;
+    };
+    for entry in dir.flatten() {
+        if !entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(REMUX_FILE_PREFIX)
+        {
+            continue;
+        }
+        if let Some(manifest) = fs::read(entry.path())
+            .ok()
+            .and_then(|data| serde_json::from_slice::<RemuxManifest>(&data).ok())
+        {
+            files.extend(manifest.files)
+        }
+        files.push(entry.path())
+    }
+    files
+}
+
+/// Re-runs ffmpeg with the files of a previously failed run of the same output file.
+async fn remux(dst: &Path, timeout: Option<Duration>) -> Result<()> {
+    let Some(manifest) = RemuxManifest::read(dst)? else {
+        warn!(
+            "No kept files found for {}, skipping",
+            dst.to_string_lossy()
+        );
+        return Ok(());
+    };
+    validate_destination(dst, &manifest.files)?;
+    if let Some(missing) = manifest.files.iter().find(|f| !f.exists()) {
+        bail!(
+            "Kept file {} does not exist anymore, the output file must be downloaded again",
+            missing.to_string_lossy()
+        )
+    }
+
+    if let Some(first_pass_args) = &manifest.first_pass_args {
+        let result = run_ffmpeg(
+            dst,
+            first_pass_args.clone(),
+            manifest.max_frames,
+            "Analysing video (pass 1/2)".to_string(),
+            timeout,
+        )
+        .await;
+        if result.is_err() {
+            remove_two_pass_logs(&two_pass_log(dst))
+        }
+        result?
+    }
+    let result = run_ffmpeg(
+        dst,
+        manifest.args.clone(),
+        manifest.max_frames,
+        "Generating output file".to_string(),
+        timeout,
+    )
+    .await;
+    if manifest.first_pass_args.is_some() {
+        remove_two_pass_logs(&two_pass_log(dst))
+    }
+    result?;
+    manifest.remove(dst)
+}
+
+/// Prefix of the files in which the first pass of a two-pass encode stores its statistics. It only
+/// depends on the output file, so that `--remux-only` can re-run both passes with the arguments
+/// of the failed run.
+fn two_pass_log(dst: &Path) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    env::current_dir()
+        .unwrap_or_default()
+        .join(dst)
+        .hash(&mut hasher);
+    temp_directory().join(format!(".crunchy-cli_2pass_{:x}", hasher.finish()))
+}
+
+/// Removes the statistic files of a two-pass encode. Every encoder names them differently, e.g.
+/// `<prefix>-0.log` or `<prefix>.cutree`, so all files starting with the prefix are removed.
+fn remove_two_pass_logs(prefix: &Path) {
+    let (Some(dir), Some(prefix)) = (prefix.parent(), prefix.file_name()) else {
+        return;
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(prefix.to_string_lossy().as_ref())
+        {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// Metadata of the streams which are downloaded, without any urls as they contain access tokens.
+fn debug_manifest(formats: &[DownloadFormat]) -> serde_json::Value {
+    let stream = |stream_data: &StreamData, locale: &Locale| {
+        serde_json::json!({
+            "locale": locale.to_string(),
+            "bandwidth": stream_data.bandwidth,
+            "codecs": stream_data.codecs,
+            "resolution": stream_data
+                .resolution()
+                .map(|r| format!("{}x{}", r.width, r.height)),
+            "fps": stream_data.fps(),
+            "segments": stream_data.segments().len(),
+        })
+    };
+    serde_json::Value::Array(
+        formats
+            .iter()
+            .map(|format| {
+                serde_json::json!({
+                    "season_id": format.metadata.season_id,
+                    "video": stream(&format.video.0, &format.video.1),
+                    "audios": format
+                        .audios
+                        .iter()
+                        .map(|(stream_data, locale)| stream(stream_data, locale))
+                        .collect::<Vec<_>>(),
+                    "subtitles": format
+                        .subtitles
+                        .iter()
+                        .map(|(subtitle, cc)| {
+                            serde_json::json!({
+                                "locale": subtitle.locale.to_string(),
+                                "format": subtitle.format,
+                                "cc": cc,
+                            })
+                        })
+                        .collect::<Vec<_>>(),
+                })
+            })
+            .collect(),
+    )
+}
+
+/// Moves a file to another filesystem, with a progress bar.
+fn move_file(src: &Path, dst: &Path, message: String) -> Result<()> {
+    if fs::rename(src, dst).is_ok() {
+        return Ok(());
+    }
+
+    let progress = progress_enabled().then(|| {
+        add_progress_bar(
+            ProgressBar::new(fs::metadata(src).map_or(0, |m| m.len()))
+                .with_style(download_bar_style())
+                .with_message(message)
+                .with_finish(ProgressFinish::Abandon),
+        )
+    });
+    let mut reader = fs::File::open(src)?;
+    let mut writer = fs::File::create(dst)?;
+    let mut buf = vec![0; 8 * 1024 * 1024];
+    loop {
+        let read = reader.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        writer.write_all(&buf[..read])?;
+        if let Some(progress) = &progress {
+            progress.inc(read as u64)
+        }
     }
+    writer.sync_all()?;
+    fs::remove_file(src)?;
 
     Ok(())
 }
 
+async fn run_ffmpeg(
+    dst: &Path,
+    args: Vec<String>,
+    max_frames: u64,
+    message: String,
+    timeout: Option<Duration>,
+) -> Result<()> {
+    let fifo = temp_named_pipe()?;
+
+    let mut command_args = vec![
+        "-y".to_string(),
+        "-hide_banner".to_string(),
+        "-vstats_file".to_string(),
+        fifo.path().to_string_lossy().to_string(),
+    ];
+    command_args.extend(args);
+
+    debug!("{}", args::command_line("ffmpeg", &command_args));
+    debug_bundle::record_ffmpeg_args(&command_args);
+
+    // ffmpeg is awaited asynchronously, so that streams which are piped into it in low disk mode
+    // can be written meanwhile. it's killed if the timeout aborts it
+    let ffmpeg = tokio::process::Command::new(ffmpeg_binary())
+        // pass ffmpeg stdout to real stdout only if output file is stdout
+        .stdout(if dst.to_str().unwrap() == "-" {
+            Stdio::inherit()
+        } else {
+            Stdio::null()
+        })
+        .stderr(Stdio::piped())
+        .args(command_args)
+        .kill_on_drop(true)
+        .spawn()?;
+    let ffmpeg_progress_cancel = CancellationToken::new();
+    let ffmpeg_progress_cancellation_token = ffmpeg_progress_cancel.clone();
+    let ffmpeg_progress = tokio::spawn(async move {
+        ffmpeg_progress(
+            max_frames,
+            fifo,
+            message,
+            ffmpeg_progress_cancellation_token,
+        )
+        .await
+    });
+
+    let result = match with_stage_timeout("Generating the output file", timeout, async {
+        Ok(ffmpeg.wait_with_output().await?)
+    })
+    .await
+    {
+        Ok(result) => result,
+        Err(e) => {
+            ffmpeg_progress.abort();
+            return Err(e);
+        }
+    };
+    if !result.status.success() {
+        ffmpeg_progress.abort();
+        bail!(DownloadError::FFmpeg(
+            String::from_utf8_lossy(result.stderr.as_slice()).to_string()
+        ))
+    }
+    ffmpeg_progress_cancel.cancel();
+    ffmpeg_progress.await?
+}
+
 async fn ffmpeg_progress<R: AsyncReadExt + Unpin>(
     total_frames: u64,
     stats: R,
@@ -1388,16 +4558,13 @@ async fn ffmpeg_progress<R: AsyncReadExt + Unpin>(
 ) -> Result<()> {
     let current_frame = Regex::new(r"frame=\s+(?P<frame>\d+)")?;
 
-    let progress = if log::max_level() == LevelFilter::Info {
-        let progress = ProgressBar::new(total_frames)
-            .with_style(
-                ProgressStyle::with_template(":: {msg} [{wide_bar}] {percent:>3}%")
-                    .unwrap()
-                    .progress_chars("##-"),
-            )
-            .with_message(message)
-            .with_finish(ProgressFinish::Abandon);
-        progress.set_draw_target(ProgressDrawTarget::stdout());
+    let progress = if progress_enabled() {
+        let progress = add_progress_bar(
+            ProgressBar::new(total_frames)
+                .with_style(ffmpeg_bar_style())
+                .with_message(message)
+                .with_finish(ProgressFinish::Abandon),
+        );
         progress.enable_steady_tick(Duration::from_millis(200));
         Some(progress)
     } else {
@@ -0,0 +1,111 @@
+use anyhow::{bail, Result};
+use log::debug;
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Version of the download archive format. If the format changes, increase it and add a migration
+/// to [`DownloadArchive::open`].
+const DOWNLOAD_ARCHIVE_VERSION: u32 = 1;
+
+/// Prefix of the first line of a download archive which contains its format version.
+const HEADER_PREFIX: &str = "# crunchy-cli download archive v";
+
+/// File which stores the identifiers of all episodes which were downloaded successfully, one per
+/// line. Episodes which are already stored in it are skipped.
+pub struct DownloadArchive {
+    path: PathBuf,
+    identifiers: HashSet<String>,
+}
+
+impl DownloadArchive {
+    pub fn open(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self {
+                path: path.to_path_buf(),
+                identifiers: HashSet::new(),
+            });
+        }
+
+        let content = fs::read_to_string(path)?;
+        // archives without header were written before the format was versioned
+        let version = match content
+            .lines()
+            .next()
+            .and_then(|l| l.strip_prefix(HEADER_PREFIX))
+        {
+            Some(version) => version.trim().parse::<u32>()?,
+            None => 0,
+        };
+        if version > DOWNLOAD_ARCHIVE_VERSION {
+            bail!(
+                "Download archive {} has format version {} which is only supported by a newer version of crunchy-cli",
+                path.to_string_lossy(),
+                version
+            )
+        }
+
+        let archive = Self {
+            path: path.to_path_buf(),
+            identifiers: content
+                .lines()
+                .map(|l| l.trim().to_string())
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .collect(),
+        };
+        if version < 1 {
+            // version 0 has the same content as version 1 but no header
+            archive.rewrite()?;
+            debug!(
+                "Migrated download archive {} to format version {}",
+                path.to_string_lossy(),
+                DOWNLOAD_ARCHIVE_VERSION
+            )
+        }
+
+        Ok(archive)
+    }
+
+    pub fn contains(&self, identifier: &str) -> bool {
+        self.identifiers.contains(identifier)
+    }
+
+    pub fn identifiers(&self) -> impl Iterator<Item = &String> {
+        self.identifiers.iter()
+    }
+
+    /// Adds the identifier to the archive. It's written to the file immediately, so that it's
+    /// stored even if a later download fails.
+    pub fn record(&mut self, identifier: &str) -> Result<()> {
+        if !self.identifiers.insert(identifier.to_string()) {
+            return Ok(());
+        }
+
+        if let Some(parent) = self.path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?
+            }
+        }
+        let exists = self.path.exists();
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        if !exists {
+            writeln!(file, "{}{}", HEADER_PREFIX, DOWNLOAD_ARCHIVE_VERSION)?
+        }
+        writeln!(file, "{}", identifier)?;
+        Ok(())
+    }
+
+    fn rewrite(&self) -> Result<()> {
+        let mut content = format!("{}{}\n", HEADER_PREFIX, DOWNLOAD_ARCHIVE_VERSION);
+        for identifier in &self.identifiers {
+            content.push_str(identifier);
+            content.push('\n')
+        }
+        fs::write(&self.path, content)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+use reqwest::StatusCode;
+use std::fmt::{Display, Formatter};
+
+/// Errors which can occur while downloading, classified by their cause. Errors of other types can
+/// be classified with [`DownloadError::classify`].
+#[derive(Clone, Debug)]
+pub enum DownloadError {
+    /// Connection problems, timeouts or server errors.
+    Network(String),
+    /// The login or session is invalid.
+    Auth(String),
+    /// The account is not allowed to access the requested content.
+    Entitlement(String),
+    /// ffmpeg failed to process the downloaded files.
+    FFmpeg(String),
+    /// A file couldn't be read or written.
+    Filesystem(String),
+    /// An api response doesn't look like expected.
+    ApiSchema(String),
+}
+
+impl Display for DownloadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DownloadError::Network(msg) => write!(f, "Network error: {}", msg),
+            DownloadError::Auth(msg) => write!(f, "Authentication error: {}", msg),
+            DownloadError::Entitlement(msg) => write!(f, "Access denied: {}", msg),
+            DownloadError::FFmpeg(msg) => write!(f, "FFmpeg error: {}", msg),
+            DownloadError::Filesystem(msg) => write!(f, "Filesystem error: {}", msg),
+            DownloadError::ApiSchema(msg) => write!(f, "Unexpected api response: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for DownloadError {}
+
+impl DownloadError {
+    /// Classifies an error by looking at its cause chain. Returns `None` if the error is of an
+    /// unknown kind.
+    pub fn classify(err: &anyhow::Error) -> Option<DownloadError> {
+        for cause in err.chain() {
+            if let Some(download_error) = cause.downcast_ref::<DownloadError>() {
+                return Some(download_error.clone());
+            }
+
+            let msg = cause.to_string();
+            if let Some(crunchy_error) = cause.downcast_ref::<crunchyroll_rs::error::Error>() {
+                return match crunchy_error {
+                    crunchyroll_rs::error::Error::Request { message, .. }
+                        if message.starts_with("TOO_MANY_ACTIVE_STREAMS") =>
+                    {
+                        Some(DownloadError::Entitlement(msg))
+                    }
+                    crunchyroll_rs::error::Error::Request { .. }
+                    | crunchyroll_rs::error::Error::Block { .. } => {
+                        Some(DownloadError::Network(msg))
+                    }
+                    crunchyroll_rs::error::Error::Authentication { .. } => {
+                        Some(DownloadError::Auth(msg))
+                    }
+                    crunchyroll_rs::error::Error::Decode { .. } => {
+                        Some(DownloadError::ApiSchema(msg))
+                    }
+                    _ => None,
+                };
+            }
+            if let Some(reqwest_error) = cause.downcast_ref::<reqwest::Error>() {
+                return Some(match reqwest_error.status() {
+                    Some(StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => {
+                        DownloadError::Entitlement(msg)
+                    }
+                    Some(StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS) => {
+                        DownloadError::Network(msg)
+                    }
+                    Some(status) if status.is_client_error() => DownloadError::ApiSchema(msg),
+                    _ => DownloadError::Network(msg),
+                });
+            }
+            if cause.is::<std::io::Error>() {
+                return Some(DownloadError::Filesystem(msg));
+            }
+        }
+        None
+    }
+
+    /// Short name of the error class.
+    pub fn class(&self) -> &'static str {
+        match self {
+            DownloadError::Network(_) => "network",
+            DownloadError::Auth(_) => "auth",
+            DownloadError::Entitlement(_) => "entitlement",
+            DownloadError::FFmpeg(_) => "ffmpeg",
+            DownloadError::Filesystem(_) => "filesystem",
+            DownloadError::ApiSchema(_) => "api-schema",
+        }
+    }
+
+    /// If the action which caused the error might succeed when trying it again.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, DownloadError::Network(_))
+    }
+
+    /// The process exit code for this error. Unclassified errors are using `1`.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            DownloadError::Network(_) => 2,
+            DownloadError::Auth(_) => 3,
+            DownloadError::Entitlement(_) => 4,
+            DownloadError::FFmpeg(_) => 5,
+            DownloadError::Filesystem(_) => 6,
+            DownloadError::ApiSchema(_) => 7,
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use crate::utils::format::Format;
+use anyhow::{bail, Result};
+use log::debug;
+use std::path::Path;
+use std::process::Stdio;
+
+/// Runs a `--exec` or `--exec-on-error` command with its template options filled from `format`.
+/// The command is killed if the download of the episode gets cancelled, e.g. by a timeout.
+pub async fn run_exec(
+    command: &str,
+    format: &Format,
+    path: &Path,
+    error: Option<&anyhow::Error>,
+) -> Result<()> {
+    let args =
+        format.render_command(command, path, error.map(|e| format!("{:#}", e)).as_deref())?;
+    let Some((program, args)) = args.split_first() else {
+        bail!("Command is empty")
+    };
+    debug!(
+        "Running command: {}",
+        shlex::try_join(std::iter::once(program.as_str()).chain(args.iter().map(|a| a.as_str())))
+            .unwrap_or_else(|_| command.to_string())
+    );
+
+    let status = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .status()
+        .await?;
+    if !status.success() {
+        bail!("'{}' exited with {}", program, status)
+    }
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use std::path::Path;
+
+/// Escapes every char of `value` which is contained in `special_chars` with a backslash.
+fn escape(value: &str, special_chars: &[char]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if special_chars.contains(&c) {
+            escaped.push('\\')
+        }
+        escaped.push(c)
+    }
+    escaped
+}
+
+/// Escapes a value so that it can be used as filter option in a filtergraph (`-vf`). ffmpeg
+/// unescapes filtergraphs in two levels, first the filtergraph itself and then the options of
+/// every filter, so the value has to be escaped twice.
+/// See https://ffmpeg.org/ffmpeg-filters.html#Notes-on-filtergraph-escaping.
+pub(crate) fn escape_filter_value(value: &str) -> String {
+    let option_escaped = escape(value, &['\\', '\'', ':']);
+    escape(&option_escaped, &['\\', '\'', '[', ']', ',', ';'])
+}
+
+/// The `ass` filter which burns the subtitles at the given path into the video.
+pub(crate) fn ass_filter(path: &Path) -> String {
+    format!("ass={}", escape_filter_value(&path.to_string_lossy()))
+}
+
+/// Converts the output path to an ffmpeg argument.
+pub(crate) fn output_path(dst: &Path) -> String {
+    // prepend './' to the path on linux since ffmpeg may interpret the path incorrectly if it's
+    // just the filename. see https://github.com/crunchy-labs/crunchy-cli/issues/303 for example
+    if !cfg!(windows)
+        && dst
+            .parent()
+            .map(|p| p.as_os_str().is_empty())
+            .unwrap_or(true)
+    {
+        Path::new("./").join(dst).to_string_lossy().to_string()
+    } else {
+        dst.to_string_lossy().to_string()
+    }
+}
+
+/// Joins the arguments into a command line which can be copied into a shell of the current os.
+/// Only used to display the command, the arguments itself are passed unquoted to ffmpeg.
+pub(crate) fn command_line(program: &str, args: &[String]) -> String {
+    let mut command_line = vec![program.to_string()];
+    for arg in args {
+        command_line.push(quote(arg))
+    }
+    command_line.join(" ")
+}
+
+#[cfg(not(windows))]
+fn quote(arg: &str) -> String {
+    shlex::try_quote(arg).map_or_else(|_| arg.to_string(), |q| q.to_string())
+}
+
+#[cfg(windows)]
+fn quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"', '&', '|', '<', '>', '^']) {
+        return arg.to_string();
+    }
+    format!("\"{}\"", arg.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_filter_value_plain() {
+        assert_eq!(escape_filter_value("subtitle.ass"), "subtitle.ass")
+    }
+
+    #[test]
+    fn escape_filter_value_option_chars() {
+        // escaped for the filter option and the backslash escaped again for the filtergraph
+        assert_eq!(escape_filter_value("it's"), r"it\\\'s");
+        assert_eq!(escape_filter_value("a:b"), r"a\\:b")
+    }
+
+    #[test]
+    fn escape_filter_value_filtergraph_chars() {
+        assert_eq!(escape_filter_value("[1],[2];x"), r"\[1\]\,\[2\]\;x")
+    }
+
+    #[test]
+    fn ass_filter_special_path() {
+        assert_eq!(
+            ass_filter(Path::new(r"/tmp/sub's [1]:x\y.ass")),
+            r"ass=/tmp/sub\\\'s \[1\]\\:x\\\\y.ass"
+        );
+        assert_eq!(
+            ass_filter(Path::new(r"C:\Users\sub.ass")),
+            r"ass=C\\:\\\\Users\\\\sub.ass"
+        )
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn command_line_quoting() {
+        let args = vec![
+            "-i".to_string(),
+            "it's a [test].mkv".to_string(),
+            "-vf".to_string(),
+            ass_filter(Path::new("/tmp/sub's.ass")),
+            String::new(),
+        ];
+        let command_line = command_line("ffmpeg", &args);
+        assert!(command_line.starts_with("ffmpeg -i "));
+        // the shell must get back exactly the arguments which are passed to ffmpeg
+        assert_eq!(
+            shlex::split(&command_line).unwrap(),
+            [vec!["ffmpeg".to_string()], args].concat()
+        )
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn command_line_quoting() {
+        let args = vec![
+            "-i".to_string(),
+            "a \"b\".mkv".to_string(),
+            "a&b".to_string(),
+            String::new(),
+        ];
+        assert_eq!(
+            command_line("ffmpeg", &args),
+            r#"ffmpeg -i "a ""b"".mkv" "a&b" """#
+        )
+    }
+}
@@ -0,0 +1,771 @@
+use lazy_static::lazy_static;
+use log::{debug, warn};
+use regex::Regex;
+use std::fmt;
+use std::fmt::Formatter;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+pub mod args;
+pub mod version;
+
+pub const SOFTSUB_CONTAINERS: [&str; 3] = ["mkv", "mov", "mp4"];
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FFmpegPreset {
+    Predefined(
+        FFmpegCodec,
+        Option<FFmpegHwAccel>,
+        FFmpegQuality,
+        FFmpegRateControl,
+    ),
+    Custom(Option<String>),
+}
+
+/// Overrides the video quality of a predefined preset, either with a constant rate factor or a
+/// target bitrate (e.g. `2500k`). Only one of both can be set.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FFmpegRateControl {
+    pub crf: Option<u8>,
+    pub bitrate: Option<String>,
+}
+
+impl FFmpegRateControl {
+    /// Replaces the quality arguments of the preset output arguments with the rate control. The
+    /// encoders name their constant quality option differently, so `crf` is translated to the
+    /// option of the used encoder.
+    fn apply(&self, output: &mut Vec<String>) {
+        if self.crf.is_none() && self.bitrate.is_none() {
+            return;
+        }
+
+        let mut i = 0;
+        while i < output.len() {
+            if ["-crf", "-q:v", "-global_quality", "-qp", "-rc_mode", "-b:v"]
+                .contains(&output[i].as_str())
+            {
+                output.drain(i..(i + 2).min(output.len()));
+            } else {
+                i += 1
+            }
+        }
+
+        if let Some(crf) = self.crf {
+            let encoder = video_encoder(output).unwrap_or_default().to_string();
+            if encoder.ends_with("_videotoolbox") {
+                // See the comment for apple h264 hwaccel
+                let q = ((-(crf as f32) / 51.0 + 1.0) * 99.0 + 1.0).clamp(1.0, 100.0);
+                output.extend(["-q:v".to_string(), (q as u8).to_string()])
+            } else if encoder.ends_with("_qsv") {
+                output.extend(["-global_quality".to_string(), crf.to_string()])
+            } else if encoder.ends_with("_vaapi") {
+                output.extend(["-rc_mode", "CQP", "-qp"].map(|arg| arg.to_string()));
+                output.push(crf.to_string())
+            } else {
+                output.extend(["-crf".to_string(), crf.to_string()])
+            }
+        }
+        if let Some(bitrate) = &self.bitrate {
+            output.extend(["-b:v".to_string(), bitrate.clone()])
+        }
+    }
+}
+
+/// One pass of a two-pass encode. The first pass only analyses the video and stores its statistics
+/// in the files starting with `log_file`, the second pass uses them to encode the video.
+#[derive(Clone, Copy, Debug)]
+pub struct FFmpegPass<'a> {
+    pub number: u8,
+    pub log_file: &'a Path,
+}
+
+/// Software encoders which support two-pass encoding.
+const TWO_PASS_ENCODERS: [&str; 5] = ["libx264", "libx265", "libvpx", "libvpx-vp9", "libaom-av1"];
+
+lazy_static! {
+    static ref PREDEFINED_PRESET: Regex = Regex::new(r"^\w+(-\w+)*?$").unwrap();
+    static ref ENCODERS: Vec<String> = available_encoders();
+    static ref FFMPEG_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
+
+/// Uses the ffmpeg binary at `path` instead of the one in `PATH`. Must be called before ffmpeg is
+/// run the first time.
+pub fn set_ffmpeg_path(path: PathBuf) {
+    *FFMPEG_PATH.lock().unwrap() = Some(path)
+}
+
+/// If an explicit ffmpeg binary is used, see [`set_ffmpeg_path`].
+pub fn has_ffmpeg_path() -> bool {
+    FFMPEG_PATH.lock().unwrap().is_some()
+}
+
+/// The ffmpeg binary which is used for every ffmpeg call.
+pub fn ffmpeg_binary() -> PathBuf {
+    FFMPEG_PATH
+        .lock()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("ffmpeg"))
+}
+
+/// The ffprobe binary next to [`ffmpeg_binary`] if it exists, otherwise the one in `PATH`.
+pub fn ffprobe_binary() -> PathBuf {
+    let ffmpeg = ffmpeg_binary();
+    let ffprobe = ffmpeg.with_file_name(format!("ffprobe{}", std::env::consts::EXE_SUFFIX));
+    if ffmpeg.components().count() > 1 && ffprobe.exists() {
+        ffprobe
+    } else {
+        PathBuf::from("ffprobe")
+    }
+}
+
+macro_rules! ffmpeg_enum {
+    (enum $name:ident { $($field:ident),* }) => {
+        #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+        pub enum $name {
+            $(
+                $field
+            ),*,
+        }
+
+        impl $name {
+            fn all() -> Vec<$name> {
+                vec![
+                    $(
+                        $name::$field
+                    ),*,
+                ]
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                match self {
+                    $(
+                        &$name::$field => write!(f, "{}", stringify!($field).to_string().to_lowercase())
+                    ),*
+                }
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = anyhow::Error;
+
+            fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+                match s {
+                    $(
+                        stringify!($field) => Ok($name::$field)
+                    ),*,
+                    _ => anyhow::bail!("{} is not a valid {}", s, stringify!($name).to_lowercase())
+                }
+            }
+        }
+    }
+}
+
+ffmpeg_enum! {
+    enum FFmpegCodec {
+        H264,
+        H265,
+        Av1
+    }
+}
+
+ffmpeg_enum! {
+    enum FFmpegHwAccel {
+        Nvidia,
+        Amd,
+        Apple,
+        Intel,
+        Vaapi
+    }
+}
+
+impl FFmpegHwAccel {
+    /// Names of the ffmpeg encoder families which are also accepted as hardware acceleration.
+    fn from_alias(s: &str) -> Option<FFmpegHwAccel> {
+        Some(match s {
+            "nvenc" => FFmpegHwAccel::Nvidia,
+            "amf" => FFmpegHwAccel::Amd,
+            "videotoolbox" => FFmpegHwAccel::Apple,
+            "qsv" => FFmpegHwAccel::Intel,
+            _ => return None,
+        })
+    }
+}
+
+ffmpeg_enum! {
+    enum FFmpegQuality {
+        Lossless,
+        Normal,
+        Low
+    }
+}
+
+impl Default for FFmpegPreset {
+    fn default() -> Self {
+        Self::Custom(Some("-c:v copy -c:a copy".to_string()))
+    }
+}
+
+impl FFmpegPreset {
+    pub(crate) fn available_matches(
+    ) -> Vec<(FFmpegCodec, Option<FFmpegHwAccel>, Option<FFmpegQuality>)> {
+        let codecs = vec![
+            (
+                FFmpegCodec::H264,
+                FFmpegHwAccel::all(),
+                FFmpegQuality::all(),
+            ),
+            (
+                FFmpegCodec::H265,
+                FFmpegHwAccel::all(),
+                FFmpegQuality::all(),
+            ),
+            (
+                FFmpegCodec::Av1,
+                vec![
+                    FFmpegHwAccel::Nvidia,
+                    FFmpegHwAccel::Amd,
+                    FFmpegHwAccel::Intel,
+                    FFmpegHwAccel::Vaapi,
+                ],
+                FFmpegQuality::all(),
+            ),
+        ];
+
+        let mut return_values = vec![];
+
+        for (codec, hwaccels, qualities) in codecs {
+            return_values.push((codec.clone(), None, None));
+            for hwaccel in hwaccels.clone() {
+                return_values.push((codec.clone(), Some(hwaccel), None));
+            }
+            for quality in qualities.clone() {
+                return_values.push((codec.clone(), None, Some(quality)))
+            }
+            for hwaccel in hwaccels {
+                for quality in qualities.clone() {
+                    return_values.push((codec.clone(), Some(hwaccel.clone()), Some(quality)))
+                }
+            }
+        }
+
+        return_values
+    }
+
+    pub(crate) fn available_matches_human_readable() -> Vec<String> {
+        let mut return_values = vec![];
+
+        for (codec, hwaccel, quality) in FFmpegPreset::available_matches() {
+            let mut description_details = vec![];
+            if let Some(h) = &hwaccel {
+                description_details.push(format!("{h} hardware acceleration"))
+            }
+            if let Some(q) = &quality {
+                description_details.push(format!("{q} video quality/compression"))
+            }
+
+            let description = if description_details.is_empty() {
+                format!("{codec} encoded with default video quality/compression",)
+            } else if description_details.len() == 1 {
+                format!("{} encoded with {}", codec, description_details[0])
+            } else {
+                let first = description_details.remove(0);
+                let last = description_details.remove(description_details.len() - 1);
+                let mid = if !description_details.is_empty() {
+                    format!(", {} ", description_details.join(", "))
+                } else {
+                    "".to_string()
+                };
+
+                format!("{codec} encoded with {first}{mid} and {last}",)
+            };
+
+            return_values.push(format!(
+                "{} ({})",
+                vec![
+                    Some(codec.to_string()),
+                    hwaccel.map(|h| h.to_string()),
+                    quality.map(|q| q.to_string())
+                ]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<String>>()
+                .join("-"),
+                description
+            ))
+        }
+        return_values
+    }
+
+    pub(crate) fn parse(s: &str) -> Result<FFmpegPreset, String> {
+        if !PREDEFINED_PRESET.is_match(s) {
+            return Ok(FFmpegPreset::Custom(Some(s.to_string())));
+        }
+
+        let mut codec: Option<FFmpegCodec> = None;
+        let mut hwaccel: Option<FFmpegHwAccel> = None;
+        let mut quality: Option<FFmpegQuality> = None;
+        for token in s.split('-') {
+            if let Some(c) = FFmpegCodec::all()
+                .into_iter()
+                .find(|p| p.to_string() == token.to_lowercase())
+            {
+                if let Some(cc) = codec {
+                    return Err(format!("cannot use multiple codecs (found {cc} and {c})",));
+                }
+                codec = Some(c)
+            } else if let Some(h) = FFmpegHwAccel::all()
+                .into_iter()
+                .find(|p| p.to_string() == token.to_lowercase())
+                .or_else(|| FFmpegHwAccel::from_alias(&token.to_lowercase()))
+            {
+                if let Some(hh) = hwaccel {
+                    return Err(format!(
+                        "cannot use multiple hardware accelerations (found {hh} and {h})",
+                    ));
+                }
+                hwaccel = Some(h)
+            } else if let Some(q) = FFmpegQuality::all()
+                .into_iter()
+                .find(|p| p.to_string() == token.to_lowercase())
+            {
+                if let Some(qq) = quality {
+                    return Err(format!(
+                        "cannot use multiple ffmpeg preset qualities (found {qq} and {q})",
+                    ));
+                }
+                quality = Some(q)
+            } else {
+                return Err(format!(
+                    "'{}' is not a valid ffmpeg preset (unknown token '{}')",
+                    s, token
+                ));
+            }
+        }
+
+        if let Some(c) = codec {
+            if !FFmpegPreset::available_matches().contains(&(
+                c.clone(),
+                hwaccel.clone(),
+                quality.clone(),
+            )) {
+                return Err("ffmpeg preset is not supported".to_string());
+            }
+            Ok(FFmpegPreset::Predefined(
+                c,
+                hwaccel,
+                quality.unwrap_or(FFmpegQuality::Normal),
+                FFmpegRateControl::default(),
+            ))
+        } else {
+            Err("cannot use ffmpeg preset with without a codec".to_string())
+        }
+    }
+
+    /// The ffmpeg video encoder of a predefined preset.
+    fn encoder(&self) -> Option<&'static str> {
+        let FFmpegPreset::Predefined(codec, hwaccel, _, _) = self else {
+            return None;
+        };
+        Some(match (codec, hwaccel) {
+            (FFmpegCodec::H264, None) => "libx264",
+            (FFmpegCodec::H264, Some(FFmpegHwAccel::Nvidia)) => "h264_nvenc",
+            (FFmpegCodec::H264, Some(FFmpegHwAccel::Amd)) => "h264_amf",
+            (FFmpegCodec::H264, Some(FFmpegHwAccel::Apple)) => "h264_videotoolbox",
+            (FFmpegCodec::H264, Some(FFmpegHwAccel::Intel)) => "h264_qsv",
+            (FFmpegCodec::H264, Some(FFmpegHwAccel::Vaapi)) => "h264_vaapi",
+            (FFmpegCodec::H265, None) => "libx265",
+            (FFmpegCodec::H265, Some(FFmpegHwAccel::Nvidia)) => "hevc_nvenc",
+            (FFmpegCodec::H265, Some(FFmpegHwAccel::Amd)) => "hevc_amf",
+            (FFmpegCodec::H265, Some(FFmpegHwAccel::Apple)) => "hevc_videotoolbox",
+            (FFmpegCodec::H265, Some(FFmpegHwAccel::Intel)) => "hevc_qsv",
+            (FFmpegCodec::H265, Some(FFmpegHwAccel::Vaapi)) => "hevc_vaapi",
+            (FFmpegCodec::Av1, None) => "libsvtav1",
+            (FFmpegCodec::Av1, Some(FFmpegHwAccel::Nvidia)) => "av1_nvenc",
+            (FFmpegCodec::Av1, Some(FFmpegHwAccel::Amd)) => "av1_amf",
+            (FFmpegCodec::Av1, Some(FFmpegHwAccel::Apple)) => "av1_videotoolbox",
+            (FFmpegCodec::Av1, Some(FFmpegHwAccel::Intel)) => "av1_qsv",
+            (FFmpegCodec::Av1, Some(FFmpegHwAccel::Vaapi)) => "av1_vaapi",
+        })
+    }
+
+    /// Sets the rate control of a predefined preset. Custom presets are returned unchanged, their
+    /// quality must be set in the custom output arguments.
+    pub(crate) fn with_rate_control(self, rate_control: FFmpegRateControl) -> FFmpegPreset {
+        match self {
+            FFmpegPreset::Predefined(codec, hwaccel, quality, _) => {
+                FFmpegPreset::Predefined(codec, hwaccel, quality, rate_control)
+            }
+            custom => custom,
+        }
+    }
+
+    /// Checks if the used ffmpeg supports every encoder the preset uses. If the encoders couldn't
+    /// be determined, the preset is assumed to work.
+    pub(crate) fn check_encoders(&self) -> anyhow::Result<()> {
+        if ENCODERS.is_empty() {
+            return Ok(());
+        }
+        let (_, output) = self.clone().into_input_output_args(None);
+        for pair in output.windows(2) {
+            let option = pair[0].as_str();
+            let is_codec_option = ["-c", "-codec", "-vcodec", "-acodec", "-scodec"]
+                .contains(&option)
+                || option.starts_with("-c:")
+                || option.starts_with("-codec:");
+            if !is_codec_option || pair[1] == "copy" {
+                continue;
+            }
+            if !ENCODERS.contains(&pair[1]) {
+                anyhow::bail!(
+                    "The ffmpeg binary ({}) doesn't support the encoder '{}' which the ffmpeg preset uses. Use an ffmpeg build which includes it, e.g. with `--ffmpeg-path`",
+                    ffmpeg_binary().to_string_lossy(),
+                    pair[1]
+                )
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks if the installed ffmpeg supports the hardware encoder of the preset. If not, the
+    /// preset falls back to the software encoder of the same codec.
+    pub(crate) fn with_available_encoder(self) -> FFmpegPreset {
+        let Some(encoder) = self.encoder() else {
+            return self;
+        };
+        // if the encoders couldn't be determined at all, the preset is tried anyway
+        if ENCODERS.is_empty() || ENCODERS.iter().any(|e| e == encoder) {
+            return self;
+        }
+
+        let FFmpegPreset::Predefined(codec, Some(hwaccel), quality, rate_control) = self else {
+            return self;
+        };
+        let fallback = FFmpegPreset::Predefined(codec, None, quality, rate_control);
+        warn!(
+            "The {} hardware encoder ({}) is not available in your ffmpeg installation, falling back to software encoding ({})",
+            hwaccel,
+            encoder,
+            fallback.encoder().unwrap()
+        );
+        fallback
+    }
+
+    /// If the preset encodes the video with a target bitrate (`-b:v`) and an encoder which supports
+    /// two-pass encoding.
+    pub(crate) fn supports_two_pass(&self) -> bool {
+        let (_, output) = self.clone().into_input_output_args(None);
+        output.iter().any(|arg| arg == "-b:v")
+            && video_encoder(&output).is_some_and(|encoder| TWO_PASS_ENCODERS.contains(&encoder))
+    }
+
+    /// The input and output arguments of the preset. If `pass` is given, the output arguments
+    /// additionally contain the options for this pass of a two-pass encode, see
+    /// [`FFmpegPreset::supports_two_pass`].
+    pub(crate) fn into_input_output_args(
+        self,
+        pass: Option<FFmpegPass>,
+    ) -> (Vec<String>, Vec<String>) {
+        let (input, mut output) = self.into_args();
+        if let Some(pass) = pass {
+            add_pass_args(&mut output, pass)
+        }
+        (input, output)
+    }
+
+    fn into_args(self) -> (Vec<String>, Vec<String>) {
+        match self {
+            FFmpegPreset::Custom(output) => (
+                vec![],
+                output.map_or(vec![], |o| shlex::split(&o).unwrap_or_default()),
+            ),
+            FFmpegPreset::Predefined(codec, hwaccel_opt, quality, rate_control) => {
+                let mut input = vec![];
+                let mut output = vec![];
+
+                match codec {
+                    FFmpegCodec::H264 => {
+                        let mut crf_quality = || match quality {
+                            FFmpegQuality::Lossless => output.extend(["-crf", "18"]),
+                            FFmpegQuality::Normal => (),
+                            FFmpegQuality::Low => output.extend(["-crf", "35"]),
+                        };
+
+                        if let Some(hwaccel) = hwaccel_opt {
+                            match hwaccel {
+                                FFmpegHwAccel::Nvidia => {
+                                    input.extend(NVIDIA_INPUT);
+                                    crf_quality();
+                                    output.extend(["-c:v", "h264_nvenc", "-c:a", "copy"])
+                                }
+                                FFmpegHwAccel::Amd => {
+                                    crf_quality();
+                                    output.extend(["-c:v", "h264_amf", "-c:a", "copy"])
+                                }
+                                FFmpegHwAccel::Apple => {
+                                    // Apple's Video Toolbox encoders ignore `-crf`, use `-q:v`
+                                    // instead. It's on a scale of 1-100, 100 being lossless. Just
+                                    // did some math ((-a/51+1)*99+1 where `a` is the old crf value)
+                                    // so these settings very likely need some more tweaking
+                                    match quality {
+                                        FFmpegQuality::Lossless => output.extend(["-q:v", "65"]),
+                                        FFmpegQuality::Normal => (),
+                                        FFmpegQuality::Low => output.extend(["-q:v", "32"]),
+                                    }
+
+                                    output.extend(["-c:v", "h264_videotoolbox", "-c:a", "copy"])
+                                }
+                                FFmpegHwAccel::Intel => {
+                                    input.extend(QSV_INPUT);
+                                    // Quick Sync Video ignores `-crf` too, its equivalent is
+                                    // `-global_quality`
+                                    match quality {
+                                        FFmpegQuality::Lossless => {
+                                            output.extend(["-global_quality", "18"])
+                                        }
+                                        FFmpegQuality::Normal => (),
+                                        FFmpegQuality::Low => {
+                                            output.extend(["-global_quality", "35"])
+                                        }
+                                    }
+                                    output.extend(["-c:v", "h264_qsv", "-c:a", "copy"])
+                                }
+                                FFmpegHwAccel::Vaapi => {
+                                    input.extend(VAAPI_INPUT);
+                                    vaapi_quality(&mut output, &quality, "18");
+                                    output.extend(["-c:v", "h264_vaapi", "-c:a", "copy"])
+                                }
+                            }
+                        } else {
+                            crf_quality();
+                            output.extend(["-c:v", "libx264", "-c:a", "copy"])
+                        }
+                    }
+                    FFmpegCodec::H265 => {
+                        let mut crf_quality = || match quality {
+                            FFmpegQuality::Lossless => output.extend(["-crf", "20"]),
+                            FFmpegQuality::Normal => (),
+                            FFmpegQuality::Low => output.extend(["-crf", "35"]),
+                        };
+
+                        if let Some(hwaccel) = hwaccel_opt {
+                            match hwaccel {
+                                FFmpegHwAccel::Nvidia => {
+                                    input.extend(NVIDIA_INPUT);
+                                    crf_quality();
+                                    output.extend([
+                                        "-c:v",
+                                        "hevc_nvenc",
+                                        "-c:a",
+                                        "copy",
+                                        "-tag:v",
+                                        "hvc1",
+                                    ])
+                                }
+                                FFmpegHwAccel::Amd => {
+                                    crf_quality();
+                                    output.extend(["-c:v", "hevc_amf", "-c:a", "copy"])
+                                }
+                                FFmpegHwAccel::Apple => {
+                                    // See the comment for apple h264 hwaccel
+                                    match quality {
+                                        FFmpegQuality::Lossless => output.extend(["-q:v", "61"]),
+                                        FFmpegQuality::Normal => (),
+                                        FFmpegQuality::Low => output.extend(["-q:v", "32"]),
+                                    }
+
+                                    output.extend([
+                                        "-c:v",
+                                        "hevc_videotoolbox",
+                                        "-c:a",
+                                        "copy",
+                                        "-tag:v",
+                                        "hvc1",
+                                    ])
+                                }
+                                FFmpegHwAccel::Intel => {
+                                    input.extend(QSV_INPUT);
+                                    // See the comment for intel h264 hwaccel
+                                    match quality {
+                                        FFmpegQuality::Lossless => {
+                                            output.extend(["-global_quality", "20"])
+                                        }
+                                        FFmpegQuality::Normal => (),
+                                        FFmpegQuality::Low => {
+                                            output.extend(["-global_quality", "35"])
+                                        }
+                                    }
+                                    output.extend([
+                                        "-c:v", "hevc_qsv", "-c:a", "copy", "-tag:v", "hvc1",
+                                    ])
+                                }
+                                FFmpegHwAccel::Vaapi => {
+                                    input.extend(VAAPI_INPUT);
+                                    vaapi_quality(&mut output, &quality, "20");
+                                    output.extend([
+                                        "-c:v",
+                                        "hevc_vaapi",
+                                        "-c:a",
+                                        "copy",
+                                        "-tag:v",
+                                        "hvc1",
+                                    ])
+                                }
+                            }
+                        } else {
+                            crf_quality();
+                            output.extend(["-c:v", "libx265", "-c:a", "copy", "-tag:v", "hvc1"])
+                        }
+                    }
+                    FFmpegCodec::Av1 => {
+                        let mut crf_quality = || match quality {
+                            FFmpegQuality::Lossless => output.extend(["-crf", "22"]),
+                            FFmpegQuality::Normal => (),
+                            FFmpegQuality::Low => output.extend(["-crf", "35"]),
+                        };
+
+                        match hwaccel_opt {
+                            Some(FFmpegHwAccel::Nvidia) => {
+                                input.extend(NVIDIA_INPUT);
+                                crf_quality();
+                                output.extend(["-c:v", "av1_nvenc", "-c:a", "copy"]);
+                            }
+                            Some(FFmpegHwAccel::Amd) => {
+                                crf_quality();
+                                output.extend(["-c:v", "av1_amf", "-c:a", "copy"]);
+                            }
+                            Some(FFmpegHwAccel::Intel) => {
+                                input.extend(QSV_INPUT);
+                                match quality {
+                                    FFmpegQuality::Lossless => {
+                                        output.extend(["-global_quality", "22"])
+                                    }
+                                    FFmpegQuality::Normal => (),
+                                    FFmpegQuality::Low => output.extend(["-global_quality", "35"]),
+                                }
+                                output.extend(["-c:v", "av1_qsv", "-c:a", "copy"]);
+                            }
+                            Some(FFmpegHwAccel::Vaapi) => {
+                                input.extend(VAAPI_INPUT);
+                                vaapi_quality(&mut output, &quality, "22");
+                                output.extend(["-c:v", "av1_vaapi", "-c:a", "copy"]);
+                            }
+                            // not offered in `FFmpegPreset::available_matches`
+                            Some(FFmpegHwAccel::Apple) | None => {
+                                crf_quality();
+                                output.extend(["-c:v", "libsvtav1", "-c:a", "copy"]);
+                            }
+                        }
+                    }
+                }
+
+                let mut output = output
+                    .into_iter()
+                    .map(|s| s.to_string())
+                    .collect::<Vec<String>>();
+                rate_control.apply(&mut output);
+
+                (
+                    input
+                        .into_iter()
+                        .map(|s| s.to_string())
+                        .collect::<Vec<String>>(),
+                    output,
+                )
+            }
+        }
+    }
+}
+
+const NVIDIA_INPUT: [&str; 6] = [
+    "-hwaccel",
+    "cuda",
+    "-hwaccel_output_format",
+    "cuda",
+    "-c:v",
+    "h264_cuvid",
+];
+const QSV_INPUT: [&str; 4] = ["-hwaccel", "qsv", "-hwaccel_output_format", "qsv"];
+const VAAPI_INPUT: [&str; 6] = [
+    "-hwaccel",
+    "vaapi",
+    "-hwaccel_output_format",
+    "vaapi",
+    "-vaapi_device",
+    "/dev/dri/renderD128",
+];
+
+/// The video encoder which is set in the output arguments of a preset.
+fn video_encoder(output: &[String]) -> Option<&str> {
+    output
+        .windows(2)
+        .rev()
+        .find(|w| ["-c:v", "-codec:v", "-vcodec"].contains(&w[0].as_str()))
+        .map(|w| w[1].as_str())
+}
+
+fn add_pass_args(output: &mut Vec<String>, pass: FFmpegPass) {
+    let log_file = pass.log_file.to_string_lossy().to_string();
+    // libx265 ignores `-pass` and `-passlogfile`, the pass must be set via its own parameters
+    if video_encoder(output) == Some("libx265") {
+        // ':' separates the parameters and must be escaped, e.g. in windows paths
+        let params = format!(
+            "pass={}:stats={}",
+            pass.number,
+            log_file.replace('\\', "\\\\").replace(':', "\\:")
+        );
+        match output.iter().position(|arg| arg == "-x265-params") {
+            Some(i) if i + 1 < output.len() => {
+                output[i + 1] = format!("{}:{}", output[i + 1], params)
+            }
+            _ => output.extend(["-x265-params".to_string(), params]),
+        }
+    } else {
+        output.extend([
+            "-pass".to_string(),
+            pass.number.to_string(),
+            "-passlogfile".to_string(),
+            log_file,
+        ])
+    }
+}
+
+/// VA-API encoders don't support `-crf`, a constant quantizer is used instead.
+fn vaapi_quality(output: &mut Vec<&str>, quality: &FFmpegQuality, lossless_qp: &'static str) {
+    match quality {
+        FFmpegQuality::Lossless => output.extend(["-rc_mode", "CQP", "-qp", lossless_qp]),
+        FFmpegQuality::Normal => (),
+        FFmpegQuality::Low => output.extend(["-rc_mode", "CQP", "-qp", "35"]),
+    }
+}
+
+/// Get the names of all encoders the installed ffmpeg supports.
+fn available_encoders() -> Vec<String> {
+    let output = match Command::new(ffmpeg_binary())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .args(["-hide_banner", "-encoders"])
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => {
+            debug!("Failed to get available ffmpeg encoders: {}", e);
+            return vec![];
+        }
+    };
+    // every encoder line looks like ' V....D libx264              libx264 H.264 / AVC ...'. the
+    // legend before the encoders has the same format, but with `=` as name
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let flags = parts.next()?;
+            let name = parts.next()?;
+            (flags.len() == 6 && name != "=").then(|| name.to_string())
+        })
+        .collect()
+}
@@ -0,0 +1,30 @@
+use crate::utils::ffmpeg::ffmpeg_binary;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::process::{Command, Stdio};
+
+lazy_static! {
+    static ref FFMPEG_VERSION: Regex = Regex::new(r"ffmpeg version \D*(?P<major>\d+)\.").unwrap();
+}
+
+/// Oldest ffmpeg major version which supports all arguments crunchy-cli uses.
+pub const MIN_FFMPEG_VERSION: u32 = 5;
+
+/// Major version of the installed ffmpeg. Development builds whose version is a git revision
+/// return [`u32::MAX`].
+pub fn ffmpeg_version() -> Option<u32> {
+    let output = Command::new(ffmpeg_binary())
+        .arg("-version")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let version = String::from_utf8_lossy(&output.stdout).to_string();
+    if let Some(caps) = FFMPEG_VERSION.captures(&version) {
+        caps.name("major").unwrap().as_str().parse().ok()
+    } else if version.starts_with("ffmpeg version N-") {
+        Some(u32::MAX)
+    } else {
+        None
+    }
+}
@@ -2,6 +2,7 @@ use anyhow::Result;
 use crunchyroll_rs::{
     Concert, Episode, MediaCollection, Movie, MovieListing, MusicVideo, Season, Series,
 };
+use log::info;
 
 pub trait Filter {
     type T: Send + Sized;
@@ -90,3 +91,69 @@ pub fn real_dedup_vec<T: Clone + Eq>(input: &mut Vec<T>) {
     }
     *input = dedup
 }
+
+/// US TV parental guideline ratings, which Crunchyroll uses as maturity ratings. Ordered from the
+/// least to the most mature.
+const MATURITY_RATINGS: [&str; 6] = ["TV-Y", "TV-Y7", "TV-G", "TV-PG", "TV-14", "TV-MA"];
+
+/// The most mature rating which is still allowed to be downloaded.
+#[derive(Clone, Debug)]
+pub struct MaxMaturity(usize);
+
+impl MaxMaturity {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        MATURITY_RATINGS
+            .iter()
+            .position(|r| r.eq_ignore_ascii_case(s))
+            .map(Self)
+            .ok_or(format!(
+                "'{}' is not a valid maturity rating. Valid ratings are {}",
+                s,
+                MATURITY_RATINGS.join(", ")
+            ))
+    }
+
+    /// Media without a known rating is only allowed if it isn't flagged as mature or if mature
+    /// media is allowed anyway.
+    fn allows(&self, maturity_ratings: &[String], is_mature: bool) -> bool {
+        let levels: Vec<usize> = maturity_ratings
+            .iter()
+            .filter_map(|r| {
+                MATURITY_RATINGS
+                    .iter()
+                    .position(|m| m.eq_ignore_ascii_case(r))
+            })
+            .collect();
+        match levels.iter().max() {
+            Some(level) => *level <= self.0,
+            None => !is_mature || self.0 == MATURITY_RATINGS.len() - 1,
+        }
+    }
+}
+
+/// Checks if media must be skipped because it's more mature than allowed and logs it if so.
+pub fn is_too_mature(
+    max_maturity: Option<&MaxMaturity>,
+    kind: &str,
+    title: &str,
+    maturity_ratings: &[String],
+    is_mature: bool,
+) -> bool {
+    let Some(max_maturity) = max_maturity else {
+        return false;
+    };
+    if max_maturity.allows(maturity_ratings, is_mature) {
+        return false;
+    }
+    info!(
+        "Skipping {} '{}' because of its maturity rating ({})",
+        kind,
+        title,
+        if maturity_ratings.is_empty() {
+            "mature".to_string()
+        } else {
+            maturity_ratings.join(", ")
+        }
+    );
+    true
+}
@@ -17,3 +17,14 @@ pub fn format_time_delta(time_delta: &TimeDelta) -> String {
         milliseconds
     )
 }
+
+/// Formats bytes as megabytes or, from one gigabyte on, as gigabytes.
+pub fn format_size(bytes: u64) -> String {
+    let mb = bytes as f64 / 1024.0 / 1024.0;
+    let gb = mb / 1024.0;
+    if gb < 1.0 {
+        format!("{}MB", mb.ceil())
+    } else {
+        format!("{:.2}GB", gb)
+    }
+}
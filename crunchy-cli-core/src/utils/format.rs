@@ -1,14 +1,19 @@
+use crate::utils::download::CoverArt;
 use crate::utils::filter::real_dedup_vec;
 use crate::utils::locale::LanguageTagging;
 use crate::utils::log::tab_info;
 use crate::utils::os::{is_special_file, sanitize};
+use crate::utils::video::Codec;
 use anyhow::{bail, Result};
-use chrono::{Datelike, Duration};
-use crunchyroll_rs::media::{Resolution, SkipEvents, Stream, StreamData, Subtitle};
+use chrono::{DateTime, Datelike, Duration, Local, Utc};
+use crunchyroll_rs::media::{
+    Resolution, SkipEvents, SkipEventsEvent, Stream, StreamData, Subtitle,
+};
 use crunchyroll_rs::{Concert, Episode, Locale, MediaCollection, Movie, MusicVideo};
-use log::{debug, info};
+use log::{debug, info, warn};
+use regex::{Captures, Regex};
 use std::cmp::Ordering;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::path::{Path, PathBuf};
 
@@ -38,9 +43,20 @@ pub struct SingleFormat {
     pub relative_episode_number: Option<u32>,
     pub sequence_number: f32,
     pub relative_sequence_number: Option<f32>,
+    pub special_label: Option<String>,
+
+    pub maturity_ratings: Vec<String>,
+    pub is_mature: bool,
 
     pub duration: Duration,
 
+    /// If the media is premium only and can therefore not be downloaded with the current account.
+    /// Only set if the media is listed but not downloaded.
+    pub unavailable: bool,
+    /// When the media becomes available. Announced episodes which aren't released yet have a time
+    /// in the future.
+    pub available_from: Option<DateTime<Utc>>,
+
     source: MediaCollection,
 }
 
@@ -85,7 +101,12 @@ impl SingleFormat {
             sequence_number: episode.sequence_number,
             relative_episode_number,
             relative_sequence_number,
+            special_label: special_label(&episode),
+            maturity_ratings: episode.maturity_ratings.clone(),
+            is_mature: episode.is_mature,
             duration: episode.duration,
+            unavailable: false,
+            available_from: Some(episode.availability_starts),
             source: episode.into(),
         }
     }
@@ -110,7 +131,12 @@ impl SingleFormat {
             relative_episode_number: Some(1),
             sequence_number: 1.0,
             relative_sequence_number: Some(1.0),
+            special_label: None,
+            maturity_ratings: movie.maturity_ratings.clone(),
+            is_mature: movie.is_mature,
             duration: movie.duration,
+            unavailable: false,
+            available_from: None,
             source: movie.into(),
         }
     }
@@ -135,7 +161,12 @@ impl SingleFormat {
             relative_episode_number: Some(1),
             sequence_number: 1.0,
             relative_sequence_number: Some(1.0),
+            special_label: None,
+            maturity_ratings: vec![],
+            is_mature: music_video.is_mature,
             duration: music_video.duration,
+            unavailable: false,
+            available_from: None,
             source: music_video.into(),
         }
     }
@@ -160,7 +191,12 @@ impl SingleFormat {
             relative_episode_number: Some(1),
             sequence_number: 1.0,
             relative_sequence_number: Some(1.0),
+            special_label: None,
+            maturity_ratings: vec![],
+            is_mature: concert.is_mature,
             duration: concert.duration,
+            unavailable: false,
+            available_from: None,
             source: concert.into(),
         }
     }
@@ -190,6 +226,28 @@ impl SingleFormat {
         }
     }
 
+    /// Url of the largest image of the given cover art source.
+    pub async fn cover_art_url(&self, cover_art: CoverArt) -> Result<Option<String>> {
+        let images = match (&self.source, cover_art) {
+            (MediaCollection::Episode(e), CoverArt::Episode) => {
+                e.images.clone().unwrap_or_default().thumbnail
+            }
+            (MediaCollection::Episode(e), CoverArt::Series) => e.series().await?.images.poster_tall,
+            (MediaCollection::Movie(m), CoverArt::Episode) => {
+                m.images.clone().unwrap_or_default().thumbnail
+            }
+            (MediaCollection::Movie(m), CoverArt::Series) => {
+                m.movie_listing().await?.images.poster_tall
+            }
+            _ => return Ok(None),
+        };
+        Ok(images
+            .into_iter()
+            .flatten()
+            .max_by_key(|i| i.width)
+            .map(|i| i.source))
+    }
+
     pub fn source_type(&self) -> String {
         match &self.source {
             MediaCollection::Episode(_) => "episode",
@@ -206,7 +264,80 @@ impl SingleFormat {
     }
 
     pub fn is_special(&self) -> bool {
-        self.sequence_number == 0.0 || self.sequence_number.fract() != 0.0
+        self.special_label.is_some()
+            || self.sequence_number == 0.0
+            || self.sequence_number.fract() != 0.0
+    }
+
+    /// Waits until the media is released if it's announced but not available yet. Returns
+    /// immediately if it's already available.
+    pub async fn wait_for_release(&self) {
+        let Some(available_from) = self.available_from else {
+            return;
+        };
+        if available_from <= Utc::now() {
+            return;
+        }
+        info!(
+            "Waiting until {} for '{}' to be released",
+            available_from
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M"),
+            self.title
+        );
+        // the remaining time is re-calculated regularly, so that a suspended system doesn't let
+        // the download sleep too long
+        while let Ok(remaining) = (available_from - Utc::now()).to_std() {
+            tokio::time::sleep(remaining.min(std::time::Duration::from_secs(60))).await
+        }
+    }
+
+    pub fn has_blank_metadata(&self) -> bool {
+        self.title.trim().is_empty() || self.description.trim().is_empty()
+    }
+
+    /// Some dubbed episodes have an empty title or description. These are taken from the japanese
+    /// version, or any other version which has them, instead.
+    pub async fn fill_blank_metadata(&mut self) {
+        let MediaCollection::Episode(episode) = &self.source else {
+            return;
+        };
+        if !self.has_blank_metadata() {
+            return;
+        }
+
+        let mut locales = vec![Locale::ja_JP];
+        if let Ok(available_versions) = episode.available_versions().await {
+            locales.extend(
+                available_versions
+                    .into_iter()
+                    .filter(|l| l != &Locale::ja_JP && l != &self.audio),
+            )
+        }
+        for locale in locales {
+            let versions = match episode.version(vec![locale.clone()]).await {
+                Ok(versions) => versions,
+                Err(e) => {
+                    debug!(
+                        "Failed to get {} version of {}: {}",
+                        locale, self.episode_id, e
+                    );
+                    continue;
+                }
+            };
+            for version in versions {
+                if self.title.trim().is_empty() && !version.title.trim().is_empty() {
+                    debug!("Using {} title for {}", locale, self.episode_id);
+                    self.title = version.title
+                }
+                if self.description.trim().is_empty() && !version.description.trim().is_empty() {
+                    self.description = version.description
+                }
+            }
+            if !self.has_blank_metadata() {
+                break;
+            }
+        }
     }
 }
 
@@ -303,23 +434,273 @@ impl SingleFormatCollection {
             );
             for (i, (_, formats)) in episodes.iter().enumerate() {
                 let format = formats.first().unwrap();
+                let unavailable = if format.unavailable {
+                    " (premium only, unavailable)"
+                } else {
+                    ""
+                };
                 if log::max_level() == log::Level::Debug {
                     info!(
-                        "{} S{:02}E{:0>2}",
-                        format.title, format.season_number, format.episode_number
+                        "{} S{:02}E{:0>2}{}",
+                        format.title, format.season_number, format.episode_number, unavailable
                     )
                 } else {
                     tab_info!(
-                        "{}. {} » S{:02}E{:0>2}",
+                        "{}. {} » S{:02}E{:0>2}{}",
                         i + 1,
                         format.title,
                         format.season_number,
-                        format.episode_number
+                        format.episode_number,
+                        unavailable
                     )
                 }
             }
         }
     }
+
+    /// The collection as tree of series, seasons and episodes.
+    pub async fn json_output(&self) -> Vec<serde_json::Value> {
+        // skip events must be requested for every episode on their own. episodes without skip
+        // events or failed requests get a `null` value
+        let mut skip_events = HashMap::new();
+        for episodes in self.0.values() {
+            for formats in episodes.values() {
+                let format = formats.first().unwrap();
+                let events = format.skip_events().await.ok().flatten();
+                skip_events.insert(
+                    format.episode_id.clone(),
+                    events.as_ref().map(skip_events_json),
+                );
+            }
+        }
+
+        let mut series: Vec<(String, serde_json::Value)> = vec![];
+        for (season_key, episodes) in &self.0 {
+            let first_episode = episodes.first_key_value().unwrap().1.first().unwrap();
+            let season = serde_json::json!({
+                "id": first_episode.season_id,
+                "title": first_episode.season_title,
+                "number": season_key.0 .0,
+                "episodes": episodes
+                    .values()
+                    .map(|formats| {
+                        let format = formats.first().unwrap();
+                        serde_json::json!({
+                            "id": format.episode_id,
+                            "title": format.title,
+                            "number": format.episode_number,
+                            "sequence_number": format.sequence_number,
+                            "special_label": format.special_label,
+                            "maturity_ratings": format.maturity_ratings,
+                            "mature": format.is_mature,
+                            "available": !format.unavailable,
+                            "duration": format.duration.num_milliseconds() as f64 / 1000.0,
+                            "release_date": format!(
+                                "{}-{:0>2}-{:0>2}",
+                                format.release_year, format.release_month, format.release_day
+                            ),
+                            "skip_events": skip_events.get(&format.episode_id).cloned().flatten(),
+                            "versions": formats
+                                .iter()
+                                .map(|f| {
+                                    serde_json::json!({
+                                        "id": f.episode_id,
+                                        "audio": f.audio.to_string(),
+                                        "subtitles": f
+                                            .subtitles
+                                            .iter()
+                                            .map(|s| s.to_string())
+                                            .collect::<Vec<String>>(),
+                                    })
+                                })
+                                .collect::<Vec<serde_json::Value>>(),
+                        })
+                    })
+                    .collect::<Vec<serde_json::Value>>(),
+            });
+
+            if let Some((_, series)) = series
+                .iter_mut()
+                .find(|(id, _)| id == &first_episode.series_id)
+            {
+                series["seasons"].as_array_mut().unwrap().push(season)
+            } else {
+                series.push((
+                    first_episode.series_id.clone(),
+                    serde_json::json!({
+                        "id": first_episode.series_id,
+                        "name": first_episode.series_name,
+                        "seasons": [season],
+                    }),
+                ))
+            }
+        }
+        series.into_iter().map(|(_, s)| s).collect()
+    }
+
+    /// The collection as csv rows, one for every version of every episode. Multiple subtitles and
+    /// maturity ratings are separated by spaces.
+    pub fn csv_output(&self) -> Vec<String> {
+        let mut rows = vec![];
+        for episodes in self.0.values() {
+            for format in episodes.values().flatten() {
+                let row = [
+                    format.series_id.clone(),
+                    format.series_name.clone(),
+                    format.season_id.clone(),
+                    format.season_number.to_string(),
+                    format.season_title.clone(),
+                    format.episode_id.clone(),
+                    format.episode_number.clone(),
+                    format.sequence_number.to_string(),
+                    format.title.clone(),
+                    format.audio.to_string(),
+                    format
+                        .subtitles
+                        .iter()
+                        .map(|s| s.to_string())
+                        .collect::<Vec<String>>()
+                        .join(" "),
+                    format!("{:.3}", format.duration.num_milliseconds() as f64 / 1000.0),
+                    format!(
+                        "{}-{:0>2}-{:0>2}",
+                        format.release_year, format.release_month, format.release_day
+                    ),
+                    format.maturity_ratings.join(" "),
+                    (!format.unavailable).to_string(),
+                ];
+                rows.push(
+                    row.iter()
+                        .map(|field| csv_field(field))
+                        .collect::<Vec<String>>()
+                        .join(","),
+                )
+            }
+        }
+        rows
+    }
+}
+
+/// Columns of the rows of [`SingleFormatCollection::csv_output`].
+pub const CSV_COLUMNS: [&str; 15] = [
+    "series_id",
+    "series_name",
+    "season_id",
+    "season_number",
+    "season_title",
+    "episode_id",
+    "episode_number",
+    "sequence_number",
+    "title",
+    "audio",
+    "subtitles",
+    "duration",
+    "release_date",
+    "maturity_ratings",
+    "available",
+];
+
+/// Quotes a csv field if it contains characters which would break the row.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Start and end (in seconds) of the intro, credits, recap and preview.
+fn skip_events_json(skip_events: &SkipEvents) -> serde_json::Value {
+    let event = |event: &Option<SkipEventsEvent>| {
+        event
+            .as_ref()
+            .map(|e| serde_json::json!({"start": e.start, "end": e.end}))
+    };
+    serde_json::json!({
+        "intro": event(&skip_events.intro),
+        "credits": event(&skip_events.credits),
+        "recap": event(&skip_events.recap),
+        "preview": event(&skip_events.preview),
+    })
+}
+
+/// Template variables which need the skip events of an episode.
+const SKIP_EVENT_VARIABLES: [&str; 4] =
+    ["intro_start", "intro_end", "credits_start", "credits_end"];
+
+/// If the template contains variables which need the skip events. They must be requested separately,
+/// so this should be checked before to prevent unnecessary requests.
+pub fn uses_skip_event_variables(template: &str) -> bool {
+    // the variable may be followed by a width or functions, e.g. `{intro_start|upper}`
+    SKIP_EVENT_VARIABLES
+        .iter()
+        .any(|v| template.contains(&format!("{{{}", v)))
+}
+
+/// How `--list` prints the episodes which would be downloaded.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ListFormat {
+    Text,
+    Json,
+}
+
+impl ListFormat {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "text" => Self::Text,
+            "json" => Self::Json,
+            _ => return Err(format!("'{}' is not a valid list format", s)),
+        })
+    }
+}
+
+/// Order in which the episodes of a [`SingleFormatCollection`] are iterated.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum EpisodeOrder {
+    /// By season and sequence number.
+    #[default]
+    Sequence,
+    /// By the date the episodes first aired, across all seasons. Episodes which aired on the same
+    /// day keep their sequence order.
+    Air,
+    /// By episode title.
+    Alphabetical,
+}
+
+impl EpisodeOrder {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "sequence" => Self::Sequence,
+            "air" => Self::Air,
+            "alphabetical" => Self::Alphabetical,
+            _ => return Err(format!("'{}' is not a valid episode order", s)),
+        })
+    }
+}
+
+impl SingleFormatCollection {
+    pub fn into_ordered_iter(self, order: EpisodeOrder) -> SingleFormatCollectionIterator {
+        let mut episodes: Vec<Vec<SingleFormat>> = self
+            .0
+            .into_values()
+            .flat_map(|episodes| episodes.into_values())
+            .collect();
+        match order {
+            EpisodeOrder::Sequence => (),
+            EpisodeOrder::Air => episodes.sort_by_key(|formats| {
+                let format = formats.first().unwrap();
+                (
+                    format.release_year,
+                    format.release_month,
+                    format.release_day,
+                )
+            }),
+            EpisodeOrder::Alphabetical => {
+                episodes.sort_by_cached_key(|formats| formats.first().unwrap().title.to_lowercase())
+            }
+        }
+        SingleFormatCollectionIterator(episodes.into_iter())
+    }
 }
 
 impl IntoIterator for SingleFormatCollection {
@@ -327,26 +708,67 @@ impl IntoIterator for SingleFormatCollection {
     type IntoIter = SingleFormatCollectionIterator;
 
     fn into_iter(self) -> Self::IntoIter {
-        SingleFormatCollectionIterator(self)
+        self.into_ordered_iter(EpisodeOrder::Sequence)
     }
 }
 
-pub struct SingleFormatCollectionIterator(SingleFormatCollection);
+pub struct SingleFormatCollectionIterator(std::vec::IntoIter<Vec<SingleFormat>>);
 
 impl Iterator for SingleFormatCollectionIterator {
     type Item = Vec<SingleFormat>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let (_, episodes) = self.0 .0.iter_mut().next()?;
+        self.0.next()
+    }
+}
 
-        let value = episodes.pop_first().unwrap().1;
-        if episodes.is_empty() {
-            self.0 .0.pop_first();
-        }
-        Some(value)
+/// Crunchyroll labels some specials in the episode field, e.g. `SP1` or `OVA`. Specials without
+/// such a label get `SP`.
+fn special_label(episode: &Episode) -> Option<String> {
+    let label = episode.episode.trim();
+    if label.chars().any(|c| c.is_alphabetic()) {
+        Some(label.to_string())
+    } else if episode.sequence_number == 0.0 || episode.sequence_number.fract() != 0.0 {
+        Some("SP".to_string())
+    } else {
+        None
     }
 }
 
+lazy_static::lazy_static! {
+    static ref SPECIAL_LABEL_NUMBER_REGEX: Regex = Regex::new(r"\d+").unwrap();
+
+    /// Matches template options like `{title}` with an optional padding width, e.g.
+    /// `{episode_number:3}`, and optional functions, e.g. `{title|upper}`.
+    static ref TEMPLATE_REGEX: Regex =
+        Regex::new(r"\{(?P<name>\w+)(?::(?P<width>\d+))?(?P<functions>(?:\|[^{}|]+)*)\}")
+            .unwrap();
+
+    /// Invisible characters which break file names in some shells and SMB clients: bidirectional
+    /// controls (common in arabic and hebrew titles), zero-width characters and variation
+    /// selectors of emojis.
+    static ref INVISIBLE_TITLE_CHARS: Regex = Regex::new(
+        r"[\x{061C}\x{200B}-\x{200F}\x{202A}-\x{202E}\x{2060}-\x{2069}\x{FE00}-\x{FE0F}\x{FEFF}\x{E0100}-\x{E01EF}]"
+    )
+    .unwrap();
+    static ref TITLE_WHITESPACE: Regex = Regex::new(r"[\p{Cc}\s]+").unwrap();
+}
+
+/// Template options which are padded with zeros. The width can be changed with
+/// `{<option>:<width>}`.
+const PADDED_TEMPLATE_OPTIONS: [&str; 7] = [
+    "season_number",
+    "episode_number",
+    "relative_episode_number",
+    "sequence_number",
+    "relative_sequence_number",
+    "release_month",
+    "release_day",
+];
+
+/// Padding width of [`PADDED_TEMPLATE_OPTIONS`] if no custom width is given.
+const DEFAULT_PADDING_WIDTH: usize = 2;
+
 #[derive(Clone)]
 pub struct Format {
     pub title: String,
@@ -359,6 +781,8 @@ pub struct Format {
     pub width: u64,
     pub height: u64,
     pub fps: f64,
+    /// Codec of the video, e.g. 'h264'. Empty if the download contains no video.
+    pub codec: String,
 
     pub release_year: u64,
     pub release_month: u64,
@@ -376,16 +800,61 @@ pub struct Format {
     pub relative_episode_number: Option<u32>,
     pub sequence_number: f32,
     pub relative_sequence_number: Option<f32>,
+    pub special_label: Option<String>,
+
+    pub maturity_ratings: Vec<String>,
+
+    /// Only set if the skip events are needed, see [`uses_skip_event_variables`].
+    pub skip_events: Option<SkipEvents>,
 }
 
 impl Format {
     #[allow(clippy::type_complexity)]
     pub fn from_single_formats(
-        mut single_formats: Vec<(SingleFormat, StreamData, Vec<(Subtitle, bool)>)>,
+        single_formats: Vec<(SingleFormat, StreamData, Vec<(Subtitle, bool)>)>,
+    ) -> Self {
+        let first_stream = &single_formats.first().unwrap().1;
+        let (resolution, fps) = (
+            first_stream.resolution().unwrap(),
+            first_stream.fps().unwrap(),
+        );
+        let codec = Codec::from_codecs(&first_stream.codecs)
+            .map_or(first_stream.codecs.clone(), |c| c.to_string());
+        let mut format = Self::new(
+            single_formats
+                .into_iter()
+                .map(|(single_format, _, subtitles)| (single_format, subtitles))
+                .collect(),
+            resolution,
+            fps,
+        );
+        format.codec = codec;
+        format
+    }
+
+    /// Like [`Format::from_single_formats`], but for downloads which don't contain a video. The
+    /// resolution and fps are zero.
+    pub fn from_single_formats_without_stream(
+        single_formats: Vec<(SingleFormat, Vec<(Subtitle, bool)>)>,
+    ) -> Self {
+        Self::new(
+            single_formats,
+            Resolution {
+                width: 0,
+                height: 0,
+            },
+            0.0,
+        )
+    }
+
+    fn new(
+        mut single_formats: Vec<(SingleFormat, Vec<(Subtitle, bool)>)>,
+        resolution: Resolution,
+        fps: f64,
     ) -> Self {
         let locales: Vec<(Locale, Vec<Locale>)> = single_formats
             .iter()
-            .map(|(single_format, _, subtitles)| {
+            .map(|(single_format, subtitles)| {
                 (
                     single_format.audio.clone(),
                     subtitles
@@ -395,29 +864,33 @@ impl Format {
                 )
             })
             .collect();
-        let (first_format, first_stream, _) = single_formats.remove(0);
+        let (first_format, _) = single_formats.remove(0);
 
         Self {
-            title: first_format.title,
+            title: sanitize_title(&first_format.title),
             description: first_format.description,
             locales,
-            resolution: first_stream.resolution().unwrap(),
-            width: first_stream.resolution().unwrap().width,
-            height: first_stream.resolution().unwrap().height,
-            fps: first_stream.fps().unwrap(),
+            width: resolution.width,
+            height: resolution.height,
+            resolution,
+            fps,
+            codec: String::new(),
             release_year: first_format.release_year,
             release_month: first_format.release_month,
             release_day: first_format.release_day,
             series_id: first_format.series_id,
-            series_name: first_format.series_name,
+            series_name: sanitize_title(&first_format.series_name),
             season_id: first_format.season_id,
-            season_title: first_format.season_title,
+            season_title: sanitize_title(&first_format.season_title),
             season_number: first_format.season_number,
             episode_id: first_format.episode_id,
             episode_number: first_format.episode_number,
             relative_episode_number: first_format.relative_episode_number,
             sequence_number: first_format.sequence_number,
             relative_sequence_number: first_format.relative_sequence_number,
+            special_label: first_format.special_label,
+            maturity_ratings: first_format.maturity_ratings,
+            skip_events: None,
         }
     }
 
@@ -427,105 +900,151 @@ impl Format {
         path: PathBuf,
         universal: bool,
         language_tagging: Option<&LanguageTagging>,
+        max_path_length: Option<usize>,
     ) -> PathBuf {
-        let path = path
-            .to_string_lossy()
-            .to_string()
-            .replace("{title}", &sanitize(&self.title, true, universal))
-            .replace(
-                "{audio}",
-                &sanitize(
-                    self.locales
-                        .iter()
-                        .map(|(a, _)| language_tagging.map_or(a.to_string(), |t| t.for_locale(a)))
-                        .collect::<Vec<String>>()
-                        .join(
-                            &env::var("CRUNCHY_CLI_FORMAT_DELIMITER")
-                                .map_or("_".to_string(), |e| e),
-                        ),
-                    true,
-                    universal,
-                ),
-            )
-            .replace(
-                "{width}",
-                &sanitize(self.resolution.width.to_string(), true, universal),
-            )
-            .replace(
-                "{height}",
-                &sanitize(self.resolution.height.to_string(), true, universal),
-            )
-            .replace("{series_id}", &sanitize(&self.series_id, true, universal))
-            .replace(
-                "{series_name}",
-                &sanitize(&self.series_name, true, universal),
-            )
-            .replace("{season_id}", &sanitize(&self.season_id, true, universal))
-            .replace(
-                "{season_name}",
-                &sanitize(&self.season_title, true, universal),
-            )
-            .replace(
-                "{season_number}",
-                &format!(
-                    "{:0>2}",
-                    sanitize(self.season_number.to_string(), true, universal)
-                ),
-            )
-            .replace("{episode_id}", &sanitize(&self.episode_id, true, universal))
-            .replace(
-                "{episode_number}",
-                &format!("{:0>2}", sanitize(&self.episode_number, true, universal)),
-            )
-            .replace(
-                "{relative_episode_number}",
-                &format!(
-                    "{:0>2}",
-                    sanitize(
-                        self.relative_episode_number.unwrap_or_default().to_string(),
-                        true,
-                        universal,
-                    )
-                ),
-            )
-            .replace(
-                "{sequence_number}",
-                &format!(
-                    "{:0>2}",
-                    sanitize(self.sequence_number.to_string(), true, universal)
-                ),
-            )
-            .replace(
-                "{relative_sequence_number}",
-                &format!(
-                    "{:0>2}",
-                    sanitize(
-                        self.relative_sequence_number
-                            .unwrap_or_default()
-                            .to_string(),
-                        true,
-                        universal,
-                    )
-                ),
-            )
-            .replace(
-                "{release_year}",
-                &sanitize(self.release_year.to_string(), true, universal),
-            )
-            .replace(
-                "{release_month}",
-                &format!(
-                    "{:0>2}",
-                    sanitize(self.release_month.to_string(), true, universal)
-                ),
-            )
-            .replace(
-                "{release_day}",
-                &format!(
-                    "{:0>2}",
-                    sanitize(self.release_day.to_string(), true, universal)
-                ),
-            );
+        let template = path.to_string_lossy().to_string();
+        let mut path = self.render_path(&template, &self.title, universal, language_tagging);
+        let Some(max_path_length) = max_path_length else {
+            return path;
+        };
+
+        // the title is the most likely part to be long, so it gets shortened first
+        let mut title_len = self.title.chars().count();
+        while full_path_length(&path) > max_path_length && title_len > 0 {
+            title_len = title_len.saturating_sub(full_path_length(&path) - max_path_length);
+            let title: String = self.title.chars().take(title_len).collect();
+            path = self.render_path(&template, &title, universal, language_tagging);
+        }
+
+        // if the path is still too long, the file name is shortened while keeping the extension
+        let excess = full_path_length(&path).saturating_sub(max_path_length);
+        if excess > 0 {
+            let name = path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            let name_len = name.chars().count();
+            if name_len > excess {
+                let name: String = name.chars().take(name_len - excess).collect();
+                match path.extension().map(|e| e.to_string_lossy().to_string()) {
+                    Some(ext) => path.set_file_name(format!("{}.{}", name, ext)),
+                    None => path.set_file_name(name),
+                }
+            } else {
+                warn!(
+                    "Cannot shorten '{}' to the maximum path length of {} characters",
+                    path.to_string_lossy(),
+                    max_path_length
+                )
+            }
+        }
+
+        path
+    }
+
+    /// Splits `command` into its arguments and fills the template options of every argument, plus
+    /// `{path}` with `path` and `{error}` with `error`. The values are inserted as they are, the
+    /// command isn't run by a shell so they can't split or escape an argument.
+    pub fn render_command(
+        &self,
+        command: &str,
+        path: &Path,
+        error: Option<&str>,
+    ) -> Result<Vec<String>> {
+        let Some(args) = shlex::split(command) else {
+            bail!("Invalid command: {}", command)
+        };
+        let mut variables = self.template_variables(&self.title, None);
+        variables.insert("path", path.to_string_lossy().to_string());
+        variables.insert("error", error.unwrap_or_default().to_string());
+        Ok(args
+            .iter()
+            .map(|arg| fill_template(arg, &variables, |value| value))
+            .collect())
+    }
+
+    fn template_variables(
+        &self,
+        title: &str,
+        language_tagging: Option<&LanguageTagging>,
+    ) -> HashMap<&'static str, String> {
+        HashMap::from([
+            ("title", title.to_string()),
+            (
+                "audio",
+                self.locales
+                    .iter()
+                    .map(|(a, _)| language_tagging.map_or(a.to_string(), |t| t.for_locale(a)))
+                    .collect::<Vec<String>>()
+                    .join(&env::var("CRUNCHY_CLI_FORMAT_DELIMITER").map_or("_".to_string(), |e| e)),
+            ),
+            ("width", self.resolution.width.to_string()),
+            ("height", self.resolution.height.to_string()),
+            ("series_id", self.series_id.clone()),
+            ("series_name", self.series_name.clone()),
+            ("season_id", self.season_id.clone()),
+            ("season_name", self.season_title.clone()),
+            ("season_number", self.season_number.to_string()),
+            ("episode_id", self.episode_id.clone()),
+            ("episode_number", self.episode_number.clone()),
+            (
+                "relative_episode_number",
+                self.relative_episode_number.unwrap_or_default().to_string(),
+            ),
+            ("sequence_number", self.sequence_number.to_string()),
+            (
+                "relative_sequence_number",
+                self.relative_sequence_number
+                    .unwrap_or_default()
+                    .to_string(),
+            ),
+            (
+                "special_label",
+                self.special_label.clone().unwrap_or_default(),
+            ),
+            ("rating", self.rating().unwrap_or_default()),
+            ("release_year", self.release_year.to_string()),
+            ("release_month", self.release_month.to_string()),
+            ("release_day", self.release_day.to_string()),
+            (
+                "intro_start",
+                self.skip_event_time(|e| e.intro.as_ref().map(|i| i.start)),
+            ),
+            (
+                "intro_end",
+                self.skip_event_time(|e| e.intro.as_ref().map(|i| i.end)),
+            ),
+            (
+                "credits_start",
+                self.skip_event_time(|e| e.credits.as_ref().map(|c| c.start)),
+            ),
+            (
+                "credits_end",
+                self.skip_event_time(|e| e.credits.as_ref().map(|c| c.end)),
+            ),
+        ])
+    }
+
+    /// Time in seconds of a skip event, or an empty string if it isn't available.
+    fn skip_event_time(&self, time: impl Fn(&SkipEvents) -> Option<f32>) -> String {
+        self.skip_events
+            .as_ref()
+            .and_then(time)
+            .map_or(String::new(), |t| t.to_string())
+    }
+
+    fn render_path(
+        &self,
+        template: &str,
+        title: &str,
+        universal: bool,
+        language_tagging: Option<&LanguageTagging>,
+    ) -> PathBuf {
+        let variables = self.template_variables(title, language_tagging);
+        let path = fill_template(template, &variables, |value| {
+            sanitize(value, true, universal)
+        });
 
         let mut path = PathBuf::from(path);
 
@@ -590,15 +1109,193 @@ impl Format {
                 .join(", ")
         );
         tab_info!("Resolution: {}", self.resolution);
+        if !self.codec.is_empty() {
+            tab_info!("Codec: {}", self.codec)
+        }
         tab_info!("FPS: {:.2}", self.fps)
     }
 
     pub fn is_special(&self) -> bool {
-        self.sequence_number == 0.0 || self.sequence_number.fract() != 0.0
+        self.special_label.is_some()
+            || self.sequence_number == 0.0
+            || self.sequence_number.fract() != 0.0
+    }
+
+    /// The maturity rating(s), e.g. `TV-14`.
+    pub fn rating(&self) -> Option<String> {
+        (!self.maturity_ratings.is_empty()).then(|| self.maturity_ratings.join(", "))
+    }
+
+    /// Moves specials into season 0, like media servers expect them. The episode number is taken
+    /// from the special label if it contains one (e.g. `SP3` becomes episode 3), so the numbering
+    /// may have gaps.
+    pub fn move_special_to_season_zero(&mut self) {
+        if !self.is_special() {
+            return;
+        }
+        self.season_number = 0;
+        if let Some(number) = self
+            .special_label
+            .as_ref()
+            .and_then(|l| SPECIAL_LABEL_NUMBER_REGEX.find(l))
+        {
+            self.episode_number = number.as_str().trim_start_matches('0').to_string();
+            if self.episode_number.is_empty() {
+                self.episode_number = "0".to_string()
+            }
+        }
+    }
+
+    /// Applies the given cleanup rules to the series and season name.
+    pub fn clean_titles(&mut self, title_cleanup: &TitleCleanup) {
+        self.series_name = title_cleanup.clean(&self.series_name);
+        self.season_title = title_cleanup.clean(&self.season_title);
     }
 
     pub fn has_relative_fmt<S: AsRef<str>>(s: S) -> bool {
-        return s.as_ref().contains("{relative_episode_number}")
-            || s.as_ref().contains("{relative_sequence_number}");
+        TEMPLATE_REGEX
+            .captures_iter(s.as_ref())
+            .any(|c| ["relative_episode_number", "relative_sequence_number"].contains(&&c["name"]))
+    }
+}
+
+/// Regex rules whose matches are removed from series and season names.
+#[derive(Clone, Default)]
+pub struct TitleCleanup(Vec<Regex>);
+
+impl TitleCleanup {
+    pub fn new(builtin: bool, custom: Vec<Regex>) -> Self {
+        let mut rules = vec![];
+        if builtin {
+            rules.extend([
+                // dub suffixes, e.g. "(English Dub)" or "(Castilian Dub)"
+                Regex::new(r"(?i)\s*\([\w\s-]*\bdub\)").unwrap(),
+                // language suffixes, e.g. "(Russian)" or "(Portuguese)"
+                Regex::new(r"\s*\((?:English|German|French|Spanish|Castilian|Latin American Spanish|Portuguese|Brazilian Portuguese|Italian|Russian|Arabic|Hindi)\)").unwrap(),
+                // split cour suffixes, e.g. "Season 2 Part 2" or "Part 2"
+                Regex::new(r"(?i)\s*[-:]?\s*(?:Season\s+\d+\s+)?(?:Part|Cour)\s+\d+$").unwrap(),
+            ])
+        }
+        rules.extend(custom);
+        Self(rules)
+    }
+
+    pub fn clean(&self, title: &str) -> String {
+        let mut title = title.to_string();
+        for rule in &self.0 {
+            title = rule.replace_all(&title, "").to_string()
+        }
+        title.trim().to_string()
+    }
+}
+
+/// Removes invisible characters from a title before it's used in file names or metadata, see
+/// [`INVISIBLE_TITLE_CHARS`]. Control characters, e.g. line breaks, are replaced with a space.
+fn sanitize_title(title: &str) -> String {
+    let title = INVISIBLE_TITLE_CHARS.replace_all(title, "");
+    TITLE_WHITESPACE.replace_all(&title, " ").trim().to_string()
+}
+
+/// Length of the path including the current directory if the path is relative.
+fn full_path_length(path: &Path) -> usize {
+    if path.is_absolute() {
+        path.to_string_lossy().chars().count()
+    } else {
+        env::current_dir()
+            .unwrap_or_default()
+            .join(path)
+            .to_string_lossy()
+            .chars()
+            .count()
+    }
+}
+
+/// Replaces all template options in `template` with their value in `variables`. `finish` is
+/// applied to every value after padding and template functions.
+fn fill_template(
+    template: &str,
+    variables: &HashMap<&str, String>,
+    finish: impl Fn(String) -> String,
+) -> String {
+    TEMPLATE_REGEX
+        .replace_all(template, |captures: &Captures| {
+            let Some(value) = variables.get(&captures["name"]) else {
+                return captures[0].to_string();
+            };
+            let mut value = value.clone();
+            if PADDED_TEMPLATE_OPTIONS.contains(&&captures["name"]) {
+                let width = captures
+                    .name("width")
+                    .map_or(DEFAULT_PADDING_WIDTH, |w| w.as_str().parse().unwrap_or(0));
+                value = format!("{:0>1$}", value, width)
+            } else if captures.name("width").is_some() {
+                warn!("Template option {} cannot be padded", &captures["name"]);
+                return captures[0].to_string();
+            }
+            for function in captures["functions"].split('|').skip(1) {
+                match apply_template_function(&value, function) {
+                    Some(result) => value = result,
+                    None => {
+                        warn!("Invalid template function: {}", function.trim());
+                        return captures[0].to_string();
+                    }
+                }
+            }
+            finish(value)
+        })
+        .to_string()
+}
+
+/// Applies a template function like `upper` or `replace(':','-')` to the given value. Returns
+/// `None` if the function is unknown or has invalid arguments.
+fn apply_template_function(value: &str, function: &str) -> Option<String> {
+    let function = function.trim();
+    let (name, args) = match function.split_once('(') {
+        Some((name, args)) => (
+            name.trim(),
+            parse_template_function_args(args.strip_suffix(')')?)?,
+        ),
+        None => (function, vec![]),
+    };
+
+    match (name, args.as_slice()) {
+        ("upper", []) => Some(value.to_uppercase()),
+        ("lower", []) => Some(value.to_lowercase()),
+        ("replace", [from, to]) => Some(value.replace(from.as_str(), to)),
+        ("slice", [start]) => Some(value.chars().skip(start.parse().ok()?).collect()),
+        ("slice", [start, end]) => {
+            let (start, end): (usize, usize) = (start.parse().ok()?, end.parse().ok()?);
+            Some(value.chars().take(end).skip(start).collect())
+        }
+        _ => None,
+    }
+}
+
+/// Splits comma separated template function arguments. Arguments may be quoted with `'` or `"`
+/// to contain commas or surrounding whitespace.
+fn parse_template_function_args(args: &str) -> Option<Vec<String>> {
+    let mut result = vec![];
+    let mut chars = args.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(&c) = chars.peek() else { break };
+
+        let arg = if c == '\'' || c == '"' {
+            chars.next();
+            let arg: String = chars.by_ref().take_while(|n| *n != c).collect();
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            arg
+        } else {
+            let arg: String = chars.by_ref().take_while(|n| *n != ',').collect();
+            result.push(arg.trim().to_string());
+            continue;
+        };
+        result.push(arg);
+
+        match chars.next() {
+            Some(',') | None => (),
+            Some(_) => return None,
+        }
     }
+    Some(result)
 }
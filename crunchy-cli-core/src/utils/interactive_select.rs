@@ -54,16 +54,20 @@ pub fn check_for_duplicated_seasons(seasons: &mut Vec<Season>) {
 }
 
 pub fn select(prompt: &str, input: Vec<String>) -> Vec<usize> {
+    let defaults = vec![true; input.len()];
+    select_with_defaults(prompt, input, &defaults)
+}
+
+/// Like [`select`], but only the items whose entry in `defaults` is `true` are preselected.
+pub fn select_with_defaults(prompt: &str, input: Vec<String>, defaults: &[bool]) -> Vec<usize> {
     if input.is_empty() {
         return vec![];
     }
 
-    let def: Vec<bool> = (0..input.len()).map(|_| true).collect();
-
     let selection = MultiSelect::new()
         .with_prompt(prompt)
         .items(&input[..])
-        .defaults(&def[..])
+        .defaults(defaults)
         .clear(false)
         .report(false)
         .interact_on(&Term::stdout())
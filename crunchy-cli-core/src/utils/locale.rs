@@ -120,6 +120,41 @@ fn ietf_language_codes<'a>() -> Vec<(&'a str, Vec<Locale>)> {
     ]
 }
 
+/// The ISO 639-2 code of the locale, as used by the `ChapterLanguage` element of matroska files.
+pub fn iso_639_2_code(locale: &Locale) -> Option<&'static str> {
+    let codes = ietf_language_codes();
+    // a locale may be listed under multiple tags (e.g. 'hi-IN'), the tag which lists it first is
+    // the most accurate one
+    let (tag, _) = codes
+        .iter()
+        .find(|(_, l)| l.first() == Some(locale))
+        .or_else(|| codes.iter().find(|(_, l)| l.contains(locale)))?;
+    Some(match *tag {
+        "ar" => "ara",
+        "ca" => "cat",
+        "de" => "ger",
+        "en" => "eng",
+        "es" => "spa",
+        "fr" => "fre",
+        "hi" => "hin",
+        "id" => "ind",
+        "it" => "ita",
+        "ja" => "jpn",
+        "ko" => "kor",
+        "ms" => "may",
+        "pl" => "pol",
+        "pt" => "por",
+        "ru" => "rus",
+        "ta" => "tam",
+        "te" => "tel",
+        "th" => "tha",
+        "tr" => "tur",
+        "vi" => "vie",
+        "zh" => "chi",
+        _ => return None,
+    })
+}
+
 /// Return the locale of the system.
 pub fn system_locale() -> Locale {
     if let Some(system_locale) = sys_locale::get_locale() {
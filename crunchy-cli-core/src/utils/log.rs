@@ -1,13 +1,466 @@
-use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use dialoguer::console::{self, style, Term};
+use indicatif::{
+    HumanBytes, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle,
+    WeakProgressBar,
+};
+use lazy_static::lazy_static;
 use log::{
     info, set_boxed_logger, set_max_level, Level, LevelFilter, Log, Metadata, Record,
     SetLoggerError,
 };
-use std::io::{stdout, Write};
-use std::sync::Mutex;
+use std::collections::VecDeque;
+use std::io::{stderr, stdout, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Once};
 use std::thread;
 use std::time::Duration;
 
+lazy_static! {
+    static ref MULTI_PROGRESS: MultiProgress =
+        MultiProgress::with_draw_target(output_draw_target());
+    static ref OVERALL_PROGRESS: Mutex<Option<ProgressBar>> = Mutex::new(None);
+    static ref STATUS_PROGRESS: Mutex<Vec<WeakProgressBar>> = Mutex::new(vec![]);
+    static ref RECENT_LINES: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+    static ref SECRETS: Mutex<Vec<String>> = Mutex::new(vec![]);
+    static ref TERMINAL_TITLE: Mutex<TerminalTitle> = Mutex::new(TerminalTitle::default());
+    static ref COMPACT: bool = output_term()
+        .size_checked()
+        .is_some_and(|(_, width)| width < COMPACT_WIDTH);
+}
+static OUTPUT_TO_STDERR: AtomicBool = AtomicBool::new(false);
+static PROGRESS_ENABLED: AtomicBool = AtomicBool::new(true);
+static PROGRESS_DRAWN: AtomicBool = AtomicBool::new(true);
+static STATUS_THREAD: Once = Once::new();
+static TERMINAL_TITLE_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Interval in which the status of progress bars is printed if they're not drawn.
+const STATUS_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Terminals with less columns use the compact theme.
+const COMPACT_WIDTH: u16 = 80;
+
+/// Number of log lines which are kept for debug bundles.
+const RECENT_LINES_LIMIT: usize = 500;
+
+/// Prints all log lines and progress bars to stderr instead of stdout. Must be called before the
+/// logger is initialized. Used if the output file is written to stdout or a pipe, which would get
+/// corrupted otherwise.
+pub(crate) fn redirect_output_to_stderr() {
+    OUTPUT_TO_STDERR.store(true, Ordering::Relaxed)
+}
+
+fn output_term() -> Term {
+    if OUTPUT_TO_STDERR.load(Ordering::Relaxed) {
+        Term::stderr()
+    } else {
+        Term::stdout()
+    }
+}
+
+fn output_draw_target() -> ProgressDrawTarget {
+    if OUTPUT_TO_STDERR.load(Ordering::Relaxed) {
+        ProgressDrawTarget::stderr()
+    } else {
+        ProgressDrawTarget::stdout()
+    }
+}
+
+fn print_line(line: &str) {
+    if OUTPUT_TO_STDERR.load(Ordering::Relaxed) {
+        eprintln!("{}", line)
+    } else {
+        println!("{}", line)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err(format!("'{}' is not a valid color mode", s)),
+        }
+    }
+}
+
+pub(crate) fn set_color_mode(mode: &ColorMode) {
+    let enabled = match mode {
+        // `console` already detects if the terminal supports colors
+        ColorMode::Auto => return,
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+    };
+    console::set_colors_enabled(enabled);
+    console::set_colors_enabled_stderr(enabled)
+}
+
+/// Formats a single output line. Warnings and errors are printed to stderr and colored accordingly.
+pub(crate) fn format_line(level: Level, msg: &str) -> String {
+    match level {
+        Level::Error => style(format!(":: {}", msg)).red().for_stderr().to_string(),
+        Level::Warn => style(format!(":: {}", msg))
+            .yellow()
+            .for_stderr()
+            .to_string(),
+        _ => format!("{} {}", style("::").cyan().bold(), msg),
+    }
+}
+
+/// Formats a line which is shown as detail of the previous line.
+pub(crate) fn format_tab(msg: &str) -> String {
+    if log::max_level() == LevelFilter::Debug {
+        msg.to_string()
+    } else if *COMPACT {
+        format!("  {}", msg)
+    } else {
+        format!("\t{}", msg)
+    }
+}
+
+/// Formats a line which is printed while a spinner is active.
+fn format_progress_step(msg: &str) -> String {
+    format!("{} → {}", style("::").cyan().bold(), msg)
+}
+
+fn spinner_style() -> ProgressStyle {
+    #[cfg(not(windows))]
+    let finish_str = "✔";
+    #[cfg(windows)]
+    // windows does not support all unicode characters by default in their consoles, so
+    // we're using this (square root) symbol instead. microsoft.
+    let finish_str = "√";
+
+    ProgressStyle::with_template(&format!(
+        "{} {{spinner:.green}} {{msg}}",
+        style("::").cyan().bold()
+    ))
+    .unwrap()
+    .tick_strings(&["—", "\\", "|", "/", finish_str])
+}
+
+/// Style of spinners which show which parts (e.g. subtitles) are downloaded. `width` is the width the
+/// label is padded to so that it aligns with other progress bars.
+pub(crate) fn labeled_spinner_style(label: &str, width: usize) -> ProgressStyle {
+    let template = if *COMPACT {
+        format!(
+            "{} {} {{wide_msg}} {{spinner:.green}}",
+            style("::").cyan().bold(),
+            label
+        )
+    } else {
+        format!(
+            "{} {:<2$}  {{msg}} {{spinner:.green}}",
+            style("::").cyan().bold(),
+            label,
+            width
+        )
+    };
+    ProgressStyle::with_template(&template)
+        .unwrap()
+        .tick_strings(&["—", "\\", "|", "/", ""])
+}
+
+/// Style of progress bars which show the download progress of a stream.
+/// Like [`download_bar_style`], but shows `speed` (in bytes per second) instead of the speed
+/// indicatif estimates itself.
+pub(crate) fn segment_download_bar_style(speed: Arc<AtomicU64>) -> ProgressStyle {
+    let template = if *COMPACT {
+        format!(
+            "{} {{msg}} [{{wide_bar:.cyan}}] {{percent:>3}}%",
+            style("::").cyan().bold()
+        )
+    } else {
+        format!(
+            "{} {{msg}} {{bytes:>10}} {{speed:>12}} [{{wide_bar:.cyan}}] {{percent:>3}}%",
+            style("::").cyan().bold()
+        )
+    };
+    ProgressStyle::with_template(&template)
+        .unwrap()
+        .with_key(
+            "speed",
+            move |_: &ProgressState, w: &mut dyn std::fmt::Write| {
+                let _ = write!(w, "{}/s", HumanBytes(speed.load(Ordering::Relaxed)));
+            },
+        )
+        .progress_chars("##-")
+}
+
+pub(crate) fn download_bar_style() -> ProgressStyle {
+    let template = if *COMPACT {
+        format!(
+            "{} {{msg}} [{{wide_bar:.cyan}}] {{percent:>3}}%",
+            style("::").cyan().bold()
+        )
+    } else {
+        format!(
+            "{} {{msg}} {{bytes:>10}} {{bytes_per_sec:>12}} [{{wide_bar:.cyan}}] {{percent:>3}}%",
+            style("::").cyan().bold()
+        )
+    };
+    ProgressStyle::with_template(&template)
+        .unwrap()
+        .progress_chars("##-")
+}
+
+/// Style of the progress bar which shows the ffmpeg progress.
+pub(crate) fn ffmpeg_bar_style() -> ProgressStyle {
+    ProgressStyle::with_template(&format!(
+        "{} {{msg}} [{{wide_bar:.cyan}}] {{percent:>3}}%",
+        style("::").cyan().bold()
+    ))
+    .unwrap()
+    .progress_chars("##-")
+}
+
+fn overall_bar_style() -> ProgressStyle {
+    let template = if *COMPACT {
+        format!(
+            "{} {{pos}}/{{len}} [{{wide_bar:.green}}] ETA {{eta}}",
+            style("::").cyan().bold()
+        )
+    } else {
+        format!(
+            "{} Episode {{pos}}/{{len}} done [{{wide_bar:.green}}] {{elapsed_precise}} (ETA {{eta}})",
+            style("::").cyan().bold()
+        )
+    };
+    ProgressStyle::with_template(&template)
+        .unwrap()
+        .progress_chars("##-")
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ProgressMode {
+    Auto,
+    On,
+    Off,
+}
+
+impl ProgressMode {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "on" => Ok(Self::On),
+            "off" => Ok(Self::Off),
+            _ => Err(format!("'{}' is not a valid progress mode", s)),
+        }
+    }
+}
+
+pub(crate) fn set_progress_mode(mode: &ProgressMode) {
+    PROGRESS_ENABLED.store(mode != &ProgressMode::Off, Ordering::Relaxed);
+    PROGRESS_DRAWN.store(
+        match mode {
+            // drawing progress bars into a file or pipe (e.g. when running via cron) only produces
+            // a mess of control characters
+            ProgressMode::Auto => output_term().is_term(),
+            ProgressMode::On => true,
+            ProgressMode::Off => false,
+        },
+        Ordering::Relaxed,
+    )
+}
+
+/// If progress bars and spinners should be created. They may still not be drawn, see
+/// [`add_progress_bar`].
+pub(crate) fn progress_enabled() -> bool {
+    PROGRESS_ENABLED.load(Ordering::Relaxed) && log::max_level() == LevelFilter::Info
+}
+
+fn progress_drawn() -> bool {
+    progress_enabled() && PROGRESS_DRAWN.load(Ordering::Relaxed)
+}
+
+/// Adds a progress bar to the terminal output. If an overall progress bar exists, the bar is placed
+/// above it, so the overall progress always stays at the bottom. If progress bars aren't drawn,
+/// the status of the bar is printed periodically instead.
+pub(crate) fn add_progress_bar(pb: ProgressBar) -> ProgressBar {
+    if !progress_drawn() {
+        pb.set_draw_target(ProgressDrawTarget::hidden());
+        report_status(&pb);
+        return pb;
+    }
+
+    match &*OVERALL_PROGRESS.lock().unwrap() {
+        Some(overall) => MULTI_PROGRESS.insert_before(overall, pb),
+        None => MULTI_PROGRESS.add(pb),
+    }
+}
+
+fn report_status(pb: &ProgressBar) {
+    STATUS_PROGRESS.lock().unwrap().push(pb.downgrade());
+
+    STATUS_THREAD.call_once(|| {
+        thread::spawn(|| loop {
+            thread::sleep(STATUS_INTERVAL);
+
+            let mut status_progress = STATUS_PROGRESS.lock().unwrap();
+            status_progress.retain(|weak_pb| {
+                let Some(pb) = weak_pb.upgrade() else {
+                    return false;
+                };
+                if pb.is_finished() {
+                    return false;
+                }
+                print_line(&format_status(&pb));
+                true
+            })
+        });
+    })
+}
+
+/// Formats the status of a progress bar which isn't drawn as single line.
+fn format_status(pb: &ProgressBar) -> String {
+    let msg = pb.message();
+    let status = match pb.length() {
+        Some(len) if len > 0 => format!("{} {}%", msg.trim_end(), pb.position() * 100 / len),
+        _ => msg.trim_end().to_string(),
+    };
+    format_timestamped(&status)
+}
+
+fn format_timestamped(msg: &str) -> String {
+    format!(
+        "[{}] {} {}",
+        chrono::Utc::now().format("%Y-%m-%d %H:%M:%S"),
+        style("::").cyan().bold(),
+        msg
+    )
+}
+
+/// Shows how many episodes of a run are already processed. The progress bar gets removed when this
+/// struct is dropped.
+pub(crate) struct OverallProgress;
+
+impl OverallProgress {
+    pub(crate) fn new(len: usize) -> Self {
+        if progress_enabled() && len > 1 {
+            let pb = ProgressBar::new(len as u64)
+                .with_style(overall_bar_style())
+                .with_message("Overall progress");
+            let pb = if progress_drawn() {
+                MULTI_PROGRESS.add(pb)
+            } else {
+                add_progress_bar(pb)
+            };
+            pb.enable_steady_tick(Duration::from_secs(1));
+            *OVERALL_PROGRESS.lock().unwrap() = Some(pb)
+        }
+        update_terminal_title(|title| title.overall = Some((0, len)));
+        Self
+    }
+
+    pub(crate) fn set_position(&self, pos: usize) {
+        if let Some(pb) = &*OVERALL_PROGRESS.lock().unwrap() {
+            pb.set_position(pos as u64)
+        }
+        update_terminal_title(|title| {
+            if let Some((done, _)) = &mut title.overall {
+                *done = pos
+            }
+        })
+    }
+}
+
+impl Drop for OverallProgress {
+    fn drop(&mut self) {
+        if let Some(pb) = OVERALL_PROGRESS.lock().unwrap().take() {
+            pb.finish_and_clear()
+        }
+        update_terminal_title(|title| title.overall = None)
+    }
+}
+
+/// Content of the terminal window title, see [`enable_terminal_title`].
+#[derive(Default)]
+struct TerminalTitle {
+    episode: Option<String>,
+    percent: Option<u64>,
+    overall: Option<(usize, usize)>,
+    /// The last written title, so that it is only written again if it has changed.
+    written: String,
+}
+
+impl TerminalTitle {
+    fn format(&self) -> String {
+        let Some(episode) = &self.episode else {
+            return String::new();
+        };
+        let mut title = "crunchy-cli: ".to_string();
+        if let Some((done, total)) = self.overall {
+            title.push_str(&format!("[{}/{}] ", (done + 1).min(total), total))
+        }
+        title.push_str(episode);
+        if let Some(percent) = self.percent {
+            title.push_str(&format!(" - {}%", percent))
+        }
+        // control characters would end the escape sequence early
+        title.retain(|c| !c.is_control());
+        title
+    }
+}
+
+/// The terminal the title is written to. Either stream works, but only if it's a terminal and not
+/// redirected into a file or pipe.
+fn title_term() -> Option<Term> {
+    [Term::stderr(), Term::stdout()]
+        .into_iter()
+        .find(|term| term.is_term())
+}
+
+fn update_terminal_title(f: impl FnOnce(&mut TerminalTitle)) {
+    if !TERMINAL_TITLE_ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut terminal_title = TERMINAL_TITLE.lock().unwrap();
+    f(&mut terminal_title);
+    let title = terminal_title.format();
+    if title != terminal_title.written {
+        if let Some(term) = title_term() {
+            let _ = term.write_str(&format!("\x1b]0;{}\x07", title));
+        }
+        terminal_title.written = title
+    }
+}
+
+/// Shows the currently downloaded episode and its progress in the terminal window title.
+pub(crate) fn enable_terminal_title() {
+    TERMINAL_TITLE_ENABLED.store(true, Ordering::Relaxed)
+}
+
+/// Sets the episode which is shown in the terminal window title. If multiple episodes are
+/// downloaded concurrently, the title shows the one which was started last.
+pub(crate) fn set_terminal_title_episode(episode: &str) {
+    update_terminal_title(|title| {
+        title.episode = Some(episode.to_string());
+        title.percent = None
+    })
+}
+
+/// Sets the download progress of the current episode in the terminal window title.
+pub(crate) fn set_terminal_title_percent(percent: u64) {
+    update_terminal_title(|title| title.percent = Some(percent.min(100)))
+}
+
+/// Resets the terminal window title. Must be called before exiting, as the terminal otherwise keeps
+/// showing the last progress.
+pub(crate) fn clear_terminal_title() {
+    update_terminal_title(|title| {
+        title.episode = None;
+        title.percent = None;
+        title.overall = None
+    })
+}
+
 pub struct ProgressHandler {
     pub(crate) stopped: bool,
 }
@@ -37,6 +490,16 @@ macro_rules! progress {
 }
 pub(crate) use progress;
 
+/// Shows the current step of the running `progress!` spinner next to its message.
+macro_rules! progress_update {
+    ($($arg:tt)+) => {
+        {
+            log::info!(target: "progress_update", $($arg)+)
+        }
+    }
+}
+pub(crate) use progress_update;
+
 macro_rules! progress_pause {
     () => {
         {
@@ -48,18 +511,51 @@ pub(crate) use progress_pause;
 
 macro_rules! tab_info {
     ($($arg:tt)+) => {
-        if log::max_level() == log::LevelFilter::Debug {
-            info!($($arg)+)
-        } else {
-            info!("\t{}", format!($($arg)+))
-        }
+        info!("{}", $crate::utils::log::format_tab(&format!($($arg)+)))
     }
 }
 pub(crate) use tab_info;
 
+/// Hides `secret` in every following log line.
+pub(crate) fn hide_secret(secret: &str) {
+    if !secret.is_empty() {
+        SECRETS.lock().unwrap().push(secret.to_string())
+    }
+}
+
+/// Replaces all secrets which were registered via [`hide_secret`] in `text`.
+pub(crate) fn scrub(text: &str) -> String {
+    let mut text = text.to_string();
+    for secret in SECRETS.lock().unwrap().iter() {
+        text = text.replace(secret, "***")
+    }
+    text
+}
+
+fn remember_line(record: &Record, msg: &str) {
+    let mut lines = RECENT_LINES.lock().unwrap();
+    if lines.len() == RECENT_LINES_LIMIT {
+        lines.pop_front();
+    }
+    lines.push_back(format!(
+        "[{}] {} {}",
+        chrono::Utc::now().format("%Y-%m-%d %H:%M:%S"),
+        record.level(),
+        msg
+    ))
+}
+
+/// The most recent log lines, oldest first.
+pub(crate) fn recent_lines() -> Vec<String> {
+    RECENT_LINES.lock().unwrap().iter().cloned().collect()
+}
+
 pub struct CliLogger {
     level: LevelFilter,
     progress: Mutex<Option<ProgressBar>>,
+    /// Message the current spinner was started with, steps of `progress_update!` are appended to
+    /// it.
+    progress_message: Mutex<String>,
 }
 
 impl Log for CliLogger {
@@ -70,38 +566,39 @@ impl Log for CliLogger {
     fn log(&self, record: &Record) {
         if !self.enabled(record.metadata())
             || (record.target() != "progress"
+                && record.target() != "progress_update"
                 && record.target() != "progress_pause"
                 && record.target() != "progress_end"
                 && !record.target().starts_with("crunchy_cli"))
         {
             return;
         }
+        let msg = scrub(&record.args().to_string());
+        if record.target() != "progress_pause" {
+            remember_line(record, &msg)
+        }
 
         if self.level >= LevelFilter::Debug {
-            self.extended(record);
+            self.extended(record, &msg);
             return;
         }
 
         match record.target() {
-            "progress" => self.progress(record, false),
-            "progress_pause" => {
-                let progress = self.progress.lock().unwrap();
-                if let Some(p) = &*progress {
-                    p.set_draw_target(if p.is_hidden() {
-                        ProgressDrawTarget::stdout()
-                    } else {
-                        ProgressDrawTarget::hidden()
-                    })
-                }
-            }
-            "progress_end" => self.progress(record, true),
+            "progress" => self.progress(&msg, false),
+            "progress_update" => self.progress_update(&msg),
+            "progress_pause" => MULTI_PROGRESS.set_draw_target(if MULTI_PROGRESS.is_hidden() {
+                output_draw_target()
+            } else {
+                ProgressDrawTarget::hidden()
+            }),
+            "progress_end" => self.progress(&msg, true),
             _ => {
                 if self.progress.lock().unwrap().is_some() {
-                    self.progress(record, false)
+                    self.progress(&msg, false)
                 } else if record.level() > Level::Warn {
-                    self.normal(record)
+                    self.normal(record.level(), &msg)
                 } else {
-                    self.error(record)
+                    self.error(record.level(), &msg)
                 }
             }
         }
@@ -109,6 +606,7 @@ impl Log for CliLogger {
 
     fn flush(&self) {
         let _ = stdout().flush();
+        let _ = stderr().flush();
     }
 }
 
@@ -117,6 +615,7 @@ impl CliLogger {
         Self {
             level,
             progress: Mutex::new(None),
+            progress_message: Mutex::new(String::new()),
         }
     }
 
@@ -125,8 +624,8 @@ impl CliLogger {
         set_boxed_logger(Box::new(CliLogger::new(level)))
     }
 
-    fn extended(&self, record: &Record) {
-        println!(
+    fn extended(&self, record: &Record, msg: &str) {
+        print_line(&format!(
             "[{}] {}  {} ({}) {}",
             chrono::Utc::now().format("%Y-%m-%d %H:%M:%S"),
             record.level(),
@@ -135,52 +634,59 @@ impl CliLogger {
                 .target()
                 .replacen("crunchy_cli_core", "crunchy_cli", 1)
                 .replacen("progress_end", "crunchy_cli", 1)
+                .replacen("progress_update", "crunchy_cli", 1)
                 .replacen("progress", "crunchy_cli", 1),
             format!("{:?}", thread::current().id())
                 .replace("ThreadId(", "")
                 .replace(')', ""),
-            record.args()
-        )
+            msg
+        ))
     }
 
-    fn normal(&self, record: &Record) {
-        println!(":: {}", record.args())
+    fn normal(&self, level: Level, msg: &str) {
+        MULTI_PROGRESS.suspend(|| print_line(&format_line(level, msg)))
     }
 
-    fn error(&self, record: &Record) {
-        eprintln!(":: {}", record.args())
+    fn error(&self, level: Level, msg: &str) {
+        MULTI_PROGRESS.suspend(|| eprintln!("{}", format_line(level, msg)))
     }
 
-    fn progress(&self, record: &Record, stop: bool) {
+    fn progress(&self, msg: &str, stop: bool) {
         let mut progress = self.progress.lock().unwrap();
 
-        let msg = format!("{}", record.args());
-        if stop && progress.is_some() {
+        if !progress_drawn() {
+            if !msg.is_empty() {
+                MULTI_PROGRESS.suspend(|| print_line(&format_timestamped(msg)))
+            }
+        } else if stop && progress.is_some() {
             if msg.is_empty() {
                 progress.take().unwrap().finish()
             } else {
-                progress.take().unwrap().finish_with_message(msg)
+                progress
+                    .take()
+                    .unwrap()
+                    .finish_with_message(msg.to_string())
             }
         } else if let Some(p) = &*progress {
-            p.println(format!(":: → {}", msg))
+            p.println(format_progress_step(msg))
         } else {
-            #[cfg(not(windows))]
-            let finish_str = "✔";
-            #[cfg(windows)]
-            // windows does not support all unicode characters by default in their consoles, so
-            // we're using this (square root) symbol instead. microsoft.
-            let finish_str = "√";
-
-            let pb = ProgressBar::new_spinner();
-            pb.set_style(
-                ProgressStyle::with_template(":: {spinner} {msg}")
-                    .unwrap()
-                    .tick_strings(&["—", "\\", "|", "/", finish_str]),
-            );
-            pb.set_draw_target(ProgressDrawTarget::stdout());
+            let pb = add_progress_bar(ProgressBar::new_spinner().with_style(spinner_style()));
             pb.enable_steady_tick(Duration::from_millis(200));
-            pb.set_message(msg);
+            pb.set_message(msg.to_string());
+            *self.progress_message.lock().unwrap() = msg.to_string();
             *progress = Some(pb)
         }
     }
+
+    fn progress_update(&self, msg: &str) {
+        if !progress_drawn() {
+            MULTI_PROGRESS.suspend(|| print_line(&format_timestamped(msg)))
+        } else if let Some(p) = &*self.progress.lock().unwrap() {
+            p.set_message(format!(
+                "{} - {}",
+                self.progress_message.lock().unwrap(),
+                msg
+            ))
+        }
+    }
 }
@@ -1,6 +1,14 @@
+pub mod chapter_file;
 pub mod clap;
+pub mod clip;
+pub mod config;
 pub mod context;
+pub mod debug_bundle;
+pub mod destination;
 pub mod download;
+pub mod download_archive;
+pub mod error;
+pub mod exec;
 pub mod ffmpeg;
 pub mod filter;
 pub mod fmt;
@@ -8,8 +16,16 @@ pub mod format;
 pub mod interactive_select;
 pub mod locale;
 pub mod log;
+pub mod nfo;
+pub mod notify;
 pub mod os;
 pub mod parse;
+pub mod proxy_pool;
 pub mod rate_limit;
+pub mod schedule;
+pub mod segment_recording;
+pub mod size_budget;
+pub mod skip_file;
 pub mod sync;
+pub mod timeout;
 pub mod video;
@@ -0,0 +1,140 @@
+use crate::utils::format::Format;
+use anyhow::Result;
+use lazy_static::lazy_static;
+use log::debug;
+use regex::Regex;
+use std::fs;
+use std::path::Path;
+
+lazy_static! {
+    /// Directory names which media servers recognize as season directories, e.g. `Season 01`.
+    static ref SEASON_DIRECTORY_REGEX: Regex =
+        Regex::new(r"(?i)^(season|staffel|series|s)[\s._-]*\d+$").unwrap();
+}
+
+/// Writes the nfo files which media servers like Kodi or Jellyfin read their metadata from. For
+/// episodes, these are `<video name>.nfo` next to the video and `season.nfo` and `tvshow.nfo` in
+/// the season and series directory. The season and series files are only written if they don't
+/// exist yet. For movies and other videos only `<video name>.nfo` is written.
+pub fn write_nfo(video: &Path, format: &Format, is_episode: bool) -> Result<()> {
+    let path = video.with_extension("nfo");
+    if !is_episode {
+        write(&path, &movie_nfo(format))?;
+        return Ok(());
+    }
+    write(&path, &episode_nfo(format))?;
+
+    let season_directory = video.parent().unwrap_or(Path::new(""));
+    let season_nfo = season_directory.join("season.nfo");
+    if !season_nfo.exists() {
+        write(&season_nfo, &season_nfo_content(format))?
+    }
+
+    // if the episodes aren't stored in a season directory, the series and seasons share one
+    // directory
+    let series_directory = if season_directory
+        .file_name()
+        .is_some_and(|name| SEASON_DIRECTORY_REGEX.is_match(&name.to_string_lossy()))
+    {
+        season_directory.parent().unwrap_or(season_directory)
+    } else {
+        season_directory
+    };
+    let tvshow_nfo = series_directory.join("tvshow.nfo");
+    if !tvshow_nfo.exists() {
+        write(&tvshow_nfo, &tvshow_nfo_content(format))?
+    }
+
+    Ok(())
+}
+
+/// Escapes the characters which aren't allowed in xml text.
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn write(path: &Path, content: &str) -> Result<()> {
+    fs::write(path, content)?;
+    debug!("Wrote nfo file {}", path.to_string_lossy());
+    Ok(())
+}
+
+fn episode_nfo(format: &Format) -> String {
+    let mut elements = vec![
+        element("title", &format.title),
+        element("showtitle", &format.series_name),
+        element("season", &format.season_number.to_string()),
+        element("episode", &format.episode_number),
+        element("plot", &format.description),
+        element("aired", &release_date(format)),
+        unique_id(&format.episode_id),
+    ];
+    if let Some(rating) = format.rating() {
+        elements.push(element("mpaa", &rating))
+    }
+    document("episodedetails", elements)
+}
+
+fn season_nfo_content(format: &Format) -> String {
+    document(
+        "season",
+        vec![
+            element("title", &format.season_title),
+            element("seasonnumber", &format.season_number.to_string()),
+            unique_id(&format.season_id),
+        ],
+    )
+}
+
+fn tvshow_nfo_content(format: &Format) -> String {
+    let mut elements = vec![
+        element("title", &format.series_name),
+        unique_id(&format.series_id),
+    ];
+    if let Some(rating) = format.rating() {
+        elements.push(element("mpaa", &rating))
+    }
+    document("tvshow", elements)
+}
+
+fn movie_nfo(format: &Format) -> String {
+    let mut elements = vec![
+        element("title", &format.title),
+        element("plot", &format.description),
+        element("premiered", &release_date(format)),
+        element("year", &format.release_year.to_string()),
+        unique_id(&format.episode_id),
+    ];
+    if let Some(rating) = format.rating() {
+        elements.push(element("mpaa", &rating))
+    }
+    document("movie", elements)
+}
+
+fn release_date(format: &Format) -> String {
+    format!(
+        "{:04}-{:02}-{:02}",
+        format.release_year, format.release_month, format.release_day
+    )
+}
+
+fn element(name: &str, value: &str) -> String {
+    format!("  <{0}>{1}</{0}>", name, escape_xml(value))
+}
+
+fn unique_id(id: &str) -> String {
+    format!(
+        r#"  <uniqueid type="crunchyroll" default="true">{}</uniqueid>"#,
+        escape_xml(id)
+    )
+}
+
+fn document(root: &str, elements: Vec<String>) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<{0}>\n{1}\n</{0}>\n",
+        root,
+        elements.join("\n")
+    )
+}
@@ -0,0 +1,79 @@
+use crate::utils::format::SingleFormat;
+use anyhow::Result;
+use log::{debug, warn};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Client;
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Payload which is sent to `--notify-webhook` after an episode finished.
+#[derive(Serialize)]
+struct EpisodeNotification {
+    series_name: String,
+    season_number: u32,
+    episode_number: String,
+    title: String,
+    episode_id: String,
+    /// Output file, `None` if the download failed.
+    path: Option<String>,
+    /// Length of the episode in seconds.
+    duration: f64,
+    /// Size of the output file in bytes.
+    size: Option<u64>,
+    /// Time the download took in seconds.
+    download_time: f64,
+    success: bool,
+    error: Option<String>,
+}
+
+/// Posts the result of an episode download as json to `url`. `result` is the output path of the
+/// episode, skipped episodes (`Ok(None)`) aren't sent. A failed request only prints a warning, it
+/// must not affect the download.
+pub async fn notify_webhook(
+    client: &Client,
+    url: &str,
+    single_format: &SingleFormat,
+    result: &Result<Option<PathBuf>>,
+    download_time: Duration,
+) {
+    let path = match result {
+        Ok(Some(path)) => Some(path),
+        Ok(None) => return,
+        Err(_) => None,
+    };
+    let notification = EpisodeNotification {
+        series_name: single_format.series_name.clone(),
+        season_number: single_format.season_number,
+        episode_number: single_format.episode_number.clone(),
+        title: single_format.title.clone(),
+        episode_id: single_format.episode_id.clone(),
+        path: path.map(|p| p.to_string_lossy().to_string()),
+        duration: single_format.duration.num_milliseconds() as f64 / 1000.0,
+        size: path.and_then(|p| fs::metadata(p).ok()).map(|m| m.len()),
+        download_time: download_time.as_secs_f64(),
+        success: result.is_ok(),
+        error: result.as_ref().err().map(|e| format!("{:#}", e)),
+    };
+
+    let body = match serde_json::to_string(&notification) {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("Failed to serialize webhook notification: {}", e);
+            return;
+        }
+    };
+    let result = client
+        .post(url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(body)
+        .timeout(Duration::from_secs(30))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status());
+    match result {
+        Ok(_) => debug!("Sent notification for '{}' to webhook", notification.title),
+        Err(e) => warn!("Failed to send notification to webhook: {}", e),
+    }
+}
@@ -1,20 +1,40 @@
+use crate::utils::ffmpeg::ffmpeg_binary;
 use log::debug;
 use regex::{Regex, RegexBuilder};
 use std::borrow::Cow;
+use std::collections::HashSet;
+use std::ffi::OsStr;
 use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::TrySendError;
+use std::sync::Mutex;
 use std::task::{Context, Poll};
 use std::{env, fs, io};
-use tempfile::{Builder, NamedTempFile, TempPath};
+use tempfile::{Builder, NamedTempFile, TempDir, TempPath};
 use tokio::io::{AsyncRead, ReadBuf};
 
 pub fn has_ffmpeg() -> bool {
-    if let Err(e) = Command::new("ffmpeg").stderr(Stdio::null()).spawn() {
+    has_program(ffmpeg_binary())
+}
+
+/// If mkvpropedit (part of MKVToolNix) is installed.
+pub fn has_mkvpropedit() -> bool {
+    has_program("mkvpropedit")
+}
+
+fn has_program<S: AsRef<OsStr>>(name: S) -> bool {
+    if let Err(e) = Command::new(&name)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    {
         if ErrorKind::NotFound != e.kind() {
             debug!(
-                "unknown error occurred while checking if ffmpeg exists: {}",
+                "unknown error occurred while checking if {} exists: {}",
+                name.as_ref().to_string_lossy(),
                 e.kind()
             )
         }
@@ -30,14 +50,57 @@ pub fn temp_directory() -> PathBuf {
     env::var("CRUNCHY_CLI_TEMP_DIR").map_or(env::temp_dir(), PathBuf::from)
 }
 
+/// Prefix of files which store the progress of interrupted downloads. They're kept on ctrl-c so
+/// that the download can be resumed.
+pub const RESUME_FILE_PREFIX: &str = ".crunchy-cli_resume_";
+
+/// Prefix of files which list the kept files of a failed ffmpeg run. They and the files they list
+/// are kept on ctrl-c so that the output file can be generated with `--remux-only`.
+pub const REMUX_FILE_PREFIX: &str = ".crunchy-cli_remux_";
+
 /// Any tempfile should be created with this function. The prefix and directory of every file
 /// created with this function stays the same which is helpful to query all existing tempfiles and
 /// e.g. remove them in a case of ctrl-c. Having one function also good to prevent mistakes like
 /// setting the wrong prefix if done manually.
 pub fn tempfile<S: AsRef<str>>(suffix: S) -> io::Result<NamedTempFile> {
+    tempfile_with_prefix(".crunchy-cli_", suffix.as_ref())
+}
+
+static DESCRIPTIVE_TEMP_NAMES: AtomicBool = AtomicBool::new(false);
+
+/// Makes [`named_tempfile`] add its context to the file names.
+pub fn enable_descriptive_temp_names() {
+    DESCRIPTIVE_TEMP_NAMES.store(true, Ordering::Relaxed)
+}
+
+/// Like [`tempfile`], but if descriptive temp names are enabled (`--descriptive-temp-names`) the
+/// name contains `context`, e.g. `.crunchy-cli_GABC123.ja-JP.audio.a1b2c3.m4a`. This makes it
+/// possible to tell the temp files apart when debugging a failed run.
+pub fn named_tempfile<C: AsRef<str>, S: AsRef<str>>(
+    context: C,
+    suffix: S,
+) -> io::Result<NamedTempFile> {
+    if !DESCRIPTIVE_TEMP_NAMES.load(Ordering::Relaxed) {
+        return tempfile(suffix);
+    }
+    let context: String = context
+        .as_ref()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    tempfile_with_prefix(&format!(".crunchy-cli_{}.", context), suffix.as_ref())
+}
+
+fn tempfile_with_prefix(prefix: &str, suffix: &str) -> io::Result<NamedTempFile> {
     let tempfile = Builder::default()
-        .prefix(".crunchy-cli_")
-        .suffix(suffix.as_ref())
+        .prefix(prefix)
+        .suffix(suffix)
         .tempfile_in(temp_directory())?;
     debug!(
         "Created temporary file: {}",
@@ -46,6 +109,18 @@ pub fn tempfile<S: AsRef<str>>(suffix: S) -> io::Result<NamedTempFile> {
     Ok(tempfile)
 }
 
+/// Like [`tempfile`], but creates a directory which is removed with all its content when dropped.
+pub fn tempdir() -> io::Result<TempDir> {
+    let tempdir = Builder::default()
+        .prefix(".crunchy-cli_")
+        .tempdir_in(temp_directory())?;
+    debug!(
+        "Created temporary directory: {}",
+        tempdir.path().to_string_lossy()
+    );
+    Ok(tempdir)
+}
+
 pub fn cache_dir<S: AsRef<str>>(name: S) -> io::Result<PathBuf> {
     let cache_dir = temp_directory().join(format!(".crunchy-cli_{}_cache", name.as_ref()));
     fs::create_dir_all(&cache_dir)?;
@@ -138,15 +213,117 @@ pub fn temp_named_pipe() -> io::Result<TempNamedPipe> {
     }
 }
 
-/// Check if the given path exists and rename it until the new (renamed) file does not exist.
+/// How many writes a [`TempPipeWriter`] buffers before it doesn't accept any more data. Every
+/// write is usually a whole segment.
+#[cfg(not(target_os = "windows"))]
+const PIPE_BUFFERED_WRITES: usize = 4;
+
+/// Writing end of a named pipe. Everything written to it is passed on to the process which reads
+/// from the pipe, without being stored on the disk. If the reader doesn't keep up, writes fail with
+/// [`ErrorKind::WouldBlock`] until it has consumed some of the buffered data.
+pub struct TempPipeWriter {
+    path: PathBuf,
+    sender: Option<std::sync::mpsc::SyncSender<Vec<u8>>>,
+    writer: Option<tokio::task::JoinHandle<io::Result<()>>>,
+}
+
+impl TempPipeWriter {
+    /// Waits until everything was read from the pipe.
+    pub async fn finish(mut self) -> io::Result<()> {
+        self.sender.take();
+        self.writer.take().unwrap().await?
+    }
+}
+
+impl io::Write for TempPipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // the data is written by a separate thread, as writing to a pipe blocks until the reader
+        // consumes it. the buffer of the thread is limited, so that the caller can stop producing
+        // data instead of holding it in memory while the reader is busy
+        match self.sender.as_ref().unwrap().try_send(buf.to_vec()) {
+            Ok(()) => Ok(buf.len()),
+            Err(TrySendError::Full(_)) => Err(io::Error::from(ErrorKind::WouldBlock)),
+            Err(TrySendError::Disconnected(_)) => Err(io::Error::from(ErrorKind::BrokenPipe)),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for TempPipeWriter {
+    fn drop(&mut self) {
+        self.sender.take();
+        // if the reader never opened the pipe, the writer thread still blocks while opening it.
+        // opening it for reading lets the thread continue and fail on the first write
+        #[cfg(not(target_os = "windows"))]
+        if self.writer.as_ref().is_some_and(|w| !w.is_finished()) {
+            use std::os::unix::fs::OpenOptionsExt;
+            let _ = fs::OpenOptions::new()
+                .read(true)
+                .custom_flags(nix::libc::O_NONBLOCK)
+                .open(&self.path);
+        }
+    }
+}
+
+/// Creates a named pipe in the temp directory and returns its path and writing end.
+pub fn temp_pipe_writer() -> io::Result<(TempPath, TempPipeWriter)> {
+    #[cfg(not(target_os = "windows"))]
+    {
+        let path = tempfile("")?.into_temp_path();
+        let _ = fs::remove_file(&path);
+
+        nix::unistd::mkfifo(
+            path.to_string_lossy().to_string().as_str(),
+            nix::sys::stat::Mode::S_IRWXU,
+        )?;
+
+        let (sender, receiver) = std::sync::mpsc::sync_channel::<Vec<u8>>(PIPE_BUFFERED_WRITES);
+        let fifo = path.to_path_buf();
+        let fifo_path = fifo.clone();
+        let writer = tokio::task::spawn_blocking(move || {
+            use std::io::Write;
+
+            // blocks until the reader opens the pipe
+            let mut file = fs::OpenOptions::new().write(true).open(fifo_path)?;
+            for buf in receiver {
+                file.write_all(&buf)?
+            }
+            Ok(())
+        });
+
+        Ok((
+            path,
+            TempPipeWriter {
+                path: fifo,
+                sender: Some(sender),
+                writer: Some(writer),
+            },
+        ))
+    }
+    #[cfg(target_os = "windows")]
+    {
+        Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "named pipes are not supported on windows",
+        ))
+    }
+}
+
+/// Check if the given path exists and rename it until the new (renamed) file does not exist. The
+/// returned path is reserved, so that episodes which are downloaded concurrently and have the same
+/// name don't write to the same file before it is created.
 pub fn free_file(mut path: PathBuf) -> (PathBuf, bool) {
     // do not rename it if it exists but is a special file
-    if is_special_file(&path) {
+    if is_special_file(&path) || path.to_string_lossy() == "-" {
         return (path, false);
     }
 
+    let mut reserved = RESERVED_FILES.lock().unwrap();
     let mut i = 0;
-    while path.exists() {
+    while path.exists() || reserved.contains(&path) {
         i += 1;
 
         let mut ext = path.extension().unwrap_or_default().to_str().unwrap();
@@ -167,6 +344,7 @@ pub fn free_file(mut path: PathBuf) -> (PathBuf, bool) {
 
         path.set_file_name(format!("{} ({}).{}", filename, i, ext))
     }
+    reserved.insert(path.clone());
     (path, i != 0)
 }
 
@@ -176,6 +354,97 @@ pub fn is_special_file<P: AsRef<Path>>(path: P) -> bool {
     path.as_ref().exists() && !path.as_ref().is_file() && !path.as_ref().is_dir()
 }
 
+/// Filesystem types of network shares.
+const NETWORK_FILESYSTEMS: [&str; 9] = [
+    "cifs",
+    "smb3",
+    "smbfs",
+    "nfs",
+    "nfs4",
+    "afpfs",
+    "webdav",
+    "9p",
+    "fuse.sshfs",
+];
+
+/// Check if the given path is located on a network share (SMB, NFS, ...).
+pub fn is_network_path<P: AsRef<Path>>(path: P) -> bool {
+    let path = path.as_ref();
+    let mut path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir().unwrap_or_default().join(path)
+    };
+    // the mount point of a file which doesn't exist yet is the one of its first existing ancestor
+    while !path.exists() && path.pop() {}
+    let path = path.canonicalize().unwrap_or(path);
+
+    is_network_mount(&path)
+}
+
+/// Check if the drive of an existing, absolute path is a network share. Covers unc paths (e.g.
+/// `\\server\share`) as well as network shares which are mapped to a drive letter.
+#[cfg(windows)]
+fn is_network_mount(path: &Path) -> bool {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::{GetDriveTypeW, GetVolumePathNameW};
+    use windows_sys::Win32::System::WindowsProgramming::DRIVE_REMOTE;
+
+    let path: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+    // the root of the volume, e.g. `C:\` or `\\server\share\`
+    let mut root = [0u16; 1024];
+    // SAFETY: `path` is null terminated and `root` is as long as its given length, the api writes
+    // a null terminated string into it
+    unsafe {
+        if GetVolumePathNameW(path.as_ptr(), root.as_mut_ptr(), root.len() as u32) == 0 {
+            return false;
+        }
+        GetDriveTypeW(root.as_ptr()) == DRIVE_REMOTE
+    }
+}
+
+/// Check if the mount point of an existing, absolute path has a network filesystem.
+#[cfg(not(windows))]
+fn is_network_mount(path: &Path) -> bool {
+    // (mount point, filesystem type)
+    let mounts: Vec<(String, String)> = if cfg!(target_os = "macos") {
+        // lines look like `//user@server/share on /Volumes/share (smbfs, nodev, nosuid, mounted by user)`
+        let Ok(output) = Command::new("mount").output() else {
+            return false;
+        };
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| {
+                let (_, rest) = line.split_once(" on ")?;
+                let (mount_point, options) = rest.rsplit_once(" (")?;
+                let fs_type = options.split(',').next()?;
+                Some((mount_point.to_string(), fs_type.to_string()))
+            })
+            .collect()
+    } else {
+        // lines look like `//server/share /mnt/share cifs rw,relatime 0 0`, spaces in the mount
+        // point are escaped as `\040`
+        let Ok(mounts) = fs::read_to_string("/proc/mounts") else {
+            return false;
+        };
+        mounts
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split(' ');
+                let mount_point = fields.nth(1)?.replace("\\040", " ");
+                let fs_type = fields.next()?;
+                Some((mount_point, fs_type.to_string()))
+            })
+            .collect()
+    };
+
+    mounts
+        .into_iter()
+        .filter(|(mount_point, _)| path.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.len())
+        .is_some_and(|(_, fs_type)| NETWORK_FILESYSTEMS.contains(&fs_type.as_str()))
+}
+
 lazy_static::lazy_static! {
     static ref WINDOWS_NON_PRINTABLE_RE: Regex = Regex::new(r"[\x00-\x1f\x80-\x9f]").unwrap();
     static ref WINDOWS_ILLEGAL_RE: Regex = Regex::new(r#"[<>:"|?*]"#).unwrap();
@@ -188,6 +457,9 @@ lazy_static::lazy_static! {
     static ref LINUX_NON_PRINTABLE: Regex = Regex::new(r"[\x00]").unwrap();
 
     static ref RESERVED_RE: Regex = Regex::new(r"^\.+$").unwrap();
+
+    /// Output files which were chosen by [`free_file`] in this run.
+    static ref RESERVED_FILES: Mutex<HashSet<PathBuf>> = Mutex::new(HashSet::new());
 }
 
 /// Sanitizes a filename with the option to include/exclude the path separator from sanitizing.
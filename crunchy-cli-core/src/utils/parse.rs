@@ -74,6 +74,11 @@ impl UrlFilter {
 /// - `...[S1-S3,S4E2-S4E6]` - Download season 1 to 3 and episode 2 to episode 6 of season 4.
 
 /// In practice, it would look like this: `https://crunchyroll.com/series/12345678/example[S1E5-S3E2]`.
+///
+/// Localized, beta, old style and share urls are supported too, see [`normalize_url`].
+///
+/// Instead of an url, the id of a series / episode / movie can be given, either bare (`GY8VEQ95Y`)
+/// or prefixed with `id:` (`id:GY8VEQ95Y`).
 pub async fn parse_url(
     crunchy: &Crunchyroll,
     mut url: String,
@@ -133,21 +138,45 @@ pub async fn parse_url(
         UrlFilter::default()
     };
 
-    // check if the url is the old series/episode scheme which still occurs in some places (like the
-    // rss)
-    let old_url_regex = Regex::new(r"https?://(www\.)?crunchyroll\.com/.+").unwrap();
-    if old_url_regex.is_match(&url) {
-        debug!("Detected maybe old url");
-        // replace the 'http' prefix with 'https' as http is not supported by the reqwest client
-        if url.starts_with("http://") {
-            url.replace_range(0..4, "https")
+    // automation often only has the ids of series / episodes, so they're accepted directly, either
+    // bare (ids always start with 'G') or prefixed with 'id:'
+    let id_regex =
+        Regex::new(r"^(id:(?P<prefixed>[A-Za-z0-9]+)|(?P<bare>G[A-Z0-9]{7,}))$").unwrap();
+    if let Some(capture) = id_regex.captures(url.trim()) {
+        let id = capture
+            .name("prefixed")
+            .or(capture.name("bare"))
+            .unwrap()
+            .as_str();
+        debug!("Url is an id: {}", id);
+        let media_collection = crunchy
+            .media_collection_from_id(id)
+            .await
+            .map_err(|e| anyhow!("Could not find anything with id '{}': {}", id, e))?;
+        return Ok((media_collection, url_filter));
+    }
+
+    let original_url = url.clone();
+    url = normalize_url(&url);
+    if crunchyroll_rs::parse_url(&url).is_none() {
+        // old style and share links aren't media urls themselves but redirect to one. request the
+        // url, follow the redirects and extract the final url. only urls of known hosts are
+        // requested, everything else is rejected right away
+        if !is_redirecting_url(&url) {
+            bail!("'{}' is no supported Crunchyroll url", original_url)
         }
-        // the old url redirects to the new url. request the old url, follow the redirects and
-        // extract the final url
-        url = crunchy.client().get(&url).send().await?.url().to_string()
+        debug!("Url is no media url, following redirects of {}", url);
+        let response =
+            crunchy.client().get(&url).send().await.map_err(|e| {
+                anyhow!("'{}' is no supported Crunchyroll url ({})", original_url, e)
+            })?;
+        url = normalize_url(response.url().as_str())
     }
 
-    let parsed_url = crunchyroll_rs::parse_url(url).ok_or(anyhow!("Invalid url"))?;
+    let parsed_url = crunchyroll_rs::parse_url(&url).ok_or(anyhow!(
+        "'{}' is no supported Crunchyroll url",
+        original_url
+    ))?;
     debug!("Url type: {:?}", parsed_url);
     let media_collection = match parsed_url {
         UrlType::Series(id)
@@ -160,42 +189,222 @@ pub async fn parse_url(
     Ok((media_collection, url_filter))
 }
 
-/// Parse a resolution given as a [`String`] to a [`crunchyroll_rs::media::Resolution`].
-pub fn parse_resolution(mut resolution: String) -> Result<Resolution> {
-    resolution = resolution.to_lowercase();
+/// Normalize the different variants of Crunchyroll urls. Localized (`/de/watch/...`), beta
+/// (`beta.crunchyroll.com`), mobile (`m.crunchyroll.com`) and app deep links
+/// (`crunchyroll://watch/...`) are converted to the canonical `https://www.crunchyroll.com/...`
+/// url without query parameters. Urls without scheme get `https://` prepended. Old style urls and
+/// share links keep their path, they redirect to the canonical url.
+pub fn normalize_url(url: &str) -> String {
+    let url = url.trim();
+    let url = if let Some(path) = url.strip_prefix("crunchyroll://") {
+        format!(
+            "https://www.crunchyroll.com/{}",
+            path.trim_start_matches('/')
+        )
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        // http is not supported by the reqwest client
+        format!("https://{}", rest)
+    } else if !url.contains("://") {
+        format!("https://{}", url)
+    } else {
+        url.to_string()
+    };
 
-    if resolution == "best" {
-        Ok(Resolution {
-            width: u64::MAX,
-            height: u64::MAX,
-        })
-    } else if resolution == "worst" {
-        Ok(Resolution {
-            width: u64::MIN,
-            height: u64::MIN,
-        })
-    } else if resolution.ends_with('p') {
-        let without_p = resolution.as_str()[0..resolution.len() - 1]
-            .parse()
-            .map_err(|_| anyhow!("Could not find resolution"))?;
-        Ok(Resolution {
-            width: without_p * 16 / 9,
-            height: without_p,
-        })
-    } else if let Some((w, h)) = resolution.split_once('x') {
-        Ok(Resolution {
-            width: w
-                .parse()
-                .map_err(|_| anyhow!("Could not find resolution"))?,
-            height: h
-                .parse()
-                .map_err(|_| anyhow!("Could not find resolution"))?,
-        })
+    let crunchyroll_url_regex = Regex::new(
+        r"^https://(?:(?:www|beta|m)\.)?crunchyroll\.com(?P<path>/[^?#]*)?(?P<rest>[?#].*)?$",
+    )
+    .unwrap();
+    let Some(capture) = crunchyroll_url_regex.captures(&url) else {
+        return url;
+    };
+    let path = capture.name("path").map_or("", |p| p.as_str());
+
+    let media_path_regex = Regex::new(r"^(?:/[a-zA-Z]{2}(?:-(?:[a-zA-Z]{2}|\d{3}))?)?(?P<media>/(?:series|watch|movie_listing|musicvideo|concert|artist)/.+)$").unwrap();
+    if let Some(media_capture) = media_path_regex.captures(path) {
+        format!(
+            "https://www.crunchyroll.com{}",
+            media_capture.name("media").unwrap().as_str()
+        )
     } else {
-        bail!("Could not find resolution")
+        // old style urls are redirected by crunchyroll, which also needs the locale and query to
+        // resolve them
+        format!(
+            "https://www.crunchyroll.com{}{}",
+            path,
+            capture.name("rest").map_or("", |r| r.as_str())
+        )
+    }
+}
+
+/// If the url points to a host which redirects to Crunchyroll media urls, i.e. Crunchyroll itself
+/// (old style urls) or its share link service.
+fn is_redirecting_url(url: &str) -> bool {
+    Regex::new(
+        r"^https://(?:[a-zA-Z0-9-]+\.)*(?:crunchyroll\.com|crunchyroll\.app\.link)(?:[/?#]|$)",
+    )
+    .unwrap()
+    .is_match(url)
+}
+
+/// Dimension of a video a [`ResolutionConstraint`] applies to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Dimension {
+    Width,
+    Height,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+#[derive(Clone, Debug)]
+struct ResolutionConstraint {
+    dimension: Dimension,
+    comparison: Comparison,
+    value: u64,
+}
+
+impl ResolutionConstraint {
+    fn matches(&self, resolution: &Resolution) -> bool {
+        let actual = match self.dimension {
+            Dimension::Width => resolution.width,
+            Dimension::Height => resolution.height,
+        };
+        match self.comparison {
+            Comparison::Less => actual < self.value,
+            Comparison::LessOrEqual => actual <= self.value,
+            Comparison::Equal => actual == self.value,
+            Comparison::GreaterOrEqual => actual >= self.value,
+            Comparison::Greater => actual > self.value,
+        }
     }
 }
 
+/// Selects a video resolution. It consists of constraints every selected resolution must fulfill
+/// and whether the best or worst of the matching resolutions should be used.
+#[derive(Clone, Debug)]
+pub struct ResolutionSelector {
+    input: String,
+    best: bool,
+    constraints: Vec<ResolutionConstraint>,
+}
+
+impl ResolutionSelector {
+    /// If the best (and not the worst) matching resolution should be used.
+    pub fn is_best(&self) -> bool {
+        self.best
+    }
+
+    /// If the resolution fulfills all constraints.
+    pub fn matches(&self, resolution: &Resolution) -> bool {
+        self.constraints.iter().all(|c| c.matches(resolution))
+    }
+}
+
+impl std::fmt::Display for ResolutionSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.input)
+    }
+}
+
+/// Parse a resolution given as a [`String`] to a [`ResolutionSelector`].
+///
+/// Supported are:
+/// - `best` / `worst` - The best / worst resolution available.
+/// - `1080p` / `1920x1080` - Exactly this resolution.
+/// - `<=1080p,>=720p` - Constraints which all must be fulfilled, the best matching resolution is
+///   used. `<`, `<=`, `=`, `>=` and `>` are supported, `best` or `worst` can be added to the list.
+/// - `best[height<=720]` / `worst[width>=1280,height>=720]` - The best / worst resolution whose
+///   width and / or height fulfill the constraints in the brackets.
+pub fn parse_resolution(resolution: String) -> Result<ResolutionSelector> {
+    let input = resolution.trim().to_string();
+    let resolution = input.to_lowercase().replace(' ', "");
+
+    let mut best = None;
+    let terms: Vec<&str> =
+        if let Some(inner) = resolution.strip_suffix(']').and_then(|r| r.split_once('[')) {
+            match inner.0 {
+                "best" => best = Some(true),
+                "worst" => best = Some(false),
+                selector => bail!(
+                    "'{}' is not a valid resolution selector, use 'best' or 'worst'",
+                    selector
+                ),
+            }
+            inner.1.split(',').collect()
+        } else {
+            resolution.split(',').collect()
+        };
+
+    let term_regex = Regex::new(
+        r"^(?P<dimension>width|height)?(?P<comparison><=|>=|==|<|>|=)?(?P<value>\d+p|\d+x\d+|\d+)$",
+    )
+    .unwrap();
+    let mut constraints = vec![];
+    for term in terms {
+        if term == "best" || term == "worst" {
+            if best.replace(term == "best").is_some() {
+                bail!("Only one of 'best' and 'worst' can be used")
+            }
+            continue;
+        }
+        let Some(capture) = term_regex.captures(term) else {
+            bail!("Could not find resolution '{}'", term)
+        };
+        let comparison = match capture.name("comparison").map(|c| c.as_str()) {
+            Some("<") => Comparison::Less,
+            Some("<=") => Comparison::LessOrEqual,
+            Some(">=") => Comparison::GreaterOrEqual,
+            Some(">") => Comparison::Greater,
+            _ => Comparison::Equal,
+        };
+        let value = capture.name("value").unwrap().as_str();
+
+        let parse_number = |n: &str| -> Result<u64> {
+            n.parse()
+                .map_err(|_| anyhow!("Could not find resolution '{}'", term))
+        };
+        let mut push = |dimension, value| {
+            constraints.push(ResolutionConstraint {
+                dimension,
+                comparison,
+                value,
+            })
+        };
+        match (capture.name("dimension").map(|d| d.as_str()), value) {
+            (Some(dimension), value) if !value.contains('x') => push(
+                if dimension == "width" {
+                    Dimension::Width
+                } else {
+                    Dimension::Height
+                },
+                parse_number(value.trim_end_matches('p'))?,
+            ),
+            (None, value) if value.ends_with('p') => push(
+                Dimension::Height,
+                parse_number(value.trim_end_matches('p'))?,
+            ),
+            (None, value) if value.contains('x') => {
+                let (width, height) = value.split_once('x').unwrap();
+                push(Dimension::Width, parse_number(width)?);
+                push(Dimension::Height, parse_number(height)?)
+            }
+            _ => bail!("Could not find resolution '{}'", term),
+        }
+    }
+
+    Ok(ResolutionSelector {
+        input,
+        best: best.unwrap_or(true),
+        constraints,
+    })
+}
+
 /// Dirty implementation of [`f32::fract`] with more accuracy.
 pub fn fract(input: f32) -> f32 {
     if input.fract() == 0.0 {
@@ -0,0 +1,217 @@
+use crate::utils::clap::parse_proxy;
+use anyhow::{bail, Result};
+use crunchyroll_rs::error::Error;
+use log::{debug, warn};
+use reqwest::{Client, Proxy, Request, Response};
+use std::fs;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tower_service::Service;
+
+/// Consecutive failed requests after which a proxy is considered dead and removed from the pool.
+const MAX_PROXY_FAILURES: u32 = 3;
+
+/// When the pool switches to the next proxy.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ProxyRotation {
+    /// Every request uses the next proxy.
+    #[default]
+    Request,
+    /// Every episode uses the next proxy, all requests of one episode go through the same proxy.
+    Episode,
+}
+
+impl ProxyRotation {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "request" => Self::Request,
+            "episode" => Self::Episode,
+            _ => return Err(format!("'{}' is not a valid proxy rotation", s)),
+        })
+    }
+}
+
+/// Reads a file with one proxy url per line. Empty lines and lines starting with `#` are ignored.
+/// Every proxy is returned with its url without credentials, which can be safely logged.
+pub fn load_proxy_file(path: &Path) -> Result<Vec<(String, Proxy)>> {
+    let mut proxies = vec![];
+    for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_proxy(line) {
+            Ok(proxy) => proxies.push((strip_credentials(line), proxy)),
+            Err(e) => bail!(
+                "Invalid proxy in line {} of {}: {}",
+                i + 1,
+                path.to_string_lossy(),
+                e
+            ),
+        }
+    }
+    if proxies.is_empty() {
+        bail!("{} doesn't contain any proxy", path.to_string_lossy())
+    }
+    Ok(proxies)
+}
+
+fn strip_credentials(url: &str) -> String {
+    match url.split_once("://") {
+        Some((scheme, rest)) => match rest.rsplit_once('@') {
+            Some((_, host)) => format!("{}://{}", scheme, host),
+            None => url.to_string(),
+        },
+        None => url.to_string(),
+    }
+}
+
+struct PoolProxy {
+    /// Position in the proxy file. Proxies are identified by it, as multiple proxies may have the
+    /// same url without credentials.
+    id: usize,
+    url: String,
+    client: Client,
+    failures: u32,
+}
+
+struct ProxyPool {
+    proxies: Vec<PoolProxy>,
+    next: usize,
+}
+
+/// Sends requests through a list of proxies, rotating between them. Proxies which fail
+/// [`MAX_PROXY_FAILURES`] times in a row are removed and the request is retried with the next one.
+#[derive(Clone)]
+pub struct ProxyPoolService {
+    pool: Arc<Mutex<ProxyPool>>,
+    rotation: ProxyRotation,
+}
+
+impl ProxyPoolService {
+    /// Every proxy comes with its own client, as reqwest only supports one proxy per client.
+    pub fn new(proxies: Vec<(String, Client)>, rotation: ProxyRotation) -> Self {
+        Self {
+            pool: Arc::new(Mutex::new(ProxyPool {
+                proxies: proxies
+                    .into_iter()
+                    .enumerate()
+                    .map(|(id, (url, client))| PoolProxy {
+                        id,
+                        url,
+                        client,
+                        failures: 0,
+                    })
+                    .collect(),
+                next: 0,
+            })),
+            rotation,
+        }
+    }
+
+    /// Switches to the next proxy if the proxies are rotated per episode.
+    pub fn next_episode(&self) {
+        if self.rotation == ProxyRotation::Episode {
+            self.pool.lock().unwrap().next += 1
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.pool.lock().unwrap().proxies.len()
+    }
+
+    fn select(&self) -> Option<(usize, String, Client)> {
+        let mut pool = self.pool.lock().unwrap();
+        if pool.proxies.is_empty() {
+            return None;
+        }
+        let index = pool.next % pool.proxies.len();
+        if self.rotation == ProxyRotation::Request {
+            pool.next = index + 1
+        }
+        let proxy = &pool.proxies[index];
+        Some((proxy.id, proxy.url.clone(), proxy.client.clone()))
+    }
+
+    fn report(&self, id: usize, success: bool) {
+        let mut pool = self.pool.lock().unwrap();
+        let Some(index) = pool.proxies.iter().position(|p| p.id == id) else {
+            return;
+        };
+        if success {
+            pool.proxies[index].failures = 0;
+            return;
+        }
+
+        pool.proxies[index].failures += 1;
+        if pool.proxies[index].failures >= MAX_PROXY_FAILURES {
+            let proxy = pool.proxies.remove(index);
+            warn!(
+                "Removed proxy {} after {} failed requests, {} proxies left",
+                proxy.url,
+                MAX_PROXY_FAILURES,
+                pool.proxies.len()
+            )
+        } else if self.rotation == ProxyRotation::Episode {
+            // the episode continues with the next proxy instead of retrying the failed one
+            pool.next += 1
+        }
+    }
+}
+
+impl Service<Request> for ProxyPoolService {
+    type Response = Response;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let service = self.clone();
+
+        Box::pin(async move {
+            let mut req = req;
+            // every proxy gets at most one try per request, otherwise a request may hang for
+            // minutes if many proxies are dead
+            let mut tries_left = service.len();
+            loop {
+                let Some((id, url, client)) = service.select() else {
+                    return Err(Error::Request {
+                        url: req.url().to_string(),
+                        status: None,
+                        message: "No working proxy left".to_string(),
+                    });
+                };
+                let retry_req = req.try_clone();
+
+                let err = match client.execute(req).await {
+                    Ok(res) => {
+                        service.report(id, true);
+                        return Ok(res);
+                    }
+                    Err(e) => e,
+                };
+                // only connection errors are caused by the proxy, everything else is returned
+                // as is
+                if !err.is_connect() && !err.is_timeout() {
+                    return Err(err.into());
+                }
+                service.report(id, false);
+
+                tries_left = tries_left.saturating_sub(1);
+                match retry_req {
+                    Some(retry_req) if tries_left > 0 => {
+                        debug!("Request through proxy {} failed ({}), retrying", url, err);
+                        req = retry_req
+                    }
+                    _ => return Err(err.into()),
+                }
+            }
+        })
+    }
+}
@@ -1,25 +1,69 @@
+use crate::utils::os::temp_directory;
+use crate::utils::proxy_pool::ProxyPoolService;
 use async_speed_limit::Limiter;
 use crunchyroll_rs::error::Error;
+use fs2::FileExt;
 use futures_util::TryStreamExt;
+use lazy_static::lazy_static;
+use log::debug;
 use reqwest::{Client, Request, Response, ResponseBuilderExt};
+use std::fs::File;
 use std::future::Future;
-use std::io;
+use std::path::Path;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
+use std::time::Duration;
+use std::{fs, io};
 use tower_service::Service;
 
+lazy_static! {
+    /// All rate limited clients share one limiter, so that the speed limit applies to the whole
+    /// process and not to every client on its own.
+    static ref LIMITER: Mutex<Option<Limiter>> = Mutex::new(None);
+}
+
+/// Interval in which the processes which share the speed limit are counted.
+const SHARED_LIMIT_INTERVAL: Duration = Duration::from_secs(5);
+
 #[derive(Clone)]
 pub struct RateLimiterService {
     client: Arc<Client>,
+    proxy_pool: Option<ProxyPoolService>,
     rate_limiter: Limiter,
+    /// Additional limit for a part of the downloads which only gets a share of the speed limit.
+    share_limiter: Option<Limiter>,
 }
 
 impl RateLimiterService {
-    pub fn new(bytes: u32, client: Client) -> Self {
+    /// If a proxy pool is given, requests are sent through it instead of `client`.
+    pub fn new(bytes: u32, client: Client, proxy_pool: Option<ProxyPoolService>) -> Self {
         Self {
             client: Arc::new(client),
-            rate_limiter: Limiter::new(bytes as f64),
+            proxy_pool,
+            rate_limiter: LIMITER
+                .lock()
+                .unwrap()
+                .get_or_insert_with(|| Limiter::new(bytes as f64))
+                .clone(),
+            share_limiter: None,
+        }
+    }
+
+    /// A service which is limited to `fraction` of the speed limit (or of the share, if this
+    /// service is a share already), so that downloads which run at the same time can split it
+    /// between them. The speed limit of the whole process still applies.
+    pub fn share(&self, fraction: f64) -> Self {
+        let speed_limit = self
+            .share_limiter
+            .as_ref()
+            .unwrap_or(&self.rate_limiter)
+            .speed_limit();
+        Self {
+            client: self.client.clone(),
+            proxy_pool: self.proxy_pool.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            share_limiter: Some(Limiter::new(speed_limit * fraction.clamp(0.0, 1.0))),
         }
     }
 }
@@ -35,13 +79,18 @@ impl Service<Request> for RateLimiterService {
 
     fn call(&mut self, req: Request) -> Self::Future {
         let client = self.client.clone();
+        let proxy_pool = self.proxy_pool.clone();
         let rate_limiter = self.rate_limiter.clone();
+        let share_limiter = self.share_limiter.clone();
 
         Box::pin(async move {
             let mut body = vec![];
-            let res = client.execute(req).await?;
-            let _url = res.url().clone().to_string();
-            let url = _url.as_str();
+            let res = if let Some(mut proxy_pool) = proxy_pool {
+                proxy_pool.call(req).await?
+            } else {
+                client.execute(req).await?
+            };
+            let url = res.url().to_string();
 
             let mut http_res = http::Response::builder()
                 .url(res.url().clone())
@@ -59,15 +108,77 @@ impl Service<Request> for RateLimiterService {
                     .into_async_read(),
             );
 
-            futures_util::io::copy(limiter, &mut body)
-                .await
-                .map_err(|e| Error::Request {
-                    url: url.to_string(),
-                    status: None,
-                    message: e.to_string(),
-                })?;
+            let copied = if let Some(share_limiter) = share_limiter {
+                futures_util::io::copy(share_limiter.limit(limiter), &mut body).await
+            } else {
+                futures_util::io::copy(limiter, &mut body).await
+            };
+            copied.map_err(|e| Error::Request {
+                url,
+                status: None,
+                message: e.to_string(),
+            })?;
 
             Ok(Response::from(http_res.body(body).unwrap()))
         })
     }
 }
+
+/// Splits the speed limit evenly between all running crunchy-cli processes which share it. Every
+/// process creates a file in a directory in the temp directory and locks it as long as it runs.
+/// Files which aren't locked anymore belong to processes which have exited and are removed.
+pub fn share_speed_limit(bytes: u32) -> io::Result<()> {
+    let dir = temp_directory().join("crunchy-cli-speed-limit");
+    fs::create_dir_all(&dir)?;
+    // the file is locked under a temporary name before it's moved into place, otherwise other
+    // processes could see it unlocked and remove it
+    let tmp_path = dir.join(format!("{}.tmp", std::process::id()));
+    let own_file = File::create(&tmp_path)?;
+    own_file.try_lock_exclusive()?;
+    let own_path = dir.join(std::process::id().to_string());
+    fs::rename(&tmp_path, &own_path)?;
+
+    tokio::spawn(async move {
+        // the lock is released by the os when the process exits
+        let _own_file = own_file;
+        let mut last_processes = 1;
+        loop {
+            let processes = count_sharing_processes(&dir, &own_path);
+            if processes != last_processes {
+                debug!(
+                    "Sharing the speed limit with {} other process(es)",
+                    processes - 1
+                );
+                if let Some(limiter) = LIMITER.lock().unwrap().as_ref() {
+                    limiter.set_speed_limit(bytes as f64 / processes as f64)
+                }
+                last_processes = processes
+            }
+            tokio::time::sleep(SHARED_LIMIT_INTERVAL).await
+        }
+    });
+
+    Ok(())
+}
+
+/// Number of processes (including this one) which currently share the speed limit.
+fn count_sharing_processes(dir: &Path, own_path: &Path) -> usize {
+    let mut processes = 1;
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+        // files with the tmp extension are still being set up by their process
+        if path == own_path || path.extension().is_some_and(|ext| ext == "tmp") {
+            continue;
+        }
+        let Ok(file) = File::open(&path) else {
+            continue;
+        };
+        if file.try_lock_exclusive().is_ok() {
+            let _ = file.unlock();
+            let _ = fs::remove_file(&path);
+        } else {
+            processes += 1
+        }
+    }
+    processes
+}
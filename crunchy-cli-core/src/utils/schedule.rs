@@ -0,0 +1,72 @@
+use chrono::{Local, NaiveTime};
+use log::info;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// If the download threads currently wait for the schedule window, so that pausing and resuming is
+/// only reported once and not by every thread.
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// Local time window in which segments are downloaded. The window may span midnight, e.g.
+/// `22:00-06:00`.
+#[derive(Clone, Copy, Debug)]
+pub struct Schedule {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl Schedule {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let Some((start, end)) = s.split_once('-') else {
+            return Err(format!(
+                "'{}' is not a valid schedule, it must be in format <HH:MM>-<HH:MM>",
+                s
+            ));
+        };
+        let parse_time = |time: &str| {
+            NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .map_err(|_| format!("'{}' is not a valid time, it must be in format HH:MM", time))
+        };
+        let schedule = Self {
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+        };
+        if schedule.start == schedule.end {
+            return Err("the start and end of the schedule must differ".to_string());
+        }
+        Ok(schedule)
+    }
+
+    fn is_active(&self, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            time >= self.start && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// Waits until the current local time is within the schedule. Returns immediately if it
+    /// already is.
+    pub async fn wait(&self) {
+        loop {
+            let now = Local::now().time();
+            if self.is_active(now) {
+                if PAUSED.swap(false, Ordering::SeqCst) {
+                    info!("Resuming download, the schedule window started")
+                }
+                return;
+            }
+            if !PAUSED.swap(true, Ordering::SeqCst) {
+                info!(
+                    "Pausing download until {}, it's outside of the schedule window",
+                    self.start.format("%H:%M")
+                )
+            }
+
+            // the time until the window starts is re-calculated regularly, so that changes of the
+            // system time (or daylight saving time) don't let the download sleep too long
+            let until_start = (self.start - now).num_seconds().rem_euclid(24 * 60 * 60) as u64;
+            tokio::time::sleep(Duration::from_secs(until_start.clamp(1, 60))).await
+        }
+    }
+}
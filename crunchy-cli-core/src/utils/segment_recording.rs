@@ -0,0 +1,72 @@
+use anyhow::{bail, Result};
+use log::debug;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+
+/// Raw segment responses which are written into a directory on one run and read from it instead
+/// of being downloaded on another, to reproduce a run with exactly the same segments. Every segment
+/// is stored with its sha256 checksum, so that corrupted or modified recordings are detected when
+/// they're replayed.
+#[derive(Clone, Debug)]
+pub enum SegmentRecording {
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+impl SegmentRecording {
+    pub fn is_replay(&self) -> bool {
+        matches!(self, SegmentRecording::Replay(_))
+    }
+
+    /// The recorded segment of `url`, or `None` if segments are recorded and not replayed.
+    pub fn replay(&self, url: &str) -> Result<Option<Vec<u8>>> {
+        let SegmentRecording::Replay(dir) = self else {
+            return Ok(None);
+        };
+        let name = segment_name(url);
+        let path = dir.join(format!("{}.segment", name));
+        let Ok(data) = fs::read(&path) else {
+            bail!(
+                "Segment {} is not recorded in {}, the recording must be from the same episode and stream",
+                url.split('?').next().unwrap_or(url),
+                dir.to_string_lossy()
+            )
+        };
+        let checksum = fs::read_to_string(dir.join(format!("{}.sha256", name))).unwrap_or_default();
+        if checksum.trim() != hex_digest(&data) {
+            bail!(
+                "Recorded segment {} doesn't match its checksum, the recording is corrupted",
+                path.to_string_lossy()
+            )
+        }
+        debug!("Replayed segment {}", path.to_string_lossy());
+        Ok(Some(data))
+    }
+
+    /// Writes a downloaded segment into the recording directory. Does nothing if segments are
+    /// replayed.
+    pub fn record(&self, url: &str, data: &[u8]) -> Result<()> {
+        let SegmentRecording::Record(dir) = self else {
+            return Ok(());
+        };
+        fs::create_dir_all(dir)?;
+        let name = segment_name(url);
+        fs::write(dir.join(format!("{}.segment", name)), data)?;
+        fs::write(dir.join(format!("{}.sha256", name)), hex_digest(data))?;
+        Ok(())
+    }
+}
+
+/// Segments are identified by their url without query parameters, as they contain request specific
+/// tokens which differ on every run.
+fn segment_name(url: &str) -> String {
+    hex_digest(url.split('?').next().unwrap_or(url).as_bytes())
+}
+
+fn hex_digest(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
@@ -0,0 +1,116 @@
+use crate::utils::fmt::format_size;
+use crate::utils::interactive_select::select_with_defaults;
+use crate::utils::log::progress_pause;
+use dialoguer::console::Term;
+use log::{info, warn};
+use std::sync::Mutex;
+
+/// Limits the total size of the files written in one run. Every download reserves its estimated
+/// size before it starts, the reservation is replaced by the actual file size when it's done.
+pub struct SizeBudget {
+    max: u64,
+    used: Mutex<u64>,
+}
+
+impl SizeBudget {
+    pub fn new(max: u64) -> Self {
+        Self {
+            max,
+            used: Mutex::new(0),
+        }
+    }
+
+    /// Which of the estimated sizes fit into `remaining`, in order. Once one doesn't fit, none of
+    /// the following ones are selected either, so that no gaps are left in a series.
+    fn fitting(estimates: &[u64], remaining: u64) -> Vec<bool> {
+        let mut total = 0;
+        let mut fits = true;
+        estimates
+            .iter()
+            .map(|estimate| {
+                total += estimate;
+                fits = fits && total <= remaining;
+                fits
+            })
+            .collect()
+    }
+
+    /// Removes the items which don't fit into the remaining budget. If `interactive`, the user selects the
+    /// items instead, with the fitting ones preselected. Every item comes with its estimated size,
+    /// `name` describes it in the selection and the logs.
+    pub fn trim<T>(
+        &self,
+        items: Vec<(T, u64)>,
+        name: impl Fn(&T) -> String,
+        interactive: bool,
+    ) -> Vec<(T, u64)> {
+        let estimates: Vec<u64> = items.iter().map(|(_, estimate)| *estimate).collect();
+        let total: u64 = estimates.iter().sum();
+        let remaining = self.max.saturating_sub(self.used());
+        if total <= remaining {
+            info!(
+                "Estimated total size is {} ({} of the budget left)",
+                format_size(total),
+                format_size(remaining)
+            );
+            return items;
+        }
+
+        let fitting = Self::fitting(&estimates, remaining);
+        let keep = if interactive {
+            progress_pause!();
+            let _ = Term::stdout().clear_line();
+            let keep = select_with_defaults(
+                &format!(
+                    "The estimated total size ({}) exceeds the {} left of the budget. Select the episodes you want to download (space to select/deselect; enter to continue)",
+                    format_size(total),
+                    format_size(remaining)
+                ),
+                items
+                    .iter()
+                    .map(|(item, estimate)| format!("{} (~{})", name(item), format_size(*estimate)))
+                    .collect(),
+                &fitting,
+            );
+            progress_pause!();
+            keep
+        } else {
+            (0..items.len()).filter(|i| fitting[*i]).collect()
+        };
+
+        let mut kept = vec![];
+        for (i, (item, estimate)) in items.into_iter().enumerate() {
+            if keep.contains(&i) {
+                kept.push((item, estimate))
+            } else if !interactive {
+                warn!(
+                    "Skipping '{}' (~{}) as it doesn't fit into the size budget of {}",
+                    name(&item),
+                    format_size(estimate),
+                    format_size(self.max)
+                )
+            }
+        }
+        kept
+    }
+
+    /// Reserves the estimated size if it fits into the remaining budget.
+    pub fn reserve(&self, estimate: u64) -> bool {
+        let mut used = self.used.lock().unwrap();
+        if *used + estimate > self.max {
+            return false;
+        }
+        *used += estimate;
+        true
+    }
+
+    /// Replaces a reservation with the actual size. `actual` is 0 if nothing was written.
+    pub fn settle(&self, estimate: u64, actual: u64) {
+        let mut used = self.used.lock().unwrap();
+        *used = used.saturating_sub(estimate) + actual
+    }
+
+    pub fn used(&self) -> u64 {
+        *self.used.lock().unwrap()
+    }
+}
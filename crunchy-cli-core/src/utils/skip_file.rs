@@ -0,0 +1,141 @@
+use anyhow::Result;
+use crunchyroll_rs::media::SkipEvents;
+use log::debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Player specific file which tells the player which parts of a video should be skipped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SkipFileFormat {
+    /// MPlayer edl file, the parts are skipped.
+    Edl,
+    /// Kodi edl file, the parts are marked as commercial breaks which Kodi skips automatically.
+    Kodi,
+    /// mpv edl file, which plays the video without the parts if it's opened instead of the video.
+    Mpv,
+}
+
+impl SkipFileFormat {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "edl" => Self::Edl,
+            "kodi" => Self::Kodi,
+            "mpv" => Self::Mpv,
+            _ => return Err(format!("'{}' is not a valid skip file format", s)),
+        })
+    }
+
+    fn path(&self, video: &Path) -> PathBuf {
+        match self {
+            SkipFileFormat::Edl | SkipFileFormat::Kodi => video.with_extension("edl"),
+            SkipFileFormat::Mpv => video.with_extension("mpv.edl"),
+        }
+    }
+}
+
+/// Part of an episode which Crunchyroll marks as skippable.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SkipEventKind {
+    Intro,
+    Recap,
+    Preview,
+    Credits,
+}
+
+impl SkipEventKind {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "intro" => Self::Intro,
+            "recap" => Self::Recap,
+            "preview" => Self::Preview,
+            "credits" => Self::Credits,
+            _ => return Err(format!("'{}' is not a valid skip event", s)),
+        })
+    }
+}
+
+/// Start and end (in seconds) of every skip event which is one of `kinds`.
+pub fn skip_event_ranges(skip_events: &SkipEvents, kinds: &[SkipEventKind]) -> Vec<(f32, f32)> {
+    kinds
+        .iter()
+        .filter_map(|kind| match kind {
+            SkipEventKind::Intro => skip_events.intro.as_ref(),
+            SkipEventKind::Recap => skip_events.recap.as_ref(),
+            SkipEventKind::Preview => skip_events.preview.as_ref(),
+            SkipEventKind::Credits => skip_events.credits.as_ref(),
+        })
+        .filter(|e| e.end > e.start)
+        .map(|e| (e.start, e.end))
+        .collect()
+}
+
+/// Writes the recap, intro, credits and preview of a video into a skip file next to it. Nothing
+/// is written if the video has no skip events.
+pub fn write_skip_file(
+    video: &Path,
+    skip_events: &SkipEvents,
+    format: SkipFileFormat,
+) -> Result<()> {
+    let mut parts: Vec<(f32, f32)> = [
+        &skip_events.recap,
+        &skip_events.intro,
+        &skip_events.credits,
+        &skip_events.preview,
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|e| e.end > e.start)
+    .map(|e| (e.start, e.end))
+    .collect();
+    if parts.is_empty() {
+        debug!("No skip events found, not writing a skip file");
+        return Ok(());
+    }
+    parts.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let content = match format {
+        SkipFileFormat::Edl => edl(&parts, 0),
+        SkipFileFormat::Kodi => edl(&parts, 3),
+        SkipFileFormat::Mpv => mpv_edl(video, &parts),
+    };
+    let path = format.path(video);
+    fs::write(&path, content)?;
+    debug!("Wrote skip file {}", path.to_string_lossy());
+    Ok(())
+}
+
+/// Every line contains the start, end and action of a part.
+fn edl(parts: &[(f32, f32)], action: u8) -> String {
+    parts
+        .iter()
+        .map(|(start, end)| format!("{:.3}\t{:.3}\t{}\n", start, end, action))
+        .collect()
+}
+
+/// Every line is a segment of the video which is played, so the parts are left out. The last
+/// segment has no length, so it's played until the end of the video.
+fn mpv_edl(video: &Path, parts: &[(f32, f32)]) -> String {
+    let file_name = video
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    // the length prefix allows the file name to contain commas
+    let file = format!("%{}%{}", file_name.len(), file_name);
+
+    let mut content = "# mpv EDL v0\n".to_string();
+    let mut position = 0.0;
+    for (start, end) in parts {
+        if *start > position {
+            content.push_str(&format!(
+                "{},{:.3},{:.3}\n",
+                file,
+                position,
+                start - position
+            ))
+        }
+        position = position.max(*end)
+    }
+    content.push_str(&format!("{},{:.3}\n", file, position));
+    content
+}
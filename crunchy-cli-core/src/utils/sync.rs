@@ -3,10 +3,11 @@ use std::process::Stdio;
 use std::{
     cmp,
     collections::{HashMap, HashSet},
-    mem,
+    fmt, mem,
     ops::Not,
     path::Path,
     process::Command,
+    sync::{Arc, Mutex},
 };
 
 use chrono::TimeDelta;
@@ -17,6 +18,7 @@ use tempfile::TempPath;
 use anyhow::{bail, Result};
 use rusty_chromaprint::{Configuration, Fingerprinter};
 
+use super::ffmpeg::ffmpeg_binary;
 use super::fmt::format_time_delta;
 
 pub struct SyncAudio {
@@ -27,39 +29,234 @@ pub struct SyncAudio {
     pub video_idx: usize,
 }
 
+/// Chromaprints of already processed episodes, grouped by season. Episodes of the same season
+/// usually share their intro and ending, so they can be found by comparing the chromaprint of an
+/// episode with the one of the previous episode.
+#[derive(Clone, Default)]
+pub struct SeasonFingerprintCache {
+    chromaprints: Arc<Mutex<HashMap<String, Vec<u32>>>>,
+}
+
+/// The intro is only searched in the first ten minutes of an episode, matches after that are most
+/// likely the ending.
+const INTRO_MAX_START: f64 = 600.0;
+
+/// Start and end (in seconds) of audio parts which recur in every episode of a season.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RecurringParts {
+    pub intro: Option<(f32, f32)>,
+    pub ending: Option<(f32, f32)>,
+}
+
+impl SeasonFingerprintCache {
+    /// Returns the intro and ending of the given chromaprint if it shares them with the last
+    /// chromaprint of the same season. The chromaprint gets cached afterwards.
+    pub fn find_recurring_parts(
+        &self,
+        season_id: &str,
+        chromaprint: &Vec<u32>,
+        sync_tolerance: u32,
+    ) -> RecurringParts {
+        let mut chromaprints = self.chromaprints.lock().unwrap();
+
+        let mut recurring_parts = RecurringParts::default();
+        if let Some(previous_chromaprint) = chromaprints.get(season_id) {
+            let (_, ranges) =
+                compare_chromaprints(previous_chromaprint, chromaprint, sync_tolerance);
+            // the ending is searched in the second half of the episode to not mistake the intro
+            // for it if the episode is very short
+            let half_length = chromaprint.len() as f64 * 0.128 / 2.0;
+
+            let intro = ranges
+                .iter()
+                .find(|range| range.start < INTRO_MAX_START.min(half_length));
+            let ending = ranges.iter().find(|range| range.start > half_length);
+            for (name, range) in [("intro", intro), ("ending", ending)] {
+                if let Some(range) = range {
+                    debug!(
+                        "Found {} at {} - {} ({}s) for season {}",
+                        name,
+                        range.start,
+                        range.end,
+                        range.end - range.start,
+                        season_id
+                    )
+                }
+            }
+
+            recurring_parts.intro = intro.map(|range| (range.start as f32, range.end as f32));
+            recurring_parts.ending = ending.map(|range| (range.start as f32, range.end as f32));
+        }
+
+        chromaprints.insert(season_id.to_string(), chromaprint.clone());
+        recurring_parts
+    }
+}
+
+/// Part of the audios which is used to find the sync offsets. Useful if e.g. recaps or cold opens
+/// differ between the audios.
+#[derive(Clone, Debug)]
+pub struct SyncWindow {
+    pub start: TimeDelta,
+    /// End of the window, [`None`] if the window reaches until the end of the audio.
+    pub end: Option<TimeDelta>,
+}
+
+impl SyncWindow {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let error = || {
+            format!(
+                "'{}' is not a valid sync window, it must be in format <start>-<end> (e.g. 0-5:00)",
+                s
+            )
+        };
+        let (start, end) = s.split_once('-').ok_or_else(error)?;
+
+        let start = if start.is_empty() {
+            TimeDelta::zero()
+        } else {
+            parse_time(start).ok_or_else(error)?
+        };
+        let end = if end.is_empty() {
+            None
+        } else {
+            Some(parse_time(end).ok_or_else(error)?)
+        };
+
+        // matching audio parts must be at least 20 seconds long
+        if end.is_some_and(|end| (end - start).num_seconds() < 30) {
+            return Err(format!(
+                "The sync window '{}' must be at least 30 seconds long",
+                s
+            ));
+        }
+
+        Ok(Self { start, end })
+    }
+}
+
+/// Parses a time in format `[[hh:]mm:]ss`.
+fn parse_time(s: &str) -> Option<TimeDelta> {
+    let mut seconds = 0;
+    for (i, part) in s.rsplit(':').enumerate() {
+        if i > 2 {
+            return None;
+        }
+        seconds += part.trim().parse::<i64>().ok()? * 60_i64.pow(i as u32)
+    }
+    Some(TimeDelta::seconds(seconds))
+}
+
+/// An offset which was found in one run of [`sync_audios`].
+#[derive(Clone, Debug)]
+pub struct SyncSample {
+    pub format_id: usize,
+    pub offset: TimeDelta,
+    /// Length of the matching audio part (in seconds) the offset was calculated from. The longer
+    /// the part, the more reliable the offset.
+    pub confidence: f64,
+}
+
 #[derive(Debug, Clone, Copy)]
 struct TimeRange {
     start: f64,
     end: f64,
 }
 
+/// Generates the chromaprint of the whole audio.
+pub fn fingerprint_audio(audio: &SyncAudio) -> Result<Vec<u32>> {
+    generate_chromaprint(
+        &audio.path,
+        audio.sample_rate,
+        &TimeDelta::zero(),
+        &TimeDelta::zero(),
+        &TimeDelta::zero(),
+    )
+}
+
+/// Step of [`sync_audios`] which is reported to its progress callback. `current` starts at 1.
+pub enum SyncStage {
+    /// Decoding the audios to generate their chromaprints.
+    Decoding { current: usize, total: usize },
+    /// Searching matching parts of an audio and the base audio.
+    Correlating { current: usize, total: usize },
+    /// Decoding the matching parts of the audios with a higher precision.
+    Narrowing { current: usize, total: usize },
+    /// Comparing the matching parts with slightly shifted offsets.
+    Refining { current: usize, total: usize },
+}
+
+impl fmt::Display for SyncStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, current, total) = match self {
+            SyncStage::Decoding { current, total } => ("Decoding audio", current, total),
+            SyncStage::Correlating { current, total } => ("Correlating audio pair", current, total),
+            SyncStage::Narrowing { current, total } => ("Narrowing audio", current, total),
+            SyncStage::Refining { current, total } => ("Refining offsets", current, total),
+        };
+        write!(f, "{} {}/{}", name, current, total)
+    }
+}
+
+/// `full_chromaprints` contains already generated chromaprints of whole audios by format id. Missing
+/// chromaprints are generated and added to it. If `window` is set, only this part of the audios is
+/// compared and `full_chromaprints` stays untouched. The offset of every run is added to `samples`.
+/// `on_progress` is called before every step, as the sync can take minutes.
 pub fn sync_audios(
     available_audios: &Vec<SyncAudio>,
+    full_chromaprints: &mut HashMap<usize, Vec<u32>>,
     sync_tolerance: u32,
     sync_precision: u32,
+    window: Option<&SyncWindow>,
+    samples: &mut Vec<SyncSample>,
+    on_progress: impl Fn(SyncStage),
 ) -> Result<Option<HashMap<usize, TimeDelta>>> {
     let mut result: HashMap<usize, TimeDelta> = HashMap::new();
 
     let mut sync_audios = vec![];
-    let mut chromaprints = HashMap::new();
     let mut formats = HashSet::new();
+    let mut missing_chromaprints = vec![];
     for audio in available_audios {
         if formats.contains(&audio.format_id) {
             continue;
         }
         formats.insert(audio.format_id);
         sync_audios.push((audio.format_id, &audio.path, audio.sample_rate));
-        chromaprints.insert(
-            audio.format_id,
-            generate_chromaprint(
-                &audio.path,
-                audio.sample_rate,
-                &TimeDelta::zero(),
-                &TimeDelta::zero(),
-                &TimeDelta::zero(),
-            )?,
-        );
+        if window.is_none() && !full_chromaprints.contains_key(&audio.format_id) {
+            missing_chromaprints.push(audio)
+        }
     }
+    for (i, audio) in missing_chromaprints.iter().enumerate() {
+        on_progress(SyncStage::Decoding {
+            current: i + 1,
+            total: missing_chromaprints.len(),
+        });
+        full_chromaprints.insert(audio.format_id, fingerprint_audio(audio)?);
+    }
+    let mut chromaprints = if let Some(window) = window {
+        let mut chromaprints = HashMap::new();
+        for (i, sync_audio) in sync_audios.iter().enumerate() {
+            on_progress(SyncStage::Decoding {
+                current: i + 1,
+                total: sync_audios.len(),
+            });
+            chromaprints.insert(
+                sync_audio.0,
+                generate_chromaprint(
+                    sync_audio.1,
+                    sync_audio.2,
+                    &window.start,
+                    &window.end.unwrap_or_else(TimeDelta::zero),
+                    &TimeDelta::zero(),
+                )?,
+            );
+        }
+        chromaprints
+    } else {
+        full_chromaprints.clone()
+    };
+    // the time ranges of the chromaprints are relative to the start of the window
+    let window_start = window.map_or(0.0, |w| w.start.num_milliseconds() as f64 / 1000.0);
     sync_audios.sort_by_key(|sync_audio| chromaprints.get(&sync_audio.0).unwrap().len());
 
     let base_audio = sync_audios.remove(0);
@@ -67,7 +264,11 @@ pub fn sync_audios(
     let mut start = f64::MAX;
     let mut end = f64::MIN;
     let mut initial_offsets = HashMap::new();
-    for audio in &sync_audios {
+    for (i, audio) in sync_audios.iter().enumerate() {
+        on_progress(SyncStage::Correlating {
+            current: i + 1,
+            total: sync_audios.len(),
+        });
         debug!(
             "Initial comparison of format {} to {}",
             audio.0, &base_audio.0
@@ -109,13 +310,18 @@ pub fn sync_audios(
 
     debug!(
         "Found matching audio parts at {} - {}, narrowing search",
-        start, end
+        start + window_start,
+        end + window_start
     );
 
-    let start = TimeDelta::milliseconds((start * 1000.0) as i64 - 20000);
-    let end = TimeDelta::milliseconds((end * 1000.0) as i64 + 20000);
+    let start = TimeDelta::milliseconds(((start + window_start) * 1000.0) as i64 - 20000);
+    let end = TimeDelta::milliseconds(((end + window_start) * 1000.0) as i64 + 20000);
 
-    for sync_audio in &sync_audios {
+    for (i, sync_audio) in sync_audios.iter().enumerate() {
+        on_progress(SyncStage::Narrowing {
+            current: i + 1,
+            total: sync_audios.len(),
+        });
         let chromaprint = generate_chromaprint(
             sync_audio.1,
             sync_audio.2,
@@ -129,6 +335,10 @@ pub fn sync_audios(
     let mut runs: HashMap<usize, i64> = HashMap::new();
     let iterator_range_limits: i64 = 2 ^ sync_precision as i64;
     for i in -iterator_range_limits..=iterator_range_limits {
+        on_progress(SyncStage::Refining {
+            current: (i + iterator_range_limits) as usize + 1,
+            total: (iterator_range_limits * 2) as usize + 1,
+        });
         let base_offset = TimeDelta::milliseconds(
             ((0.128 / iterator_range_limits as f64 * i as f64) * 1000.0) as i64,
         );
@@ -149,7 +359,12 @@ pub fn sync_audios(
             if offset.is_none() {
                 continue;
             }
-            let offset = offset.unwrap();
+            let (offset, confidence) = offset.unwrap();
+            samples.push(SyncSample {
+                format_id: audio.0,
+                offset,
+                confidence,
+            });
 
             result.insert(
                 audio.0,
@@ -186,7 +401,7 @@ fn find_offset(
     rhs_shift: &TimeDelta,
     start: &TimeDelta,
     sync_tolerance: u32,
-) -> Option<TimeDelta> {
+) -> Option<(TimeDelta, f64)> {
     let (lhs_ranges, rhs_ranges) = compare_chromaprints(lhs.1, rhs.1, sync_tolerance);
     if lhs_ranges.is_empty() || rhs_ranges.is_empty() {
         return None;
@@ -209,7 +424,130 @@ fn find_offset(
         rhs.0,
         lhs.0
     );
-    Some(offset)
+    Some((offset, lhs_range.end - lhs_range.start))
+}
+
+/// Colors of the formats in [`sync_plot`].
+const SYNC_PLOT_COLORS: [&str; 6] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b",
+];
+
+/// Draws the offsets of all runs against their confidence as svg, so it's visible how much the
+/// single runs differ. `offsets` are the final offsets by format id, `labels` the names of the
+/// formats.
+pub fn sync_plot(
+    samples: &[SyncSample],
+    offsets: &HashMap<usize, TimeDelta>,
+    labels: &HashMap<usize, String>,
+    applied: bool,
+) -> String {
+    const WIDTH: f64 = 640.0;
+    const HEIGHT: f64 = 360.0;
+    const MARGIN: f64 = 50.0;
+
+    let offset_values: Vec<f64> = samples
+        .iter()
+        .map(|s| s.offset.num_milliseconds() as f64)
+        .chain(offsets.values().map(|o| o.num_milliseconds() as f64))
+        .collect();
+    let mut min_x = offset_values.iter().copied().fold(f64::MAX, f64::min);
+    let mut max_x = offset_values.iter().copied().fold(f64::MIN, f64::max);
+    if offset_values.is_empty() {
+        (min_x, max_x) = (0.0, 0.0)
+    }
+    let padding_x = ((max_x - min_x) * 0.1).max(50.0);
+    (min_x, max_x) = (min_x - padding_x, max_x + padding_x);
+    let max_y = samples
+        .iter()
+        .map(|s| s.confidence)
+        .fold(0.0, f64::max)
+        .max(1.0)
+        * 1.1;
+
+    let x = |offset: f64| MARGIN + (offset - min_x) / (max_x - min_x) * (WIDTH - 2.0 * MARGIN);
+    let y = |confidence: f64| HEIGHT - MARGIN - confidence / max_y * (HEIGHT - 2.0 * MARGIN);
+
+    let mut svg = vec![
+        format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" font-family="sans-serif" font-size="12">"#
+        ),
+        format!(r#"<rect width="{WIDTH}" height="{HEIGHT}" fill="white"/>"#),
+        format!(
+            r#"<text x="{MARGIN}" y="20" font-size="14">Audio sync ({})</text>"#,
+            if applied {
+                "offsets applied"
+            } else {
+                "offsets rejected"
+            }
+        ),
+        format!(
+            r#"<polyline points="{MARGIN},{MARGIN} {MARGIN},{0} {1},{0}" fill="none" stroke="black"/>"#,
+            HEIGHT - MARGIN,
+            WIDTH - MARGIN
+        ),
+        format!(
+            r#"<text x="{}" y="{}" text-anchor="middle">offset (ms)</text>"#,
+            WIDTH / 2.0,
+            HEIGHT - 10.0
+        ),
+        format!(
+            r#"<text x="15" y="{}" text-anchor="middle" transform="rotate(-90 15 {})">matching audio (s)</text>"#,
+            HEIGHT / 2.0,
+            HEIGHT / 2.0
+        ),
+        format!(
+            r#"<text x="{MARGIN}" y="{}" text-anchor="middle">{}</text><text x="{}" y="{}" text-anchor="middle">{}</text>"#,
+            HEIGHT - MARGIN + 15.0,
+            min_x.round(),
+            WIDTH - MARGIN,
+            HEIGHT - MARGIN + 15.0,
+            max_x.round()
+        ),
+        format!(
+            r#"<text x="{}" y="{MARGIN}" text-anchor="end">{:.1}</text>"#,
+            MARGIN - 5.0,
+            max_y
+        ),
+    ];
+
+    let mut format_ids: Vec<usize> = labels.keys().copied().collect();
+    format_ids.sort();
+    for (i, format_id) in format_ids.iter().enumerate() {
+        let color = SYNC_PLOT_COLORS[i % SYNC_PLOT_COLORS.len()];
+        let format_samples: Vec<&SyncSample> = samples
+            .iter()
+            .filter(|s| &s.format_id == format_id)
+            .collect();
+        for sample in &format_samples {
+            svg.push(format!(
+                r#"<circle cx="{:.1}" cy="{:.1}" r="4" fill="{color}" fill-opacity="0.7"/>"#,
+                x(sample.offset.num_milliseconds() as f64),
+                y(sample.confidence)
+            ))
+        }
+        let offset = offsets.get(format_id);
+        if let Some(offset) = offset {
+            let offset_x = x(offset.num_milliseconds() as f64);
+            svg.push(format!(
+                r#"<line x1="{offset_x:.1}" y1="{MARGIN}" x2="{offset_x:.1}" y2="{}" stroke="{color}" stroke-dasharray="4 3"/>"#,
+                HEIGHT - MARGIN
+            ))
+        }
+        svg.push(format!(
+            r#"<text x="{}" y="{}" fill="{color}" text-anchor="end">{} ({} runs{})</text>"#,
+            WIDTH - MARGIN,
+            MARGIN + 15.0 * i as f64,
+            labels.get(format_id).unwrap(),
+            format_samples.len(),
+            offset.map_or("".to_string(), |o| format!(
+                ", offset {}ms",
+                o.num_milliseconds()
+            ))
+        ))
+    }
+    svg.push("</svg>".to_string());
+
+    svg.join("\n")
 }
 
 fn generate_chromaprint(
@@ -229,7 +567,7 @@ fn generate_chromaprint(
     let mut printer = Fingerprinter::new(&Configuration::preset_test1());
     printer.start(sample_rate, 2)?;
 
-    let mut command = Command::new("ffmpeg");
+    let mut command = Command::new(ffmpeg_binary());
     command
         .arg("-hide_banner")
         .arg("-y")
@@ -0,0 +1,29 @@
+use anyhow::{bail, Result};
+use std::future::Future;
+use std::time::Duration;
+
+/// Runs `future` and aborts it if it didn't finish within `timeout`. `stage` names the part of the
+/// download in the error message, so that it's visible which part got stuck.
+pub async fn with_stage_timeout<T>(
+    stage: &str,
+    timeout: Option<Duration>,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let Some(timeout) = timeout else {
+        return future.await;
+    };
+    match tokio::time::timeout(timeout, future).await {
+        Ok(result) => result,
+        Err(_) => bail!(
+            "{} did not finish within {} seconds and was aborted",
+            stage,
+            timeout.as_secs()
+        ),
+    }
+}
+
+/// Converts a timeout in seconds as given on the command line to a [`Duration`]. 0 disables the
+/// timeout.
+pub fn seconds_to_timeout(seconds: u64) -> Option<Duration> {
+    (seconds > 0).then(|| Duration::from_secs(seconds))
+}
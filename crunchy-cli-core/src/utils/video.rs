@@ -1,12 +1,60 @@
+use crate::utils::parse::ResolutionSelector;
 use anyhow::{bail, Result};
-use crunchyroll_rs::media::{Resolution, Stream, StreamData};
+use crunchyroll_rs::media::{Stream, StreamData};
 use crunchyroll_rs::Locale;
+use log::{debug, warn};
 
+/// Video codec which should be preferred if a stream is available in multiple codecs.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Codec {
+    H264,
+    H265,
+    #[default]
+    Any,
+}
+
+impl Codec {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Ok(match s.to_lowercase().as_str() {
+            "h264" | "avc" => Self::H264,
+            "h265" | "hevc" => Self::H265,
+            "any" => Self::Any,
+            _ => return Err(format!("'{}' is not a valid codec", s)),
+        })
+    }
+
+    /// The codec of a video stream. `codecs` is the codecs string of the stream manifest, e.g.
+    /// 'avc1.640028,mp4a.40.2'.
+    pub fn from_codecs(codecs: &str) -> Option<Self> {
+        codecs.split(',').find_map(|codec| {
+            match codec.trim().split('.').next().unwrap_or_default() {
+                "avc1" | "avc3" => Some(Self::H264),
+                "hvc1" | "hev1" => Some(Self::H265),
+                _ => None,
+            }
+        })
+    }
+}
+
+impl std::fmt::Display for Codec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Codec::H264 => write!(f, "h264"),
+            Codec::H265 => write!(f, "h265"),
+            Codec::Any => write!(f, "any"),
+        }
+    }
+}
+
+/// Returns the video and audio stream and if the video contains hardsubs. The last element are the
+/// video streams with a lower resolution than the selected one, highest first.
+#[allow(clippy::type_complexity)]
 pub async fn stream_data_from_stream(
     stream: &Stream,
-    resolution: &Resolution,
+    resolution: &ResolutionSelector,
+    codec: Codec,
     hardsub_subtitle: Option<Locale>,
-) -> Result<Option<(StreamData, StreamData, bool)>> {
+) -> Result<Option<(StreamData, StreamData, bool, Vec<StreamData>)>> {
     let (hardsub_locale, mut contains_hardsub) = if hardsub_subtitle.is_some() {
         (hardsub_subtitle, true)
     } else {
@@ -27,15 +75,76 @@ pub async fn stream_data_from_stream(
         }
     }
     .unwrap();
-    videos.sort_by(|a, b| a.bandwidth.cmp(&b.bandwidth).reverse());
+    // videos are sorted by resolution, highest first. videos with the same resolution are sorted
+    // by bandwidth, so that the selection is always the same for the same input
+    videos.sort_by(|a, b| {
+        let (a_resolution, b_resolution) = (a.resolution().unwrap(), b.resolution().unwrap());
+        b_resolution
+            .height
+            .cmp(&a_resolution.height)
+            .then(b_resolution.width.cmp(&a_resolution.width))
+            .then(b.bandwidth.cmp(&a.bandwidth))
+    });
     audios.sort_by(|a, b| a.bandwidth.cmp(&b.bandwidth).reverse());
 
-    let video_variant = match resolution.height {
-        u64::MAX => Some(videos.into_iter().next().unwrap()),
-        u64::MIN => Some(videos.into_iter().last().unwrap()),
-        _ => videos
-            .into_iter()
-            .find(|v| resolution.height == v.resolution().unwrap().height),
+    let available: Vec<String> = videos
+        .iter()
+        .map(|v| v.resolution().unwrap().to_string())
+        .collect();
+    let mut candidates: Vec<usize> = videos
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| resolution.matches(&v.resolution().unwrap()))
+        .map(|(i, _)| i)
+        .collect();
+    if candidates.is_empty() {
+        warn!(
+            "No video matches the resolution '{}', available resolutions are {}",
+            resolution,
+            available.join(", ")
+        );
+        return Ok(None);
+    }
+
+    // the codec is only a preference, if no video with a matching resolution is available in it,
+    // the other codecs are used
+    if codec != Codec::Any {
+        if candidates
+            .iter()
+            .any(|i| Codec::from_codecs(&videos[*i].codecs) == Some(codec))
+        {
+            candidates.retain(|i| Codec::from_codecs(&videos[*i].codecs) == Some(codec))
+        } else {
+            debug!(
+                "No video with resolution '{}' is available in {}, using any codec",
+                resolution, codec
+            )
+        }
+    }
+
+    // every video below the selected one is a fallback, even if it doesn't match the resolution,
+    // as a lower resolution is still better than no video at all
+    let position = if resolution.is_best() {
+        candidates[0]
+    } else {
+        *candidates.last().unwrap()
     };
-    Ok(video_variant.map(|v| (v, audios.first().unwrap().clone(), contains_hardsub)))
+    let lower_videos = videos.split_off(position + 1);
+    Ok(Some((
+        videos.pop().unwrap(),
+        audios.first().unwrap().clone(),
+        contains_hardsub,
+        lower_videos,
+    )))
+}
+
+/// Check if two streams are the same by comparing their segment urls. Query parameters are ignored
+/// as they may contain request specific tokens.
+pub fn is_same_stream(a: &StreamData, b: &StreamData) -> bool {
+    let (a_segments, b_segments) = (a.segments(), b.segments());
+    a_segments.len() == b_segments.len()
+        && a_segments
+            .iter()
+            .zip(b_segments.iter())
+            .all(|(a, b)| a.url.split('?').next() == b.url.split('?').next())
 }
@@ -0,0 +1,88 @@
+use crate::download::Download;
+use crate::login::LoginMethod;
+use crate::utils::clap::clap_parse_interval;
+use crate::utils::context::Context;
+use crate::utils::error::DownloadError;
+use crate::utils::os::is_special_file;
+use crate::{create_ctx, Cli, Execute};
+use anyhow::{bail, Result};
+use chrono::{Local, TimeDelta};
+use clap::Parser;
+use log::{error, info, warn};
+use std::time::Duration;
+
+#[derive(Clone, Debug, clap::Parser)]
+#[clap(about = "Periodically check series or seasons for new episodes and download them")]
+#[command(arg_required_else_help(true))]
+pub struct Watch {
+    #[arg(help = "Interval in which the urls are checked for new episodes")]
+    #[arg(
+        long_help = "Interval in which the urls are checked for new episodes. \
+    Either seconds or a number with a unit, e.g. '30m', '6h' or '1d'"
+    )]
+    #[arg(long, default_value = "1h", value_parser = clap_parse_interval)]
+    pub(crate) interval: Duration,
+
+    #[command(flatten)]
+    pub(crate) download: Download,
+
+    /// Command line arguments, used to login again if the session got invalid.
+    #[arg(skip)]
+    pub(crate) args: Vec<String>,
+    /// Login method of the first login, with the credentials already resolved.
+    #[arg(skip)]
+    pub(crate) login_method: Option<LoginMethod>,
+}
+
+impl Execute for Watch {
+    fn pre_check(&mut self) -> Result<()> {
+        if self.download.download_archive.is_none() {
+            bail!("`--download-archive` is required to remember which episodes were already downloaded")
+        }
+        if self.download.list.is_some() {
+            bail!("`--list` can't be used with the watch command")
+        }
+        if self.download.output == "-" || is_special_file(&self.download.output) {
+            bail!("The watch command can only write into files")
+        }
+        // nobody is there to answer interactive prompts
+        self.download.yes = true;
+
+        self.download.pre_check()
+    }
+
+    async fn execute(self, mut ctx: Context) -> Result<()> {
+        loop {
+            info!("Checking for new episodes");
+            match self.download.clone().execute(ctx.clone()).await {
+                Ok(()) => (),
+                Err(e) if matches!(DownloadError::classify(&e), Some(DownloadError::Auth(_))) => {
+                    warn!("Session got invalid ({}), logging in again", e);
+                    let mut cli = Cli::parse_from(&self.args);
+                    if let Some(login_method) = &self.login_method {
+                        cli.login_method = login_method.clone()
+                    }
+                    match create_ctx(&mut cli).await {
+                        Ok(new_ctx) => {
+                            ctx = new_ctx;
+                            // the episodes which failed because of the invalid session are
+                            // downloaded right away
+                            continue;
+                        }
+                        Err(e) => bail!("Failed to login again: {}", e),
+                    }
+                }
+                // a failed check must not stop watching, the next check may succeed
+                Err(e) => error!("Failed to download new episodes: {}", e),
+            }
+
+            if let Some(next_check) = TimeDelta::from_std(self.interval)
+                .ok()
+                .and_then(|interval| Local::now().checked_add_signed(interval))
+            {
+                info!("Next check at {}", next_check.format("%Y-%m-%d %H:%M:%S"))
+            }
+            tokio::time::sleep(self.interval).await
+        }
+    }
+}